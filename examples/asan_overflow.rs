@@ -0,0 +1,20 @@
+//! Overflows one arena allocation into the next, which AddressSanitizer
+//! reports with the `sanitize` feature.
+//!
+//! Run with `RUSTFLAGS=-Zsanitizer=address cargo +nightly run --example
+//! asan_overflow --features sanitize --target x86_64-unknown-linux-gnu`.
+//! ASAN reports the write one byte past the end of `first` and aborts.
+//! Without the feature, ASAN sees the whole buffer as one allocation and
+//! the program prints 0.
+
+extern crate memory_arena;
+use memory_arena::*;
+
+fn main() {
+    let a = Arena::new(1024, 64).unwrap();
+    let first = a.alloc_bytes(10).unwrap().as_mut_ptr();
+    // The u64 is aligned to 8, so the 6 bytes after `first` stay unallocated.
+    let second = a.new_box(0u64).unwrap();
+    unsafe { first.add(10).write(1) };
+    println!("{}", *second);
+}
@@ -1,6 +1,7 @@
 extern crate memory_arena;
 use memory_arena::*;
 
+#[allow(dead_code)]
 #[derive(Debug)]
 enum List<'a, T> {
     Nil,
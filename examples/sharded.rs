@@ -0,0 +1,38 @@
+//! Compares allocation throughput of a `ShardedArena` with one shard per
+//! thread against one with a single shard, and therefore a single contended
+//! cursor.
+//!
+//! Run with `cargo run --release --features std --example sharded`.
+
+extern crate memory_arena;
+use memory_arena::*;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const THREADS: usize = 32;
+const PER_THREAD: usize = 200_000;
+
+fn run(shards: usize) -> Duration {
+    let a = ShardedArena::new(THREADS * PER_THREAD * 16, 64, shards).unwrap();
+    let start = Instant::now();
+    thread::scope(|s| {
+        for t in 0..THREADS {
+            let a = &a;
+            s.spawn(move || {
+                for i in 0..PER_THREAD {
+                    let b = a.new_box((t * PER_THREAD + i) as u64).unwrap();
+                    std::hint::black_box(&b);
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+fn main() {
+    for &shards in &[1, THREADS] {
+        let elapsed = run(shards);
+        let rate = (THREADS * PER_THREAD) as f64 / elapsed.as_secs_f64() / 1e6;
+        println!("{:>2} shard(s): {:>10?}, {:.1}M allocations/s", shards, elapsed, rate);
+    }
+}
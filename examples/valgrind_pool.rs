@@ -0,0 +1,39 @@
+//! Allocates, resets and drops an Arena, for running under Memcheck with
+//! the `valgrind` feature.
+//!
+//! Run with `cargo build --example valgrind_pool --features valgrind` and
+//! `valgrind --leak-check=full target/debug/examples/valgrind_pool`. The
+//! read of `stale` after the reset is reported as an invalid read, and the
+//! Arena that is leaked at the end shows up as a leak together with the
+//! allocations still live in it. Pass `clean` as an argument to skip both,
+//! for a run with no errors.
+
+extern crate memory_arena;
+use memory_arena::*;
+
+fn main() {
+    let clean = std::env::args().any(|a| a == "clean");
+    let mut a = Arena::new(4096, 64).unwrap();
+    let mut total = 0u64;
+    for round in 0..3u64 {
+        let values = a.new_box([round; 32]).unwrap();
+        let label = a.alloc_str(&format!("round {}", round)).unwrap();
+        total += values.iter().sum::<u64>() + label.len() as u64;
+        drop((values, label));
+        a.reset();
+    }
+    let stale = ArenaBox::leak(a.new_box(7u64).unwrap()) as *const u64;
+    a.reset();
+    if !clean {
+        total += unsafe { stale.read_volatile() };
+    }
+    println!("{}", total);
+
+    let kept = Arena::new(1024, 64).unwrap();
+    ArenaBox::leak(kept.new_box([1u32; 16]).unwrap());
+    if clean {
+        drop(kept);
+    } else {
+        std::mem::forget(kept);
+    }
+}
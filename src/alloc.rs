@@ -7,69 +7,88 @@
 // except according to those terms.
 
 use core::fmt;
-use core::result;
-#[allow(unused_imports)]
-use libc::{c_int, c_void, size_t};
-
-
-#[cfg(not(windows))]
-pub(crate) use libc::posix_memalign;
-#[cfg(not(windows))]
-pub(crate) use libc::free as c_free;
-#[cfg(windows)]
-extern {
-    fn _aligned_malloc(size: size_t, alignment: size_t) -> *mut c_void;
-    fn _get_errno(p: *mut c_int) -> c_int;
-    fn _aligned_free(p: *mut c_void);
-}
+#[cfg(feature = "libc")]
+use libc::c_int;
 
-type Result<T> = result::Result<T, AllocError>;
+#[cfg(all(feature = "alloc", not(feature = "libc-malloc")))]
+mod global;
+#[cfg(feature = "libc")]
+mod sys;
 
-#[cfg(not(windows))]
-pub(crate) unsafe fn aligned_alloc(size: usize, alignment: usize) -> Result<*mut u8> {
-    assert!(alignment.count_ones() == 1);
-    let mut mem: *mut c_void = 0 as *mut c_void;
-    if size == 0 {
-        return Err(AllocError::ZeroSizeAlloc);
-    }
-    let errno = posix_memalign(&mut mem, alignment, size);
-    if errno != 0 {
-        Err(AllocError::Errno(errno))
-    } else {
-        Ok(mem as *mut u8)
-    }
+#[cfg(all(feature = "alloc", not(feature = "libc-malloc")))]
+pub(crate) use self::global::{aligned_alloc, free};
+#[cfg(feature = "libc")]
+pub(crate) use self::sys::*;
+
+/// Returns a lower bound on the size of a virtual memory page, for targets
+/// that cannot be asked.
+#[cfg(not(feature = "libc"))]
+pub(crate) fn page_size() -> usize {
+    4096
 }
 
-#[cfg(windows)]
-pub(crate) unsafe fn aligned_alloc(size: usize, alignment: usize) -> Result<*mut u8> {
-    assert!(alignment.count_ones() == 1);
-    if size == 0 {
-        return Err(AllocError::ZeroSizeAlloc);
-    }
-    let mem = _aligned_malloc(size, alignment);
-    if mem.is_null() {
-        let mut errno: c_int = 0;
-        _get_errno(&mut errno);
-        Err(AllocError::Errno(errno))
+/// Checks that memory can be allocated with the given alignment, which
+/// has to be a power of two.
+#[inline]
+pub(crate) fn check_alignment(alignment: usize) -> Result<(), AllocError> {
+    if alignment.is_power_of_two() {
+        Ok(())
     } else {
-        Ok(mem as *mut u8)
+        Err(AllocError::InvalidAlignment { alignment })
     }
 }
 
-#[cfg(not(windows))]
-pub(crate) unsafe fn free(ptr: *mut u8) {
-    c_free(ptr as *mut c_void);
+// The number of system allocations made by the current thread that have not
+// been freed yet, so tests can check that every buffer is freed exactly once.
+#[cfg(test)]
+thread_local! {
+    pub(crate) static LIVE_ALLOCATIONS: ::core::cell::Cell<isize> = const { ::core::cell::Cell::new(0) };
+    pub(crate) static TOTAL_ALLOCATIONS: ::core::cell::Cell<usize> = const { ::core::cell::Cell::new(0) };
 }
 
-#[cfg(windows)]
-pub(crate) unsafe fn free(ptr: *mut u8) {
-    _aligned_free(ptr as *mut c_void);
+#[cfg(all(test, feature = "alloc"))]
+pub(crate) fn count_allocation(delta: isize) {
+    LIVE_ALLOCATIONS.with(|live| live.set(live.get() + delta));
+    if delta > 0 {
+        TOTAL_ALLOCATIONS.with(|total| total.set(total.get() + delta as usize));
+    }
 }
 
+/// The error type for allocations, from an `Arena` or from the system.
+///
+/// Some variants only exist with some features, such as `Errno` with
+/// `libc`, and more may be added, so matches on it need a wildcard arm.
 #[derive(Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum AllocError {
     ZeroSizeAlloc,
+    /// The system allocator failed with the given error number.
+    #[cfg(feature = "libc")]
     Errno(c_int),
+    /// The Arena does not have enough memory left.
+    Exhausted,
+    /// The global allocator could not provide the memory for an Arena.
+    OutOfMemory,
+    /// The allocation would take the Arena past the quota set with
+    /// `Arena::set_quota`, of which `used` bytes are already taken.
+    QuotaExceeded { quota: usize, used: usize },
+    /// An Arena made of several regions has no single region with `size`
+    /// bytes left; the largest one has `largest` bytes left.
+    NoRegionFits { size: usize, largest: usize },
+    /// The memory at `addr` requested from `Arena::new_at` is already in
+    /// use, or cannot be mapped.
+    AddressUnavailable { addr: usize },
+    /// A page-level operation was asked of an Arena whose memory is not
+    /// made of whole pages of its own; see `Arena::new_pages`.
+    NotPageBacked,
+    /// The requested alignment is not a power of two.
+    InvalidAlignment { alignment: usize },
+    /// A slice that only grows in place, like that of a `SliceBuilder`,
+    /// cannot grow because something else was allocated after it.
+    Interleaved,
+    /// A string for `Arena::alloc_cstr` has a NUL byte at `position`, so
+    /// it cannot be made into a C string.
+    InteriorNul { position: usize },
 }
 
 impl fmt::Display for AllocError {
@@ -77,23 +96,183 @@ impl fmt::Display for AllocError {
         match *self {
             AllocError::ZeroSizeAlloc => write!(f, "zero sized allocation is not supported"),
 
+            #[cfg(feature = "libc")]
             AllocError::Errno(errno) => write!(f, "system allocation error number: {}", errno),
+
+            AllocError::Exhausted => write!(f, "arena does not have enough memory left"),
+
+            AllocError::OutOfMemory => write!(f, "out of memory"),
+
+            AllocError::QuotaExceeded { quota, used } => write!(
+                f,
+                "arena quota of {} bytes exceeded, {} bytes already used",
+                quota, used
+            ),
+
+            AllocError::NoRegionFits { size, largest } => write!(
+                f,
+                "no arena region has {} bytes left, the largest has {}",
+                size, largest
+            ),
+
+            AllocError::AddressUnavailable { addr } => {
+                write!(f, "memory at address {:#x} is not available", addr)
+            }
+
+            AllocError::NotPageBacked => write!(f, "arena memory is not page-backed"),
+
+            AllocError::InvalidAlignment { alignment } => {
+                write!(f, "alignment {} is not a power of two", alignment)
+            }
+
+            AllocError::Interleaved => {
+                write!(f, "another allocation follows the slice, so it cannot grow in place")
+            }
+
+            AllocError::InteriorNul { position } => {
+                write!(f, "string has a NUL byte at position {}", position)
+            }
         }
     }
 }
 
-#[cfg(test)]
+#[cfg(feature = "std")]
+impl ::std::error::Error for AllocError {}
+
+#[cfg(feature = "std")]
+impl AllocError {
+    /// Converts the error into an `io::Error` of kind `OutOfMemory`, like
+    /// its `From` implementation, without spelling out the target type.
+    /// `AllocError::InteriorNul` is not about memory, and is of kind
+    /// `InvalidInput` instead.
+    ///
+    /// The `AllocError` is kept as the `io::Error`'s payload, so the
+    /// details, such as an error number or the sizes involved, survive in
+    /// its `Display` text and through `io::Error::get_ref`.
+    pub fn into_io(self) -> ::std::io::Error {
+        let kind = match self {
+            AllocError::InteriorNul { .. } => ::std::io::ErrorKind::InvalidInput,
+            _ => ::std::io::ErrorKind::OutOfMemory,
+        };
+        ::std::io::Error::new(kind, self)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<AllocError> for ::std::io::Error {
+    fn from(e: AllocError) -> Self {
+        e.into_io()
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
 mod tests {
     #[allow(unused_imports)]
     use super::*;
+    use std::vec::Vec;
+    use test_util::live_allocations;
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn into_io_error() {
+        use std::io;
+        use std::string::ToString;
+
+        let e = AllocError::QuotaExceeded { quota: 64, used: 60 };
+        let text = e.to_string();
+        let io: io::Error = e.into();
+        assert_eq!(io.kind(), io::ErrorKind::OutOfMemory);
+        assert_eq!(io.to_string(), text);
+        assert_eq!(
+            io.get_ref().and_then(|e| e.downcast_ref::<AllocError>()),
+            Some(&AllocError::QuotaExceeded { quota: 64, used: 60 })
+        );
+        let f = || -> io::Result<()> { Err(AllocError::Exhausted)? };
+        assert_eq!(f().unwrap_err().to_string(), "arena does not have enough memory left");
+        assert_eq!(AllocError::OutOfMemory.into_io().kind(), io::ErrorKind::OutOfMemory);
+        let nul = AllocError::InteriorNul { position: 3 }.into_io();
+        assert_eq!(nul.kind(), io::ErrorKind::InvalidInput);
+    }
+
     #[test]
     fn aligned_alloc_and_free() {
         unsafe {
             let alignment = 1024;
-            let ptr = aligned_alloc(alignment, ::core::mem::size_of::<isize>()).unwrap();
+            let size = ::core::mem::size_of::<isize>();
+            let ptr = aligned_alloc(size, alignment).unwrap();
             let iptr = ptr as *mut isize;
             *iptr = 0;
-            free(ptr);
+            free(ptr, size, alignment);
+        }
+    }
+
+    #[test]
+    fn aligned_alloc_page_alignment() {
+        let before = live_allocations();
+        let sizes = [1, 7, 4096, 4097, 3 * 4096 + 5, 1 << 20];
+        let ptrs: Vec<_> = sizes
+            .iter()
+            .map(|&size| (unsafe { aligned_alloc(size, 4096).unwrap() }, size))
+            .collect();
+        assert_eq!(live_allocations(), before + sizes.len() as isize);
+        for &(ptr, size) in &ptrs {
+            assert_eq!(ptr as usize % 4096, 0);
+            unsafe { ::core::ptr::write_bytes(ptr, 0xa5, size) };
+        }
+        for (ptr, size) in ptrs {
+            unsafe { free(ptr, size, 4096) };
+        }
+        assert_eq!(live_allocations(), before);
+    }
+
+    #[test]
+    fn aligned_alloc_invalid_alignment() {
+        for &alignment in &[0, 3, 24, 1025] {
+            let err = unsafe { aligned_alloc(16, alignment) };
+            assert_eq!(err, Err(AllocError::InvalidAlignment { alignment }));
         }
+        // Alignments below the pointer size are fine, even for
+        // `posix_memalign`.
+        for &alignment in &[1, 2, 4] {
+            unsafe {
+                let ptr = aligned_alloc(16, alignment).unwrap();
+                free(ptr, 16, alignment);
+            }
+        }
+    }
+
+    #[cfg(not(feature = "libc-malloc"))]
+    #[test]
+    fn aligned_alloc_out_of_memory() {
+        let before = live_allocations();
+        assert_eq!(unsafe { aligned_alloc(usize::MAX - 4095, 4096) }, Err(AllocError::OutOfMemory));
+        let huge = isize::MAX as usize - 8191;
+        assert_eq!(unsafe { aligned_alloc(huge, 4096) }, Err(AllocError::OutOfMemory));
+        assert_eq!(live_allocations(), before);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn alloc_error_propagates() {
+        use std::boxed::Box;
+        use std::error::Error;
+        use std::string::ToString;
+        use Arena;
+
+        fn f() -> Result<(), Box<dyn Error>> {
+            let _ = Arena::new(0x1000, 0x1000)?;
+            Ok(())
+        }
+
+        fn g(a: &Arena) -> Result<(), Box<dyn Error>> {
+            a.new_uninit::<[u8; 0x2000]>()?;
+            Ok(())
+        }
+
+        assert!(f().is_ok());
+        let a = ::test_util::checked_arena(0x1000, 0x1000);
+        let err = g(&a).unwrap_err();
+        assert_eq!(err.to_string(), "arena does not have enough memory left");
+        assert_eq!(err.downcast_ref::<::AllocError>(), Some(&AllocError::Exhausted));
     }
 }
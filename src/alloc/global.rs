@@ -0,0 +1,45 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Heap memory from the Rust global allocator, so that a
+//! `#[global_allocator]` such as jemalloc or mimalloc is honored.
+
+use core::alloc::Layout;
+use core::result;
+use rust_alloc::alloc;
+
+#[cfg(test)]
+use super::count_allocation;
+use super::{check_alignment, AllocError};
+
+type Result<T> = result::Result<T, AllocError>;
+
+pub(crate) unsafe fn aligned_alloc(size: usize, alignment: usize) -> Result<*mut u8> {
+    check_alignment(alignment)?;
+    if size == 0 {
+        return Err(AllocError::ZeroSizeAlloc);
+    }
+    // The only way a power-of-two alignment makes an invalid layout is a
+    // size so close to `isize::MAX` that no allocator could satisfy it.
+    let layout = Layout::from_size_align(size, alignment).map_err(|_| AllocError::OutOfMemory)?;
+    let mem = alloc::alloc(layout);
+    if mem.is_null() {
+        return Err(AllocError::OutOfMemory);
+    }
+    #[cfg(test)]
+    count_allocation(1);
+    Ok(mem)
+}
+
+/// Frees memory returned by `aligned_alloc`, which has to be given the same
+/// size and alignment.
+pub(crate) unsafe fn free(ptr: *mut u8, size: usize, alignment: usize) {
+    #[cfg(test)]
+    count_allocation(-1);
+    alloc::dealloc(ptr, Layout::from_size_align_unchecked(size, alignment));
+}
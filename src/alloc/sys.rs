@@ -0,0 +1,324 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Memory straight from the operating system, on top of `libc`: mapped
+//! pages, and with the `libc-malloc` feature, `posix_memalign` or
+//! `_aligned_malloc` in place of the Rust global allocator.
+
+use core::result;
+#[allow(unused_imports)]
+use libc::{c_int, c_void, size_t};
+
+#[cfg(test)]
+use super::count_allocation;
+#[cfg(feature = "libc-malloc")]
+use super::check_alignment;
+use super::AllocError;
+
+#[cfg(all(feature = "libc-malloc", not(windows)))]
+pub(crate) use libc::posix_memalign;
+#[cfg(all(feature = "libc-malloc", not(windows)))]
+pub(crate) use libc::free as c_free;
+#[cfg(all(feature = "libc-malloc", windows))]
+extern {
+    fn _aligned_malloc(size: size_t, alignment: size_t) -> *mut c_void;
+    fn _get_errno(p: *mut c_int) -> c_int;
+    fn _aligned_free(p: *mut c_void);
+}
+
+type Result<T> = result::Result<T, AllocError>;
+
+#[cfg(all(feature = "libc-malloc", not(windows)))]
+pub(crate) unsafe fn aligned_alloc(size: usize, alignment: usize) -> Result<*mut u8> {
+    check_alignment(alignment)?;
+    // `posix_memalign` also wants a multiple of the pointer size, which any
+    // power of two at least that large is.
+    let alignment = alignment.max(::core::mem::size_of::<*mut c_void>());
+    let mut mem: *mut c_void = ::core::ptr::null_mut();
+    if size == 0 {
+        return Err(AllocError::ZeroSizeAlloc);
+    }
+    let errno = posix_memalign(&mut mem, alignment, size);
+    if errno != 0 {
+        Err(AllocError::Errno(errno))
+    } else {
+        #[cfg(test)]
+        count_allocation(1);
+        Ok(mem as *mut u8)
+    }
+}
+
+#[cfg(all(feature = "libc-malloc", windows))]
+pub(crate) unsafe fn aligned_alloc(size: usize, alignment: usize) -> Result<*mut u8> {
+    check_alignment(alignment)?;
+    if size == 0 {
+        return Err(AllocError::ZeroSizeAlloc);
+    }
+    let mem = _aligned_malloc(size, alignment);
+    if mem.is_null() {
+        let mut errno: c_int = 0;
+        _get_errno(&mut errno);
+        Err(AllocError::Errno(errno))
+    } else {
+        #[cfg(test)]
+        count_allocation(1);
+        Ok(mem as *mut u8)
+    }
+}
+
+#[cfg(all(feature = "libc-malloc", not(windows)))]
+pub(crate) unsafe fn free(ptr: *mut u8, _size: usize, _alignment: usize) {
+    #[cfg(test)]
+    count_allocation(-1);
+    c_free(ptr as *mut c_void);
+}
+
+#[cfg(all(feature = "libc-malloc", windows))]
+pub(crate) unsafe fn free(ptr: *mut u8, _size: usize, _alignment: usize) {
+    #[cfg(test)]
+    count_allocation(-1);
+    _aligned_free(ptr as *mut c_void);
+}
+
+/// Returns the calling thread's `errno`.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "emscripten"))]
+fn errno() -> c_int {
+    unsafe { *libc::__errno_location() }
+}
+
+/// Returns the calling thread's `errno`.
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+fn errno() -> c_int {
+    unsafe { *libc::__error() }
+}
+
+/// Returns the calling thread's `errno`.
+#[cfg(any(target_os = "openbsd", target_os = "netbsd"))]
+fn errno() -> c_int {
+    unsafe { *libc::__errno() }
+}
+
+/// Maps `size` bytes of zeroed, readable and writable memory, at exactly
+/// `addr` if it is given, without replacing any existing mapping.
+#[cfg(not(windows))]
+pub(crate) unsafe fn map(addr: Option<usize>, size: usize) -> Result<*mut u8> {
+    if size == 0 {
+        return Err(AllocError::ZeroSizeAlloc);
+    }
+    // Older kernels and other systems treat the address as a hint only, so
+    // the result is checked either way.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let fixed = libc::MAP_FIXED_NOREPLACE;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    let fixed = 0;
+    let (hint, flags) = match addr {
+        Some(addr) => (addr as *mut c_void, fixed),
+        None => (::core::ptr::null_mut(), 0),
+    };
+    let mem = libc::mmap(
+        hint,
+        size,
+        libc::PROT_READ | libc::PROT_WRITE,
+        libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | flags,
+        -1,
+        0,
+    );
+    if mem == libc::MAP_FAILED {
+        return Err(match addr {
+            Some(addr) => AllocError::AddressUnavailable { addr },
+            None => AllocError::Errno(errno()),
+        });
+    }
+    if let Some(addr) = addr {
+        if mem as usize != addr {
+            libc::munmap(mem, size);
+            return Err(AllocError::AddressUnavailable { addr });
+        }
+    }
+    #[cfg(test)]
+    count_allocation(1);
+    Ok(mem as *mut u8)
+}
+
+/// Reserves `size` bytes of zeroed, readable and writable address space,
+/// without setting aside memory or swap for it; pages are only committed
+/// when they are first touched.
+#[cfg(unix)]
+pub(crate) unsafe fn map_reserved(size: usize) -> Result<*mut u8> {
+    if size == 0 {
+        return Err(AllocError::ZeroSizeAlloc);
+    }
+    // Elsewhere, anonymous mappings are committed lazily anyway.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let noreserve = libc::MAP_NORESERVE;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    let noreserve = 0;
+    let mem = libc::mmap(
+        ::core::ptr::null_mut(),
+        size,
+        libc::PROT_READ | libc::PROT_WRITE,
+        libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | noreserve,
+        -1,
+        0,
+    );
+    if mem == libc::MAP_FAILED {
+        return Err(AllocError::Errno(errno()));
+    }
+    #[cfg(test)]
+    count_allocation(1);
+    Ok(mem as *mut u8)
+}
+
+#[cfg(windows)]
+#[repr(C)]
+struct SystemInfo {
+    processor_architecture: u16,
+    reserved: u16,
+    page_size: u32,
+    minimum_application_address: *mut c_void,
+    maximum_application_address: *mut c_void,
+    active_processor_mask: usize,
+    number_of_processors: u32,
+    processor_type: u32,
+    allocation_granularity: u32,
+    processor_level: u16,
+    processor_revision: u16,
+}
+
+#[cfg(windows)]
+extern "system" {
+    fn VirtualAlloc(addr: *mut c_void, size: size_t, ty: u32, protect: u32) -> *mut c_void;
+    fn VirtualFree(addr: *mut c_void, size: size_t, ty: u32) -> c_int;
+    fn VirtualProtect(addr: *mut c_void, size: size_t, protect: u32, old: *mut u32) -> c_int;
+    fn GetSystemInfo(info: *mut SystemInfo);
+    fn GetLastError() -> u32;
+}
+
+#[cfg(windows)]
+const PAGE_NOACCESS: u32 = 0x01;
+#[cfg(windows)]
+const PAGE_READONLY: u32 = 0x02;
+#[cfg(windows)]
+const PAGE_READWRITE: u32 = 0x04;
+
+/// Maps `size` bytes of zeroed, readable and writable memory, at exactly
+/// `addr` if it is given, without replacing any existing mapping.
+#[cfg(windows)]
+pub(crate) unsafe fn map(addr: Option<usize>, size: usize) -> Result<*mut u8> {
+    const MEM_COMMIT: u32 = 0x1000;
+    const MEM_RESERVE: u32 = 0x2000;
+    if size == 0 {
+        return Err(AllocError::ZeroSizeAlloc);
+    }
+    let hint = addr.unwrap_or(0) as *mut c_void;
+    let mem = VirtualAlloc(hint, size, MEM_RESERVE | MEM_COMMIT, PAGE_READWRITE);
+    if mem.is_null() {
+        return Err(match addr {
+            Some(addr) => AllocError::AddressUnavailable { addr },
+            None => AllocError::Errno(GetLastError() as c_int),
+        });
+    }
+    #[cfg(test)]
+    count_allocation(1);
+    Ok(mem as *mut u8)
+}
+
+/// Unmaps memory returned by `map`.
+#[cfg(not(windows))]
+pub(crate) unsafe fn unmap(ptr: *mut u8, size: usize) {
+    #[cfg(test)]
+    count_allocation(-1);
+    libc::munmap(ptr as *mut c_void, size);
+}
+
+/// Unmaps memory returned by `map`.
+#[cfg(windows)]
+pub(crate) unsafe fn unmap(ptr: *mut u8, _size: usize) {
+    const MEM_RELEASE: u32 = 0x8000;
+    #[cfg(test)]
+    count_allocation(-1);
+    VirtualFree(ptr as *mut c_void, 0, MEM_RELEASE);
+}
+
+/// Makes mapped memory read-only, or readable and writable again.
+#[cfg(not(windows))]
+pub(crate) unsafe fn protect(ptr: *mut u8, size: usize, read_only: bool) -> Result<()> {
+    let prot = if read_only {
+        libc::PROT_READ
+    } else {
+        libc::PROT_READ | libc::PROT_WRITE
+    };
+    if libc::mprotect(ptr as *mut c_void, size, prot) != 0 {
+        return Err(AllocError::Errno(errno()));
+    }
+    Ok(())
+}
+
+/// Makes mapped memory read-only, or readable and writable again.
+#[cfg(windows)]
+pub(crate) unsafe fn protect(ptr: *mut u8, size: usize, read_only: bool) -> Result<()> {
+    let prot = if read_only { PAGE_READONLY } else { PAGE_READWRITE };
+    let mut old = 0;
+    if VirtualProtect(ptr as *mut c_void, size, prot, &mut old) == 0 {
+        return Err(AllocError::Errno(GetLastError() as c_int));
+    }
+    Ok(())
+}
+
+/// Makes mapped memory inaccessible, so that any access to it faults.
+#[cfg(not(windows))]
+pub(crate) unsafe fn guard(ptr: *mut u8, size: usize) -> Result<()> {
+    if libc::mprotect(ptr as *mut c_void, size, libc::PROT_NONE) != 0 {
+        return Err(AllocError::Errno(errno()));
+    }
+    Ok(())
+}
+
+/// Makes mapped memory inaccessible, so that any access to it faults.
+#[cfg(windows)]
+pub(crate) unsafe fn guard(ptr: *mut u8, size: usize) -> Result<()> {
+    let mut old = 0;
+    if VirtualProtect(ptr as *mut c_void, size, PAGE_NOACCESS, &mut old) == 0 {
+        return Err(AllocError::Errno(GetLastError() as c_int));
+    }
+    Ok(())
+}
+
+/// The page size, once it has been queried.
+static PAGE_SIZE: ::core::sync::atomic::AtomicUsize = ::core::sync::atomic::AtomicUsize::new(0);
+
+/// Returns the size of a virtual memory page, querying the operating system
+/// only the first time.
+pub(crate) fn page_size() -> usize {
+    use core::sync::atomic::Ordering;
+    match PAGE_SIZE.load(Ordering::Relaxed) {
+        0 => {
+            let size = query_page_size();
+            PAGE_SIZE.store(size, Ordering::Relaxed);
+            size
+        }
+        size => size,
+    }
+}
+
+#[cfg(not(windows))]
+fn query_page_size() -> usize {
+    match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        n if n > 0 => n as usize,
+        _ => 4096,
+    }
+}
+
+#[cfg(windows)]
+fn query_page_size() -> usize {
+    unsafe {
+        let mut info: SystemInfo = ::core::mem::zeroed();
+        GetSystemInfo(&mut info);
+        info.page_size as usize
+    }
+}
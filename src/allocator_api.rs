@@ -0,0 +1,188 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The unstable `Allocator` trait, so that standard collections can
+//! allocate from an Arena, as in `Vec::new_in(&arena)`.
+
+use core::alloc::{AllocError, Allocator, Layout};
+use core::ptr::{self, NonNull};
+
+use Arena;
+
+fn block(p: *mut u8, size: usize) -> NonNull<[u8]> {
+    unsafe { NonNull::new_unchecked(ptr::slice_from_raw_parts_mut(p, size)) }
+}
+
+/// Allocates from the Arena with the usual bump-allocator semantics.
+///
+/// Deallocating is a no-op, as the memory is only reclaimed when the Arena
+/// is cleared or dropped. Growing or shrinking the most recent allocation
+/// moves the cursor in place; any other block that grows is moved to a new
+/// allocation, and the old one is left behind.
+///
+/// # Examples
+///
+/// ```
+/// #![feature(allocator_api)]
+/// # use memory_arena::*;
+/// let a = Arena::new(1 << 16, 4096).unwrap();
+/// let mut v = Vec::new_in(&a);
+/// v.extend(0..1000u32);
+/// let b = Box::new_in(7u64, &a);
+/// assert_eq!((v[999], *b), (999, 7));
+/// ```
+unsafe impl Allocator for &Arena {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        match self.try_aligned_alloc(layout.size(), layout.align()) {
+            Ok(p) => Ok(block(p, layout.size())),
+            Err(_) => Err(AllocError),
+        }
+    }
+
+    unsafe fn deallocate(&self, _ptr: NonNull<u8>, _layout: Layout) {}
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let p = ptr.as_ptr();
+        if (p as usize).is_multiple_of(new_layout.align())
+            && self.resize_in_place(p, old_layout.size(), new_layout.size())
+        {
+            return Ok(block(p, new_layout.size()));
+        }
+        let new = self.allocate(new_layout)?;
+        ptr::copy_nonoverlapping(p, new.as_ptr() as *mut u8, old_layout.size());
+        Ok(new)
+    }
+
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let new = self.grow(ptr, old_layout, new_layout)?;
+        let tail = (new.as_ptr() as *mut u8).add(old_layout.size());
+        ptr::write_bytes(tail, 0, new_layout.size() - old_layout.size());
+        Ok(new)
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let p = ptr.as_ptr();
+        if (p as usize).is_multiple_of(new_layout.align()) {
+            // The tail goes back to the Arena if this is the most recent
+            // allocation, and is left behind otherwise.
+            self.resize_in_place(p, old_layout.size(), new_layout.size());
+            return Ok(block(p, new_layout.size()));
+        }
+        let new = self.allocate(new_layout)?;
+        ptr::copy_nonoverlapping(p, new.as_ptr() as *mut u8, new_layout.size());
+        Ok(new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use std::boxed::Box;
+    use std::vec::Vec;
+    use test_util::checked_arena;
+
+    fn inside(a: &Arena, p: *const u8, len: usize) -> bool {
+        let start = a.mem as usize;
+        p as usize >= start && p as usize + len <= start + a.capacity()
+    }
+
+    #[test]
+    fn allocator_vec_stays_in_arena() {
+        let a = checked_arena(1 << 16, 4096);
+        let mut v: Vec<u32, &Arena> = Vec::new_in(&*a);
+        let mut moves = 0;
+        let mut last = v.as_ptr();
+        for i in 0..10_000 {
+            v.push(i);
+            if v.as_ptr() != last {
+                moves += 1;
+                last = v.as_ptr();
+            }
+        }
+        assert!(inside(&a, v.as_ptr() as *const u8, v.capacity() * 4));
+        assert!(v.iter().copied().eq(0..10_000));
+        // The Vec is the only allocation, so after the first one it always
+        // grows in place.
+        assert_eq!(moves, 1);
+        assert_eq!(a.used(), v.capacity() * 4);
+
+        v.truncate(10);
+        v.shrink_to_fit();
+        assert_eq!(a.used(), 40);
+    }
+
+    #[test]
+    fn allocator_interleaved() {
+        let a = checked_arena(1 << 20, 4096);
+        let mut v: Vec<u64, &Arena> = Vec::new_in(&*a);
+        let mut boxes = Vec::new();
+        for i in 0..5000u64 {
+            v.push(i);
+            if i % 100 == 0 {
+                boxes.push(Box::new_in([i as u8; 3], &*a));
+            }
+        }
+        assert!(inside(&a, v.as_ptr() as *const u8, v.capacity() * 8));
+        assert!(v.iter().copied().eq(0..5000));
+        for (i, b) in boxes.iter().enumerate() {
+            assert!(inside(&a, &**b as *const [u8; 3] as *const u8, 3));
+            assert_eq!(**b, [(i * 100) as u8; 3]);
+        }
+
+        // Growing a block that is no longer the most recent moves it.
+        let mut w: Vec<u16, &Arena> = Vec::with_capacity_in(4, &*a);
+        w.extend([1, 2, 3, 4]);
+        let _after = Box::new_in(0u8, &*a);
+        let before = w.as_ptr();
+        w.push(5);
+        assert_ne!(w.as_ptr(), before);
+        assert_eq!(w, [1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn allocator_layouts() {
+        let a = checked_arena(4096, 4096);
+        let alloc = &*a;
+        let _ = alloc.allocate(Layout::new::<u8>()).unwrap();
+        let p = alloc.allocate(Layout::from_size_align(16, 64).unwrap()).unwrap();
+        assert_eq!(p.as_ptr() as *mut u8 as usize % 64, 0);
+        assert_eq!(p.len(), 16);
+        unsafe {
+            // Growing to a stricter alignment than the block has moves it.
+            let old = Layout::from_size_align(16, 64).unwrap();
+            let new = Layout::from_size_align(32, 128).unwrap();
+            (p.as_ptr() as *mut u8).write_bytes(9, 16);
+            let q = alloc.grow_zeroed(p.cast(), old, new).unwrap();
+            let q = q.as_ptr() as *mut u8;
+            assert_eq!(q as usize % 128, 0);
+            let bytes = ::core::slice::from_raw_parts(q, 32);
+            assert_eq!((&bytes[..16], &bytes[16..]), (&[9u8; 16][..], &[0u8; 16][..]));
+        }
+
+        let zst = alloc.allocate(Layout::from_size_align(0, 256).unwrap()).unwrap();
+        assert_eq!(zst.len(), 0);
+        assert_eq!(zst.as_ptr() as *mut u8 as usize % 256, 0);
+        assert!(alloc.allocate(Layout::from_size_align(8192, 8).unwrap()).is_err());
+    }
+}
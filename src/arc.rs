@@ -0,0 +1,265 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A thread-safe reference-counted pointer to a value in an arena.
+
+use core::borrow::Borrow;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::Deref;
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{self, AtomicUsize, Ordering};
+
+use {Arena, ArenaBox};
+
+/// Counts above this are treated as a leak of `ArenaArc`s, as in
+/// `std::sync::Arc`.
+const MAX_REFCOUNT: usize = isize::MAX as usize;
+
+struct ArcInner<T> {
+    strong: AtomicUsize,
+    value: T,
+}
+
+/// A thread-safe reference-counted pointer to a value in an arena.
+///
+/// `ArenaArc<T>` is the arena counterpart of `std::sync::Arc<T>`: cloning it
+/// bumps a counter stored next to the value, and the value is dropped when
+/// the last clone goes away. The memory stays in the arena, like that of an
+/// `ArenaBox`. Since an `ArenaArc` only touches its own value and counter,
+/// it can be sent to other threads whenever `T: Send + Sync`, no matter
+/// which kind of arena it came from; the lifetime `'a` makes sure the arena
+/// outlives all clones.
+///
+/// # Examples
+///
+/// ```
+/// # use memory_arena::*;
+/// # #[cfg(feature = "alloc")] {
+/// let a = Arena::new(1024, 1024).unwrap();
+/// let shared = a.new_arc(String::from("config")).unwrap();
+/// std::thread::scope(|s| {
+///     for _ in 0..4 {
+///         let shared = shared.clone();
+///         s.spawn(move || assert_eq!(*shared, "config"));
+///     }
+/// });
+/// assert_eq!(ArenaArc::strong_count(&shared), 1);
+/// # }
+/// ```
+pub struct ArenaArc<'a, T> {
+    ptr: NonNull<ArcInner<T>>,
+    phantom: PhantomData<(&'a (), ArcInner<T>)>,
+}
+
+unsafe impl<'a, T: Send + Sync> Send for ArenaArc<'a, T> {}
+unsafe impl<'a, T: Send + Sync> Sync for ArenaArc<'a, T> {}
+
+impl<'a, T> ArenaArc<'a, T> {
+    fn from_inner(b: ArenaBox<'a, ArcInner<T>>) -> Self {
+        ArenaArc {
+            ptr: unsafe { NonNull::new_unchecked(ArenaBox::into_raw(b)) },
+            phantom: PhantomData,
+        }
+    }
+
+    fn inner(&self) -> &ArcInner<T> {
+        unsafe { self.ptr.as_ref() }
+    }
+
+    /// Returns the number of clones of `this`, including itself.
+    pub fn strong_count(this: &Self) -> usize {
+        this.inner().strong.load(Ordering::Acquire)
+    }
+
+    /// Returns `true` if both point to the same value.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.ptr == other.ptr
+    }
+
+    /// Returns a mutable reference to the value if `this` is its only
+    /// clone.
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        if this.inner().strong.load(Ordering::Acquire) == 1 {
+            Some(unsafe { &mut (*this.ptr.as_ptr()).value })
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a, T> Clone for ArenaArc<'a, T> {
+    fn clone(&self) -> Self {
+        // A new clone can only be made from an existing one, which already
+        // keeps the value alive, so there is nothing to synchronize with.
+        let old = self.inner().strong.fetch_add(1, Ordering::Relaxed);
+        assert!(old <= MAX_REFCOUNT, "ArenaArc reference count overflow");
+        ArenaArc {
+            ptr: self.ptr,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, T> Drop for ArenaArc<'a, T> {
+    fn drop(&mut self) {
+        if self.inner().strong.fetch_sub(1, Ordering::Release) != 1 {
+            return;
+        }
+        // Make every use of the value by other clones happen before it is
+        // dropped.
+        atomic::fence(Ordering::Acquire);
+        unsafe { ptr::drop_in_place(&mut (*self.ptr.as_ptr()).value) }
+    }
+}
+
+impl<'a, T> Deref for ArenaArc<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner().value
+    }
+}
+
+impl<'a, T> AsRef<T> for ArenaArc<'a, T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<'a, T> Borrow<T> for ArenaArc<'a, T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<'a, T: fmt::Debug> fmt::Debug for ArenaArc<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: fmt::Display> fmt::Display for ArenaArc<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl Arena {
+    /// Places `x` in the Arena and returns the first `ArenaArc` to it.
+    ///
+    /// If there is not enough available memory in the Arena, then the
+    /// original value is returned, wrapped in `Result::Err`.
+    pub fn new_arc<'a, T>(&'a self, x: T) -> Result<ArenaArc<'a, T>, T> {
+        let inner = ArcInner {
+            strong: AtomicUsize::new(1),
+            value: x,
+        };
+        match self.new_box(inner) {
+            Ok(b) => Ok(ArenaArc::from_inner(b)),
+            Err(inner) => Err(inner.value),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::ShardedArena {
+    /// Places `x` in the arena and returns the first `ArenaArc` to it.
+    ///
+    /// If there is not enough available memory in any shard, then the
+    /// original value is returned, wrapped in `Result::Err`.
+    pub fn new_arc<'a, T>(&'a self, x: T) -> Result<ArenaArc<'a, T>, T> {
+        let inner = ArcInner {
+            strong: AtomicUsize::new(1),
+            value: x,
+        };
+        match self.new_box(inner) {
+            Ok(b) => Ok(ArenaArc::from_inner(b)),
+            Err(inner) => Err(inner.value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use std::sync::Mutex;
+    use std::thread;
+    use std::vec::Vec;
+    use test_util::checked_arena;
+
+    #[derive(Debug)]
+    struct Counted<'m>(u32, &'m Mutex<usize>);
+
+    impl<'m> Drop for Counted<'m> {
+        fn drop(&mut self) {
+            *self.1.lock().unwrap() += 1;
+        }
+    }
+
+    #[test]
+    fn arc_threads() {
+        let drops = Mutex::new(0);
+        let a = checked_arena(1024, 1024);
+        let shared = a.new_arc(Counted(7, &drops)).unwrap();
+        thread::scope(|s| {
+            for _ in 0..8 {
+                let mine = shared.clone();
+                s.spawn(move || {
+                    let clones: Vec<_> = (0..100).map(|_| mine.clone()).collect();
+                    assert!(clones.iter().all(|c| c.0 == 7 && ArenaArc::ptr_eq(c, &mine)));
+                });
+            }
+        });
+        assert_eq!(*drops.lock().unwrap(), 0);
+        assert_eq!(ArenaArc::strong_count(&shared), 1);
+        let last = thread::scope(|s| s.spawn(move || drop(shared)).join());
+        assert!(last.is_ok());
+        assert_eq!(*drops.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn arc_get_mut() {
+        let a = checked_arena(1024, 1024);
+        let mut x = a.new_arc(1u32).unwrap();
+        *ArenaArc::get_mut(&mut x).unwrap() += 1;
+        let y = x.clone();
+        assert!(ArenaArc::get_mut(&mut x).is_none());
+        assert_eq!(ArenaArc::strong_count(&y), 2);
+        drop(y);
+        assert_eq!(ArenaArc::get_mut(&mut x), Some(&mut 2));
+        let z = a.new_arc(2u32).unwrap();
+        assert!(!ArenaArc::ptr_eq(&x, &z));
+    }
+
+    #[test]
+    fn arc_exhausted() {
+        let a = checked_arena(8, 8);
+        assert_eq!(a.new_arc(5u64).unwrap_err(), 5);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn arc_sharded() {
+        let drops = Mutex::new(0);
+        let a = ::ShardedArena::new(4096, 64, 4).unwrap();
+        thread::scope(|s| {
+            for i in 0..4 {
+                let a = &a;
+                let drops = &drops;
+                s.spawn(move || {
+                    let x = a.new_arc(Counted(i, drops)).unwrap();
+                    let y = x.clone();
+                    thread::scope(|s| s.spawn(move || assert_eq!(y.0, i)).join().unwrap());
+                });
+            }
+        });
+        assert_eq!(*drops.lock().unwrap(), 4);
+    }
+}
@@ -6,57 +6,612 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use core::alloc::Layout;
 use core::cell::Cell;
+use core::ffi::CStr;
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::pin::Pin;
+use core::ptr::NonNull;
 use arena_box::ArenaBox;
+#[cfg(feature = "alloc")]
+use backing::BackingMemory;
+use cleanup::Cleanups;
+#[cfg(feature = "alloc")]
+use cleanup::Trigger;
+use regions::Regions;
+#[cfg(feature = "registry")]
+use registry::Used;
 
+#[cfg(not(feature = "registry"))]
+type Used = Cell<usize>;
+
+/// A fixed-size buffer that values are allocated from by bumping a cursor.
+///
+/// # Zero-sized types
+///
+/// Zero-sized values never take up any of the Arena's memory. Allocating
+/// one never fails, not even in an Arena with no capacity left, and does
+/// not count against the quota or add alignment padding. Their destructors
+/// still run when their boxes are dropped, like those of any other value.
+/// Slices of zero-sized elements can be of any length.
+///
+/// # Poisoning
+///
+/// With the `poison` feature, debug builds fill every allocation with
+/// `0xA5` bytes before handing it out, and memory that a reset, rewind or
+/// reclaim makes available again with `0x5A` bytes, so reading memory that
+/// was never written or was already released turns up an obvious pattern
+/// rather than plausible old values. [`ArenaBuilder::poison`] turns it on
+/// or off regardless of the build.
+///
+/// With the `sanitize` feature, in nightly builds with
+/// `-Zsanitizer=address`, AddressSanitizer also reports any access to the
+/// memory of the main buffer that is not currently allocated, such as an
+/// overflow into the padding after an allocation, or into memory given
+/// back by a reset.
+///
+/// With the `valgrind` feature, the main buffer is described to Valgrind
+/// as a memory pool, so that Memcheck reports reads of memory given back
+/// by a reset or rewind, and arena allocations that are still live when a
+/// leaked Arena's memory is.
+///
+/// [`ArenaBuilder::poison`]: struct.ArenaBuilder.html#method.poison
 pub struct Arena {
     size: usize,
-    used: Cell<usize>,
-    mem: *mut u8,
+    pub(crate) used: Used,
+    pub(crate) mem: *mut u8,
+    /// The smaller of `size` and the quota, so that allocations only need
+    /// to check one bound.
+    limit: usize,
+    quota: Option<usize>,
+    min_align: usize,
+    min_align_padding: Cell<usize>,
+    /// Where `mem` came from, and so how to release it.
+    pub(crate) backing: Backing,
+    /// Further memory regions to fall back to when `mem` is full.
+    pub(crate) regions: Regions,
+    pub(crate) cleanups: Cleanups,
+    /// The number of live `ArenaBox`es and other users of the memory.
+    #[cfg(feature = "live-count")]
+    pub(crate) live: Cell<usize>,
+    stats: Cell<ArenaStats>,
+    /// The tag of the Arena, in the upper half, and its generation, which
+    /// handles from `Arena::insert` carry, so they can be checked against
+    /// it with a single compare.
+    pub(crate) handle_stamp: Cell<u64>,
+    /// Whether allocations and reclaimed memory are filled with
+    /// `POISON_ALLOC` and `POISON_RESET`.
+    #[cfg(feature = "poison")]
+    pub(crate) poison: bool,
+}
+
+/// The byte that fresh allocations are filled with when poisoning is on.
+#[cfg(feature = "poison")]
+pub(crate) const POISON_ALLOC: u8 = 0xA5;
+
+/// The byte that memory is filled with when poisoning is on and the memory
+/// is made available again by a reset.
+#[cfg(feature = "poison")]
+pub(crate) const POISON_RESET: u8 = 0x5A;
+
+/// Allocation statistics of an `Arena`, as returned by [`Arena::stats`].
+///
+/// The statistics cover the whole life of the Arena, and are not cleared
+/// by [`Arena::reset`], so that the peak of one run can be used to size
+/// the next. Zero-sized allocations take no memory and are not counted.
+///
+/// [`Arena::stats`]: struct.Arena.html#method.stats
+/// [`Arena::reset`]: struct.Arena.html#method.reset
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ArenaStats {
+    /// The number of allocations that succeeded, less the ones given back
+    /// with `Arena::reclaim`.
+    pub allocations: usize,
+    /// The number of allocations that failed for lack of memory or quota.
+    pub failed_allocations: usize,
+    /// The largest value `Arena::used` has had.
+    pub peak_used: usize,
+}
+
+/// Where the buffer of an `Arena` came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Backing {
+    /// Allocated with `::alloc::aligned_alloc` with the given alignment, so
+    /// it has to be freed.
+    #[cfg(feature = "alloc")]
+    Heap(usize),
+    /// Borrowed from the caller, so it is left alone.
+    Borrowed,
+    /// Not allocated at all, because the Arena has no capacity; `mem` is
+    /// only a dangling, aligned pointer.
+    #[cfg(feature = "alloc")]
+    Empty,
+    /// Mapped with `::alloc::map`, so the given number of bytes have to be
+    /// unmapped.
+    #[cfg(feature = "libc")]
+    Mapped(usize),
+    /// Mapped with `::alloc::map` with a guard page after the buffer, which
+    /// starts `offset` bytes into the `len` bytes that have to be unmapped.
+    #[cfg(feature = "libc")]
+    Guarded { offset: usize, len: usize },
+    /// Allocated from a boxed `BackingMemory` with the given alignment, which
+    /// has to be given the memory back and then be dropped.
+    #[cfg(feature = "alloc")]
+    Custom {
+        backend: *mut dyn BackingMemory,
+        alignment: usize,
+    },
 }
 
 impl Arena {
+    /// Creates an Arena of `size` bytes from the heap, whose memory starts
+    /// at a multiple of `alignment`.
+    ///
+    /// If `alignment` is not a power of two, `AllocError::InvalidAlignment`
+    /// is returned.
+    #[cfg(feature = "alloc")]
     pub fn new(size: usize, alignment: usize) -> Result<Self, ::alloc::AllocError> {
+        ::alloc::check_alignment(alignment)?;
         if size == 0 {
-            Ok(Self {
-                size: size,
-                used: Cell::new(0),
-                mem: 1 as *mut u8,
-            })
+            let mem = ::core::ptr::without_provenance_mut(alignment);
+            Ok(Self::from_parts(mem, size, Backing::Empty, Regions::new()))
         } else {
             unsafe {
                 let mem = ::alloc::aligned_alloc(size, alignment)?;
-                Ok(Self {
-                    size: size,
-                    used: Cell::new(0),
-                    mem: mem,
-                })
+                Ok(Self::from_parts(mem, size, Backing::Heap(alignment), Regions::new()))
             }
         }
     }
 
-    fn aligned_alloc(&self, size: usize, alignment: usize) -> Option<*mut u8> {
-        assert!(alignment.count_ones() == 1);
+    pub(crate) fn from_parts(mem: *mut u8, size: usize, backing: Backing, regions: Regions) -> Self {
+        #[allow(unused_mut)]
+        let mut arena = Self {
+            size,
+            used: Used::new(0),
+            mem,
+            limit: size,
+            quota: None,
+            min_align: 1,
+            min_align_padding: Cell::new(0),
+            backing,
+            regions,
+            cleanups: Cleanups::new(),
+            #[cfg(feature = "live-count")]
+            live: Cell::new(0),
+            stats: Cell::new(ArenaStats::default()),
+            handle_stamp: Cell::new(::index::first_stamp()),
+            #[cfg(feature = "poison")]
+            poison: cfg!(debug_assertions),
+        };
+        #[cfg(feature = "registry")]
+        arena.used.register(None, size);
+        ::sanitize::poison(mem, size);
+        if size != 0 {
+            ::valgrind::create_pool(mem, size);
+        }
+        arena
+    }
+
+    /// Raises the alignment of every subsequent allocation to at least
+    /// `align` bytes, regardless of the alignment of the allocated type.
+    ///
+    /// This is for hardware and data structures that need all objects on,
+    /// say, cache-line boundaries. The padding this adds on top of what the
+    /// types themselves require is reported by
+    /// [`Arena::min_alignment_padding`]. Zero-sized values take up no memory
+    /// and are not affected.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `align` is not a power of two.
+    ///
+    /// [`Arena::min_alignment_padding`]: #method.min_alignment_padding
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let mut a = Arena::new(1024, 1024).unwrap();
+    /// a.set_min_alignment(64);
+    /// let x = a.new_box(1u8).unwrap();
+    /// let y = a.new_box(2u8).unwrap();
+    /// assert_eq!(&*y as *const u8 as usize - &*x as *const u8 as usize, 64);
+    /// assert_eq!(a.min_alignment_padding(), 63);
+    /// # }
+    /// ```
+    pub fn set_min_alignment(&mut self, align: usize) {
+        assert!(align.is_power_of_two(), "minimum alignment {} is not a power of two", align);
+        self.min_align = align;
+    }
+
+    /// Returns the minimum alignment set with [`Arena::set_min_alignment`],
+    /// which is 1 by default.
+    ///
+    /// [`Arena::set_min_alignment`]: #method.set_min_alignment
+    pub fn min_alignment(&self) -> usize {
+        self.min_align
+    }
+
+    /// Returns the number of bytes that were spent on padding because of
+    /// the minimum alignment, beyond the padding the allocated types needed
+    /// on their own.
+    pub fn min_alignment_padding(&self) -> usize {
+        self.min_align_padding.get()
+    }
+
+    /// Limits the memory handed out by the Arena to `bytes`, even if it has
+    /// more capacity than that.
+    ///
+    /// Once an allocation would take the Arena past the quota, it fails,
+    /// and the methods that report an `AllocError` report
+    /// `AllocError::QuotaExceeded`. The quota can be raised, lowered or
+    /// removed at any time; lowering it below what is already used only
+    /// affects future allocations.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let mut a = Arena::new(1024, 1024).unwrap();
+    /// a.set_quota(8);
+    /// let _ = a.new_box(1u64).unwrap();
+    /// assert_eq!(a.new_box(2u64), Err(2));
+    /// a.set_quota(16);
+    /// assert!(a.new_box(2u64).is_ok());
+    /// # }
+    /// ```
+    pub fn set_quota(&mut self, bytes: usize) {
+        self.quota = Some(bytes);
+        self.limit = bytes.min(self.size);
+    }
+
+    /// Removes the quota set with [`Arena::set_quota`], so the whole
+    /// capacity is available again.
+    ///
+    /// [`Arena::set_quota`]: #method.set_quota
+    pub fn clear_quota(&mut self) {
+        self.quota = None;
+        self.limit = self.size;
+    }
+
+    /// Returns the quota set with [`Arena::set_quota`], if any.
+    ///
+    /// [`Arena::set_quota`]: #method.set_quota
+    pub fn quota(&self) -> Option<usize> {
+        self.quota
+    }
+
+    /// Returns the total size of the Arena's memory in bytes, including
+    /// that of its extra regions, regardless of any quota.
+    pub fn capacity(&self) -> usize {
+        self.size + self.regions.size()
+    }
+
+    /// Returns the number of bytes handed out so far, including alignment
+    /// padding.
+    pub fn used(&self) -> usize {
+        self.used.get() + self.regions.used()
+    }
+
+    /// Returns the number of bytes that can still be handed out, taking
+    /// the quota into account.
+    ///
+    /// This is an upper bound: an allocation larger than `remaining()`
+    /// always fails, but one that fits may still fail, because it may need
+    /// alignment padding, and because a single allocation never spans two
+    /// regions.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(12, 16).unwrap();
+    /// let _ = a.new_box(1u8).unwrap();
+    /// assert_eq!((a.used(), a.remaining()), (1, 11));
+    /// // 11 bytes are left, but a u64 needs 7 bytes of padding first.
+    /// assert_eq!(a.new_box(2u64), Err(2));
+    /// assert_eq!(a.remaining(), 11);
+    /// # }
+    /// ```
+    pub fn remaining(&self) -> usize {
+        let free = self.capacity() - self.used();
+        match self.quota {
+            Some(quota) => free.min(quota.saturating_sub(self.used())),
+            None => free,
+        }
+    }
+
+    /// Returns how many values of type `T` can still be allocated one by
+    /// one, for example with `new_box`, from the Arena's main buffer.
+    ///
+    /// This follows the allocation code exactly, including the alignment
+    /// padding in front of each value, the minimum alignment and the
+    /// quota, so that many allocations are guaranteed to succeed. An Arena
+    /// with extra regions may fit more; those are not counted. Any number
+    /// of zero-sized values fits.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(64, 64).unwrap();
+    /// let _ = a.new_box(1u8).unwrap();
+    /// // The first u64 starts after 7 bytes of padding.
+    /// assert_eq!(a.remaining_for::<u64>(), 7);
+    /// assert_eq!(a.remaining_for::<[u8; 63]>(), 1);
+    /// assert!(!a.can_fit::<[u8; 64]>());
+    /// # }
+    /// ```
+    pub fn remaining_for<T>(&self) -> usize {
+        let size = ::core::mem::size_of::<T>();
+        if size == 0 {
+            return usize::MAX;
+        }
+        let alignment = ::core::mem::align_of::<T>().max(self.min_align);
         let unaligned_p = self.mem as usize + self.used.get();
         let aligned_p = (unaligned_p + alignment - 1) & !(alignment - 1);
         let offset = aligned_p - unaligned_p;
-        if self.used.get() + size + offset > self.size {
-            return None;
+        let end = match self.used.get().checked_add(size + offset) {
+            Some(end) if end <= self.limit => end,
+            _ => return 0,
+        };
+        // Every further value starts at the first multiple of the alignment
+        // after the one before.
+        let stride = (size + alignment - 1) & !(alignment - 1);
+        1 + (self.limit - end) / stride
+    }
+
+    /// Returns `true` if a value of type `T` can be allocated from the
+    /// Arena's main buffer; see [`Arena::remaining_for`].
+    ///
+    /// [`Arena::remaining_for`]: #method.remaining_for
+    pub fn can_fit<T>(&self) -> bool {
+        self.remaining_for::<T>() > 0
+    }
+
+    /// Returns `true` if `ptr` points into memory that the Arena has handed
+    /// out, in its main buffer or in one of its extra regions.
+    ///
+    /// Only the used part of the memory counts, so a pointer one past the
+    /// end of the most recent allocation is not contained, and an Arena
+    /// with nothing allocated contains no pointers at all. Zero-sized
+    /// allocations get dangling pointers that are not in the Arena either.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let x = a.new_box([0u8; 16]).unwrap();
+    /// assert!(a.contains(&x[15]));
+    /// assert!(!a.contains(x.as_ptr().wrapping_add(16)));
+    /// # }
+    /// ```
+    pub fn contains(&self, ptr: *const u8) -> bool {
+        let offset = (ptr as usize).wrapping_sub(self.mem as usize);
+        offset < self.used.get() || self.regions.contains(ptr as usize)
+    }
+
+    /// Returns `true` if all of `r` lies in memory that the Arena has
+    /// handed out, like [`Arena::contains`].
+    ///
+    /// A zero-sized value is contained if its address is.
+    ///
+    /// [`Arena::contains`]: #method.contains
+    pub fn contains_value<T: ?Sized>(&self, r: &T) -> bool {
+        let start = r as *const T as *const u8;
+        let size = ::core::mem::size_of_val(r);
+        if size == 0 {
+            return self.contains(start);
+        }
+        let last = start.wrapping_add(size - 1);
+        let offset = (start as usize).wrapping_sub(self.mem as usize);
+        if offset < self.used.get() {
+            return offset + size <= self.used.get();
+        }
+        self.regions.contains_range(start as usize, last as usize)
+    }
+
+    pub(crate) fn aligned_alloc(&self, size: usize, alignment: usize) -> Option<*mut u8> {
+        self.try_aligned_alloc(size, alignment).ok()
+    }
+
+    /// Like `aligned_alloc`, but tells running out of capacity apart from
+    /// running into the quota.
+    ///
+    /// Zero-sized allocations always succeed with a dangling pointer that
+    /// is aligned to `alignment`, and leave the cursor where it is.
+    pub(crate) fn try_aligned_alloc(
+        &self,
+        size: usize,
+        alignment: usize,
+    ) -> Result<*mut u8, ::alloc::AllocError> {
+        ::alloc::check_alignment(alignment)?;
+        if size == 0 {
+            return Ok(::core::ptr::without_provenance_mut(alignment));
+        }
+        let padded_size = match self.bump(size, alignment) {
+            Ok(p) => return Ok(p),
+            Err(Some(padded_size)) => padded_size,
+            Err(None) => {
+                self.count_allocation(false);
+                return Err(::alloc::AllocError::Exhausted);
+            }
+        };
+        let result = self.alloc_spill(size, alignment, padded_size);
+        self.count_allocation(result.is_ok());
+        if let Ok(p) = result {
+            self.mark_allocated(p, size);
+        }
+        result
+    }
+
+    /// Like `try_aligned_alloc`, but never falls back to the extra regions,
+    /// for allocations that have to be at an offset into the main buffer.
+    pub(crate) fn try_alloc_main(
+        &self,
+        size: usize,
+        alignment: usize,
+    ) -> Result<*mut u8, ::alloc::AllocError> {
+        ::alloc::check_alignment(alignment)?;
+        if size == 0 {
+            return Ok(::core::ptr::without_provenance_mut(alignment));
+        }
+        self.bump(size, alignment).map_err(|padded_size| {
+            self.count_allocation(false);
+            padded_size.map_or(::alloc::AllocError::Exhausted, |padded_size| self.main_full_error(padded_size))
+        })
+    }
+
+    /// Allocates a non-zero `size` bytes from the main buffer, or returns
+    /// the size including the padding it would have needed if they do not
+    /// fit, or `None` if that size overflows `usize`.
+    #[inline]
+    fn bump(&self, size: usize, alignment: usize) -> Result<*mut u8, Option<usize>> {
+        let unaligned_p = self.mem as usize + self.used.get();
+        let align_up = |alignment: usize| (unaligned_p + alignment - 1) & !(alignment - 1);
+        let aligned_p = align_up(alignment.max(self.min_align));
+        let offset = aligned_p - unaligned_p;
+        let padded_size = size.checked_add(offset).ok_or(None)?;
+        let end = self.used.get().checked_add(padded_size).ok_or(None)?;
+        if end > self.limit {
+            return Err(Some(padded_size));
+        }
+        self.used.set(end);
+        let extra = aligned_p - align_up(alignment);
+        self.min_align_padding.set(self.min_align_padding.get() + extra);
+        self.count_allocation(true);
+        self.mark_allocated(aligned_p as *mut u8, size);
+        Ok(aligned_p as *mut u8)
+    }
+
+    /// Prepares `len` freshly allocated bytes at `p` for use: they are made
+    /// accessible to ASAN, added to the Valgrind pool if they are from the
+    /// main buffer, and filled with `POISON_ALLOC` if poisoning is on.
+    #[inline]
+    fn mark_allocated(&self, p: *mut u8, len: usize) {
+        ::sanitize::unpoison(p, len);
+        if cfg!(feature = "valgrind") && (p as usize).wrapping_sub(self.mem as usize) < self.size {
+            ::valgrind::alloc(self.mem, p, len);
+        }
+        #[cfg(feature = "poison")]
+        if self.poison {
+            unsafe { ::core::ptr::write_bytes(p, POISON_ALLOC, len) };
+        }
+    }
+
+    /// Marks the main buffer from offset `from` up to the cursor as free,
+    /// before the cursor is moved back to `from`: it is filled with
+    /// `POISON_RESET` if poisoning is on, made inaccessible to ASAN, and its
+    /// Valgrind pool chunks are trimmed away.
+    #[inline]
+    fn mark_released(&self, from: usize) {
+        let len = self.used.get() - from;
+        let p = unsafe { self.mem.add(from) };
+        #[cfg(feature = "poison")]
+        if self.poison {
+            unsafe { ::core::ptr::write_bytes(p, POISON_RESET, len) };
+        }
+        ::sanitize::poison(p, len);
+        if self.size != 0 {
+            ::valgrind::trim(self.mem, from);
+        }
+    }
+
+    /// Counts an allocation that succeeded or failed in the statistics.
+    #[inline]
+    fn count_allocation(&self, ok: bool) {
+        let mut stats = self.stats.get();
+        if ok {
+            stats.allocations += 1;
+        } else {
+            stats.failed_allocations += 1;
+        }
+        self.stats.set(stats);
+        self.update_peak();
+    }
+
+    /// Raises the peak of the statistics to the current usage, after the
+    /// cursor was moved forward.
+    #[inline]
+    fn update_peak(&self) {
+        let mut stats = self.stats.get();
+        stats.peak_used = stats.peak_used.max(self.used());
+        self.stats.set(stats);
+    }
+
+    /// Returns the allocation statistics of the Arena.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let mut a = Arena::new(64, 64).unwrap();
+    /// let _ = a.new_box([0u8; 48]).unwrap();
+    /// assert!(a.new_box([0u8; 32]).is_err());
+    /// a.reset();
+    /// let _ = a.new_box(0u64).unwrap();
+    /// let stats = a.stats();
+    /// assert_eq!((stats.allocations, stats.failed_allocations), (2, 1));
+    /// assert_eq!(stats.peak_used, 48);
+    /// # }
+    /// ```
+    pub fn stats(&self) -> ArenaStats {
+        self.stats.get()
+    }
+
+    /// Handles an allocation that did not fit in the main region, by
+    /// trying the other regions if there are any, or by reporting why it
+    /// failed. `padded_size` is the size including the padding the main
+    /// region would have needed.
+    #[cold]
+    fn alloc_spill(
+        &self,
+        size: usize,
+        alignment: usize,
+        padded_size: usize,
+    ) -> Result<*mut u8, ::alloc::AllocError> {
+        if self.regions.is_empty() {
+            return Err(self.main_full_error(padded_size));
+        }
+        let used = self.used.get() + self.regions.used();
+        if let Some(quota) = self.quota {
+            if used.checked_add(size).is_none_or(|end| end > quota) {
+                return Err(::alloc::AllocError::QuotaExceeded { quota, used });
+            }
+        }
+        let alignment = alignment.max(self.min_align);
+        self.regions.alloc(size, alignment).ok_or_else(|| {
+            let largest = self.regions.largest_remaining().max(self.size - self.used.get());
+            ::alloc::AllocError::NoRegionFits { size, largest }
+        })
+    }
+
+    /// Reports why `padded_size` bytes do not fit in the main buffer.
+    fn main_full_error(&self, padded_size: usize) -> ::alloc::AllocError {
+        let used = self.used.get();
+        match self.quota {
+            Some(quota) if used + padded_size <= self.size => {
+                ::alloc::AllocError::QuotaExceeded { quota, used }
+            }
+            _ => ::alloc::AllocError::Exhausted,
         }
-        self.used.set(self.used.get() + size + offset);
-        Some(aligned_p as *mut u8)
     }
 
     fn alloc<T>(&self) -> Option<*mut T> {
         let size = ::core::mem::size_of::<T>();
         if size == 0 {
-            return Some(::core::mem::align_of::<T>() as *mut T);
+            return Some(::core::ptr::dangling_mut());
         }
         let alignment = ::core::mem::align_of::<T>();
-        match self.aligned_alloc(size, alignment) {
-            None => None,
-            Some(p) => Some(p as *mut T),
-        }
+        self.aligned_alloc(size, alignment).map(|p| p as *mut T)
     }
 
     /// Allocates memory from the Arena, places x into it,
@@ -69,6 +624,7 @@ impl Arena {
     ///
     /// ```
     /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
     /// let alignment = 1024;
     /// let size = 1024;
     /// let a = Arena::new(size, alignment).unwrap();
@@ -76,6 +632,7 @@ impl Arena {
     /// assert_eq!(*num, 42);
     /// *num += 1;
     /// assert_eq!(*num, 43);
+    /// # }
     /// ```
     ///
     /// The following example shows the behavior when the
@@ -84,12 +641,14 @@ impl Arena {
     ///
     /// ```
     /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
     /// let alignment = 512;
     /// let alignment = 512;
     /// let size = 1;
     /// let a = Arena::new(size, alignment).unwrap();
     /// let i: usize = 42;
     /// assert_eq!(a.new_box(i), Err(42));
+    /// # }
     /// ```
     ///
     /// The following example will not compile, because the ArenaBox
@@ -112,63 +671,3610 @@ impl Arena {
                 unsafe {
                     ::core::ptr::write(p, x);
                 }
-                Ok(unsafe { ArenaBox::from_raw(p) })
+                Ok(unsafe { ArenaBox::from_raw_in(p, self) })
             }
         }
     }
-}
 
-impl Drop for Arena {
-    fn drop(&mut self) {
-        unsafe {
-            ::alloc::free(self.mem);
+    /// Like [`Arena::new_box`], but places `x` at a multiple of `align`, if
+    /// that is stricter than the alignment of `T`.
+    ///
+    /// The padding this takes counts towards [`Arena::used`]. Zero-sized
+    /// values take no memory, but their pointer is still aligned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `align` is not a power of two.
+    ///
+    /// [`Arena::new_box`]: #method.new_box
+    /// [`Arena::used`]: #method.used
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let _ = a.new_box(1u8).unwrap();
+    /// let descriptor = a.new_box_aligned([0u32; 4], 128).unwrap();
+    /// assert_eq!(&*descriptor as *const _ as usize % 128, 0);
+    /// assert_eq!(a.used(), 128 + 16);
+    /// # }
+    /// ```
+    pub fn new_box_aligned<'a, T>(&'a self, x: T, align: usize) -> Result<ArenaBox<'a, T>, T> {
+        assert!(align.is_power_of_two(), "alignment {} is not a power of two", align);
+        let size = ::core::mem::size_of::<T>();
+        let align = align.max(::core::mem::align_of::<T>());
+        match self.try_aligned_alloc(size, align) {
+            Ok(p) => {
+                let p = p as *mut T;
+                unsafe { ::core::ptr::write(p, x) };
+                Ok(unsafe { ArenaBox::from_raw_in(p, self) })
+            }
+            Err(_) => Err(x),
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    #[allow(unused_imports)]
-    use super::*;
-    #[test]
-    fn arena_box() {
-        let alignment = 1024;
-        let size = 1024;
-        let a = Arena::new(size, alignment).unwrap();
-        let mut num = a.new_box(42).unwrap();
-        assert_eq!(*num, 42);
-        *num += 1;
-        assert_eq!(*num, 43);
+    /// Like [`Arena::new_box`], but on failure also reports how much memory
+    /// was asked for and how much was left.
+    ///
+    /// [`Arena::new_box`]: #method.new_box
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(12, 16).unwrap();
+    /// let _ = a.new_box(1u8).unwrap();
+    /// let err = a.try_new_box(2u64).err().unwrap();
+    /// assert_eq!((err.requested, err.align, err.remaining), (8, 8, 11));
+    /// assert_eq!(err.value, 2);
+    /// # }
+    /// ```
+    pub fn try_new_box<'a, T>(&'a self, x: T) -> Result<ArenaBox<'a, T>, ArenaFull<T>> {
+        let size = ::core::mem::size_of::<T>();
+        let align = ::core::mem::align_of::<T>();
+        let remaining = self.remaining();
+        match self.try_aligned_alloc(size, align) {
+            Ok(p) => {
+                let p = p as *mut T;
+                unsafe { ::core::ptr::write(p, x) };
+                Ok(unsafe { ArenaBox::from_raw_in(p, self) })
+            }
+            Err(_) => Err(ArenaFull {
+                value: x,
+                requested: size,
+                align: align.max(self.min_align),
+                remaining,
+            }),
+        }
     }
-    #[test]
-    fn arena_out_of_memory() {
-        let alignment = 512;
-        let size = 1;
-        let a = Arena::new(size, alignment).unwrap();
-        let i: usize = 42;
-        assert_eq!(a.new_box(i), Err(42));
+
+    /// Moves the array `value` into the Arena, and returns the resulting
+    /// `ArenaBox`, wrapped in `Result::Ok`.
+    ///
+    /// This is [`Arena::new_box`] for arrays, with the length kept in the
+    /// type. An empty array takes up no memory. If there is not enough
+    /// available memory in the Arena, then the array is returned as it was,
+    /// wrapped in `Result::Err`.
+    ///
+    /// [`Arena::new_box`]: #method.new_box
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let primes = a.new_array([2u8, 3, 5, 7]).unwrap();
+    /// assert_eq!(primes.len(), 4);
+    /// assert_eq!(primes[3], 7);
+    /// # }
+    /// ```
+    pub fn new_array<'a, T, const N: usize>(
+        &'a self,
+        value: [T; N],
+    ) -> Result<ArenaBox<'a, [T; N]>, [T; N]> {
+        self.new_box(value)
     }
-    #[test]
-    fn arena_aligned_alloc() {
-        let a = Arena::new(1024, 1024).unwrap();
-        let p1 = a.aligned_alloc(1, 1).unwrap();
-        let p2 = a.aligned_alloc(1, 4).unwrap();
-        let p3 = a.aligned_alloc(1, 8).unwrap();
-        let p4 = a.aligned_alloc(1, 512).unwrap();
-        assert!(((p1 as usize) % 1024) == 0);
-        assert!(((p2 as usize) % 4) == 0);
-        assert!(((p3 as usize) % 8) == 0);
-        assert!(((p4 as usize) % 512) == 0);
+
+    /// Places `x` in the Arena, and returns a plain mutable reference to
+    /// it that lives as long as the Arena is borrowed.
+    ///
+    /// This is for values without destructors, such as plain data and
+    /// references to other arena values, that need neither the drop of an
+    /// `ArenaBox` nor its size. The value stays where it is until the Arena
+    /// is reset, and its destructor never runs; debug builds panic if `T`
+    /// has one, since that is almost always a mistake. To leak such a value
+    /// on purpose, use [`ArenaBox::leak`] instead. Like a leaked box, the
+    /// value counts as live for [`Arena::try_reset`] from then on. If there
+    /// is not enough available memory in the Arena, then `x` is returned,
+    /// wrapped in `Result::Err`.
+    ///
+    /// [`ArenaBox::leak`]: struct.ArenaBox.html#method.leak
+    /// [`Arena::try_reset`]: #method.try_reset
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// struct Span<'a> {
+    ///     text: &'a str,
+    ///     start: usize,
+    /// }
+    ///
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let text = ArenaBox::leak(a.alloc_str("let x = 1;").unwrap());
+    /// let span = a.alloc_ref(Span { text: &text[4..5], start: 4 }).ok().unwrap();
+    /// span.start -= 4;
+    /// assert_eq!((span.text, span.start), ("x", 0));
+    /// # }
+    /// ```
+    ///
+    /// The reference cannot outlive the Arena:
+    ///
+    /// ```compile_fail,E0597
+    /// # use memory_arena::*;
+    /// let x = {
+    ///     let a = Arena::new(1024, 1024).unwrap();
+    ///     a.alloc_ref(5u32).unwrap()
+    /// };
+    /// ```
+    // Like `alloc_bytes`, the value is a leaked box, which nothing else can
+    // reach, and which keeps `try_reset` from succeeding while it lives.
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_ref<T>(&self, x: T) -> Result<&mut T, T> {
+        debug_assert!(
+            !::core::mem::needs_drop::<T>(),
+            "alloc_ref never drops its values; use ArenaBox::leak to leak a value with a destructor"
+        );
+        self.new_box(x).map(ArenaBox::leak)
     }
-    #[test]
-    #[should_panic]
-    fn arena_invalid_alignment() {
-        let _ = Arena::new(1024, 1025).unwrap();
+
+    /// Places `x` in the Arena, and returns it pinned, like `Box::pin`.
+    ///
+    /// If there is not enough available memory in the Arena, then `x` is
+    /// returned, wrapped in `Result::Err`.
+    ///
+    /// # Safety
+    ///
+    /// The box must be dropped, not leaked, before its memory can be handed
+    /// out again; see [`ArenaBox::into_pin`].
+    ///
+    /// [`ArenaBox::into_pin`]: struct.ArenaBox.html#method.into_pin
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// use std::future::Future;
+    /// use std::task::{Context, Poll, Waker};
+    ///
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let mut f = unsafe { a.pin_box(std::future::ready(3)) }.ok().unwrap();
+    /// let mut cx = Context::from_waker(Waker::noop());
+    /// assert_eq!(f.as_mut().poll(&mut cx), Poll::Ready(3));
+    /// # }
+    /// ```
+    pub unsafe fn pin_box<'a, T>(&'a self, x: T) -> Result<Pin<ArenaBox<'a, T>>, T> {
+        self.new_box(x).map(|b| ArenaBox::into_pin(b))
     }
-    #[test]
-    #[should_panic]
-    fn arena_aligned_alloc_invalid_alignment() {
-        let a = Arena::new(1024, 1024).unwrap();
-        let _ = a.aligned_alloc(1, 3).unwrap();
+
+    /// Allocates memory from the Arena, and only then calls `f` to produce
+    /// the value to place into it.
+    ///
+    /// This is for values that are expensive to build or that should not
+    /// be built at all unless there is room for them. If there is not
+    /// enough available memory in the Arena, `f` is returned, unused,
+    /// wrapped in `Result::Err`. `f` may allocate from the Arena itself,
+    /// and its allocations are placed after the reserved slot.
+    ///
+    /// The result of `f` is written straight into the slot, and optimized
+    /// builds usually construct it there, but Rust does not guarantee that
+    /// no temporary copy is made on the stack. For very large values, use
+    /// [`Arena::new_uninit`] and initialize the memory through its pointer.
+    ///
+    /// If `f` panics, the slot is left uninitialized and is never dropped,
+    /// and its memory is given back unless `f` allocated from the Arena
+    /// first.
+    ///
+    /// [`Arena::new_uninit`]: #method.new_uninit
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let table = a.new_box_with(|| {
+    ///     let mut squares = [0u32; 16];
+    ///     for (i, x) in squares.iter_mut().enumerate() {
+    ///         *x = (i * i) as u32;
+    ///     }
+    ///     squares
+    /// });
+    /// assert_eq!(table.ok().unwrap()[15], 225);
+    /// # }
+    /// ```
+    pub fn new_box_with<'a, T, F: FnOnce() -> T>(&'a self, f: F) -> Result<ArenaBox<'a, T>, F> {
+        // Gives the slot back if `f` panics and nothing was allocated
+        // after it.
+        struct Unwind<'a> {
+            arena: &'a Arena,
+            used: (usize, usize),
+            padding: usize,
+        }
+
+        impl<'a> Drop for Unwind<'a> {
+            fn drop(&mut self) {
+                let (before, after) = self.used;
+                if self.arena.used.get() == after {
+                    self.arena.mark_released(before);
+                    self.arena.used.set(before);
+                    self.arena.min_align_padding.set(self.padding);
+                }
+            }
+        }
+
+        let before = self.used.get();
+        let padding = self.min_align_padding.get();
+        let p = match self.alloc::<T>() {
+            None => return Err(f),
+            Some(p) => p,
+        };
+        let unwind = Unwind {
+            arena: self,
+            used: (before, self.used.get()),
+            padding,
+        };
+        unsafe { ::core::ptr::write(p, f()) };
+        ::core::mem::forget(unwind);
+        Ok(unsafe { ArenaBox::from_raw_in(p, self) })
+    }
+
+    /// Allocates uninitialized memory for a `T` from the Arena.
+    ///
+    /// The memory is neither zeroed nor otherwise written to, which makes
+    /// this suitable for large buffers that are filled in by other code,
+    /// such as a foreign function. Once the value has been initialized,
+    /// [`ArenaBox::assume_init`] turns the box into an `ArenaBox<T>`.
+    /// Dropping the box before that does not drop a `T`.
+    ///
+    /// [`ArenaBox::assume_init`]: struct.ArenaBox.html#method.assume_init
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(8192, 4096).unwrap();
+    /// let mut buf = a.new_uninit::<[u8; 4096]>().unwrap();
+    /// unsafe {
+    ///     std::ptr::write_bytes(buf.as_mut_ptr() as *mut u8, 0xff, 4096);
+    /// }
+    /// let buf = unsafe { buf.assume_init() };
+    /// assert!(buf.iter().all(|&b| b == 0xff));
+    /// # }
+    /// ```
+    pub fn new_uninit<'a, T>(
+        &'a self,
+    ) -> Result<ArenaBox<'a, MaybeUninit<T>>, ::alloc::AllocError> {
+        let size = ::core::mem::size_of::<T>();
+        let p = self.try_aligned_alloc(size, ::core::mem::align_of::<T>())? as *mut MaybeUninit<T>;
+        Ok(unsafe { ArenaBox::from_raw_in(p, self) })
+    }
+
+    /// Allocates a slot for a `T` from the Arena, and writes `T::default()`
+    /// into it.
+    ///
+    /// The memory is reserved before `T::default` is called, so if there is
+    /// not enough of it left, the error is returned and `default` is never
+    /// run. The value is written straight into the slot rather than moved
+    /// there from a local, and optimized builds usually construct it in
+    /// place, but as with [`Arena::new_box_with`], Rust does not guarantee
+    /// that no temporary copy is made on the stack. For values too large
+    /// for the stack, use [`Arena::new_uninit`] and initialize the memory
+    /// through its pointer.
+    ///
+    /// [`Arena::new_box_with`]: #method.new_box_with
+    /// [`Arena::new_uninit`]: #method.new_uninit
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let counts = a.new_default::<[u32; 32]>().unwrap();
+    /// assert!(counts.iter().all(|&n| n == 0));
+    /// # }
+    /// ```
+    pub fn new_default<'a, T: Default>(&'a self) -> Result<ArenaBox<'a, T>, ::alloc::AllocError> {
+        let slot = self.new_uninit::<T>()?;
+        Ok(slot.write(T::default()))
+    }
+
+    /// Allocates a slice of `len` values of type `T` from the Arena, and
+    /// sets each of them to `T::default()`.
+    ///
+    /// The elements are built one at a time, each written into its own
+    /// slot, so no array of them is ever put together on the stack. If the
+    /// size of the slice overflows `usize`, or there is not enough memory
+    /// left for it, the error is returned and `default` is never run. If
+    /// `default` panics, the elements built so far are dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let mut names = a.new_default_slice::<String>(3).unwrap();
+    /// names[1].push_str("middle");
+    /// assert_eq!(&*names, ["", "middle", ""]);
+    /// # }
+    /// ```
+    pub fn new_default_slice<'a, T: Default>(
+        &'a self,
+        len: usize,
+    ) -> Result<ArenaBox<'a, [T]>, ::alloc::AllocError> {
+        self.alloc_slice_from(len, ::core::iter::repeat_with(T::default))
+    }
+
+    /// Allocates a new slot from the Arena and clones the value of `b` into
+    /// it.
+    ///
+    /// `b` may come from any Arena, including this one; only its value is
+    /// used. The memory is allocated before `T::clone` is called, so if
+    /// there is not enough of it left, the error is returned and `clone` is
+    /// never run.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let b = Arena::new(1024, 1024).unwrap();
+    /// let mut x = a.new_box(String::from("arena")).ok().unwrap();
+    /// let y = b.clone_box(&x).unwrap();
+    /// x.push_str("s");
+    /// assert_eq!(*y, "arena");
+    /// # }
+    /// ```
+    pub fn clone_box<'a, T: Clone>(
+        &'a self,
+        b: &ArenaBox<T>,
+    ) -> Result<ArenaBox<'a, T>, ::alloc::AllocError> {
+        let slot = self.new_uninit::<T>()?;
+        Ok(slot.write((**b).clone()))
+    }
+
+    /// Allocates uninitialized memory for `len` values of type `T` from the
+    /// Arena.
+    ///
+    /// Like [`Arena::new_uninit`], but for a slice, which
+    /// [`ArenaBox::assume_init`] turns into an `ArenaBox<[T]>` once every
+    /// element has been initialized. A length of zero takes up no memory.
+    /// If the size of the slice overflows `usize`, or there is not enough
+    /// memory left for it, the error is returned.
+    ///
+    /// [`Arena::new_uninit`]: #method.new_uninit
+    /// [`ArenaBox::assume_init`]: struct.ArenaBox.html#method.assume_init-1
+    pub fn new_uninit_slice<'a, T>(
+        &'a self,
+        len: usize,
+    ) -> Result<ArenaBox<'a, [MaybeUninit<T>]>, ::alloc::AllocError> {
+        let size = len
+            .checked_mul(::core::mem::size_of::<T>())
+            .ok_or(::alloc::AllocError::Exhausted)?;
+        let p = self.try_aligned_alloc(size, ::core::mem::align_of::<T>())? as *mut MaybeUninit<T>;
+        let s = ::core::ptr::slice_from_raw_parts_mut(p, len);
+        Ok(unsafe { ArenaBox::from_raw_in(s, self) })
+    }
+
+    /// Allocates memory for a `T` from the Arena, filled with zero bytes.
+    ///
+    /// This is `Box::new_zeroed` for the Arena: the bytes are cleared with
+    /// a single `write_bytes`, whatever the memory held before, and no `T`
+    /// is constructed. For types that are valid when all zero, such as
+    /// integers, arrays of them and plain `#[repr(C)]` structs,
+    /// [`ArenaBox::assume_init`] then turns the box into an `ArenaBox<T>`.
+    ///
+    /// [`ArenaBox::assume_init`]: struct.ArenaBox.html#method.assume_init
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// #[repr(C)]
+    /// struct Header {
+    ///     magic: u32,
+    ///     flags: u16,
+    ///     len: u64,
+    /// }
+    ///
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let mut h = unsafe { a.new_zeroed::<Header>().unwrap().assume_init() };
+    /// assert_eq!((h.magic, h.flags, h.len), (0, 0, 0));
+    /// h.magic = 0xfeed;
+    /// # }
+    /// ```
+    pub fn new_zeroed<'a, T>(
+        &'a self,
+    ) -> Result<ArenaBox<'a, MaybeUninit<T>>, ::alloc::AllocError> {
+        let mut b = self.new_uninit::<T>()?;
+        unsafe { ::core::ptr::write_bytes(b.as_mut_ptr(), 0, 1) };
+        Ok(b)
+    }
+
+    /// Allocates memory for `len` values of type `T` from the Arena, filled
+    /// with zero bytes.
+    ///
+    /// Like [`Arena::new_zeroed`], but for a slice, as with
+    /// [`Arena::new_uninit_slice`]. A length of zero takes up no memory. If
+    /// the size of the slice overflows `usize`, or there is not enough
+    /// memory left for it, the error is returned.
+    ///
+    /// [`Arena::new_zeroed`]: #method.new_zeroed
+    /// [`Arena::new_uninit_slice`]: #method.new_uninit_slice
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(4096, 4096).unwrap();
+    /// let histogram = unsafe { a.new_zeroed_slice::<u64>(256).unwrap().assume_init() };
+    /// assert!(histogram.iter().all(|&n| n == 0));
+    /// # }
+    /// ```
+    pub fn new_zeroed_slice<'a, T>(
+        &'a self,
+        len: usize,
+    ) -> Result<ArenaBox<'a, [MaybeUninit<T>]>, ::alloc::AllocError> {
+        let mut b = self.new_uninit_slice::<T>(len)?;
+        unsafe { ::core::ptr::write_bytes(b.as_mut_ptr(), 0, len) };
+        Ok(b)
+    }
+
+    /// Allocates `len` zeroed bytes from the Arena and returns them as a
+    /// scratch buffer.
+    ///
+    /// Unlike an `ArenaBox`, the buffer is borrowed straight from the Arena
+    /// and never freed on its own; it stays allocated until the Arena is
+    /// reset or dropped. If there is not enough available memory, the error
+    /// is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let msg = a.alloc_bytes(5).unwrap();
+    /// msg[..2].copy_from_slice(b"hi");
+    /// assert_eq!(msg, b"hi\0\0\0");
+    /// # }
+    /// ```
+    ///
+    /// The buffer cannot outlive the Arena:
+    ///
+    /// ```compile_fail,E0597
+    /// # use memory_arena::*;
+    /// let msg = {
+    ///     let a = Arena::new(1024, 1024).unwrap();
+    ///     a.alloc_bytes(16).unwrap()
+    /// };
+    /// ```
+    // The bytes are leaked arena memory, so handing out `&mut` from `&self`
+    // is what the Arena is for.
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_bytes(&self, len: usize) -> Result<&mut [u8], ::alloc::AllocError> {
+        let buf = self.alloc_bytes_uninit(len)?;
+        unsafe {
+            ::core::ptr::write_bytes(buf.as_mut_ptr(), 0, len);
+            Ok(&mut *(buf as *mut [MaybeUninit<u8>] as *mut [u8]))
+        }
+    }
+
+    /// Like [`Arena::alloc_bytes`], but leaves the buffer uninitialized.
+    ///
+    /// [`Arena::alloc_bytes`]: #method.alloc_bytes
+    pub fn alloc_bytes_uninit(&self, len: usize) -> Result<&mut [MaybeUninit<u8>], ::alloc::AllocError> {
+        Ok(ArenaBox::leak(self.new_uninit_slice(len)?))
+    }
+
+    /// Copies `src` into the Arena and returns the copy.
+    ///
+    /// The elements are copied with a single `memcpy`, into memory aligned
+    /// for `T`. An empty slice takes up no memory. If there is not enough
+    /// memory left for the copy, nothing is allocated and the error is
+    /// returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let bytes = a.alloc_slice_copy(b"arena").unwrap();
+    /// let words = a.alloc_slice_copy(&[1u32, 2, 3]).unwrap();
+    /// assert_eq!(&*bytes, b"arena");
+    /// assert_eq!(&*words, [1, 2, 3]);
+    /// # }
+    /// ```
+    pub fn alloc_slice_copy<'a, T: Copy>(
+        &'a self,
+        src: &[T],
+    ) -> Result<ArenaBox<'a, [T]>, ::alloc::AllocError> {
+        self.alloc_slice_copy_aligned(src, 1)
+    }
+
+    /// Like [`Arena::alloc_slice_copy`], but places the copy at a multiple
+    /// of `align`, if that is stricter than the alignment of `T`.
+    ///
+    /// The padding this takes counts towards [`Arena::used`]. If `align`
+    /// is not a power of two, `AllocError::InvalidAlignment` is returned.
+    ///
+    /// [`Arena::alloc_slice_copy`]: #method.alloc_slice_copy
+    /// [`Arena::used`]: #method.used
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let _ = a.new_box(1u8).unwrap();
+    /// let lanes = a.alloc_slice_copy_aligned(&[1.0f32; 16], 64).unwrap();
+    /// assert_eq!(lanes.as_ptr() as usize % 64, 0);
+    /// assert_eq!(a.used(), 64 + 64);
+    /// # }
+    /// ```
+    pub fn alloc_slice_copy_aligned<'a, T: Copy>(
+        &'a self,
+        src: &[T],
+        align: usize,
+    ) -> Result<ArenaBox<'a, [T]>, ::alloc::AllocError> {
+        ::alloc::check_alignment(align)?;
+        let size = ::core::mem::size_of_val(src);
+        let align = align.max(::core::mem::align_of::<T>());
+        let p = self.try_aligned_alloc(size, align)? as *mut T;
+        unsafe {
+            ::core::ptr::copy_nonoverlapping(src.as_ptr(), p, src.len());
+            let s = ::core::ptr::slice_from_raw_parts_mut(p, src.len());
+            Ok(ArenaBox::from_raw_in(s, self))
+        }
+    }
+
+    /// Copies `s` into the Arena and returns the copy.
+    ///
+    /// An empty string takes up no memory. If there is not enough memory
+    /// left for the copy, nothing is allocated and the error is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let token = {
+    ///     let input = String::from("let x = 1;");
+    ///     a.alloc_str(&input[4..5]).unwrap()
+    /// };
+    /// assert_eq!(&*token, "x");
+    /// # }
+    /// ```
+    pub fn alloc_str<'a>(&'a self, s: &str) -> Result<ArenaBox<'a, str>, ::alloc::AllocError> {
+        let (bytes, live) = ArenaBox::into_raw_parts(self.alloc_slice_copy(s.as_bytes())?);
+        Ok(unsafe { ArenaBox::from_raw_parts(bytes as *mut str, live) })
+    }
+
+    /// Copies `s` into the Arena followed by a NUL byte, and returns the
+    /// copy as a C string.
+    ///
+    /// Only the bytes of `s` and the NUL are allocated, so an empty string
+    /// takes up a single byte. The string stays where it is until the Arena
+    /// is reset, so a pointer to it, from `ArenaBox::leak` for instance,
+    /// can be handed to C code for as long as the Arena is borrowed. If `s`
+    /// contains a NUL byte, `AllocError::InteriorNul` is returned with its
+    /// position, and nothing is allocated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let name = ArenaBox::leak(a.alloc_cstr("eth0").unwrap());
+    /// assert_eq!(name.to_bytes_with_nul(), b"eth0\0");
+    /// let err = a.alloc_cstr("eth\01").unwrap_err();
+    /// assert_eq!(err, AllocError::InteriorNul { position: 3 });
+    /// # }
+    /// ```
+    pub fn alloc_cstr<'a>(&'a self, s: &str) -> Result<ArenaBox<'a, CStr>, ::alloc::AllocError> {
+        let bytes = s.as_bytes();
+        if let Some(position) = bytes.iter().position(|&b| b == 0) {
+            return Err(::alloc::AllocError::InteriorNul { position });
+        }
+        let p = self.try_aligned_alloc(bytes.len() + 1, 1)?;
+        unsafe {
+            ::core::ptr::copy_nonoverlapping(bytes.as_ptr(), p, bytes.len());
+            *p.add(bytes.len()) = 0;
+            let with_nul = ::core::slice::from_raw_parts(p, bytes.len() + 1);
+            let c = CStr::from_bytes_with_nul_unchecked(with_nul) as *const CStr;
+            Ok(ArenaBox::from_raw_in(c as *mut CStr, self))
+        }
+    }
+
+    /// Clones each element of `src` into the Arena and returns the clones.
+    ///
+    /// If there is not enough memory left for the clones, nothing is
+    /// allocated and the error is returned. If a clone panics, the elements
+    /// cloned so far are dropped, and the memory taken for them is given
+    /// back to the Arena unless something else was allocated in the
+    /// meantime.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let names = [String::from("left"), String::from("right")];
+    /// let copy = a.alloc_slice_clone(&names).unwrap();
+    /// assert_eq!(&*copy, names);
+    /// # }
+    /// ```
+    pub fn alloc_slice_clone<'a, T: Clone>(
+        &'a self,
+        src: &[T],
+    ) -> Result<ArenaBox<'a, [T]>, ::alloc::AllocError> {
+        self.alloc_slice_from(src.len(), src.iter().cloned())
+    }
+
+    /// Moves the items of `iter` into a new slice in the Arena, and returns
+    /// the slice.
+    ///
+    /// Room for `iter.len()` items is reserved up front, and the items are
+    /// written straight into it. If the iterator yields fewer items than
+    /// its length promised, the slice is shorter, and the unused room is
+    /// given back to the Arena if possible. If it yields more, the extra
+    /// items are left in the iterator. If there is not enough memory for
+    /// the reported length, nothing is taken from the iterator, and the
+    /// error is returned.
+    ///
+    /// If the iterator panics, the items written so far are dropped, and
+    /// their memory is given back unless something else was allocated in
+    /// the meantime.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let squares = a.alloc_from_iter((0..5u32).map(|i| i * i)).unwrap();
+    /// assert_eq!(&*squares, [0, 1, 4, 9, 16]);
+    /// # }
+    /// ```
+    pub fn alloc_from_iter<'a, I>(
+        &'a self,
+        iter: I,
+    ) -> Result<ArenaBox<'a, [I::Item]>, ::alloc::AllocError>
+    where
+        I: IntoIterator,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let iter = iter.into_iter();
+        self.alloc_slice_from(iter.len(), iter)
+    }
+
+    /// Moves the items of `iter` into a new slice in the Arena, without
+    /// knowing their number up front, and returns the slice.
+    ///
+    /// The slice is built at the end of the Arena's memory, and grows in
+    /// place for as long as nothing else is allocated after it. If the
+    /// iterator itself allocates from the Arena, the slice is moved to a
+    /// new, larger allocation whenever it has to grow, and the memory it
+    /// moved out of stays used until the Arena is reset. Any room left over
+    /// at the end is given back to the Arena if possible.
+    ///
+    /// If the Arena runs out of memory, the items collected so far and the
+    /// rest of the iterator are dropped, and the error is returned. If the
+    /// iterator panics, the items collected so far are dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let words = a.alloc_from_iter_growing("a bb ccc".split(' ').map(str::len)).unwrap();
+    /// assert_eq!(&*words, [1, 2, 3]);
+    /// assert_eq!(a.used(), 3 * std::mem::size_of::<usize>());
+    /// # }
+    /// ```
+    pub fn alloc_from_iter_growing<'a, I: IntoIterator>(
+        &'a self,
+        iter: I,
+    ) -> Result<ArenaBox<'a, [I::Item]>, ::alloc::AllocError> {
+        let mut slice = GrowingSlice::<I::Item>::new(self);
+        for x in iter {
+            if slice.len == slice.cap {
+                slice.grow()?;
+            }
+            unsafe { ::core::ptr::write(slice.ptr.add(slice.len), x) };
+            slice.len += 1;
+        }
+        Ok(slice.finish())
+    }
+
+    /// Reserves room for `len` items, and moves up to `len` items of `iter`
+    /// into it.
+    fn alloc_slice_from<'a, T, I: Iterator<Item = T>>(
+        &'a self,
+        len: usize,
+        iter: I,
+    ) -> Result<ArenaBox<'a, [T]>, ::alloc::AllocError> {
+        let size = len
+            .checked_mul(::core::mem::size_of::<T>())
+            .ok_or(::alloc::AllocError::Exhausted)?;
+        let before = self.used.get();
+        let padding = self.min_align_padding.get();
+        let p = self.try_aligned_alloc(size, ::core::mem::align_of::<T>())? as *mut T;
+        let mut done = PartialSlice {
+            arena: self,
+            ptr: p,
+            len: 0,
+            used: (before, self.used.get()),
+            padding,
+        };
+        for x in iter.take(len) {
+            unsafe { ::core::ptr::write(p.add(done.len), x) };
+            done.len += 1;
+        }
+        let written = done.len;
+        let (before, after) = done.used;
+        ::core::mem::forget(done);
+        let unused = (len - written) * ::core::mem::size_of::<T>();
+        // Only give back the tail if it came from the main region.
+        if unused > 0 && after != before && self.used.get() == after {
+            self.mark_released(after - unused);
+            self.used.set(after - unused);
+        }
+        let s = ::core::ptr::slice_from_raw_parts_mut(p, written);
+        Ok(unsafe { ArenaBox::from_raw_in(s, self) })
+    }
+
+    /// Copies `s` into the Arena, splits the copy at every `sep`, and
+    /// returns the copy together with a slice of its fields.
+    ///
+    /// The fields borrow the Arena's copy rather than `s`, so they stay
+    /// valid for as long as the Arena does. They are exactly the ones
+    /// `s.split(sep)` produces, including empty fields between adjacent
+    /// separators and after a trailing one. Since the fields can outlive
+    /// any box, the copy is leaked like with [`ArenaBox::leak`], and stays
+    /// allocated until the Arena is reset.
+    ///
+    /// [`ArenaBox::leak`]: struct.ArenaBox.html#method.leak
+    ///
+    /// If there is not enough memory for both the copy and the slice,
+    /// nothing is allocated and the error is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let (line, fields) = a.alloc_split("name=arena=", '=').unwrap();
+    /// assert_eq!(line, "name=arena=");
+    /// assert_eq!(&*fields, ["name", "arena", ""]);
+    /// # }
+    /// ```
+    pub fn alloc_split<'a>(
+        &'a self,
+        s: &str,
+        sep: char,
+    ) -> Result<(&'a str, ArenaBox<'a, [&'a str]>), ::alloc::AllocError> {
+        self.alloc_fields(s, |s| s.split(sep))
+    }
+
+    /// Like [`Arena::alloc_split`], but splits the copy at runs of
+    /// whitespace, as `str::split_whitespace` does.
+    ///
+    /// [`Arena::alloc_split`]: #method.alloc_split
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let (_, fields) = a.alloc_split_whitespace("  listen 0.0.0.0:80\n").unwrap();
+    /// assert_eq!(&*fields, ["listen", "0.0.0.0:80"]);
+    /// # }
+    /// ```
+    pub fn alloc_split_whitespace<'a>(
+        &'a self,
+        s: &str,
+    ) -> Result<(&'a str, ArenaBox<'a, [&'a str]>), ::alloc::AllocError> {
+        self.alloc_fields(s, str::split_whitespace)
+    }
+
+    fn alloc_fields<'a, 's, I, F>(
+        &'a self,
+        s: &'s str,
+        split: F,
+    ) -> Result<(&'a str, ArenaBox<'a, [&'a str]>), ::alloc::AllocError>
+    where
+        I: Iterator<Item = &'s str>,
+        F: Fn(&'s str) -> I,
+    {
+        // The copy and the fields share one allocation, the copy first, so
+        // a failure leaves every cursor, of the buffer and of any region
+        // the block would have spilled into, where it was.
+        let count = split(s).count();
+        let alignment = if count == 0 { 1 } else { ::core::mem::align_of::<&str>() };
+        let fields_at = s
+            .len()
+            .checked_add(alignment - 1)
+            .ok_or(::alloc::AllocError::Exhausted)?
+            & !(alignment - 1);
+        let size = count
+            .checked_mul(::core::mem::size_of::<&str>())
+            .and_then(|n| n.checked_add(fields_at))
+            .ok_or(::alloc::AllocError::Exhausted)?;
+        let block: *mut u8 = if size == 0 {
+            ::core::ptr::dangling_mut()
+        } else {
+            self.try_aligned_alloc(size, alignment)?
+        };
+        let copy = block;
+        let fields: *mut &'a str = if count == 0 {
+            ::core::ptr::dangling_mut()
+        } else {
+            unsafe { block.add(fields_at) as *mut &'a str }
+        };
+        unsafe {
+            ::core::ptr::copy_nonoverlapping(s.as_ptr(), copy, s.len());
+            let copy = ::core::ptr::slice_from_raw_parts_mut(copy, s.len()) as *mut str;
+            let copy: &'a str = ArenaBox::leak(ArenaBox::from_raw_in(copy, self));
+            // Each field of `s` maps to the same range of the copy.
+            for (i, field) in split(s).enumerate() {
+                let start = field.as_ptr() as usize - s.as_ptr() as usize;
+                ::core::ptr::write(fields.add(i), &copy[start..start + field.len()]);
+            }
+            let fields = ::core::ptr::slice_from_raw_parts_mut(fields, count);
+            Ok((copy, ArenaBox::from_raw_in(fields, self)))
+        }
+    }
+
+    /// Hands out all of the remaining memory in the Arena as a single
+    /// uninitialized byte buffer.
+    ///
+    /// If a quota is set, the buffer only extends up to the quota.
+    /// After this call the Arena has no memory left, so every further
+    /// allocation fails until the unused part of the buffer is returned
+    /// with [`Arena::give_back`].
+    ///
+    /// The buffer counts as one allocation in [`Arena::stats`], even if
+    /// part of it is given back later. When the Arena is already full the
+    /// buffer is empty, and like other zero-sized allocations is not
+    /// counted.
+    ///
+    /// [`Arena::give_back`]: #method.give_back
+    /// [`Arena::stats`]: #method.stats
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let buf = a.take_remaining();
+    /// assert_eq!(buf.len(), 1024);
+    /// assert_eq!(a.new_box(0u8), Err(0));
+    /// # }
+    /// ```
+    pub fn take_remaining<'a>(&'a self) -> ArenaBox<'a, [MaybeUninit<u8>]> {
+        let used = self.used.get();
+        let end = self.limit.max(used);
+        let p = (self.mem as usize + used) as *mut MaybeUninit<u8>;
+        self.used.set(end);
+        if end != used {
+            self.count_allocation(true);
+        }
+        self.mark_allocated(p as *mut u8, end - used);
+        unsafe { ArenaBox::from_raw_in(::core::ptr::slice_from_raw_parts_mut(p, end - used), self) }
+    }
+
+    /// Returns the last `unused_tail_len` bytes of a buffer obtained from
+    /// [`Arena::take_remaining`] to the Arena, and gives back the shrunk
+    /// buffer.
+    ///
+    /// The next allocation starts right after the bytes that were kept.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `unused_tail_len` is larger than the buffer, or if the
+    /// buffer does not end at the Arena's current cursor, which is the case
+    /// when it was not taken from this Arena.
+    ///
+    /// [`Arena::take_remaining`]: #method.take_remaining
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let buf = a.take_remaining();
+    /// let buf = a.give_back(buf, 1000);
+    /// assert_eq!(buf.len(), 24);
+    /// assert!(a.new_box(0u8).is_ok());
+    /// # }
+    /// ```
+    pub fn give_back<'a>(
+        &'a self,
+        buf: ArenaBox<'a, [MaybeUninit<u8>]>,
+        unused_tail_len: usize,
+    ) -> ArenaBox<'a, [MaybeUninit<u8>]> {
+        let len = buf.len();
+        assert!(unused_tail_len <= len, "cannot give back more than the buffer holds");
+        let (raw, live) = ArenaBox::into_raw_parts(buf);
+        let raw = raw as *mut MaybeUninit<u8>;
+        assert_eq!(
+            raw as usize + len,
+            self.mem as usize + self.used.get(),
+            "buffer does not end at the arena cursor"
+        );
+        self.mark_released(self.used.get() - unused_tail_len);
+        self.used.set(self.used.get() - unused_tail_len);
+        let kept = ::core::ptr::slice_from_raw_parts_mut(raw, len - unused_tail_len);
+        unsafe { ArenaBox::from_raw_parts(kept, live) }
+    }
+
+    /// Drops `b`, and returns its memory to the Arena if it is the most
+    /// recent allocation.
+    ///
+    /// This is for speculative allocations that turn out not to be needed
+    /// right away. If `b` ends at the Arena's current cursor, the cursor is
+    /// moved back to where `b` starts, its allocation is taken out of the
+    /// [`Arena::stats`], and `true` is returned. Otherwise, dropping `b`
+    /// behaves like a normal drop, and `false` is returned. Padding in
+    /// front of `b` is not given back.
+    ///
+    /// Only memory owned by a box can be given back this way. Memory that
+    /// can be borrowed for as long as the Arena lives, such as a box passed
+    /// to [`ArenaBox::leak`] or the copy made by [`Arena::alloc_split`], is
+    /// never in a box, so it stays allocated until the Arena is reset.
+    ///
+    /// [`Arena::stats`]: #method.stats
+    /// [`ArenaBox::leak`]: struct.ArenaBox.html#method.leak
+    /// [`Arena::alloc_split`]: #method.alloc_split
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let keep = a.new_box([0u8; 16]).unwrap();
+    /// let maybe = a.new_box([0u8; 16]).unwrap();
+    /// assert!(a.reclaim(maybe));
+    /// let again = a.new_box([0u8; 16]).unwrap();
+    /// assert_eq!(&again[0] as *const u8 as usize, &keep[0] as *const u8 as usize + 16);
+    /// # }
+    /// ```
+    pub fn reclaim<'a, T: ?Sized>(&'a self, b: ArenaBox<'a, T>) -> bool {
+        let start = &*b as *const T as *const u8 as usize;
+        let size = ::core::mem::size_of_val(&*b);
+        drop(b);
+        let base = self.mem as usize;
+        if size != 0 && start >= base && start + size == base + self.used.get() {
+            self.mark_released(start - base);
+            self.used.set(start - base);
+            let mut stats = self.stats.get();
+            stats.allocations = stats.allocations.saturating_sub(1);
+            self.stats.set(stats);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Resizes the `old_size` bytes at `p` to `new_size` bytes, if they are
+    /// the most recent allocation from the main buffer and the new size
+    /// fits, and returns whether it did.
+    pub(crate) fn resize_in_place(&self, p: *mut u8, old_size: usize, new_size: usize) -> bool {
+        let start = match (p as usize).checked_sub(self.mem as usize) {
+            Some(start) => start,
+            None => return false,
+        };
+        if old_size == 0
+            || start + old_size != self.used.get()
+            || new_size > self.limit.saturating_sub(start)
+        {
+            return false;
+        }
+        if new_size > old_size {
+            self.mark_allocated(unsafe { p.add(old_size) }, new_size - old_size);
+        } else {
+            self.mark_released(start + new_size);
+        }
+        self.used.set(start + new_size);
+        self.update_peak();
+        true
+    }
+
+    /// Registers a callback to run when the Arena's allocations are
+    /// released, which happens when the Arena is reset or dropped.
+    ///
+    /// This ties resources that are not memory, such as file or FFI handles,
+    /// to the scope of the Arena, like cleanups in an APR pool. Callbacks
+    /// run in reverse registration order, and each one runs exactly once.
+    /// A panicking callback does not prevent the others from running.
+    ///
+    /// The callback is stored outside of the Arena's memory, so registering
+    /// it does not use up any capacity. If the storage for it cannot be
+    /// allocated, the callback is returned, wrapped in `Result::Err`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// assert!(a.on_reset(|| println!("released")).is_ok());
+    /// drop(a); // prints "released"
+    /// ```
+    #[cfg(feature = "alloc")]
+    pub fn on_reset<F: FnOnce() + 'static>(&self, f: F) -> Result<(), F> {
+        self.cleanups.push(f, Trigger::Reset).map_err(|(f, _)| f)
+    }
+
+    /// Registers a callback to run when the Arena is dropped.
+    ///
+    /// Callbacks run in reverse registration order, together with the ones
+    /// registered with [`Arena::on_reset`], and before the Arena's memory is
+    /// freed. See [`Arena::on_reset`] for details.
+    ///
+    /// [`Arena::on_reset`]: #method.on_reset
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// assert!(a.on_drop(|| println!("dropped")).is_ok());
+    /// drop(a); // prints "dropped"
+    /// ```
+    #[cfg(feature = "alloc")]
+    pub fn on_drop<F: FnOnce() + 'static>(&self, f: F) -> Result<(), F> {
+        self.cleanups.push(f, Trigger::Drop).map_err(|(f, _)| f)
+    }
+
+    /// Makes all of the Arena's memory available again, so it can be reused
+    /// without allocating a new buffer.
+    ///
+    /// Taking `&mut self` guarantees that no `ArenaBox` or other borrow of
+    /// the Arena is still alive. The `on_reset` callbacks run, and all of
+    /// the memory, including that of the extra regions, can be allocated
+    /// again. The `on_drop` callbacks and the quota are kept.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let mut a = Arena::new(8, 8).unwrap();
+    /// for pass in 0..3u64 {
+    ///     let x = a.new_box(pass).unwrap();
+    ///     assert_eq!(a.new_box(0u8), Err(0));
+    ///     drop(x);
+    ///     a.reset();
+    /// }
+    /// # }
+    /// ```
+    ///
+    /// Boxes from before the reset cannot be used afterwards:
+    ///
+    /// ```compile_fail
+    /// # use memory_arena::*;
+    /// let mut a = Arena::new(8, 8).unwrap();
+    /// let x = a.new_box(1u64).unwrap();
+    /// a.reset();
+    /// assert_eq!(*x, 1);
+    /// ```
+    pub fn reset(&mut self) {
+        self.clear();
+        // Only boxes whose raw pointers were leaked can still be counted.
+        #[cfg(feature = "live-count")]
+        self.live.set(0);
+    }
+
+    /// Returns the current position of the Arena's cursor, for
+    /// [`Arena::rewind`] to roll the Arena back to.
+    ///
+    /// [`Arena::rewind`]: #method.rewind
+    pub fn checkpoint(&self) -> ArenaCheckpoint {
+        ArenaCheckpoint {
+            mem: self.mem as usize,
+            used: self.used.get(),
+            padding: self.min_align_padding.get(),
+        }
+    }
+
+    /// Makes the memory allocated since `cp` was taken available again.
+    ///
+    /// Like [`Arena::reset`], this takes `&mut self`, so no `ArenaBox` from
+    /// after the checkpoint can still be alive; allocations from before it
+    /// are kept. No cleanup callbacks run. Memory allocated from the extra
+    /// regions of an Arena made with [`Arena::from_regions`] is not given
+    /// back until the next reset.
+    ///
+    /// [`Arena::reset`]: #method.reset
+    /// [`Arena::from_regions`]: #method.from_regions
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cp` was taken from a different Arena, or from this one
+    /// before a reset or rewind to an earlier position.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let mut a = Arena::new(1024, 1024).unwrap();
+    /// let plan = a.new_box([1u32; 4]).unwrap();
+    /// # drop(plan);
+    /// let cp = a.checkpoint();
+    /// for candidate in 0..10u64 {
+    ///     let scratch = a.new_box([candidate; 8]).unwrap();
+    ///     # drop(scratch);
+    ///     a.rewind(cp);
+    /// }
+    /// assert_eq!(a.used(), 16);
+    /// # }
+    /// ```
+    pub fn rewind(&mut self, cp: ArenaCheckpoint) {
+        assert!(cp.mem == self.mem as usize, "checkpoint is from a different arena");
+        assert!(cp.used <= self.used.get(), "checkpoint is past the arena cursor");
+        self.next_generation();
+        self.mark_released(cp.used);
+        self.used.set(cp.used);
+        self.min_align_padding.set(cp.padding);
+    }
+
+    /// Moves the cursor back to `to` if it is still at `at`, so that the
+    /// allocations made between the two checkpoints are available again,
+    /// and returns whether it did.
+    pub(crate) fn roll_back(&self, to: ArenaCheckpoint, at: ArenaCheckpoint) -> bool {
+        if self.used.get() != at.used || at.used == to.used {
+            return false;
+        }
+        self.mark_released(to.used);
+        self.used.set(to.used);
+        self.min_align_padding.set(to.padding);
+        true
+    }
+
+    /// Calls `f` with the Arena, then makes what `f` allocated available
+    /// again, even if `f` panics.
+    ///
+    /// `f` has to work for any lifetime of the Arena it is given, so no
+    /// `ArenaBox` it allocates can outlive the call, whether as the return
+    /// value or stashed in a captured variable. Taking `&mut self` keeps
+    /// `f` from allocating through another reference to the Arena. Like
+    /// [`Arena::rewind`], the end of the scope starts a new
+    /// [`Arena::generation`], so handles cannot escape it either.
+    ///
+    /// [`Arena::rewind`]: #method.rewind
+    /// [`Arena::generation`]: #method.generation
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let mut a = Arena::new(1024, 1024).unwrap();
+    /// let best = (0..10u64)
+    ///     .map(|candidate| {
+    ///         a.scope(|a| {
+    ///             let scratch = a.new_box([candidate; 8]).unwrap();
+    ///             scratch.iter().sum::<u64>()
+    ///         })
+    ///     })
+    ///     .max();
+    /// assert_eq!((best, a.used()), (Some(72), 0));
+    /// # }
+    /// ```
+    ///
+    /// Boxes cannot escape the scope:
+    ///
+    /// ```compile_fail
+    /// # use memory_arena::*;
+    /// let mut a = Arena::new(1024, 1024).unwrap();
+    /// let x = a.scope(|a| a.new_box(1u64).unwrap());
+    /// ```
+    ///
+    /// ```compile_fail
+    /// # use memory_arena::*;
+    /// let mut a = Arena::new(1024, 1024).unwrap();
+    /// let mut kept = Vec::new();
+    /// a.scope(|a| kept.push(a.new_box(1u64).unwrap()));
+    /// ```
+    pub fn scope<F, R>(&mut self, f: F) -> R
+    where
+        F: for<'s> FnOnce(&'s Arena) -> R,
+    {
+        struct Rewind<'a>(&'a Arena, ArenaCheckpoint);
+
+        impl<'a> Drop for Rewind<'a> {
+            fn drop(&mut self) {
+                self.0.next_generation();
+                self.0.mark_released(self.1.used);
+                self.0.used.set(self.1.used);
+                self.0.min_align_padding.set(self.1.padding);
+            }
+        }
+
+        let rewind = Rewind(self, self.checkpoint());
+        f(rewind.0)
+    }
+
+    /// Runs the `on_reset` callbacks and makes all of the memory available
+    /// again, for owners that can prove nothing borrows the Arena anymore.
+    pub(crate) fn clear(&self) {
+        self.cleanups.run_resets();
+        #[cfg(feature = "poison")]
+        if self.poison {
+            self.regions.fill_used(POISON_RESET);
+        }
+        self.regions.clear();
+        self.mark_released(0);
+        self.used.set(0);
+        self.min_align_padding.set(0);
+        self.next_generation();
+    }
+
+    /// Touches every page of the Arena's unused memory, so that the
+    /// operating system maps it in now rather than on first use.
+    ///
+    /// Freshly allocated memory is usually only backed by physical pages
+    /// once it is written to, and each first write costs a page fault. For
+    /// latency-sensitive code, such as an audio callback, call this once
+    /// after construction, or use [`ArenaBuilder::prefault`]. Memory that
+    /// has already been handed out is left alone. To also keep the pages
+    /// from being swapped out, lock them afterwards, for example with
+    /// `mlockall(MCL_CURRENT)` on Unix.
+    ///
+    /// [`ArenaBuilder::prefault`]: struct.ArenaBuilder.html#method.prefault
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let mut a = Arena::new(1 << 20, 4096).unwrap();
+    /// a.prefault();
+    /// let samples = a.new_box([0f32; 1024]).unwrap();
+    /// # let _ = samples;
+    /// # }
+    /// ```
+    pub fn prefault(&mut self) {
+        let page = ::alloc::page_size();
+        let start = self.mem as usize;
+        let mut addr = start + self.used.get();
+        let unused = self.size - self.used.get();
+        ::sanitize::unpoison(addr as *const u8, unused);
+        ::valgrind::make_defined(addr as *const u8, unused);
+        while addr < start + self.size {
+            unsafe { ::core::ptr::write_volatile(addr as *mut u8, 0) };
+            addr = (addr & !(page - 1)) + page;
+        }
+        ::sanitize::poison((start + self.used.get()) as *const u8, unused);
+        ::valgrind::make_noaccess((start + self.used.get()) as *const u8, unused);
+    }
+
+    /// Allocates memory for `layout` at exactly `offset` bytes from the
+    /// start of the Arena, and returns a pointer to it.
+    ///
+    /// The placement succeeds only if no memory at or past `offset` has been
+    /// handed out yet, the resulting address satisfies `layout.align()` and
+    /// the Arena's minimum alignment, and the allocation fits in the Arena.
+    /// Any gap between the previously used memory and `offset` becomes
+    /// padding, and subsequent allocations start after the placed one. A
+    /// zero-sized placement still moves the cursor to `offset`, but like
+    /// other zero-sized allocations is not counted in [`Arena::stats`].
+    ///
+    /// [`Arena::stats`]: #method.stats
+    ///
+    /// This is useful when emitting a binary format where a structure must
+    /// land at an offset that was promised earlier, such as a table at a
+    /// page boundary.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// use std::alloc::Layout;
+    ///
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let table = a.alloc_at_offset(256, Layout::new::<[u32; 4]>()).unwrap();
+    /// let header = a.alloc_at_offset(0, Layout::new::<u64>());
+    /// assert_eq!(header, Err(PlacementError::OffsetPassed { offset: 0, used: 272 }));
+    /// # let _ = table;
+    /// # }
+    /// ```
+    pub fn alloc_at_offset(
+        &self,
+        offset: usize,
+        layout: Layout,
+    ) -> Result<NonNull<u8>, PlacementError> {
+        let used = self.used.get();
+        if offset < used {
+            return Err(PlacementError::OffsetPassed { offset, used });
+        }
+        let exhausted = || {
+            self.count_allocation(false);
+            Err(PlacementError::Exhausted {
+                offset,
+                size: layout.size(),
+                capacity: self.limit,
+            })
+        };
+        // Past the end, the address may not even fit in a `usize`.
+        if offset > self.limit {
+            return exhausted();
+        }
+        let addr = self.mem as usize + offset;
+        let align = layout.align().max(self.min_align);
+        if addr & (align - 1) != 0 {
+            return Err(PlacementError::Misaligned { offset, align });
+        }
+        let end = match offset.checked_add(layout.size()) {
+            Some(end) if end <= self.limit => end,
+            _ => return exhausted(),
+        };
+        self.used.set(end);
+        if layout.size() == 0 {
+            self.update_peak();
+        } else {
+            self.count_allocation(true);
+        }
+        self.mark_allocated(addr as *mut u8, layout.size());
+        Ok(unsafe { NonNull::new_unchecked(addr as *mut u8) })
+    }
+}
+
+#[cfg(feature = "std")]
+impl Arena {
+    /// Leaks the Arena and returns a reference to it that lives for the
+    /// rest of the program.
+    ///
+    /// This is the supported way to get `'static` boxes out of an Arena,
+    /// such as for interned strings or global configuration. The Arena
+    /// struct and its backing memory are never freed, and neither its
+    /// `on_drop` callbacks nor the destructors of its boxes run unless the
+    /// boxes themselves are dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// let a: &'static Arena = Arena::new(1024, 1024).unwrap().leak();
+    /// let name: ArenaBox<'static, [u8; 4]> = a.new_box(*b"main").unwrap();
+    /// # let _ = name;
+    /// ```
+    pub fn leak(self) -> &'static Arena {
+        ::std::boxed::Box::leak(::std::boxed::Box::new(self))
+    }
+}
+
+#[cfg(feature = "live-count")]
+impl Arena {
+    /// Returns the number of live allocations that keep the Arena from
+    /// being reset by [`Arena::try_reset`].
+    ///
+    /// [`Arena::try_reset`]: #method.try_reset
+    pub fn live_count(&self) -> usize {
+        self.live.get()
+    }
+
+    /// Releases all of the Arena's allocations, if none of them are live
+    /// anymore.
+    ///
+    /// This is for owners that share the Arena and know that every box is
+    /// gone at some point, but cannot prove it to the borrow checker. With
+    /// the `live-count` feature, the Arena counts every `ArenaBox` it hands
+    /// out until the box is dropped, along with every `ArenaRope`,
+    /// `TaggedArenaPtr` and `ChildArena` that uses it. A box passed to
+    /// [`ArenaBox::into_raw`], the value of an `ArenaArc` and a value
+    /// placed with `Arena::insert` stay counted for good, since nothing
+    /// tracks their raw pointers.
+    ///
+    /// On success, the `on_reset` callbacks run and all of the memory,
+    /// including that of the extra regions, can be allocated again. The
+    /// `on_drop` callbacks and the quota are kept.
+    ///
+    /// [`ArenaBox::into_raw`]: struct.ArenaBox.html#method.into_raw
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let x = a.new_box(1u64).unwrap();
+    /// assert_eq!(a.try_reset(), Err(ResetError { live: 1 }));
+    /// drop(x);
+    /// assert_eq!(a.try_reset(), Ok(()));
+    /// ```
+    pub fn try_reset(&self) -> Result<(), ResetError> {
+        let live = self.live.get();
+        if live != 0 {
+            return Err(ResetError { live });
+        }
+        self.clear();
+        Ok(())
+    }
+}
+
+impl Arena {
+    /// Verifies the internal consistency of the Arena, panicking with a
+    /// description of the first violated invariant.
+    ///
+    /// This is meant for tests and fuzzing, and is only available with the
+    /// `check-invariants` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let _ = a.new_box(42).unwrap();
+    /// a.check_invariants();
+    /// ```
+    #[cfg(any(test, feature = "check-invariants"))]
+    pub fn check_invariants(&self) {
+        let used = self.used.get();
+        assert!(!self.mem.is_null(), "arena invariant violated: base pointer is null");
+        assert!(
+            used <= self.size,
+            "arena invariant violated: used ({}) exceeds size ({})",
+            used,
+            self.size
+        );
+        assert!(
+            (self.mem as usize).checked_add(self.size).is_some(),
+            "arena invariant violated: buffer of {} bytes at {:p} wraps the address space",
+            self.size,
+            self.mem
+        );
+        self.regions.check_invariants();
+    }
+}
+
+/// A position of an Arena's cursor, from `Arena::checkpoint`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArenaCheckpoint {
+    /// The address of the Arena's buffer, which tells Arenas apart even if
+    /// they move.
+    mem: usize,
+    used: usize,
+    padding: usize,
+}
+
+/// Shows the Arena's address and sizes, but none of its contents. `{:?}`
+/// prints them on one line, and `{:#?}` one field per line.
+impl fmt::Debug for Arena {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Arena")
+            .field("mem", &self.mem)
+            .field("size", &self.capacity())
+            .field("used", &self.used())
+            .field("remaining", &self.remaining())
+            .finish()
+    }
+}
+
+/// The error type for `Arena::try_new_box`, which hands back the value
+/// that did not fit.
+///
+/// All sizes are in bytes. `requested` does not include the alignment
+/// padding the allocation would have needed, which is why it can fail even
+/// when `requested` is no more than `remaining`.
+pub struct ArenaFull<T> {
+    /// The value that could not be placed in the Arena.
+    pub value: T,
+    /// The size of the value.
+    pub requested: usize,
+    /// The alignment the value needed, including the Arena's minimum
+    /// alignment.
+    pub align: usize,
+    /// What `Arena::remaining` returned before the allocation.
+    pub remaining: usize,
+}
+
+impl<T> ArenaFull<T> {
+    /// Returns the value that could not be placed in the Arena.
+    pub fn into_value(self) -> T {
+        self.value
+    }
+}
+
+/// Shows the sizes only, so that `T` does not need to be `Debug`.
+impl<T> fmt::Debug for ArenaFull<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ArenaFull")
+            .field("requested", &self.requested)
+            .field("align", &self.align)
+            .field("remaining", &self.remaining)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Display for ArenaFull<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "arena cannot fit {} bytes aligned to {}, {} bytes remaining",
+            self.requested, self.align, self.remaining
+        )
+    }
+}
+
+/// The error type for `Arena::alloc_at_offset`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PlacementError {
+    /// Memory at or past `offset` has already been handed out.
+    OffsetPassed { offset: usize, used: usize },
+    /// The address at `offset` does not satisfy the requested alignment.
+    Misaligned { offset: usize, align: usize },
+    /// An allocation of `size` bytes at `offset` does not fit in the Arena,
+    /// or its quota if that is smaller.
+    Exhausted { offset: usize, size: usize, capacity: usize },
+}
+
+impl fmt::Display for PlacementError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PlacementError::OffsetPassed { offset, used } => write!(
+                f,
+                "cannot place at offset {}, arena already used {} bytes",
+                offset, used
+            ),
+            PlacementError::Misaligned { offset, align } => write!(
+                f,
+                "offset {} is not aligned to {} bytes",
+                offset, align
+            ),
+            PlacementError::Exhausted { offset, size, capacity } => write!(
+                f,
+                "{} bytes at offset {} exceed the arena capacity of {} bytes",
+                size, offset, capacity
+            ),
+        }
+    }
+}
+
+/// The error type for `Arena::try_reset`, holding the number of live
+/// allocations that prevented the reset.
+#[cfg(feature = "live-count")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResetError {
+    pub live: usize,
+}
+
+#[cfg(feature = "live-count")]
+impl fmt::Display for ResetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cannot reset arena with {} live allocations", self.live)
+    }
+}
+
+/// A slice of unknown final length being built at the end of an Arena's
+/// memory. The items are dropped if building it fails, and the memory is
+/// given back if it is still at the end.
+pub(crate) struct GrowingSlice<'a, T> {
+    pub(crate) arena: &'a Arena,
+    pub(crate) ptr: *mut T,
+    pub(crate) len: usize,
+    pub(crate) cap: usize,
+    /// The cursor and the minimum alignment padding from before the
+    /// slice's memory was allocated.
+    rewind: (usize, usize),
+}
+
+impl<'a, T> GrowingSlice<'a, T> {
+    /// The smallest number of items room is made for at a time.
+    const MIN_CAPACITY: usize = 4;
+
+    /// Returns an empty slice, which does not take any memory yet.
+    pub(crate) fn new(arena: &'a Arena) -> Self {
+        GrowingSlice {
+            arena,
+            ptr: ::core::ptr::NonNull::dangling().as_ptr(),
+            len: 0,
+            cap: if ::core::mem::size_of::<T>() == 0 { usize::MAX } else { 0 },
+            rewind: (0, 0),
+        }
+    }
+
+    /// Returns `true` if the slice's memory ends at the Arena's cursor.
+    fn at_tail(&self) -> bool {
+        let end = self.ptr as usize + self.cap * ::core::mem::size_of::<T>();
+        self.cap > 0 && end == self.arena.mem as usize + self.arena.used.get()
+    }
+
+    /// Makes room for at least one more item, preferably twice as many as
+    /// there are, by extending the slice in place or else by moving it.
+    fn grow(&mut self) -> Result<(), ::alloc::AllocError> {
+        if self.extend() {
+            return Ok(());
+        }
+        let size = ::core::mem::size_of::<T>();
+        let wanted = self.cap.saturating_mul(2).max(Self::MIN_CAPACITY);
+        let mut result = Err(::alloc::AllocError::Exhausted);
+        for &cap in &[wanted, self.cap + 1] {
+            let bytes = cap.checked_mul(size).ok_or(::alloc::AllocError::Exhausted)?;
+            let rewind = (self.arena.used.get(), self.arena.min_align_padding.get());
+            match self.arena.try_aligned_alloc(bytes, ::core::mem::align_of::<T>()) {
+                Ok(p) => {
+                    let p = p as *mut T;
+                    unsafe { ::core::ptr::copy_nonoverlapping(self.ptr, p, self.len) };
+                    self.ptr = p;
+                    self.cap = cap;
+                    self.rewind = rewind;
+                    return Ok(());
+                }
+                Err(e) => result = Err(e),
+            }
+        }
+        result
+    }
+
+    /// Makes room for at least one more item like `grow`, but never moves
+    /// the items once there are any. Fails with `AllocError::Interleaved`
+    /// if that is not possible because something else was allocated from
+    /// the main buffer after them.
+    pub(crate) fn grow_in_place(&mut self) -> Result<(), ::alloc::AllocError> {
+        if self.cap == 0 {
+            return self.grow();
+        }
+        if self.extend() {
+            return Ok(());
+        }
+        let in_main = (self.ptr as usize).wrapping_sub(self.arena.mem as usize) < self.arena.size;
+        if in_main && !self.at_tail() {
+            Err(::alloc::AllocError::Interleaved)
+        } else {
+            Err(::alloc::AllocError::Exhausted)
+        }
+    }
+
+    /// Extends the slice at the end of the Arena's memory, to twice as many
+    /// items if possible, and returns whether it did.
+    fn extend(&mut self) -> bool {
+        let size = ::core::mem::size_of::<T>();
+        let wanted = self.cap.saturating_mul(2).max(Self::MIN_CAPACITY);
+        for &cap in &[wanted, self.cap + 1] {
+            if self.at_tail() {
+                let extra = (cap - self.cap).saturating_mul(size);
+                let end = self.arena.used.get().checked_add(extra);
+                if let Some(end) = end.filter(|&end| end <= self.arena.limit) {
+                    self.arena.mark_allocated(unsafe { self.ptr.add(self.cap) } as *mut u8, extra);
+                    self.arena.used.set(end);
+                    self.arena.update_peak();
+                    self.cap = cap;
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Gives the unused room at the end back to the Arena, and returns the
+    /// items as an arena box.
+    pub(crate) fn finish(mut self) -> ArenaBox<'a, [T]> {
+        self.shrink();
+        let s = ::core::ptr::slice_from_raw_parts_mut(self.ptr, self.len);
+        let arena = self.arena;
+        ::core::mem::forget(self);
+        unsafe { ArenaBox::from_raw_in(s, arena) }
+    }
+
+    /// Gives the unused room at the end back to the Arena, if possible.
+    fn shrink(&mut self) {
+        if self.at_tail() {
+            let unused = (self.cap - self.len) * ::core::mem::size_of::<T>();
+            self.arena.mark_released(self.arena.used.get() - unused);
+            self.arena.used.set(self.arena.used.get() - unused);
+            self.cap = self.len;
+        }
+    }
+}
+
+impl<'a, T> Drop for GrowingSlice<'a, T> {
+    fn drop(&mut self) {
+        unsafe {
+            let done = ::core::ptr::slice_from_raw_parts_mut(self.ptr, self.len);
+            ::core::ptr::drop_in_place(done);
+        }
+        if self.at_tail() {
+            self.arena.mark_released(self.rewind.0);
+            self.arena.used.set(self.rewind.0);
+            self.arena.min_align_padding.set(self.rewind.1);
+        }
+    }
+}
+
+/// The items of a slice being filled in, which are dropped if filling it
+/// panics. The cursor is rewound if it is still right after them.
+struct PartialSlice<'a, T> {
+    arena: &'a Arena,
+    ptr: *mut T,
+    len: usize,
+    /// The cursor before and after the slice was reserved.
+    used: (usize, usize),
+    padding: usize,
+}
+
+impl<'a, T> Drop for PartialSlice<'a, T> {
+    fn drop(&mut self) {
+        unsafe {
+            let done = ::core::ptr::slice_from_raw_parts_mut(self.ptr, self.len);
+            ::core::ptr::drop_in_place(done);
+        }
+        let (before, after) = self.used;
+        if self.arena.used.get() == after {
+            self.arena.mark_released(before);
+            self.arena.used.set(before);
+            self.arena.min_align_padding.set(self.padding);
+        }
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        // Free the memory even if a cleanup callback panics.
+        #[cfg_attr(not(feature = "alloc"), allow(dead_code))]
+        struct Free(*mut u8, usize, Backing);
+
+        impl Drop for Free {
+            fn drop(&mut self) {
+                // The memory goes back to where it came from usable.
+                ::sanitize::unpoison(self.0, self.1);
+                if self.1 != 0 {
+                    ::valgrind::destroy_pool(self.0, self.1);
+                }
+                match self.2 {
+                    #[cfg(feature = "alloc")]
+                    Backing::Heap(alignment) => unsafe { ::alloc::free(self.0, self.1, alignment) },
+                    Backing::Borrowed => {}
+                    #[cfg(feature = "alloc")]
+                    Backing::Empty => {}
+                    #[cfg(feature = "libc")]
+                    Backing::Mapped(size) => unsafe { ::alloc::unmap(self.0, size) },
+                    #[cfg(feature = "libc")]
+                    Backing::Guarded { offset, len } => unsafe { ::alloc::unmap(self.0.sub(offset), len) },
+                    #[cfg(feature = "alloc")]
+                    Backing::Custom { backend, alignment } => unsafe {
+                        let backend = ::rust_alloc::boxed::Box::from_raw(backend);
+                        backend.deallocate(NonNull::new_unchecked(self.0), self.1, alignment);
+                    },
+                }
+            }
+        }
+
+        let _mem = Free(self.mem, self.size, self.backing);
+        #[cfg(feature = "registry")]
+        self.used.unregister();
+        self.cleanups.run_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use test_util::{checked_arena, DropCounter};
+    #[cfg(feature = "alloc")]
+    use test_util::total_allocations;
+    use core::cell::Cell;
+    #[cfg(feature = "alloc")]
+    use core::cell::RefCell;
+    use std::panic;
+    use std::rc::Rc;
+    use std::string::String;
+    use std::vec::Vec;
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn arena_page_aligned() {
+        #[repr(align(4096))]
+        struct Page([u8; 4096]);
+
+        for &size in &[4096, 3 * 4096, 5000] {
+            let a = checked_arena(size, 4096);
+            assert_eq!(a.mem as usize % 4096, 0);
+            let page = a.new_box(Page([1; 4096])).ok().unwrap();
+            assert_eq!(&*page as *const Page, a.mem as *const Page);
+            assert_eq!(page.0[4095], 1);
+        }
+    }
+    #[test]
+    fn arena_box() {
+        let alignment = 1024;
+        let size = 1024;
+        let a = checked_arena(size, alignment);
+        let mut num = a.new_box(42).unwrap();
+        assert_eq!(*num, 42);
+        *num += 1;
+        assert_eq!(*num, 43);
+    }
+    #[test]
+    fn arena_out_of_memory() {
+        let alignment = 512;
+        let size = 1;
+        let a = checked_arena(size, alignment);
+        let i: usize = 42;
+        assert_eq!(a.new_box(i), Err(42));
+    }
+    #[test]
+    fn arena_clone_box() {
+        let a = checked_arena(1024, 1024);
+        let b = checked_arena(1024, 1024);
+        let mut x = a.new_box([1u32, 2, 3]).unwrap();
+        let same = a.clone_box(&x).unwrap();
+        let other = b.clone_box(&x).unwrap();
+        x[0] = 7;
+        assert_eq!(*x, [7, 2, 3]);
+        assert_eq!(*same, [1, 2, 3]);
+        assert_eq!(*other, [1, 2, 3]);
+        assert_eq!(&*other as *const _ as *const u8, b.mem as *const u8);
+    }
+    #[test]
+    fn arena_clone_box_exhausted() {
+        struct Counted<'c>(&'c Cell<usize>);
+        impl<'c> Clone for Counted<'c> {
+            fn clone(&self) -> Self {
+                self.0.set(self.0.get() + 1);
+                Counted(self.0)
+            }
+        }
+        let clones = Cell::new(0);
+        let a = checked_arena(16, 16);
+        let x = a.new_box(Counted(&clones)).ok().unwrap();
+        let _fill = a.new_box(0u64).unwrap();
+        assert_eq!(a.clone_box(&x).err(), Some(::alloc::AllocError::Exhausted));
+        assert_eq!(clones.get(), 0);
+    }
+    #[test]
+    fn arena_new_array_empty() {
+        let a = checked_arena(8, 8);
+        let empty = a.new_array::<u64, 0>([]).unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(a.used(), 0);
+    }
+    #[test]
+    fn arena_new_array_near_capacity() {
+        let a = checked_arena(1024, 1024);
+        let _first = a.new_box(0u8).unwrap();
+        let mut big = [0u8; 1023];
+        big[1022] = 9;
+        let big = a.new_array(big).unwrap();
+        assert_eq!(big[1022], 9);
+        assert_eq!(a.used(), 1024);
+        assert_eq!(a.new_array([1u8]), Err([1u8]));
+    }
+    #[test]
+    fn arena_new_array_drop() {
+        let drops = Cell::new(0);
+        let a = checked_arena(64, 64);
+        let xs = a.new_array([DropCounter(&drops), DropCounter(&drops)]).ok().unwrap();
+        assert_eq!(drops.get(), 0);
+        drop(xs);
+        assert_eq!(drops.get(), 2);
+        let full = [
+            DropCounter(&drops),
+            DropCounter(&drops),
+            DropCounter(&drops),
+            DropCounter(&drops),
+            DropCounter(&drops),
+            DropCounter(&drops),
+            DropCounter(&drops),
+            DropCounter(&drops),
+            DropCounter(&drops),
+        ];
+        let back = a.new_array(full).err().unwrap();
+        assert_eq!(drops.get(), 2);
+        assert!(back.iter().all(|d| ::core::ptr::eq(d.0, &drops)));
+        drop(back);
+        assert_eq!(drops.get(), 11);
+    }
+    #[test]
+    fn arena_pin_box() {
+        use core::marker::PhantomPinned;
+
+        // Points at its own `value` once pinned.
+        struct SelfRef<'c> {
+            value: u32,
+            this: *const u32,
+            _drops: DropCounter<'c>,
+            _pinned: PhantomPinned,
+        }
+
+        impl<'c> SelfRef<'c> {
+            fn link(self: Pin<&mut Self>) {
+                let this = unsafe { self.get_unchecked_mut() };
+                this.this = &this.value;
+            }
+        }
+
+        let drops = Cell::new(0);
+        let a = checked_arena(1024, 1024);
+        let mut x = unsafe {
+            a.pin_box(SelfRef {
+                value: 5,
+                this: ::core::ptr::null(),
+                _drops: DropCounter(&drops),
+                _pinned: PhantomPinned,
+            })
+        }
+        .ok()
+        .unwrap();
+        x.as_mut().link();
+        for i in 0..16u64 {
+            let _ = a.new_box(i).unwrap();
+        }
+        assert_eq!(unsafe { *x.this }, 5);
+        assert!(::core::ptr::eq(x.this, &x.value));
+        drop(x);
+        assert_eq!(drops.get(), 1);
+        assert!(unsafe { checked_arena(1, 1).pin_box(0u64) }.is_err());
+    }
+    #[test]
+    fn arena_pin_box_future() {
+        use core::future::Future;
+        use core::task::{Context, Poll, Waker};
+
+        // Ready on the second poll.
+        struct Twice<'c>(bool, DropCounter<'c>);
+
+        impl<'c> Future for Twice<'c> {
+            type Output = usize;
+            fn poll(mut self: Pin<&mut Self>, _: &mut Context) -> Poll<usize> {
+                if self.0 {
+                    Poll::Ready(self.1 .0.get())
+                } else {
+                    self.0 = true;
+                    Poll::Pending
+                }
+            }
+        }
+
+        let drops = Cell::new(0);
+        let a = checked_arena(1024, 1024);
+        let mut f = unsafe { a.pin_box(Twice(false, DropCounter(&drops))) }.ok().unwrap();
+        let mut cx = Context::from_waker(Waker::noop());
+        assert_eq!(f.as_mut().poll(&mut cx), Poll::Pending);
+        assert_eq!(f.as_mut().poll(&mut cx), Poll::Ready(0));
+        drop(f);
+        assert_eq!(drops.get(), 1);
+    }
+    #[test]
+    fn arena_aligned_alloc() {
+        let a = checked_arena(1024, 1024);
+        let p1 = a.aligned_alloc(1, 1).unwrap();
+        let p2 = a.aligned_alloc(1, 4).unwrap();
+        let p3 = a.aligned_alloc(1, 8).unwrap();
+        let p4 = a.aligned_alloc(1, 512).unwrap();
+        assert!((p1 as usize).is_multiple_of(1024));
+        assert!((p2 as usize).is_multiple_of(4));
+        assert!((p3 as usize).is_multiple_of(8));
+        assert!((p4 as usize).is_multiple_of(512));
+    }
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn arena_min_alignment() {
+        let mut a = checked_arena(1024, 1024);
+        a.set_min_alignment(64);
+        let bytes: Vec<_> = (0..4).map(|i| a.new_box(i as u8).unwrap()).collect();
+        let words: Vec<_> = (0..4).map(|i| a.new_box(i as u64).unwrap()).collect();
+        for p in bytes.iter().map(|b| &**b as *const u8 as usize) {
+            assert!(p.is_multiple_of(64));
+        }
+        for p in words.iter().map(|w| &**w as *const u64 as usize) {
+            assert!(p.is_multiple_of(64));
+        }
+        // Seven allocations are each followed by a gap up to the next
+        // 64-byte boundary, and the last `u64` takes up 8 bytes. Of the gap
+        // before each `u64`, 7 bytes would have been needed anyway.
+        assert_eq!(a.used.get(), 7 * 64 + 8);
+        assert_eq!(a.min_alignment_padding(), 3 * 63 + 4 * 56);
+        assert_eq!(
+            a.alloc_at_offset(520, Layout::new::<u64>()),
+            Err(PlacementError::Misaligned { offset: 520, align: 64 })
+        );
+        assert!(a.alloc_at_offset(512, Layout::new::<u64>()).is_ok());
+        a.check_invariants();
+    }
+    #[cfg(feature = "alloc")]
+    #[test]
+    #[should_panic(expected = "minimum alignment 3 is not a power of two")]
+    fn arena_invalid_min_alignment() {
+        let mut a = checked_arena(1024, 1024);
+        a.set_min_alignment(3);
+    }
+    fn check_split(a: &Arena, s: &str, sep: char) {
+        let (copy, fields) = a.alloc_split(s, sep).unwrap();
+        assert_eq!(copy, s);
+        assert_eq!(&*fields, &s.split(sep).collect::<Vec<_>>()[..]);
+        let range = copy.as_ptr() as usize..copy.as_ptr() as usize + copy.len() + 1;
+        for field in fields.iter() {
+            assert!(range.contains(&(field.as_ptr() as usize)));
+            assert!(range.contains(&(field.as_ptr() as usize + field.len())));
+        }
+    }
+    #[test]
+    fn arena_alloc_split() {
+        let a = checked_arena(4096, 4096);
+        for s in &["a,b,c", "", ",", "a,,b", ",a,", "abc", "\u{e9},\u{1f980},,x"] {
+            check_split(&a, s, ',');
+        }
+        check_split(&a, "k\u{e9}y\u{e9}value\u{e9}", '\u{e9}');
+        let (copy, fields) = a.alloc_split_whitespace(" \tport  8080 \n").unwrap();
+        assert_eq!(&*fields, ["port", "8080"]);
+        assert!(copy.as_ptr() < fields[0].as_ptr());
+        let (_, fields) = a.alloc_split_whitespace("   ").unwrap();
+        assert!(fields.is_empty());
+    }
+    #[test]
+    fn arena_alloc_split_exhausted() {
+        let a = checked_arena(48, 64);
+        assert_eq!(a.alloc_split("a,b,c", ',').err(), Some(::alloc::AllocError::Exhausted));
+        assert_eq!(a.used.get(), 0);
+        let (_, fields) = a.alloc_split("a,b", ',').unwrap();
+        assert_eq!(&*fields, ["a", "b"]);
+    }
+    #[test]
+    fn arena_try_new_box() {
+        let mut a = checked_arena(60, 64);
+        let x = a.try_new_box(1u8).unwrap();
+        let err = a.try_new_box([7u32; 16]).err().unwrap();
+        assert_eq!((err.requested, err.align, err.remaining), (64, 4, 59));
+        assert_eq!(err.value, [7; 16]);
+        let y = a.try_new_box([0u8; 50]).unwrap();
+        // 9 bytes are left, but an aligned u64 needs 5 bytes of padding
+        // first.
+        let err = a.try_new_box(3u64).err().unwrap();
+        assert_eq!((err.requested, err.align, err.remaining), (8, 8, 9));
+        assert_eq!(err.into_value(), 3);
+        assert!(a.try_new_box(()).is_ok());
+        drop((x, y));
+
+        a.set_min_alignment(16);
+        let err = a.try_new_box(0u16).err().unwrap();
+        assert_eq!((err.requested, err.align, err.remaining), (2, 16, 9));
+        assert_eq!(format!("{:?}", err), "ArenaFull { requested: 2, align: 16, remaining: 9, .. }");
+        assert_eq!(format!("{}", err), "arena cannot fit 2 bytes aligned to 16, 9 bytes remaining");
+    }
+    #[test]
+    fn arena_debug() {
+        let a = checked_arena(1024, 1024);
+        let _x = a.new_box(7u32).unwrap();
+        let expected = format!(
+            "Arena {{ mem: {:p}, size: 1024, used: 4, remaining: 1020 }}",
+            a.mem
+        );
+        assert_eq!(format!("{:?}", *a), expected);
+        let pretty = format!("{:#?}", *a);
+        assert_eq!(pretty.lines().count(), 6);
+        for field in &["mem: 0x", "size: 1024,", "used: 4,", "remaining: 1020,"] {
+            assert!(pretty.contains(field), "{} is missing from {}", field, pretty);
+        }
+    }
+    #[test]
+    fn arena_stats() {
+        let mut a = checked_arena(64, 64);
+        assert_eq!(a.stats(), ArenaStats::default());
+        {
+            let _x = a.new_box(1u8).unwrap();
+            let _y = a.new_box(2u64).unwrap(); // after 7 bytes of padding
+            let _unit = a.new_box(()).unwrap();
+            assert!(a.new_box([0u8; 64]).is_err());
+            let _z = a.alloc_slice_copy(&[1u32; 4]).unwrap();
+            assert_eq!(a.used(), 32);
+        }
+        let expected = ArenaStats {
+            allocations: 3,
+            failed_allocations: 1,
+            peak_used: 32,
+        };
+        assert_eq!(a.stats(), expected);
+        a.reset();
+        assert_eq!(a.stats(), expected);
+        {
+            let _x = a.new_box(0u16).unwrap();
+            assert_eq!(a.stats().peak_used, 32);
+            let rest = a.take_remaining();
+            assert_eq!(rest.len(), 62);
+        }
+        assert_eq!(
+            a.stats(),
+            ArenaStats {
+                allocations: 5,
+                failed_allocations: 1,
+                peak_used: 64,
+            }
+        );
+    }
+    #[test]
+    fn arena_stats_grow_in_place() {
+        let a = checked_arena(1024, 1024);
+        let mut v = a.new_vec::<u32>();
+        for i in 0..100 {
+            v.push(i);
+        }
+        let s = a.alloc_from_iter_growing((0..10u64).filter(|i| i % 2 == 0)).unwrap();
+        assert_eq!(s.len(), 5);
+        // The vector grew in place from its first buffer of 4, and so did
+        // the slice of unknown length.
+        assert_eq!(a.stats().allocations, 2);
+        assert_eq!(a.stats().peak_used, 512 + 64);
+        assert_eq!(a.used(), 512 + 40);
+    }
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn arena_quota() {
+        let mut a = checked_arena(1024, 1024);
+        a.set_quota(100);
+        assert_eq!(a.quota(), Some(100));
+        // `set_quota` needs `&mut self`, so the boxes are dropped right
+        // away; their memory stays used.
+        for i in 0..12u64 {
+            let _ = a.new_box(i).unwrap();
+        }
+        assert_eq!(a.new_box(12u64), Err(12));
+        assert_eq!(
+            a.try_aligned_alloc(8, 8),
+            Err(::alloc::AllocError::QuotaExceeded { quota: 100, used: 96 })
+        );
+        assert!(a.new_box(0u32).is_ok());
+        assert_eq!(
+            a.alloc_split("a b", ' ').err(),
+            Some(::alloc::AllocError::QuotaExceeded { quota: 100, used: 100 })
+        );
+        assert_eq!(a.take_remaining().len(), 0);
+        a.set_quota(200);
+        for i in 12..24u64 {
+            let _ = a.new_box(i).unwrap();
+        }
+        assert_eq!(a.used.get(), 200);
+        assert_eq!(a.take_remaining().len(), 0);
+        a.set_quota(50);
+        assert_eq!(a.take_remaining().len(), 0);
+        assert_eq!(a.used.get(), 200);
+        a.clear_quota();
+        assert_eq!(a.take_remaining().len(), 824);
+        assert_eq!(a.try_aligned_alloc(1, 1), Err(::alloc::AllocError::Exhausted));
+        a.check_invariants();
+    }
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn arena_size_overflow() {
+        let mut a = checked_arena(1024, 1024);
+        let _ = a.new_box(1u8).unwrap();
+        for &size in &[usize::MAX, usize::MAX - 1, usize::MAX - 1024] {
+            assert_eq!(a.alloc_bytes_uninit(size).err(), Some(::alloc::AllocError::Exhausted));
+            assert_eq!(a.new_uninit_slice::<u8>(size).err(), Some(::alloc::AllocError::Exhausted));
+            assert_eq!(a.try_aligned_alloc(size, 8), Err(::alloc::AllocError::Exhausted));
+            assert_eq!(a.try_alloc_main(size, 8), Err(::alloc::AllocError::Exhausted));
+        }
+        assert_eq!(a.used(), 1);
+        a.set_quota(100);
+        assert_eq!(a.alloc_bytes_uninit(usize::MAX).err(), Some(::alloc::AllocError::Exhausted));
+        assert_eq!(a.used(), 1);
+        a.check_invariants();
+        let mut first = [0u8; 16];
+        let mut second = [0u8; 32];
+        let b = Arena::from_regions(&mut [&mut first[..], &mut second[..]]).unwrap();
+        let _ = b.new_box([1u8; 12]).unwrap();
+        assert_eq!(b.alloc_bytes_uninit(usize::MAX).err(), Some(::alloc::AllocError::Exhausted));
+        assert_eq!(b.regions.alloc(usize::MAX - 1, 1), None);
+        assert_eq!(b.used(), 12);
+    }
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn arena_prefault() {
+        let mut a = checked_arena(64 * 1024 + 100, 4096);
+        a.prefault();
+        let _ = a.new_box(0x0123_4567_89ab_cdefu64).unwrap();
+        let p = a.mem as *const u64;
+        a.prefault();
+        assert_eq!(unsafe { *p }, 0x0123_4567_89ab_cdef);
+        let buf = a.take_remaining();
+        assert_eq!(buf.len(), 64 * 1024 + 92);
+    }
+    #[test]
+    fn arena_alloc_at_offset_image() {
+        let a = checked_arena(1024, 1024);
+        let header = a.alloc_at_offset(0, Layout::new::<[u8; 8]>()).unwrap();
+        let table = a.alloc_at_offset(512, Layout::new::<[u32; 4]>()).unwrap();
+        assert_eq!(header.as_ptr(), a.mem);
+        assert_eq!(table.as_ptr() as usize - a.mem as usize, 512);
+        assert_eq!(a.used.get(), 512 + 16);
+        unsafe {
+            ::core::ptr::write(header.as_ptr() as *mut [u8; 8], *b"ARENAIMG");
+            ::core::ptr::write(table.as_ptr() as *mut [u32; 4], [1, 2, 3, 4]);
+            let image = ::core::slice::from_raw_parts(a.mem, a.used.get());
+            assert_eq!(&image[..8], b"ARENAIMG");
+            assert_eq!(&image[512..516], &1u32.to_ne_bytes());
+            assert_eq!(&image[524..528], &4u32.to_ne_bytes());
+        }
+        let p = a.aligned_alloc(1, 1).unwrap();
+        assert_eq!(p as usize - a.mem as usize, 528);
+        let end = a.alloc_at_offset(600, Layout::new::<()>()).unwrap();
+        assert_eq!(end.as_ptr() as usize - a.mem as usize, 600);
+        assert_eq!((a.stats().allocations, a.stats().peak_used), (3, 600));
+    }
+    #[test]
+    fn arena_alloc_at_offset_errors() {
+        let a = checked_arena(1024, 1024);
+        let _ = a.alloc_at_offset(64, Layout::new::<u64>()).unwrap();
+        assert_eq!(
+            a.alloc_at_offset(8, Layout::new::<u8>()),
+            Err(PlacementError::OffsetPassed { offset: 8, used: 72 })
+        );
+        assert_eq!(
+            a.alloc_at_offset(100, Layout::new::<u64>()),
+            Err(PlacementError::Misaligned { offset: 100, align: 8 })
+        );
+        assert_eq!(
+            a.alloc_at_offset(1020, Layout::new::<u64>()),
+            Err(PlacementError::Misaligned { offset: 1020, align: 8 })
+        );
+        assert_eq!(
+            a.alloc_at_offset(1020, Layout::new::<[u8; 8]>()),
+            Err(PlacementError::Exhausted { offset: 1020, size: 8, capacity: 1024 })
+        );
+        for &offset in &[1025, usize::MAX - 7, usize::MAX] {
+            assert_eq!(
+                a.alloc_at_offset(offset, Layout::new::<u64>()),
+                Err(PlacementError::Exhausted { offset, size: 8, capacity: 1024 })
+            );
+        }
+        assert_eq!(a.used.get(), 72);
+        assert!(a.alloc_at_offset(1016, Layout::new::<u64>()).is_ok());
+        assert_eq!(a.used.get(), 1024);
+    }
+    #[test]
+    fn arena_take_remaining_give_back() {
+        let a = checked_arena(1024, 1024);
+        let _ = a.new_box(1u32).unwrap();
+        let mut buf = a.take_remaining();
+        assert_eq!(buf.len(), 1020);
+        assert_eq!(a.size - a.used.get(), 0);
+        assert!(a.aligned_alloc(1, 1).is_none());
+        for (i, b) in buf[..510].iter_mut().enumerate() {
+            *b = MaybeUninit::new(i as u8);
+        }
+        let buf = a.give_back(buf, 510);
+        assert_eq!(buf.len(), 510);
+        assert_eq!(a.used.get(), 4 + 510);
+        assert_eq!(a.stats().allocations, 2);
+        let p = a.aligned_alloc(1, 1).unwrap();
+        assert_eq!(p as usize, buf.as_ptr() as usize + 510);
+        assert_eq!(unsafe { buf[509].assume_init() }, (509 & 0xff) as u8);
+    }
+    #[test]
+    fn arena_take_remaining_empty() {
+        let a = checked_arena(8, 8);
+        let _ = a.new_box(0u64).unwrap();
+        let buf = a.take_remaining();
+        assert_eq!(buf.len(), 0);
+        let buf = a.give_back(buf, 0);
+        assert_eq!(buf.len(), 0);
+        assert_eq!(a.used.get(), 8);
+        assert_eq!(a.stats().allocations, 1);
+    }
+    #[test]
+    #[should_panic(expected = "buffer does not end at the arena cursor")]
+    fn arena_give_back_foreign_buffer() {
+        let a = checked_arena(64, 64);
+        let b = checked_arena(64, 64);
+        let buf = a.take_remaining();
+        let _ = b.give_back(buf, 1);
+    }
+    #[cfg(feature = "alloc")]
+    #[test]
+    #[should_panic(expected = "arena invariant violated: used (1025) exceeds size (1024)")]
+    fn arena_check_invariants_used() {
+        let a = checked_arena(1024, 1024);
+        a.used.set(1025);
+        a.check_invariants();
+    }
+    #[cfg(feature = "alloc")]
+    #[test]
+    #[should_panic(expected = "arena invariant violated: base pointer is null")]
+    fn arena_check_invariants_null() {
+        let mut a = ::core::mem::ManuallyDrop::new(Arena::new(0, 1).unwrap());
+        a.mem = ::core::ptr::null_mut();
+        a.check_invariants();
+    }
+    #[cfg(feature = "alloc")]
+    #[test]
+    #[should_panic(expected = "arena invariant violated: buffer of 16 bytes")]
+    fn arena_check_invariants_wrap() {
+        let mut a = ::core::mem::ManuallyDrop::new(Arena::new(0, 1).unwrap());
+        a.mem = (usize::MAX - 8) as *mut u8;
+        a.size = 16;
+        a.check_invariants();
+    }
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn arena_cleanup_order() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        {
+            let a = checked_arena(1024, 1024);
+            for i in 0..6 {
+                let _ = a.new_box([i; 8]).unwrap();
+                let log = log.clone();
+                if i % 2 == 0 {
+                    assert!(a.on_reset(move || log.borrow_mut().push(i)).is_ok());
+                } else {
+                    assert!(a.on_drop(move || log.borrow_mut().push(i)).is_ok());
+                }
+            }
+            assert_eq!(a.used.get(), 6 * 8 * 4);
+            assert!(log.borrow().is_empty());
+        }
+        assert_eq!(*log.borrow(), [5, 4, 3, 2, 1, 0]);
+    }
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn arena_cleanup_panic() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let a = checked_arena(1024, 1024);
+        for i in 0..3 {
+            let log = log.clone();
+            assert!(a.on_drop(move || log.borrow_mut().push(i)).is_ok());
+        }
+        assert!(a.on_drop(|| panic!("cleanup failed")).is_ok());
+        for i in 3..6 {
+            let log = log.clone();
+            assert!(a.on_drop(move || log.borrow_mut().push(i)).is_ok());
+        }
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(move || drop(a)));
+        assert!(result.is_err());
+        assert_eq!(*log.borrow(), [5, 4, 3, 2, 1, 0]);
+    }
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn arena_invalid_alignment() {
+        let before = ::test_util::live_allocations();
+        for &alignment in &[0, 3, 1025, usize::MAX] {
+            assert_eq!(
+                Arena::new(1024, alignment).err(),
+                Some(::alloc::AllocError::InvalidAlignment { alignment })
+            );
+            assert!(Arena::new(0, alignment).is_err());
+        }
+        assert_eq!(::test_util::live_allocations(), before);
+        let a = checked_arena(1024, 1 << 16);
+        assert_eq!(a.mem as usize % (1 << 16), 0);
+        for &alignment in &[1, 2, 4] {
+            assert!(Arena::new(16, alignment).is_ok());
+        }
+    }
+    #[cfg(feature = "poison")]
+    #[test]
+    #[cfg_attr(feature = "sanitize", ignore = "reads memory given back to the Arena")]
+    fn arena_poison() {
+        let mut a = checked_arena(256, 64);
+        let p = a.new_uninit::<[u8; 16]>().unwrap().as_ptr();
+        assert_eq!(unsafe { *p }, [0xA5; 16]);
+        let x = a.new_box([1u8; 16]).unwrap();
+        let q = &*x as *const [u8; 16];
+        assert!(a.reclaim(x));
+        assert_eq!(unsafe { *q }, [0x5A; 16]);
+        let buf = a.take_remaining();
+        assert!(buf.iter().all(|b| unsafe { b.assume_init() } == 0xA5));
+        let buf = a.give_back(buf, 200);
+        assert_eq!(unsafe { *(a.mem.add(255)) }, 0x5A);
+        drop(buf);
+        a.scope(|a| {
+            let _ = a.new_box(7u64).unwrap();
+        });
+        assert_eq!(unsafe { *(a.mem.add(56)) }, 0x5A);
+        let cp = a.checkpoint();
+        let y = a.new_box([2u8; 8]).unwrap();
+        let r = &*y as *const [u8; 8];
+        drop(y);
+        a.rewind(cp);
+        assert_eq!(unsafe { *r }, [0x5A; 8]);
+        a.reset();
+        assert_eq!(unsafe { *p }, [0x5A; 16]);
+    }
+    #[cfg(not(feature = "poison"))]
+    #[test]
+    #[cfg_attr(feature = "sanitize", ignore = "reads memory given back to the Arena")]
+    fn arena_no_poison() {
+        let mut a = checked_arena(64, 64);
+        let x = a.new_box([0x11u8; 16]).unwrap();
+        let p = &*x as *const [u8; 16];
+        drop(x);
+        a.reset();
+        assert_eq!(unsafe { *p }, [0x11; 16]);
+        let y = a.new_uninit::<[u8; 16]>().unwrap();
+        assert_eq!(unsafe { *y.as_ptr() }, [0x11; 16]);
+    }
+    #[test]
+    fn arena_remaining_for() {
+        #[derive(Default)]
+        #[repr(align(16))]
+        struct Wide {
+            _bytes: [u8; 20],
+        }
+        fn check<T: Default>(a: &Arena) {
+            let predicted = a.remaining_for::<T>();
+            assert_eq!(a.can_fit::<T>(), predicted > 0);
+            let mut n = 0;
+            while let Ok(b) = a.new_box(T::default()) {
+                ArenaBox::leak(b);
+                n += 1;
+            }
+            assert_eq!(n, predicted, "{} with minimum alignment {}", ::core::any::type_name::<T>(), a.min_alignment());
+        }
+        for &min_align in &[1, 4, 8, 64] {
+            for &skew in &[0, 1, 3] {
+                for &quota in &[None, Some(700)] {
+                    let mut a = checked_arena(1000, 64);
+                    a.set_min_alignment(min_align);
+                    if let Some(quota) = quota {
+                        a.set_quota(quota);
+                    }
+                    let fill = |a: &mut Arena, check: fn(&Arena)| {
+                        a.reset();
+                        for _ in 0..skew {
+                            ArenaBox::leak(a.new_box(0u8).unwrap());
+                        }
+                        check(a);
+                    };
+                    fill(&mut a, check::<u8>);
+                    fill(&mut a, check::<[u8; 3]>);
+                    fill(&mut a, check::<u32>);
+                    fill(&mut a, check::<[u64; 3]>);
+                    fill(&mut a, check::<Wide>);
+                    // A full arena has no room for anything bigger either.
+                    check::<u8>(&a);
+                    assert_eq!(a.remaining_for::<u64>(), 0);
+                }
+            }
+        }
+        let a = checked_arena(0, 8);
+        assert_eq!(a.remaining_for::<u8>(), 0);
+        assert_eq!(a.remaining_for::<()>(), usize::MAX);
+        assert!(a.can_fit::<()>());
+    }
+    #[test]
+    fn arena_contains() {
+        let a = checked_arena(64, 16);
+        let b = checked_arena(64, 16);
+        assert!(!a.contains(a.mem));
+        let x = a.new_box([1u8; 16]).unwrap();
+        let y = a.new_box(2u32).unwrap();
+        let z = b.new_box(3u32).unwrap();
+        assert!(a.contains(&x[0]) && a.contains(&x[15]));
+        assert!(a.contains_value(&*x) && a.contains_value(&x[4..8]));
+        assert!(a.contains_value(&*y));
+        assert!(!a.contains_value(&*z) && b.contains_value(&*z));
+        let end = (&*y as *const u32).wrapping_add(1) as *const u8;
+        assert!(!a.contains(end));
+        assert!(a.contains(end.wrapping_sub(1)));
+        assert!(!a.contains(a.mem.wrapping_sub(1)));
+        assert!(!a.contains(::core::ptr::null()));
+        assert!(!a.contains_value(&()));
+        assert!(a.contains_value(&x[16..]));
+        assert!(!a.contains_value(&7u64));
+        let empty = checked_arena(0, 8);
+        assert!(!empty.contains(empty.mem));
+        assert!(!empty.contains_value(&*x));
+    }
+    #[test]
+    fn arena_alloc_bytes() {
+        let a = checked_arena(1024, 1024);
+        let x = a.new_box(0xffu8).unwrap();
+        let buf = a.alloc_bytes(100).unwrap();
+        let y = a.new_box(0xeeu8).unwrap();
+        let empty = a.alloc_bytes(0).unwrap();
+        let raw = a.alloc_bytes_uninit(16).unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(raw.len(), 16);
+        assert!(buf.iter().all(|&b| b == 0));
+        buf.fill(0x11);
+        let start = buf.as_ptr() as usize;
+        for p in &[&*x as *const u8 as usize, &*y as *const u8 as usize, raw.as_ptr() as usize] {
+            assert!(*p < start || *p >= start + 100);
+        }
+        assert_eq!((*x, *y), (0xff, 0xee));
+        assert_eq!(a.used(), 1 + 100 + 1 + 16);
+        assert_eq!(a.alloc_bytes(1024).err(), Some(::alloc::AllocError::Exhausted));
+    }
+    #[cfg(feature = "live-count")]
+    #[test]
+    fn arena_alloc_bytes_stays_live() {
+        let a = checked_arena(64, 64);
+        let _ = a.alloc_bytes(8).unwrap();
+        assert_eq!(a.try_reset(), Err(ResetError { live: 1 }));
+    }
+    #[test]
+    fn arena_new_box_aligned() {
+        let drops = Cell::new(0);
+        let a = checked_arena(1024, 1024);
+        let _x = a.new_box(1u8).unwrap();
+        let y = a.new_box_aligned(DropCounter(&drops), 64).ok().unwrap();
+        assert_eq!(&*y as *const _ as usize - a.mem as usize, 64);
+        assert_eq!(a.used(), 64 + 8);
+        // A weaker alignment than the type's own is ignored.
+        let z = a.new_box_aligned(7u64, 2).unwrap();
+        assert_eq!(&*z as *const u64 as usize % 8, 0);
+        assert_eq!(a.used(), 80);
+        let unit = a.new_box_aligned((), 4096).unwrap();
+        assert_eq!(&*unit as *const () as usize % 4096, 0);
+        assert_eq!(a.used(), 80);
+        assert_eq!(a.new_box_aligned(3u8, 2048), Err(3));
+        assert_eq!(a.used(), 80);
+        drop(y);
+        assert_eq!(drops.get(), 1);
+    }
+    #[test]
+    #[should_panic(expected = "alignment 48 is not a power of two")]
+    fn arena_new_box_aligned_invalid() {
+        let a = checked_arena(1024, 1024);
+        let _ = a.new_box_aligned(0u8, 48);
+    }
+    #[test]
+    fn arena_alloc_slice_copy_aligned() {
+        let a = checked_arena(1024, 1024);
+        let _x = a.new_box(1u16).unwrap();
+        let lanes = a.alloc_slice_copy_aligned(&[1.5f32; 8], 32).unwrap();
+        assert_eq!(lanes.as_ptr() as usize - a.mem as usize, 32);
+        assert_eq!(&*lanes, &[1.5; 8]);
+        assert_eq!(a.used(), 64);
+        let empty = a.alloc_slice_copy_aligned::<u8>(&[], 256).unwrap();
+        assert_eq!(empty.as_ptr() as usize % 256, 0);
+        assert_eq!(a.used(), 64);
+        assert_eq!(
+            a.alloc_slice_copy_aligned(&[0u8], 24).err(),
+            Some(::alloc::AllocError::InvalidAlignment { alignment: 24 })
+        );
+        assert_eq!(a.alloc_slice_copy_aligned(&[0u8], 2048).err(), Some(::alloc::AllocError::Exhausted));
+        assert_eq!(a.used(), 64);
+    }
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn arena_zero_size() {
+        let before = ::test_util::live_allocations();
+        {
+            let mut a = checked_arena(0, 8);
+            assert_eq!(a.mem as usize % 8, 0);
+            assert_eq!((a.capacity(), a.remaining()), (0, 0));
+            // Zero-sized values take no memory, so they still fit.
+            assert!(a.new_box(()).is_ok());
+            assert!(a.alloc_slice_copy::<u32>(&[]).is_ok());
+            assert_eq!(a.new_box(1u8), Err(1));
+            assert_eq!(a.try_aligned_alloc(1, 1), Err(::alloc::AllocError::Exhausted));
+            assert!(a.take_remaining().is_empty());
+            a.reset();
+        }
+        for &alignment in &[1, 4096] {
+            drop(Arena::new(0, alignment).unwrap());
+        }
+        assert_eq!(::test_util::live_allocations(), before);
+    }
+    #[test]
+    fn arena_aligned_alloc_invalid_alignment() {
+        let a = checked_arena(1024, 1024);
+        for &alignment in &[0, 3, 1025] {
+            assert_eq!(
+                a.try_aligned_alloc(1, alignment),
+                Err(::alloc::AllocError::InvalidAlignment { alignment })
+            );
+        }
+        assert_eq!(a.used(), 0);
+        assert_eq!(a.stats().failed_allocations, 0);
+    }
+
+    #[cfg(all(feature = "std", not(feature = "live-count")))]
+    #[test]
+    fn arena_leak() {
+        use std::sync::OnceLock;
+        use std::thread;
+        static GREETING: OnceLock<ArenaBox<'static, [u8; 5]>> = OnceLock::new();
+        let a = Arena::new(64, 64).unwrap().leak();
+        assert!(GREETING.set(a.new_box(*b"hello").unwrap()).is_ok());
+        let read = thread::spawn(|| *GREETING.get().unwrap().as_ref()).join();
+        assert_eq!(read.unwrap(), *b"hello");
+    }
+
+    #[cfg(all(feature = "live-count", feature = "alloc"))]
+    #[test]
+    fn arena_try_reset() {
+        let a = checked_arena(64, 64);
+        let resets = Rc::new(RefCell::new(Vec::new()));
+        let r = resets.clone();
+        assert!(a.on_drop(move || r.borrow_mut().push("drop")).is_ok());
+        let r = resets.clone();
+        assert!(a.on_reset(move || r.borrow_mut().push("reset")).is_ok());
+        let x = a.new_box([1u8; 64]).unwrap();
+        assert_eq!(a.try_reset(), Err(ResetError { live: 1 }));
+        assert!(resets.borrow().is_empty());
+        drop(x);
+        assert_eq!(a.try_reset(), Ok(()));
+        assert_eq!(*resets.borrow(), ["reset"]);
+        assert_eq!(a.used.get(), 0);
+        assert!(a.new_box([2u8; 64]).is_ok());
+    }
+
+    #[cfg(all(feature = "live-count", feature = "alloc"))]
+    #[test]
+    fn arena_try_reset_holders() {
+        let a = checked_arena(1024, 1024);
+        {
+            let b = checked_arena(64, 64);
+            let (_, fields) = b.alloc_split("a,b", ',').unwrap();
+            let kept: &str = fields[1];
+            assert_eq!(b.live_count(), 2);
+            drop(fields);
+            // The fields borrow the copy for as long as the Arena lives, so
+            // it is leaked.
+            assert_eq!(b.try_reset(), Err(ResetError { live: 1 }));
+            assert_eq!(kept, "b");
+        }
+        let buf = a.take_remaining();
+        let unused = buf.len() - 8;
+        let buf = a.give_back(buf, unused);
+        assert_eq!(a.live_count(), 1);
+        drop(buf);
+        let child = a.child(64).unwrap();
+        assert_eq!(a.try_reset(), Err(ResetError { live: 1 }));
+        drop(child);
+        assert_eq!(a.try_reset(), Ok(()));
+    }
+
+    #[cfg(feature = "live-count")]
+    #[test]
+    fn arena_try_reset_into_raw() {
+        let a = checked_arena(64, 64);
+        let p = ArenaBox::into_raw(a.new_box(5u32).unwrap());
+        assert_eq!(a.try_reset(), Err(ResetError { live: 1 }));
+        drop(unsafe { ArenaBox::from_raw(p) });
+        assert_eq!(a.live_count(), 1);
+        assert!(a.try_reset().is_err());
+    }
+    #[test]
+    fn arena_reclaim() {
+        let a = checked_arena(1024, 1024);
+        let drops = Cell::new(0);
+        let cursor = || (a.used.get(), a.stats().allocations);
+        let x = a.new_box((1u64, DropCounter(&drops))).unwrap();
+        assert_eq!(cursor(), (16, 1));
+        let y = a.new_box([2u8; 24]).unwrap();
+        assert_eq!(cursor(), (40, 2));
+        assert!(!a.reclaim(x));
+        assert_eq!((cursor(), drops.get()), ((40, 2), 1));
+        let z = a.new_box((3u64, DropCounter(&drops))).unwrap();
+        assert_eq!(cursor(), (56, 3));
+        assert!(a.reclaim(z));
+        assert_eq!((cursor(), drops.get()), ((40, 2), 2));
+        assert!(a.reclaim(y));
+        assert_eq!(cursor(), (16, 1));
+        let w = a.new_box(4u8).unwrap();
+        let v = a.new_box(5u32).unwrap();
+        assert_eq!(cursor(), (24, 3));
+        assert!(!a.reclaim(w));
+        assert_eq!(cursor(), (24, 3));
+        assert!(a.reclaim(v));
+        assert_eq!(cursor(), (20, 2));
+        assert!(!a.reclaim(a.new_box(()).unwrap()));
+        let other = checked_arena(64, 64);
+        assert!(!a.reclaim(other.new_box(6u8).unwrap()));
+        assert_eq!(cursor(), (20, 2));
+        let stats = a.stats();
+        assert_eq!((stats.failed_allocations, stats.peak_used), (0, 56));
+    }
+    #[test]
+    fn arena_reclaim_split() {
+        let a = checked_arena(1024, 1024);
+        let (copy, fields) = a.alloc_split("ab,cd", ',').unwrap();
+        let kept: &str = fields[1];
+        let end = a.used.get();
+        assert!(a.reclaim(fields));
+        // The copy the fields borrow is not in a box, and stays allocated.
+        assert_eq!(a.used.get(), end - 2 * ::core::mem::size_of::<&str>());
+        let _ = a.alloc_slice_copy(&[0xffu8; 8]).unwrap();
+        assert_eq!((copy, kept), ("ab,cd", "cd"));
+    }
+    // Dropping a bogus `List` read out of uninitialized memory tends to
+    // crash, and dropping a bogus `DropCounter` shows up in the counts.
+    #[allow(dead_code)]
+    #[derive(Debug)]
+    enum List<'a> {
+        Nil,
+        Cons(DropCounter<'a>, ArenaBox<'a, List<'a>>),
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn arena_new_box_does_not_drop_garbage() {
+        let drops = Cell::new(0);
+        let mut buf = [0xa5u8; 1024];
+        let a = Arena::from_regions(&mut [&mut buf[..]]).unwrap();
+        let mut list = a.new_box(List::Nil).unwrap();
+        for _ in 0..10 {
+            list = a.new_box(List::Cons(DropCounter(&drops), list)).unwrap();
+        }
+        let pair = a.new_box((DropCounter(&drops), Rc::new(1))).unwrap();
+        assert_eq!(drops.get(), 0);
+        drop((list, pair));
+        assert_eq!(drops.get(), 11);
+    }
+
+    #[test]
+    fn arena_new_box_reused_memory() {
+        let drops = Cell::new(0);
+        let a = checked_arena(4096, 4096);
+        for round in 1..=10 {
+            // Leave the previous round's values behind in the memory, so
+            // the next round writes over their bit patterns.
+            let boxes: Vec<_> = (0..100)
+                .map(|_| a.new_box(Some(DropCounter(&drops))).unwrap())
+                .collect();
+            assert_eq!(drops.get(), (round - 1) * 100);
+            ::core::mem::forget(boxes);
+            drops.set(drops.get() + 100);
+            a.clear();
+        }
+        assert_eq!(drops.get(), 1000);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn arena_reset() {
+        let drops = Rc::new(Cell::new(0));
+        let mut a = checked_arena(1024, 64);
+        a.set_quota(512);
+        let d = drops.clone();
+        assert!(a.on_reset(move || d.set(d.get() + 1)).is_ok());
+        let d = drops.clone();
+        assert!(a.on_drop(move || d.set(d.get() + 10)).is_ok());
+        let mem = a.mem;
+        let allocations = total_allocations();
+        for pass in 0..1000u64 {
+            let boxes: Vec<_> = (0..64).map(|i| a.new_box(pass * 64 + i).unwrap()).collect();
+            assert_eq!(a.new_box(0u64), Err(0));
+            assert_eq!(*boxes[63], pass * 64 + 63);
+            drop(boxes);
+            a.reset();
+            assert_eq!(a.used.get(), 0);
+        }
+        assert_eq!(total_allocations(), allocations);
+        assert_eq!(a.mem, mem);
+        assert_eq!(a.quota(), Some(512));
+        assert_eq!(drops.get(), 1);
+        drop(a);
+        assert_eq!(drops.get(), 11);
+    }
+
+    #[test]
+    fn arena_rewind() {
+        let mut a = checked_arena(256, 64);
+        let keep = a.new_box(7u8).unwrap();
+        let kept = &*keep as *const u8;
+        drop(keep);
+        let cp = a.checkpoint();
+        let first = {
+            let x = a.new_box([1u64; 8]).unwrap();
+            let _y = a.new_box(2u16).unwrap();
+            &*x as *const [u64; 8] as usize
+        };
+        a.rewind(cp);
+        assert_eq!(a.used(), 1);
+        let again = a.new_box([3u64; 8]).unwrap();
+        assert_eq!(&*again as *const [u64; 8] as usize, first);
+        drop(again);
+        // Rewinding to the same checkpoint again is fine, and a full arena
+        // has room again afterwards.
+        a.rewind(cp);
+        while a.new_box(0u64).is_ok() {}
+        a.rewind(cp);
+        assert!(a.new_box([0u8; 200]).is_ok());
+        a.rewind(cp);
+        assert_eq!(unsafe { *kept }, 7);
+
+        a.set_min_alignment(16);
+        let cp = a.checkpoint();
+        let _ = a.new_box(1u8).unwrap();
+        let _ = a.new_box(1u8).unwrap();
+        assert_eq!(a.min_alignment_padding(), 30);
+        a.rewind(cp);
+        assert_eq!((a.used(), a.min_alignment_padding()), (1, 0));
+    }
+
+    #[test]
+    fn arena_scope() {
+        let mut a = checked_arena(256, 64);
+        let drops = Cell::new(0);
+        let outer = a.new_box(1u32).unwrap();
+        let outer_p = &*outer as *const u32;
+        drop(outer);
+        let (inner_p, sum) = a.scope(|a| {
+            let x = a.new_box([2u64; 4]).unwrap();
+            let _d = a.new_box(DropCounter(&drops)).unwrap();
+            (&*x as *const [u64; 4] as usize, x.iter().sum::<u64>())
+        });
+        assert_eq!((sum, drops.get(), a.used()), (8, 1, 4));
+        let again = a.new_box([0u64; 4]).unwrap();
+        assert_eq!(&*again as *const [u64; 4] as usize, inner_p);
+        drop(again);
+        assert_eq!(unsafe { *outer_p }, 1);
+    }
+
+    #[test]
+    fn arena_scope_panic() {
+        let mut a = checked_arena(256, 64);
+        let drops = Cell::new(0);
+        let _ = a.new_box(1u8).unwrap();
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            a.scope(|a| {
+                let _d = a.new_box(DropCounter(&drops)).unwrap();
+                let _ = a.new_box([0u8; 100]).unwrap();
+                panic!("rejected");
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!((drops.get(), a.used()), (1, 1));
+        assert!(a.new_box([0u8; 255]).is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "checkpoint is from a different arena")]
+    fn arena_rewind_other_arena() {
+        let a = checked_arena(64, 8);
+        let mut b = checked_arena(64, 8);
+        let cp = a.checkpoint();
+        b.rewind(cp);
+    }
+
+    #[test]
+    #[should_panic(expected = "checkpoint is past the arena cursor")]
+    fn arena_rewind_after_reset() {
+        let mut a = checked_arena(64, 8);
+        let _ = a.new_box(1u64).unwrap();
+        let cp = a.checkpoint();
+        a.reset();
+        a.rewind(cp);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn arena_reset_empty() {
+        let mut a = checked_arena(64, 64);
+        a.reset();
+        assert_eq!(a.used.get(), 0);
+        let mut a = Arena::new(0, 64).unwrap();
+        a.reset();
+        assert_eq!(*a.new_box(()).unwrap(), ());
+        assert_eq!(a.new_box(0u8), Err(0));
+        a.reset();
+        // Dropping a zero-sized Arena frees its dangling base pointer.
+        ::core::mem::forget(a);
+    }
+
+    #[test]
+    fn arena_used_remaining_capacity() {
+        let a = checked_arena(62, 64);
+        assert_eq!((a.capacity(), a.used(), a.remaining()), (62, 0, 62));
+        let _x = a.new_box(1u8).unwrap();
+        let _y = a.new_box(2u32).unwrap();
+        assert_eq!((a.used(), a.remaining()), (8, 54));
+        let _z = a.new_box(3u16).unwrap();
+        let _w = a.new_box(4u64).unwrap();
+        assert_eq!((a.used(), a.remaining()), (24, 38));
+        let _v = a.new_box([5u8; 33]).unwrap();
+        assert_eq!((a.used(), a.remaining()), (57, 5));
+        // Fits in what is left, but not after its padding.
+        assert_eq!(a.new_box(6u32), Err(6));
+        assert_eq!(a.remaining(), 5);
+        let _u = a.new_box([7u8; 5]).unwrap();
+        assert_eq!((a.used(), a.remaining()), (62, 0));
+        assert_eq!(a.new_box(8u8), Err(8));
+
+        let mut a = checked_arena(64, 64);
+        a.set_quota(16);
+        assert_eq!((a.capacity(), a.remaining()), (64, 16));
+        drop(a.new_box([1u8; 10]).unwrap());
+        assert_eq!(a.remaining(), 6);
+        assert_eq!(a.new_box([2u8; 7]), Err([2; 7]));
+        drop(a.new_box([3u8; 6]).unwrap());
+        assert_eq!(a.remaining(), 0);
+        a.set_quota(8);
+        assert_eq!((a.used(), a.remaining()), (16, 0));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn arena_used_remaining_regions() {
+        let mut first = [0u8; 16];
+        let mut second = [0u8; 32];
+        let a = Arena::from_regions(&mut [&mut first[..], &mut second[..]]).unwrap();
+        assert_eq!((a.capacity(), a.remaining()), (48, 48));
+        let _x = a.new_box([1u8; 12]).unwrap();
+        let _y = a.new_box([2u8; 20]).unwrap();
+        assert_eq!((a.used(), a.remaining()), (32, 16));
+        // 16 bytes are left in total, but only 12 of them in one piece.
+        assert!(a.new_box([3u8; 13]).is_err());
+        let _z = a.new_box([4u8; 12]).unwrap();
+        assert_eq!((a.used(), a.remaining()), (44, 4));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn arena_alloc_split_exhausted_regions() {
+        let mut first = [0u8; 16];
+        let mut second = [0u8; 48];
+        let a = Arena::from_regions(&mut [&mut first[..], &mut second[..]]).unwrap();
+        let _x = a.new_box([1u8; 14]).unwrap();
+        // The copy alone would fit in the second region, but not together
+        // with the fields, and neither of them may be left behind there.
+        let err = ::alloc::AllocError::NoRegionFits { size: 56, largest: 48 };
+        assert_eq!(a.alloc_split("a,b,c", ',').err(), Some(err));
+        assert_eq!(a.used(), 14);
+        a.check_invariants();
+        let (copy, fields) = a.alloc_split("a,b", ',').unwrap();
+        assert_eq!((copy, &*fields), ("a,b", &["a", "b"][..]));
+        a.check_invariants();
+    }
+
+    #[test]
+    fn arena_alloc_slice_copy() {
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        #[repr(align(16))]
+        struct Aligned(u8);
+
+        let a = checked_arena(128, 64);
+        let bytes = a.alloc_slice_copy(&[1u8, 2, 3]).unwrap();
+        assert_eq!(&*bytes, [1, 2, 3]);
+        assert_eq!(a.used(), 3);
+        let words = a.alloc_slice_copy(&[4u64, 5]).unwrap();
+        assert_eq!(&*words, [4, 5]);
+        assert_eq!(words.as_ptr() as usize % 8, 0);
+        assert_eq!(a.used(), 24);
+        let aligned = a.alloc_slice_copy(&[Aligned(6), Aligned(7)]).unwrap();
+        assert_eq!(&*aligned, [Aligned(6), Aligned(7)]);
+        assert_eq!(aligned.as_ptr() as usize % 16, 0);
+        assert_eq!(a.used(), 64);
+
+        let empty = a.alloc_slice_copy::<u64>(&[]).unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(empty.as_ptr() as usize % 8, 0);
+        assert_eq!(a.used(), 64);
+
+        assert_eq!(a.alloc_slice_copy(&[0u8; 65]).err(), Some(::alloc::AllocError::Exhausted));
+        assert_eq!(a.used(), 64);
+        assert_eq!(a.alloc_slice_copy(&[8u8; 64]).unwrap().len(), 64);
+        assert_eq!(a.remaining(), 0);
+    }
+
+    #[test]
+    fn arena_alloc_slice_clone() {
+        let a = checked_arena(1024, 64);
+        let names: Vec<_> = (0..5).map(|i| Rc::new(format!("name {}", i))).collect();
+        let copy = a.alloc_slice_clone(&names).unwrap();
+        assert_eq!(&*copy, &names[..]);
+        assert!(names.iter().all(|n| Rc::strong_count(n) == 2));
+        drop(copy);
+        assert!(names.iter().all(|n| Rc::strong_count(n) == 1));
+        let used = a.used();
+        assert!(a.alloc_slice_clone::<String>(&[]).unwrap().is_empty());
+        assert_eq!(a.used(), used);
+
+        let big: Vec<_> = (0..200).map(Rc::new).collect();
+        assert_eq!(a.alloc_slice_clone(&big).err(), Some(::alloc::AllocError::Exhausted));
+        assert!(big.iter().all(|n| Rc::strong_count(n) == 1));
+        assert_eq!(a.used(), used);
+    }
+
+    #[test]
+    fn arena_alloc_slice_clone_panic() {
+        struct PanicOnClone<'c> {
+            id: usize,
+            clones: &'c Cell<usize>,
+            drops: &'c Cell<usize>,
+        }
+
+        impl<'c> Clone for PanicOnClone<'c> {
+            fn clone(&self) -> Self {
+                assert!(self.id != 3, "clone of element 3");
+                self.clones.set(self.clones.get() + 1);
+                PanicOnClone { ..*self }
+            }
+        }
+
+        impl<'c> Drop for PanicOnClone<'c> {
+            fn drop(&mut self) {
+                self.drops.set(self.drops.get() + 1);
+            }
+        }
+
+        let (clones, drops) = (Cell::new(0), Cell::new(0));
+        let src: Vec<_> = (0..6)
+            .map(|id| PanicOnClone { id, clones: &clones, drops: &drops })
+            .collect();
+        let a = checked_arena(1024, 64);
+        let _x = a.new_box(1u8).unwrap();
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| a.alloc_slice_clone(&src)));
+        assert!(result.is_err());
+        assert_eq!((clones.get(), drops.get()), (3, 3));
+        assert_eq!(a.used(), 1);
+        drop(src);
+        assert_eq!(drops.get(), 9);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn arena_alloc_slice_clone_every_failure_point() {
+        struct Flaky<'c> {
+            id: usize,
+            panic_at: usize,
+            clones: &'c Cell<usize>,
+            drops: &'c Cell<usize>,
+        }
+
+        impl<'c> Clone for Flaky<'c> {
+            fn clone(&self) -> Self {
+                assert!(self.id != self.panic_at, "clone of element {}", self.id);
+                self.clones.set(self.clones.get() + 1);
+                Flaky { ..*self }
+            }
+        }
+
+        impl<'c> Drop for Flaky<'c> {
+            fn drop(&mut self) {
+                self.drops.set(self.drops.get() + 1);
+            }
+        }
+
+        const LEN: usize = 6;
+        let size = LEN * ::core::mem::size_of::<Flaky>();
+        // A clone panicking at each element in turn.
+        for panic_at in 0..LEN {
+            let (clones, drops) = (Cell::new(0), Cell::new(0));
+            let src: Vec<_> = (0..LEN)
+                .map(|id| Flaky { id, panic_at, clones: &clones, drops: &drops })
+                .collect();
+            let a = checked_arena(1024, 64);
+            let _x = a.new_box(1u8).unwrap();
+            let result = panic::catch_unwind(panic::AssertUnwindSafe(|| a.alloc_slice_clone(&src)));
+            assert!(result.is_err());
+            assert_eq!((clones.get(), drops.get()), (panic_at, panic_at));
+            assert_eq!(a.used(), 1);
+            let copy = a.alloc_slice_clone(&src[..panic_at]).unwrap();
+            assert_eq!(copy.len(), panic_at);
+        }
+        // Too little memory, at each size short of what the clones need.
+        let (clones, drops) = (Cell::new(0), Cell::new(0));
+        let src: Vec<_> = (0..LEN)
+            .map(|id| Flaky { id, panic_at: LEN, clones: &clones, drops: &drops })
+            .collect();
+        let mut a = checked_arena(1024, 64);
+        for quota in 0..size {
+            a.set_quota(quota);
+            assert!(a.alloc_slice_clone(&src).is_err());
+            assert_eq!((a.used(), clones.get(), drops.get()), (0, 0, 0));
+        }
+        a.set_quota(size);
+        let copy = a.alloc_slice_clone(&src).unwrap();
+        assert_eq!((a.used(), clones.get()), (size, LEN));
+        drop(copy);
+        assert_eq!(drops.get(), LEN);
+    }
+
+    #[test]
+    fn arena_alloc_str() {
+        let a = checked_arena(16, 16);
+        let s = a.alloc_str("h\u{e9}llo \u{1f980}").unwrap();
+        assert_eq!(&*s, "h\u{e9}llo \u{1f980}");
+        assert_eq!((s.len(), s.chars().count()), (11, 7));
+        assert_eq!(format!("{}", s), "h\u{e9}llo \u{1f980}");
+        let empty = a.alloc_str("").unwrap();
+        assert_eq!(&*empty, "");
+        assert_eq!(a.used(), 11);
+        assert_eq!(a.alloc_str("\u{4e2d}\u{6587}").err(), Some(::alloc::AllocError::Exhausted));
+        assert_eq!(a.used(), 11);
+        let rest = a.alloc_str("\u{e9}\u{e9}x").unwrap();
+        assert_eq!(a.remaining(), 0);
+        fn hash_of<H: ::core::hash::Hash + ?Sized>(x: &H) -> u64 {
+            use core::hash::Hasher;
+            let mut h = ::std::collections::hash_map::DefaultHasher::new();
+            x.hash(&mut h);
+            h.finish()
+        }
+        assert_eq!(hash_of(&rest), hash_of("\u{e9}\u{e9}x"));
+        assert_ne!(hash_of(&s), hash_of("\u{e9}\u{e9}x"));
+    }
+    #[test]
+    fn arena_alloc_ref() {
+        let drops = Cell::new(0);
+        {
+            let a = checked_arena(32, 32);
+            let x = a.alloc_ref(5u64).unwrap();
+            let y = a.alloc_ref(::core::mem::ManuallyDrop::new(DropCounter(&drops))).ok().unwrap();
+            *x += 1;
+            let z = a.alloc_ref((&*x, 3u8)).unwrap();
+            assert_eq!((*z.0, z.1), (6, 3));
+            assert_eq!(y.0 as *const Cell<usize>, &drops as *const Cell<usize>);
+            assert_eq!(a.used(), 8 + 8 + 16);
+            assert_eq!(a.alloc_ref(1u8), Err(1));
+            assert_eq!(a.alloc_ref(()), Ok(&mut ()));
+        }
+        assert_eq!(drops.get(), 0);
+    }
+    #[cfg(feature = "live-count")]
+    #[test]
+    fn arena_alloc_ref_live_count() {
+        let a = checked_arena(64, 64);
+        let x: *mut u64 = a.alloc_ref(5u64).unwrap();
+        assert_eq!(a.live_count(), 1);
+        assert_eq!(a.try_reset(), Err(ResetError { live: 1 }));
+        let y: *mut u64 = a.alloc_ref(6u64).unwrap();
+        assert_ne!(x, y);
+        assert_eq!(unsafe { (*x, *y) }, (5, 6));
+        assert_eq!(a.try_reset(), Err(ResetError { live: 2 }));
+    }
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "alloc_ref never drops its values")]
+    fn arena_alloc_ref_needs_drop() {
+        let a = checked_arena(1024, 1024);
+        let _ = a.alloc_ref(String::from("leaked"));
+    }
+    #[test]
+    fn arena_new_default() {
+        struct Frame {
+            pixels: [u32; 4096],
+            id: u32,
+        }
+        thread_local!(static DEFAULTS: Cell<usize> = const { Cell::new(0) });
+        impl Default for Frame {
+            fn default() -> Self {
+                DEFAULTS.with(|n| n.set(n.get() + 1));
+                Frame { pixels: [0xff00_00ff; 4096], id: 7 }
+            }
+        }
+        let count = || DEFAULTS.with(Cell::get);
+        let a = checked_arena(64 * 1024, 4096);
+        let f = a.new_default::<Frame>().unwrap();
+        assert!(f.pixels.iter().all(|&p| p == 0xff00_00ff));
+        assert_eq!((f.id, count()), (7, 1));
+        let s = a.new_default_slice::<Frame>(2).unwrap();
+        assert!(s.iter().all(|f| f.id == 7 && f.pixels[4095] == 0xff00_00ff));
+        assert_eq!(count(), 3);
+        // `default` is never called without room for the values.
+        assert_eq!(a.new_default::<Frame>().err(), Some(::alloc::AllocError::Exhausted));
+        assert_eq!(a.new_default_slice::<Frame>(2).err(), Some(::alloc::AllocError::Exhausted));
+        assert!(a.new_default_slice::<Frame>(usize::MAX).is_err());
+        assert_eq!((count(), a.used(), a.new_default_slice::<Frame>(0).unwrap().len()), (3, 3 * 16388, 0));
+    }
+    #[test]
+    fn arena_new_default_slice_small_stack() {
+        // A 1 MiB slice of 16 KiB elements, built on a thread whose stack
+        // could not hold the whole slice.
+        #[derive(Clone)]
+        struct Chunk([u8; 16 * 1024]);
+        impl Default for Chunk {
+            fn default() -> Self {
+                Chunk([0xa5; 16 * 1024])
+            }
+        }
+        let handle = ::std::thread::Builder::new()
+            .stack_size(256 * 1024)
+            .spawn(|| {
+                let a = checked_arena(1 << 20, 4096);
+                let s = a.new_default_slice::<Chunk>(64).unwrap();
+                s.iter().all(|c| c.0.iter().all(|&b| b == 0xa5))
+            })
+            .unwrap();
+        assert!(handle.join().unwrap());
+    }
+    #[test]
+    fn arena_new_default_slice_panics() {
+        thread_local!(static COUNTS: Cell<(usize, usize)> = const { Cell::new((0, 0)) });
+        struct Flaky;
+        impl Default for Flaky {
+            fn default() -> Self {
+                let (made, dropped) = COUNTS.with(Cell::get);
+                assert!(made != 3, "fourth element");
+                COUNTS.with(|c| c.set((made + 1, dropped)));
+                Flaky
+            }
+        }
+        impl Drop for Flaky {
+            fn drop(&mut self) {
+                COUNTS.with(|c| c.set((c.get().0, c.get().1 + 1)));
+            }
+        }
+        let a = checked_arena(1024, 1024);
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| a.new_default_slice::<Flaky>(8).map(drop)));
+        assert!(result.is_err());
+        assert_eq!(COUNTS.with(Cell::get), (3, 3));
+        assert_eq!(a.used(), 0);
+        a.check_invariants();
+    }
+    #[test]
+    fn arena_new_zeroed() {
+        #[repr(C)]
+        struct Plain {
+            a: u8,
+            b: u64,
+            c: [u16; 3],
+        }
+        let mut a = checked_arena(256, 64);
+        a.alloc_bytes(256).unwrap().iter_mut().for_each(|b| *b = 0xee);
+        a.reset();
+        let _ = a.new_box(1u8).unwrap();
+        let p = unsafe { a.new_zeroed::<Plain>().unwrap().assume_init() };
+        assert_eq!(&*p as *const Plain as usize % 8, 0);
+        assert_eq!((p.a, p.b, p.c), (0, 0, [0; 3]));
+        let bytes = unsafe {
+            ::core::slice::from_raw_parts(&*p as *const Plain as *const u8, ::core::mem::size_of::<Plain>())
+        };
+        assert!(bytes.iter().all(|&b| b == 0));
+        assert_eq!(a.used(), 8 + 24);
+
+        let s = unsafe { a.new_zeroed_slice::<u32>(5).unwrap().assume_init() };
+        assert_eq!((s.len(), s.as_ptr() as usize % 4), (5, 0));
+        assert_eq!(&*s, [0; 5]);
+        assert_eq!(a.used(), 32 + 20);
+        assert_eq!(a.new_zeroed_slice::<u64>(0).unwrap().len(), 0);
+        assert_eq!(a.used(), 52);
+        assert_eq!(a.new_zeroed_slice::<u64>(26).err(), Some(::alloc::AllocError::Exhausted));
+        assert!(a.new_zeroed_slice::<u64>(usize::MAX / 4).is_err());
+        let rest = unsafe { a.new_zeroed_slice::<u8>(204).unwrap().assume_init() };
+        assert!(rest.iter().all(|&b| b == 0));
+        assert_eq!(a.remaining(), 0);
+    }
+    #[test]
+    fn arena_alloc_cstr() {
+        let a = checked_arena(16, 16);
+        let s = a.alloc_cstr("h\u{e9}llo").unwrap();
+        assert_eq!(s.to_str(), Ok("h\u{e9}llo"));
+        assert_eq!(s.to_bytes_with_nul().len(), 7);
+        let empty = a.alloc_cstr("").unwrap();
+        assert_eq!((empty.to_str(), empty.to_bytes_with_nul()), (Ok(""), &b"\0"[..]));
+        assert_eq!(a.used(), 8);
+        let err = a.alloc_cstr("a\0b\0").unwrap_err();
+        assert_eq!(err, ::alloc::AllocError::InteriorNul { position: 1 });
+        assert_eq!(a.used(), 8);
+        assert_eq!(a.alloc_cstr("12345678").err(), Some(::alloc::AllocError::Exhausted));
+        let rest = ArenaBox::leak(a.alloc_cstr("1234567").unwrap());
+        assert_eq!(a.remaining(), 0);
+        // The pointer handed to C stays valid while the Arena is borrowed.
+        let p = rest.as_ptr();
+        assert_eq!(unsafe { CStr::from_ptr(p) }.to_str(), Ok("1234567"));
+    }
+
+    #[test]
+    fn arena_new_box_with() {
+        struct Node<'a> {
+            value: u32,
+            children: [Option<ArenaBox<'a, Node<'a>>>; 2],
+        }
+
+        let a = checked_arena(1024, 64);
+        let root = a
+            .new_box_with(|| Node {
+                value: 1,
+                children: [
+                    a.new_box(Node { value: 2, children: [None, None] }).ok(),
+                    a.new_box(Node { value: 3, children: [None, None] }).ok(),
+                ],
+            })
+            .ok()
+            .unwrap();
+        let child = root.children[1].as_ref().unwrap();
+        assert_eq!((root.value, child.value), (1, 3));
+        // The slot was reserved before the children were allocated.
+        assert!((&*root as *const Node as usize) < (&**child as *const Node as usize));
+
+        let called = Cell::new(false);
+        let full = checked_arena(8, 8);
+        let f = full.new_box_with(|| {
+            called.set(true);
+            [0u64; 2]
+        });
+        let f = match f {
+            Err(f) => f,
+            Ok(_) => panic!("allocated more than the arena holds"),
+        };
+        assert!(!called.get());
+        assert_eq!(full.used(), 0);
+        assert_eq!(f(), [0; 2]);
+    }
+
+    #[test]
+    fn arena_new_box_with_panic() {
+        let drops = Cell::new(0);
+        let a = checked_arena(1024, 64);
+        let _x = a.new_box(1u8).unwrap();
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            a.new_box_with(|| -> DropCounter { panic!("constructor failed") })
+        }));
+        assert!(result.is_err());
+        assert_eq!(a.used(), 1);
+
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            a.new_box_with(|| {
+                let _inner = a.new_box(DropCounter(&drops)).unwrap();
+                if drops.get() == 0 {
+                    panic!("constructor failed");
+                }
+                DropCounter(&drops)
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(drops.get(), 1);
+        // Cannot rewind past the allocation made by the closure.
+        assert!(a.used() > 1);
+        let y = a.new_box_with(|| DropCounter(&drops)).ok().unwrap();
+        drop(y);
+        assert_eq!(drops.get(), 2);
+    }
+
+    #[test]
+    fn arena_new_uninit() {
+        #[repr(align(64))]
+        struct Line([u8; 64]);
+
+        let drops = Cell::new(0);
+        let a = checked_arena(1024, 1024);
+        let _x = a.new_box(1u8).unwrap();
+        let mut line = a.new_uninit::<Line>().unwrap();
+        assert_eq!(line.as_ptr() as usize % 64, 0);
+        assert_eq!(a.used(), 128);
+        unsafe {
+            let p = line.as_mut_ptr() as *mut u8;
+            for i in 0..64 {
+                p.add(i).write(i as u8);
+            }
+        }
+        let line = unsafe { line.assume_init() };
+        assert!(line.0.iter().enumerate().all(|(i, &b)| b == i as u8));
+
+        let uninit = a.new_uninit::<DropCounter>().unwrap();
+        drop(uninit);
+        let counter = a.new_uninit().unwrap().write(DropCounter(&drops));
+        assert_eq!(drops.get(), 0);
+        drop(counter);
+        assert_eq!(drops.get(), 1);
+
+        assert_eq!(a.new_uninit::<[u8; 1024]>().err(), Some(::alloc::AllocError::Exhausted));
+        assert!(a.new_uninit::<()>().is_ok());
+    }
+
+    #[test]
+    fn arena_new_uninit_slice() {
+        let drops = Cell::new(0);
+        let a = checked_arena(1024, 1024);
+        let _x = a.new_box(1u8).unwrap();
+        let mut s = a.new_uninit_slice::<DropCounter>(8).unwrap();
+        assert_eq!(s.len(), 8);
+        assert_eq!(a.used(), 8 + 8 * ::core::mem::size_of::<DropCounter>());
+        for x in &mut s[..5] {
+            x.write(DropCounter(&drops));
+        }
+        // Dropping a partially initialized slice drops none of it.
+        drop(s);
+        assert_eq!(drops.get(), 0);
+
+        let mut s = a.new_uninit_slice::<DropCounter>(8).unwrap();
+        for x in s.iter_mut() {
+            x.write(DropCounter(&drops));
+        }
+        let s = unsafe { s.assume_init() };
+        assert_eq!((s.len(), drops.get()), (8, 0));
+        drop(s);
+        assert_eq!(drops.get(), 8);
+
+        let used = a.used();
+        assert!(a.new_uninit_slice::<u64>(0).unwrap().is_empty());
+        assert_eq!(a.used(), used);
+        assert_eq!(
+            a.new_uninit_slice::<u64>(usize::MAX / 4).err(),
+            Some(::alloc::AllocError::Exhausted)
+        );
+        assert_eq!(a.new_uninit_slice::<u8>(1024).err(), Some(::alloc::AllocError::Exhausted));
+        assert_eq!(a.used(), used);
+        assert_eq!(a.new_uninit_slice::<()>(usize::MAX).unwrap().len(), usize::MAX);
+    }
+
+    /// An iterator that claims `len` items but yields `actual`, and panics
+    /// instead of yielding item `panic_at`.
+    struct Liar<'c> {
+        len: usize,
+        actual: usize,
+        panic_at: Option<usize>,
+        next: usize,
+        drops: &'c Cell<usize>,
+    }
+
+    impl<'c> Iterator for Liar<'c> {
+        type Item = DropCounter<'c>;
+
+        fn next(&mut self) -> Option<DropCounter<'c>> {
+            if self.next == self.actual {
+                return None;
+            }
+            assert!(Some(self.next) != self.panic_at, "iterator failed");
+            self.next += 1;
+            Some(DropCounter(self.drops))
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            let left = self.len.saturating_sub(self.next);
+            (left, Some(left))
+        }
+    }
+
+    impl<'c> ExactSizeIterator for Liar<'c> {}
+
+    #[test]
+    fn arena_alloc_from_iter() {
+        let drops = Cell::new(0);
+        let a = checked_arena(1024, 1024);
+        let empty = a.alloc_from_iter(::core::iter::empty::<DropCounter>()).unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(a.used(), 0);
+        let s = a.alloc_from_iter((0..10u16).map(|i| i * 3)).unwrap();
+        assert_eq!(&*s, [0, 3, 6, 9, 12, 15, 18, 21, 24, 27]);
+        assert_eq!(a.used(), 20);
+
+        let counters = a.alloc_from_iter((0..4).map(|_| DropCounter(&drops))).unwrap();
+        assert_eq!((counters.len(), drops.get()), (4, 0));
+        drop(counters);
+        assert_eq!(drops.get(), 4);
+
+        assert_eq!(
+            a.alloc_from_iter((0..1024u32).map(u64::from)).err(),
+            Some(::alloc::AllocError::Exhausted)
+        );
+        assert_eq!(a.used(), 56);
+    }
+
+    #[test]
+    fn arena_alloc_from_iter_lies() {
+        let drops = Cell::new(0);
+        let a = checked_arena(1024, 1024);
+        let liar = |len, actual| Liar { len, actual, panic_at: None, next: 0, drops: &drops };
+        let short = a.alloc_from_iter(liar(8, 3)).unwrap();
+        assert_eq!(short.len(), 3);
+        // The room for the missing items is given back.
+        assert_eq!(a.used(), 3 * ::core::mem::size_of::<DropCounter>());
+        let mut long = liar(2, 5);
+        let s = a.alloc_from_iter(&mut long).unwrap();
+        assert_eq!(s.len(), 2);
+        assert_eq!(long.count(), 3);
+        assert_eq!(drops.get(), 3);
+        drop((short, s));
+        assert_eq!(drops.get(), 8);
+    }
+
+    #[test]
+    fn arena_alloc_from_iter_panic() {
+        let drops = Cell::new(0);
+        let a = checked_arena(1024, 1024);
+        let _x = a.new_box(1u8).unwrap();
+        let liar = Liar { len: 6, actual: 6, panic_at: Some(4), next: 0, drops: &drops };
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| a.alloc_from_iter(liar)));
+        assert!(result.is_err());
+        assert_eq!(drops.get(), 4);
+        assert_eq!(a.used(), 1);
+    }
+
+    #[test]
+    fn arena_alloc_from_iter_growing() {
+        let drops = Cell::new(0);
+        let a = checked_arena(4096, 4096);
+        assert!(a.alloc_from_iter_growing(None::<u64>).unwrap().is_empty());
+        assert_eq!(a.used(), 0);
+        let _x = a.new_box(1u8).unwrap();
+        // The slice starts after the padding for its alignment, and grows
+        // in place from there.
+        let s = a.alloc_from_iter_growing((0..100u64).filter(|i| i % 3 == 0)).unwrap();
+        assert_eq!(s.len(), 34);
+        assert!(s.iter().enumerate().all(|(i, &x)| x == 3 * i as u64));
+        assert_eq!(s.as_ptr() as usize % 8, 0);
+        assert_eq!(a.used(), 8 + 34 * 8);
+
+        let counters = a
+            .alloc_from_iter_growing((0..10).map(|_| DropCounter(&drops)))
+            .unwrap();
+        assert_eq!((counters.len(), drops.get()), (10, 0));
+        drop(counters);
+        assert_eq!(drops.get(), 10);
+
+        let units = a.alloc_from_iter_growing(::core::iter::repeat_n((), 1000)).unwrap();
+        assert_eq!(units.len(), 1000);
+        assert_eq!(a.used(), 8 + 34 * 8 + 10 * 8);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn arena_alloc_from_iter_growing_min_alignment() {
+        let mut a = checked_arena(1024, 1024);
+        a.set_min_alignment(64);
+        let _x = a.new_box(1u8).unwrap();
+        let s = a.alloc_from_iter_growing(b"growing".iter().cloned()).unwrap();
+        assert_eq!(&*s, b"growing");
+        assert_eq!(s.as_ptr() as usize % 64, 0);
+        assert_eq!(a.used(), 64 + 7);
+    }
+
+    #[test]
+    fn arena_alloc_from_iter_growing_interleaved() {
+        let a = checked_arena(4096, 4096);
+        let mut others = Vec::new();
+        let s = a
+            .alloc_from_iter_growing((0..20u32).inspect(|&i| {
+                others.push(a.new_box(i as u8).unwrap());
+            }))
+            .unwrap();
+        assert!(s.iter().enumerate().all(|(i, &x)| x == i as u32));
+        assert!(others.iter().enumerate().all(|(i, x)| **x == i as u8));
+        // The boxes, the padding in front of the first buffer, and every
+        // buffer the slice had to move to since a box followed it.
+        assert_eq!(a.used(), 20 + 3 + 4 * (4 + 8 + 16 + 32));
+    }
+
+    #[test]
+    fn arena_alloc_from_iter_growing_exhausted() {
+        let drops = Cell::new(0);
+        let a = checked_arena(64, 64);
+        let _x = a.new_box(1u8).unwrap();
+        let yielded = Cell::new(0);
+        let result = a.alloc_from_iter_growing((0..100).map(|_| {
+            yielded.set(yielded.get() + 1);
+            DropCounter(&drops)
+        }));
+        assert_eq!(result.err(), Some(::alloc::AllocError::Exhausted));
+        // Room was made for 7 counters, the last one of them on its own.
+        assert_eq!((yielded.get(), drops.get()), (8, 8));
+        assert_eq!(a.used(), 1);
+        assert_eq!(a.alloc_from_iter_growing(0..7u64).unwrap().len(), 7);
+        assert_eq!(a.remaining(), 0);
+    }
+
+    #[test]
+    fn arena_alloc_from_iter_growing_panic() {
+        let drops = Cell::new(0);
+        let a = checked_arena(1024, 1024);
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            a.alloc_from_iter_growing((0..10).map(|i| {
+                assert!(i != 6, "iterator failed");
+                DropCounter(&drops)
+            }))
+        }));
+        assert!(result.is_err());
+        assert_eq!(drops.get(), 6);
+        assert_eq!(a.used(), 0);
     }
 }
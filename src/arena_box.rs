@@ -40,8 +40,10 @@
 //!
 //! ```
 //! # use memory_arena::*;
+//! # #[cfg(feature = "alloc")] {
 //! let a = Arena::new(1024, 1024).unwrap();
 //! let x = a.new_box(5).unwrap();
+//! # }
 //! ```
 //!
 //! Creating a recursive data structure:
@@ -55,12 +57,14 @@
 //! }
 //!
 //! fn main() {
+//! #     #[cfg(feature = "alloc")] {
 //!     let a = Arena::new(1024, 1024).unwrap();
 //!     let list = a.new_box(List::Nil).unwrap();
 //!     let list = a.new_box(List::Cons(1, list)).unwrap();
 //!     let list = a.new_box(List::Cons(2, list)).unwrap();
 //!     let list = a.new_box(List::Cons(3, list)).unwrap();
 //!     println!("{:?}", list);
+//! #     }
 //! }
 //! ```
 //!
@@ -81,12 +85,17 @@
 //! for a `Cons`. By introducing an `ArenaBox`, which has a defined size, we
 //! know how big `Cons` needs to be.
 
+use core::any::Any;
 use core::borrow;
 use core::cmp::Ordering;
 use core::fmt;
 use core::hash::{self, Hash, Hasher};
 use core::ops::{Deref, DerefMut};
+#[cfg(feature = "live-count")]
+use core::cell::Cell;
+#[cfg(not(feature = "live-count"))]
 use core::marker::PhantomData;
+use core::pin::Pin;
 
 use unique::Unique;
 use Arena;
@@ -94,9 +103,68 @@ use Arena;
 /// A pointer type for a value that lives in an `Arena`.
 ///
 /// See the [module-level documentation](../arena_box/) for more.
+///
+/// An `ArenaBox<T>` is `Send` and `Sync` if `T` is, regardless of the kind
+/// of arena it came from, since it only ever touches its own value. With
+/// the `live-count` feature it is neither, because dropping a box then
+/// updates its `Arena`'s non-atomic count of live allocations.
 pub struct ArenaBox<'a, T: ?Sized> {
     value: Unique<T>,
-    phantom: PhantomData<&'a Arena>,
+    live: LiveGuard<'a>,
+}
+
+/// Holds one count of an `Arena`'s live allocations for as long as it
+/// exists, when the `live-count` feature is enabled; see
+/// `Arena::try_reset`.
+///
+/// Without the feature this is zero-sized, and only keeps the lifetime of
+/// the borrow of the arena; borrowing the arena type itself would make
+/// every box `!Send`.
+pub(crate) struct LiveGuard<'a> {
+    #[cfg(feature = "live-count")]
+    count: Option<&'a Cell<usize>>,
+    #[cfg(not(feature = "live-count"))]
+    phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> LiveGuard<'a> {
+    /// Returns a guard that does not hold a count of any arena.
+    #[inline]
+    pub(crate) fn untracked() -> Self {
+        LiveGuard {
+            #[cfg(feature = "live-count")]
+            count: None,
+            #[cfg(not(feature = "live-count"))]
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns a guard that holds a count of `arena`'s live allocations.
+    #[inline]
+    pub(crate) fn new(arena: &'a Arena) -> Self {
+        #[cfg(feature = "live-count")]
+        {
+            arena.live.set(arena.live.get() + 1);
+            LiveGuard {
+                count: Some(&arena.live),
+            }
+        }
+        #[cfg(not(feature = "live-count"))]
+        {
+            let _ = arena;
+            LiveGuard::untracked()
+        }
+    }
+}
+
+#[cfg(feature = "live-count")]
+impl<'a> Drop for LiveGuard<'a> {
+    #[inline]
+    fn drop(&mut self) {
+        if let Some(count) = self.count {
+            count.set(count.get() - 1);
+        }
+    }
 }
 
 impl<'a, T: ?Sized> ArenaBox<'a, T> {
@@ -109,6 +177,8 @@ impl<'a, T: ?Sized> ArenaBox<'a, T> {
     /// only valid pointer to pass to this function is the one taken
     /// from another `ArenaBox` via the [`ArenaBox::into_raw`] function.
     ///
+    /// # Safety
+    ///
     /// This function is unsafe because improper use may lead to
     /// memory problems. For example, a double-free may occur if the
     /// function is called twice on the same raw pointer.
@@ -119,25 +189,47 @@ impl<'a, T: ?Sized> ArenaBox<'a, T> {
     ///
     /// ```
     /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
     /// let a = Arena::new(1024, 1024).unwrap();
     /// let x = a.new_box(5).unwrap();
     /// let ptr = ArenaBox::into_raw(x);
     /// let x = unsafe { ArenaBox::from_raw(ptr) };
+    /// # }
     /// ```
     #[inline]
     pub unsafe fn from_raw(raw: *mut T) -> Self {
+        ArenaBox::from_raw_parts(raw, LiveGuard::untracked())
+    }
+
+    /// Like `from_raw`, but counts the box as a live allocation of `arena`.
+    #[inline]
+    pub(crate) unsafe fn from_raw_in(raw: *mut T, arena: &'a Arena) -> Self {
+        ArenaBox::from_raw_parts(raw, LiveGuard::new(arena))
+    }
+
+    #[inline]
+    pub(crate) unsafe fn from_raw_parts(raw: *mut T, live: LiveGuard<'a>) -> Self {
         ArenaBox {
             value: Unique::new_unchecked(raw),
-            phantom: PhantomData,
+            live,
         }
     }
 
+    /// Like `into_raw`, but also hands out the box's count of live
+    /// allocations, to pass on to another owner of the value.
+    pub(crate) fn into_raw_parts(b: ArenaBox<'a, T>) -> (*mut T, LiveGuard<'a>) {
+        let b = ::core::mem::ManuallyDrop::new(b);
+        (b.value.as_ptr(), unsafe { ::core::ptr::read(&b.live) })
+    }
+
     /// Consumes the `ArenaBox`, returning the wrapped raw pointer.
     ///
     /// After calling this function, the caller is responsible for the
     /// memory previously managed by the `ArenaBox`. In particular, the
     /// caller should properly destroy `T`, by calling
-    /// `std::ptr::drop_in_place` on the pointer.
+    /// `std::ptr::drop_in_place` on the pointer. With the `live-count`
+    /// feature, the box stays counted as a live allocation of its `Arena`
+    /// for good, even if the pointer is turned back into an `ArenaBox`.
     ///
     /// Note: this is an associated function, which means that you have
     /// to call it as `ArenaBox::into_raw(b)` instead of `b.into_raw()`. This
@@ -147,15 +239,266 @@ impl<'a, T: ?Sized> ArenaBox<'a, T> {
     ///
     /// ```
     /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
     /// let a = Arena::new(1024, 1024).unwrap();
     /// let x = a.new_box(5).unwrap();
     /// let ptr = ArenaBox::into_raw(x);
+    /// # }
     /// ```
     pub fn into_raw(b: ArenaBox<T>) -> *mut T {
         let p = b.value.as_ptr();
         ::core::mem::forget(b);
         p
     }
+
+    /// Consumes the `ArenaBox`, returning a mutable reference to the value
+    /// that lives as long as the Arena.
+    ///
+    /// The value's destructor never runs. Like with `into_raw`, the
+    /// `live-count` feature keeps counting the box as a live allocation of
+    /// its `Arena` for good.
+    ///
+    /// Note: this is an associated function, which means that you have
+    /// to call it as `ArenaBox::leak(b)` instead of `b.leak()`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let name: &mut str = ArenaBox::leak(a.alloc_str("main").unwrap());
+    /// name.make_ascii_uppercase();
+    /// let names: &mut [&str] = ArenaBox::leak(a.alloc_slice_copy(&[&*name]).unwrap());
+    /// assert_eq!(names, ["MAIN"]);
+    /// # }
+    /// ```
+    pub fn leak(b: ArenaBox<'a, T>) -> &'a mut T {
+        unsafe { &mut *ArenaBox::into_raw(b) }
+    }
+
+    /// Converts the box's pointer with `f`, for `arena_box_unsize!`.
+    ///
+    /// # Safety
+    ///
+    /// `f` must return its argument, unsized to `U`.
+    #[doc(hidden)]
+    #[inline]
+    pub unsafe fn unsize_with<U: ?Sized, F>(b: ArenaBox<'a, T>, f: F) -> ArenaBox<'a, U>
+    where
+        F: FnOnce(*mut T) -> *mut U,
+    {
+        let (p, live) = ArenaBox::into_raw_parts(b);
+        ArenaBox::from_raw_parts(f(p), live)
+    }
+
+    /// Converts an `ArenaBox<T>` into a `Pin<ArenaBox<T>>`.
+    ///
+    /// This conversion does not allocate, and happens in place. An `Arena`
+    /// never moves the values it has handed out, so the pointee of an arena
+    /// box stays put until it is dropped, just like with `Box::into_pin`.
+    /// A box of an `Unpin` value needs no such promise, and can be pinned
+    /// safely with `Pin::new`.
+    ///
+    /// # Safety
+    ///
+    /// `Pin` also promises that the memory of a pinned value is not reused
+    /// before its destructor has run. `Box::into_pin` keeps that promise
+    /// even if the box is leaked, since leaked heap memory stays allocated
+    /// for good, but the memory of a leaked arena box is handed out again
+    /// once the Arena is reset, rewound or dropped, or the scope the box
+    /// was made in ends. The caller must make sure that the pinned box is
+    /// dropped before any of that happens, rather than leaked with
+    /// `mem::forget`, a reference cycle or the like.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// // The box is dropped at the end of the function, before `a` is.
+    /// let x = unsafe { ArenaBox::into_pin(a.new_box(5).unwrap()) };
+    /// assert_eq!(*x, 5);
+    /// # }
+    /// ```
+    pub unsafe fn into_pin(b: ArenaBox<'a, T>) -> Pin<ArenaBox<'a, T>> {
+        unsafe { Pin::new_unchecked(b) }
+    }
+}
+
+impl<'a, T> ArenaBox<'a, T> {
+    /// Consumes the `ArenaBox`, moving the value out of the Arena.
+    ///
+    /// The memory the value took up is not reused until the Arena is
+    /// reset. With the `live-count` feature, the box stops counting as a
+    /// live allocation of its `Arena`.
+    ///
+    /// Note: this is an associated function, which means that you have
+    /// to call it as `ArenaBox::into_inner(b)` instead of `b.into_inner()`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let best = a.new_box(String::from("plan b")).unwrap();
+    /// let best: String = ArenaBox::into_inner(best);
+    /// drop(a);
+    /// assert_eq!(best, "plan b");
+    /// # }
+    /// ```
+    pub fn into_inner(b: ArenaBox<'a, T>) -> T {
+        let (p, _live) = ArenaBox::into_raw_parts(b);
+        unsafe { ::core::ptr::read(p) }
+    }
+}
+
+impl<'a, T, const N: usize> ArenaBox<'a, [T; N]> {
+    /// Converts an arena box of an array into an arena box of a slice.
+    ///
+    /// This is the unsizing coercion that `Box<[T; N]>` gets implicitly.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let s: ArenaBox<[u8]> = ArenaBox::into_slice(a.new_box([1, 2, 3]).unwrap());
+    /// assert_eq!(&*s, &[1, 2, 3]);
+    /// # }
+    /// ```
+    pub fn into_slice(b: ArenaBox<'a, [T; N]>) -> ArenaBox<'a, [T]> {
+        let (p, live) = ArenaBox::into_raw_parts(b);
+        let p = ::core::ptr::slice_from_raw_parts_mut(p as *mut T, N);
+        unsafe { ArenaBox::from_raw_parts(p, live) }
+    }
+}
+
+impl<'a, T> ArenaBox<'a, ::core::mem::MaybeUninit<T>> {
+    /// Converts to `ArenaBox<T>`, once the value has been initialized.
+    ///
+    /// # Safety
+    ///
+    /// The value must be fully initialized, as with
+    /// `MaybeUninit::assume_init`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let mut x = a.new_uninit::<u32>().unwrap();
+    /// unsafe { x.as_mut_ptr().write(7) };
+    /// let x = unsafe { x.assume_init() };
+    /// assert_eq!(*x, 7);
+    /// # }
+    /// ```
+    pub unsafe fn assume_init(self) -> ArenaBox<'a, T> {
+        let (p, live) = ArenaBox::into_raw_parts(self);
+        ArenaBox::from_raw_parts(p as *mut T, live)
+    }
+
+    /// Initializes the value to `x`, and converts to `ArenaBox<T>`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let x = a.new_uninit().unwrap().write(String::from("arena"));
+    /// assert_eq!(*x, "arena");
+    /// # }
+    /// ```
+    pub fn write(mut self, x: T) -> ArenaBox<'a, T> {
+        unsafe {
+            self.as_mut_ptr().write(x);
+            self.assume_init()
+        }
+    }
+}
+
+impl<'a, T> ArenaBox<'a, [::core::mem::MaybeUninit<T>]> {
+    /// Converts to `ArenaBox<[T]>`, once every element has been
+    /// initialized.
+    ///
+    /// # Safety
+    ///
+    /// Every element must be fully initialized, as with
+    /// `MaybeUninit::assume_init`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let mut s = a.new_uninit_slice::<u32>(3).unwrap();
+    /// for (i, x) in s.iter_mut().enumerate() {
+    ///     x.write(i as u32);
+    /// }
+    /// let s = unsafe { s.assume_init() };
+    /// assert_eq!(&*s, [0, 1, 2]);
+    /// # }
+    /// ```
+    pub unsafe fn assume_init(self) -> ArenaBox<'a, [T]> {
+        let (p, live) = ArenaBox::into_raw_parts(self);
+        ArenaBox::from_raw_parts(p as *mut [T], live)
+    }
+}
+
+impl<'a, T: ?Sized> Unpin for ArenaBox<'a, T> {}
+
+#[cfg(feature = "coerce_unsized")]
+impl<'a, T: ?Sized, U: ?Sized> ::core::ops::CoerceUnsized<ArenaBox<'a, U>> for ArenaBox<'a, T>
+where
+    T: ::core::marker::Unsize<U>,
+{
+}
+
+impl<'a, T, const N: usize> From<ArenaBox<'a, [T; N]>> for ArenaBox<'a, [T]> {
+    /// Converts a boxed array into a boxed slice, in place.
+    fn from(b: ArenaBox<'a, [T; N]>) -> Self {
+        let (p, live) = ArenaBox::into_raw_parts(b);
+        unsafe { ArenaBox::from_raw_parts(p as *mut [T], live) }
+    }
+}
+
+/// Converts an `ArenaBox<T>` into an `ArenaBox<U>`, where `U` is an
+/// unsized type that `T` coerces to, such as a trait object or a slice.
+///
+/// This is the stable spelling of what the `coerce_unsized` feature does
+/// implicitly. The value stays where it is, and is dropped as a `T` when
+/// the new box is.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate memory_arena;
+/// # use memory_arena::*;
+/// # fn main() {
+/// # #[cfg(feature = "alloc")] {
+/// use std::fmt::Display;
+///
+/// let a = Arena::new(1024, 1024).unwrap();
+/// let items: Vec<ArenaBox<dyn Display>> = vec![
+///     arena_box_unsize!(a.new_box(5).unwrap(), dyn Display),
+///     arena_box_unsize!(a.new_box("five").unwrap(), dyn Display),
+/// ];
+/// assert_eq!(format!("{} {}", items[0], items[1]), "5 five");
+/// # }
+/// # }
+/// ```
+#[macro_export]
+macro_rules! arena_box_unsize {
+    ($b:expr, $u:ty) => {{
+        let b = $b;
+        unsafe { $crate::ArenaBox::unsize_with(b, |p| -> *mut $u { p }) }
+    }};
 }
 
 impl<'a, T: ?Sized> Drop for ArenaBox<'a, T> {
@@ -169,10 +512,6 @@ impl<'a, T: ?Sized + PartialEq> PartialEq for ArenaBox<'a, T> {
     fn eq(&self, other: &ArenaBox<T>) -> bool {
         PartialEq::eq(&**self, &**other)
     }
-    #[inline]
-    fn ne(&self, other: &ArenaBox<T>) -> bool {
-        PartialEq::ne(&**self, &**other)
-    }
 }
 
 impl<'a, T: ?Sized + PartialOrd> PartialOrd for ArenaBox<'a, T> {
@@ -252,74 +591,119 @@ impl<'a, T: ?Sized + Hasher> Hasher for ArenaBox<'a, T> {
     }
 }
 
-//TODO: fix this
-// impl<'a> ArenaBox<'a, Any> {
-//     #[inline]
-//     /// Attempt to downcast the box to a concrete type.
-//     ///
-//     /// # Examples
-//     ///
-//     /// ```
-//     /// # use memory_arena::*;
-//     /// use core::any::Any;
-//     ///
-//     /// fn print_if_string(value: ArenaBox<Any>) {
-//     ///     if let Ok(string) = value.downcast::<String>() {
-//     ///         println!("String ({}): {}", string.len(), string);
-//     ///     }
-//     /// }
-//     ///
-//     /// fn main() {
-//     ///     let a = Arena::new(1024, 1024).unwrap();
-//     ///     let my_string = "Hello World".to_string();
-//     ///     print_if_string(a.new_box(my_string).unwrap());
-//     ///     print_if_string(a.new_box(0i8).unwrap());
-//     /// }
-//     /// ```
-//     pub fn downcast<T: Any>(self) -> Result<ArenaBox<'a, T>, ArenaBox<'a, Any>> {
-//         if self.is::<T>() {
-//             unsafe {
-//                 let raw: *mut Any = ArenaBox::into_raw(self);
-//                 Ok(ArenaBox::from_raw(raw as *mut T))
-//             }
-//         } else {
-//             Err(self)
-//         }
-//     }
-// }
-
-//TODO: fix this
-// impl<'a> ArenaBox<'a, Any + Send> {
-//     #[inline]
-//     /// Attempt to downcast the box to a concrete type.
-//     ///
-//     /// # Examples
-//     ///
-//     /// ```
-//     /// # use memory_arena::*;
-//     /// use core::any::Any;
-//     ///
-//     /// fn print_if_string<'a>(value: ArenaBox<'a, Any + Send>) {
-//     ///     if let Ok(string) = value.downcast::<String>() {
-//     ///         println!("String ({}): {}", string.len(), string);
-//     ///     }
-//     /// }
-//     ///
-//     /// fn main() {
-//     ///     let my_string = "Hello World".to_string();
-//     ///     let a = Arena::new(1024, 1024).unwrap();
-//     ///     print_if_string(a.new_box(my_string).unwrap());
-//     ///     print_if_string(a.new_box(0i8).unwrap());
-//     /// }
-//     /// ```
-//     pub fn downcast<T: Any>(self) -> Result<ArenaBox<'a, T>, ArenaBox<'a, Any + Send>> {
-//         let s: ArenaBox<'a, Any + 'static> = unsafe { transmute(self) };
-//         <ArenaBox<'a, Any>>::downcast(s).map_err(|s| unsafe {
-//             // reapply the Send marker
-//             ArenaBox::from_raw(ArenaBox::into_raw(s) as *mut (Any + Send))
-//         })
-//     }
-// }
+impl<'a> ArenaBox<'a, dyn Any> {
+    /// Attempt to downcast the box to a concrete type.
+    ///
+    /// If the value is not a `T`, the box is handed back unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate memory_arena;
+    /// # use memory_arena::*;
+    /// use std::any::Any;
+    ///
+    /// fn print_if_string(value: ArenaBox<dyn Any>) {
+    ///     if let Ok(string) = value.downcast::<String>() {
+    ///         println!("String ({}): {}", string.len(), string);
+    ///     }
+    /// }
+    ///
+    /// fn main() {
+    /// #     #[cfg(feature = "alloc")] {
+    ///     let a = Arena::new(1024, 1024).unwrap();
+    ///     let my_string = "Hello World".to_string();
+    ///     print_if_string(arena_box_unsize!(a.new_box(my_string).unwrap(), dyn Any));
+    ///     print_if_string(arena_box_unsize!(a.new_box(0i8).unwrap(), dyn Any));
+    /// #     }
+    /// }
+    /// ```
+    #[inline]
+    pub fn downcast<T: Any>(self) -> Result<ArenaBox<'a, T>, ArenaBox<'a, dyn Any>> {
+        if (*self).is::<T>() {
+            let (raw, live) = ArenaBox::into_raw_parts(self);
+            Ok(unsafe { ArenaBox::from_raw_parts(raw as *mut T, live) })
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl<'a> ArenaBox<'a, dyn Any + Send> {
+    /// Attempt to downcast the box to a concrete type.
+    ///
+    /// If the value is not a `T`, the box is handed back unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate memory_arena;
+    /// # use memory_arena::*;
+    /// use std::any::Any;
+    ///
+    /// fn print_if_string(value: ArenaBox<dyn Any + Send>) {
+    ///     if let Ok(string) = value.downcast::<String>() {
+    ///         println!("String ({}): {}", string.len(), string);
+    ///     }
+    /// }
+    ///
+    /// fn main() {
+    /// #     #[cfg(feature = "alloc")] {
+    ///     let a = Arena::new(1024, 1024).unwrap();
+    ///     let my_string = "Hello World".to_string();
+    ///     print_if_string(arena_box_unsize!(a.new_box(my_string).unwrap(), dyn Any + Send));
+    ///     print_if_string(arena_box_unsize!(a.new_box(0i8).unwrap(), dyn Any + Send));
+    /// #     }
+    /// }
+    /// ```
+    #[inline]
+    pub fn downcast<T: Any>(self) -> Result<ArenaBox<'a, T>, ArenaBox<'a, dyn Any + Send>> {
+        if (*self).is::<T>() {
+            let (raw, live) = ArenaBox::into_raw_parts(self);
+            Ok(unsafe { ArenaBox::from_raw_parts(raw as *mut T, live) })
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl<'a> ArenaBox<'a, dyn Any + Send + Sync> {
+    /// Attempt to downcast the box to a concrete type.
+    ///
+    /// If the value is not a `T`, the box is handed back unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate memory_arena;
+    /// # use memory_arena::*;
+    /// use std::any::Any;
+    ///
+    /// fn print_if_string(value: ArenaBox<dyn Any + Send + Sync>) {
+    ///     if let Ok(string) = value.downcast::<String>() {
+    ///         println!("String ({}): {}", string.len(), string);
+    ///     }
+    /// }
+    ///
+    /// fn main() {
+    /// #     #[cfg(feature = "alloc")] {
+    ///     let a = Arena::new(1024, 1024).unwrap();
+    ///     let my_string = "Hello World".to_string();
+    ///     print_if_string(arena_box_unsize!(a.new_box(my_string).unwrap(), dyn Any + Send + Sync));
+    ///     print_if_string(arena_box_unsize!(a.new_box(0i8).unwrap(), dyn Any + Send + Sync));
+    /// #     }
+    /// }
+    /// ```
+    #[inline]
+    pub fn downcast<T: Any>(self) -> Result<ArenaBox<'a, T>, ArenaBox<'a, dyn Any + Send + Sync>> {
+        if (*self).is::<T>() {
+            let (raw, live) = ArenaBox::into_raw_parts(self);
+            Ok(unsafe { ArenaBox::from_raw_parts(raw as *mut T, live) })
+        } else {
+            Err(self)
+        }
+    }
+}
 
 impl<'a, T: fmt::Display + ?Sized> fmt::Display for ArenaBox<'a, T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -379,25 +763,25 @@ impl<'a, I: DoubleEndedIterator + ?Sized> DoubleEndedIterator for ArenaBox<'a, I
 
 impl<'a, T: ?Sized> borrow::Borrow<T> for ArenaBox<'a, T> {
     fn borrow(&self) -> &T {
-        &**self
+        self
     }
 }
 
 impl<'a, T: ?Sized> borrow::BorrowMut<T> for ArenaBox<'a, T> {
     fn borrow_mut(&mut self) -> &mut T {
-        &mut **self
+        self
     }
 }
 
 impl<'a, T: ?Sized> AsRef<T> for ArenaBox<'a, T> {
     fn as_ref(&self) -> &T {
-        &**self
+        self
     }
 }
 
 impl<'a, T: ?Sized> AsMut<T> for ArenaBox<'a, T> {
     fn as_mut(&mut self) -> &mut T {
-        &mut **self
+        self
     }
 }
 
@@ -405,10 +789,15 @@ impl<'a, T: ?Sized> AsMut<T> for ArenaBox<'a, T> {
 mod test {
     #[allow(unused_imports)]
     use super::*;
+    use core::cell::Cell;
+    use std::rc::Rc;
+    use std::string::{String, ToString};
+    use std::vec::Vec;
+    use test_util::{checked_arena, DropCounter};
 
     #[test]
     fn ln112() {
-        let a = Arena::new(1024, 1024).unwrap();
+        let a = checked_arena(1024, 1024);
         let x = a.new_box(5).unwrap();
         let ptr = ArenaBox::into_raw(x);
         let _ = unsafe { ArenaBox::from_raw(ptr) };
@@ -416,8 +805,150 @@ mod test {
 
     #[test]
     fn ln140() {
-        let a = Arena::new(1024, 1024).unwrap();
+        let a = checked_arena(1024, 1024);
         let x = a.new_box(5).unwrap();
         let _ = ArenaBox::into_raw(x);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn into_inner() {
+        let drops = Cell::new(0);
+        let a = checked_arena(1024, 1024);
+        let counter = ArenaBox::into_inner(a.new_box(DropCounter(&drops)).unwrap());
+        assert_eq!(drops.get(), 0);
+        drop(a);
+        assert_eq!(drops.get(), 0);
+        drop(counter);
+        assert_eq!(drops.get(), 1);
+
+        struct Plan {
+            name: String,
+            cost: u32,
+        }
+        let a = checked_arena(1024, 1024);
+        let plans: Vec<_> = (0..4)
+            .map(|i| a.new_box(Plan { name: format!("plan {}", i), cost: 10 - i }).ok().unwrap())
+            .collect();
+        let best = plans.into_iter().min_by_key(|p| p.cost).map(ArenaBox::into_inner).unwrap();
+        drop(a);
+        assert_eq!((best.name.as_str(), best.cost), ("plan 3", 7));
+    }
+
+    #[test]
+    fn leak() {
+        let drops = Cell::new(0);
+        let a = checked_arena(1024, 1024);
+        let counter = ArenaBox::leak(a.new_box(DropCounter(&drops)).unwrap());
+        let nums: &mut [u32] = ArenaBox::leak(a.alloc_slice_copy(&[1, 2, 3]).unwrap());
+        let s: &mut str = ArenaBox::leak(a.alloc_str("leaked").unwrap());
+        // Later allocations do not disturb the leaked values.
+        for i in 0..100u64 {
+            let _ = a.new_box(i).unwrap();
+        }
+        nums[0] = 10;
+        s.make_ascii_uppercase();
+        assert_eq!((&*nums, &*s), (&[10, 2, 3][..], "LEAKED"));
+        assert_eq!(counter.0.get(), 0);
+        drop(a);
+        assert_eq!(drops.get(), 0);
+    }
+
+    // Shows a name, and counts its drops.
+    struct Named<'c> {
+        name: &'static str,
+        _drops: DropCounter<'c>,
+    }
+
+    impl<'c> ::core::fmt::Display for Named<'c> {
+        fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+            f.write_str(self.name)
+        }
+    }
+
+    #[test]
+    fn unsize_dyn() {
+        use core::fmt::Display;
+        let drops = Cell::new(0);
+        let a = checked_arena(1024, 1024);
+        let items: Vec<ArenaBox<dyn Display>> = vec![
+            arena_box_unsize!(a.new_box(42u8).unwrap(), dyn Display),
+            arena_box_unsize!(a.new_box(Named { name: "first", _drops: DropCounter(&drops) }).ok().unwrap(), dyn Display),
+            arena_box_unsize!(a.new_box(String::from("text")).unwrap(), dyn Display),
+            arena_box_unsize!(a.new_box(Named { name: "second", _drops: DropCounter(&drops) }).ok().unwrap(), dyn Display),
+        ];
+        let shown: Vec<String> = items.iter().map(|x| x.to_string()).collect();
+        assert_eq!(shown, ["42", "first", "text", "second"]);
+        assert_eq!(drops.get(), 0);
+        drop(items);
+        assert_eq!(drops.get(), 2);
+    }
+
+    #[test]
+    fn unsize_slice() {
+        let drops = Cell::new(0);
+        let a = checked_arena(1024, 1024);
+        let xs = a.new_array([DropCounter(&drops), DropCounter(&drops), DropCounter(&drops)]).ok().unwrap();
+        let p = &*xs as *const _ as *const u8;
+        let xs: ArenaBox<[DropCounter]> = xs.into();
+        assert_eq!((xs.len(), &*xs as *const _ as *const u8), (3, p));
+        let ys = arena_box_unsize!(a.new_array([1u16, 2]).unwrap(), [u16]);
+        assert_eq!(&*ys, &[1, 2]);
+        drop(xs);
+        assert_eq!(drops.get(), 3);
+    }
+
+    #[cfg(feature = "coerce_unsized")]
+    #[test]
+    fn coerce_unsized() {
+        use core::fmt::Display;
+        let drops = Cell::new(0);
+        let a = checked_arena(1024, 1024);
+        let items: Vec<ArenaBox<dyn Display>> = vec![
+            a.new_box(7i32).unwrap(),
+            a.new_box(Named { name: "named", _drops: DropCounter(&drops) }).ok().unwrap(),
+        ];
+        let shown: Vec<String> = items.iter().map(|x| x.to_string()).collect();
+        assert_eq!(shown, ["7", "named"]);
+        let nums: ArenaBox<[u32]> = a.new_array([1, 2, 3]).unwrap();
+        assert_eq!(nums.len(), 3);
+        drop(items);
+        assert_eq!(drops.get(), 1);
+    }
+
+    #[test]
+    fn downcast() {
+        let token = Rc::new(());
+        let a = checked_arena(1024, 1024);
+        let x = arena_box_unsize!(a.new_box(token.clone()).unwrap(), dyn Any);
+        let x = x.downcast::<String>().err().unwrap();
+        let x = x.downcast::<Rc<u32>>().err().unwrap();
+        assert_eq!(Rc::strong_count(&token), 2);
+        let x = x.downcast::<Rc<()>>().ok().unwrap();
+        assert!(Rc::ptr_eq(&x, &token));
+        drop(x);
+        assert_eq!(Rc::strong_count(&token), 1);
+
+        let s = arena_box_unsize!(a.new_box(String::from("state")).unwrap(), dyn Any);
+        let s = s.downcast::<&str>().err().unwrap();
+        assert_eq!(s.downcast_ref::<String>().map(|s| s.as_str()), Some("state"));
+        let s = s.downcast::<String>().unwrap();
+        assert_eq!(*s, "state");
+    }
+
+    #[test]
+    fn downcast_send_sync() {
+        let a = checked_arena(1024, 1024);
+        let x = arena_box_unsize!(a.new_box(Vec::from([1u8, 2])).unwrap(), dyn Any + Send);
+        let x = x.downcast::<[u8; 2]>().err().unwrap();
+        assert_eq!(*x.downcast::<Vec<u8>>().unwrap(), [1, 2]);
+        let y = arena_box_unsize!(a.new_box(Some(7i64)).unwrap(), dyn Any + Send + Sync);
+        let y = y.downcast::<i64>().err().unwrap();
+        assert!(y.is::<Option<i64>>());
+        assert_eq!(*y.downcast::<Option<i64>>().unwrap(), Some(7));
+        // Dropping a box that failed to downcast drops the original value.
+        let token = Rc::new(());
+        let z = arena_box_unsize!(a.new_box(token.clone()).unwrap(), dyn Any);
+        drop(z.downcast::<i64>().err().unwrap());
+        assert_eq!(Rc::strong_count(&token), 1);
+    }
+}
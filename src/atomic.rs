@@ -0,0 +1,19 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The atomics behind the cursors of the arenas that threads can share.
+//!
+//! These are the `core` atomics themselves, except in builds with
+//! `--cfg loom`, where they are loom's, so that the loom tests can explore
+//! every interleaving of the cursor updates. See the Readme for how to run
+//! them.
+
+#[cfg(not(loom))]
+pub(crate) use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(loom)]
+pub(crate) use loom::sync::atomic::{AtomicUsize, Ordering};
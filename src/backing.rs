@@ -0,0 +1,188 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Arenas whose buffer comes from a user-supplied source of memory.
+
+use core::ptr::NonNull;
+use rust_alloc::boxed::Box;
+
+use alloc::AllocError;
+use arena::Backing;
+use regions::Regions;
+use Arena;
+
+/// A source of memory for the buffer of an `Arena`, for
+/// [`Arena::new_in`].
+///
+/// This is how an Arena gets its memory from a platform's own page
+/// allocator, or from instrumented memory in tests. [`SystemMemory`] is
+/// what [`Arena::new`] uses.
+///
+/// [`Arena::new_in`]: struct.Arena.html#method.new_in
+/// [`Arena::new`]: struct.Arena.html#method.new
+/// [`SystemMemory`]: struct.SystemMemory.html
+///
+/// # Safety
+///
+/// A successful `allocate` must return memory of at least `size` bytes,
+/// starting at a multiple of `align`, that nothing else uses until it is
+/// passed to `deallocate` with the same size and alignment.
+pub unsafe trait BackingMemory {
+    /// Allocates `size` bytes aligned to `align`, which is a power of two.
+    ///
+    /// # Safety
+    ///
+    /// `size` is not zero.
+    unsafe fn allocate(&self, size: usize, align: usize) -> Result<NonNull<u8>, AllocError>;
+
+    /// Frees memory returned by `allocate`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` was returned by `allocate` on this value, with the same `size`
+    /// and `align`, and has not been freed yet.
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, size: usize, align: usize);
+}
+
+/// The memory [`Arena::new`] uses: the Rust global allocator, or with the
+/// `libc-malloc` feature, `posix_memalign` or `_aligned_malloc`.
+///
+/// [`Arena::new`]: struct.Arena.html#method.new
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemMemory;
+
+unsafe impl BackingMemory for SystemMemory {
+    unsafe fn allocate(&self, size: usize, align: usize) -> Result<NonNull<u8>, AllocError> {
+        let mem = ::alloc::aligned_alloc(size, align)?;
+        Ok(NonNull::new_unchecked(mem))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, size: usize, align: usize) {
+        ::alloc::free(ptr.as_ptr(), size, align)
+    }
+}
+
+impl Arena {
+    /// Creates an Arena of `size` bytes from `backend`, whose memory starts
+    /// at a multiple of `alignment`.
+    ///
+    /// The Arena keeps `backend` until it is dropped, then gives the memory
+    /// back to it, and only then drops it. An Arena with no capacity does
+    /// not allocate at all. If `alignment` is not a power of two,
+    /// `AllocError::InvalidAlignment` is returned, and errors from the
+    /// backend are returned as they are.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// let a = Arena::new_in(1024, 64, SystemMemory).unwrap();
+    /// assert!(a.new_box(1u64).is_ok());
+    /// ```
+    pub fn new_in<B: BackingMemory + 'static>(
+        size: usize,
+        alignment: usize,
+        backend: B,
+    ) -> Result<Self, AllocError> {
+        ::alloc::check_alignment(alignment)?;
+        if size == 0 {
+            let mem = ::core::ptr::without_provenance_mut(alignment);
+            return Ok(Arena::from_parts(mem, size, Backing::Empty, Regions::new()));
+        }
+        let mem = unsafe { backend.allocate(size, alignment)? };
+        let backend = Box::into_raw(Box::new(backend) as Box<dyn BackingMemory>);
+        let backing = Backing::Custom { backend, alignment };
+        Ok(Arena::from_parts(mem.as_ptr(), size, backing, Regions::new()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use core::cell::RefCell;
+    use std::rc::Rc;
+    use std::vec::Vec;
+    use test_util::live_allocations;
+
+    #[derive(Debug, PartialEq)]
+    enum Call {
+        Allocate(usize, usize),
+        Deallocate(usize, usize, usize),
+        Drop,
+    }
+
+    /// Hands out system memory, and records what it is asked to do.
+    struct Recording(Rc<RefCell<Vec<Call>>>);
+
+    unsafe impl BackingMemory for Recording {
+        unsafe fn allocate(&self, size: usize, align: usize) -> Result<NonNull<u8>, AllocError> {
+            self.0.borrow_mut().push(Call::Allocate(size, align));
+            SystemMemory.allocate(size, align)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, size: usize, align: usize) {
+            self.0.borrow_mut().push(Call::Deallocate(ptr.as_ptr() as usize, size, align));
+            SystemMemory.deallocate(ptr, size, align)
+        }
+    }
+
+    impl Drop for Recording {
+        fn drop(&mut self) {
+            self.0.borrow_mut().push(Call::Drop);
+        }
+    }
+
+    struct Failing;
+
+    unsafe impl BackingMemory for Failing {
+        unsafe fn allocate(&self, _: usize, _: usize) -> Result<NonNull<u8>, AllocError> {
+            Err(AllocError::OutOfMemory)
+        }
+
+        unsafe fn deallocate(&self, _: NonNull<u8>, _: usize, _: usize) {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn new_in_records() {
+        let live = live_allocations();
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let mem = {
+            let a = Arena::new_in(1000, 128, Recording(calls.clone())).unwrap();
+            let x = a.new_box(7u32).unwrap();
+            assert_eq!(*x, 7);
+            assert_eq!(a.mem as usize % 128, 0);
+            assert_eq!(*calls.borrow(), [Call::Allocate(1000, 128)]);
+            a.check_invariants();
+            a.mem as usize
+        };
+        // The memory goes back before the backend goes away.
+        assert_eq!(
+            *calls.borrow(),
+            [Call::Allocate(1000, 128), Call::Deallocate(mem, 1000, 128), Call::Drop]
+        );
+        assert_eq!(live_allocations(), live);
+
+        calls.borrow_mut().clear();
+        drop(Arena::new_in(0, 8, Recording(calls.clone())).unwrap());
+        assert_eq!(*calls.borrow(), [Call::Drop]);
+        calls.borrow_mut().clear();
+        let err = Arena::new_in(16, 3, Recording(calls.clone())).err();
+        assert_eq!(err, Some(AllocError::InvalidAlignment { alignment: 3 }));
+        assert_eq!(*calls.borrow(), [Call::Drop]);
+    }
+
+    #[test]
+    fn new_in_failing() {
+        assert_eq!(Arena::new_in(1024, 8, Failing).err(), Some(AllocError::OutOfMemory));
+        let a = Arena::new_in(64, 8, SystemMemory).unwrap();
+        assert!(a.new_box([0u8; 64]).is_ok());
+    }
+}
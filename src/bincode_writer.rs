@@ -0,0 +1,117 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A `bincode` writer that writes into an `Arena`, enabled by the `bincode`
+//! feature.
+
+use bincode::enc::write::Writer;
+use bincode::error::EncodeError;
+
+use reserve::Reservation;
+use {Arena, ArenaBox};
+
+/// A `bincode` writer that appends the encoded bytes to an `Arena`.
+///
+/// The writer reserves all of the Arena's remaining memory while it is
+/// alive, so nothing else can be allocated from the Arena until it is
+/// finished or dropped. [`BincodeWriter::finish`] returns the unused memory
+/// to the Arena and the output as an `ArenaBox<[u8]>`. Running out of
+/// memory is reported as `EncodeError::UnexpectedEnd`, as with bincode's
+/// own slice writer.
+///
+/// [`BincodeWriter::finish`]: #method.finish
+///
+/// # Examples
+///
+/// ```
+/// # extern crate memory_arena;
+/// # extern crate bincode;
+/// # use memory_arena::*;
+/// # fn main() {
+/// let a = Arena::new(1024, 1024).unwrap();
+/// let mut w = BincodeWriter::new(&a);
+/// bincode::encode_into_writer((1u8, 2u32), &mut w, bincode::config::standard()).unwrap();
+/// assert_eq!(&*w.finish(), &[1, 2]);
+/// # }
+/// ```
+pub struct BincodeWriter<'a> {
+    buf: Reservation<'a>,
+}
+
+impl<'a> BincodeWriter<'a> {
+    /// Creates a writer that writes into `arena`.
+    pub fn new(arena: &'a Arena) -> Self {
+        BincodeWriter {
+            buf: Reservation::new(arena),
+        }
+    }
+
+    /// Returns the bytes written so far.
+    pub fn finish(self) -> ArenaBox<'a, [u8]> {
+        self.buf.finish()
+    }
+}
+
+impl<'a> Writer for BincodeWriter<'a> {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), EncodeError> {
+        if self.buf.extend(bytes) {
+            Ok(())
+        } else {
+            Err(EncodeError::UnexpectedEnd)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use bincode::config;
+    use serde::{Deserialize, Serialize};
+    use test_util::checked_arena;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Message<'m> {
+        id: u32,
+        name: &'m str,
+        payload: [u16; 4],
+        reply: Option<bool>,
+    }
+
+    const MESSAGE: Message<'static> = Message {
+        id: 1234,
+        name: "arena",
+        payload: [1, 2, 300, 40000],
+        reply: Some(true),
+    };
+
+    #[test]
+    fn bincode_round_trip() {
+        let a = checked_arena(1024, 1024);
+        let mut w = BincodeWriter::new(&a);
+        bincode::serde::encode_into_writer(&MESSAGE, &mut w, config::standard()).unwrap();
+        let bytes = w.finish();
+        assert_eq!(a.used.get(), bytes.len());
+        let (decoded, len): (Message, _) =
+            bincode::serde::borrow_decode_from_slice(&bytes, config::standard()).unwrap();
+        assert_eq!(len, bytes.len());
+        assert_eq!(decoded, MESSAGE);
+        assert!(a.new_box(0u8).is_ok());
+    }
+
+    #[test]
+    fn bincode_exhausted() {
+        let a = checked_arena(8, 8);
+        let _ = a.new_box(0u8).unwrap();
+        let mut w = BincodeWriter::new(&a);
+        let result = bincode::serde::encode_into_writer(&MESSAGE, &mut w, config::standard());
+        assert!(matches!(result, Err(EncodeError::UnexpectedEnd)));
+        drop(w);
+        assert_eq!(a.used.get(), 1);
+    }
+}
@@ -0,0 +1,194 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use alloc::AllocError;
+#[cfg(feature = "registry")]
+use std::string::String;
+use Arena;
+
+/// Configures an `Arena` before its memory is allocated.
+///
+/// `ArenaBuilder::new(size, alignment).build()` is equivalent to
+/// `Arena::new(size, alignment)`; the other methods set options that
+/// `Arena::new` leaves at their defaults.
+///
+/// # Examples
+///
+/// ```
+/// # use memory_arena::*;
+/// let a = ArenaBuilder::new(4096, 64).min_alignment(64).build().unwrap();
+/// assert_eq!(a.min_alignment(), 64);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ArenaBuilder {
+    size: usize,
+    alignment: usize,
+    min_alignment: usize,
+    quota: Option<usize>,
+    prefault: bool,
+    #[cfg(feature = "poison")]
+    poison: bool,
+    #[cfg(feature = "registry")]
+    name: Option<String>,
+    #[cfg(feature = "registry")]
+    register: bool,
+}
+
+impl ArenaBuilder {
+    /// Starts configuring an Arena of `size` bytes whose buffer is aligned
+    /// to `alignment` bytes.
+    pub fn new(size: usize, alignment: usize) -> Self {
+        ArenaBuilder {
+            size,
+            alignment,
+            min_alignment: 1,
+            quota: None,
+            prefault: false,
+            #[cfg(feature = "poison")]
+            poison: cfg!(debug_assertions),
+            #[cfg(feature = "registry")]
+            name: None,
+            #[cfg(feature = "registry")]
+            register: true,
+        }
+    }
+
+    /// Sets the minimum alignment of every allocation. See
+    /// [`Arena::set_min_alignment`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `align` is not a power of two.
+    ///
+    /// [`Arena::set_min_alignment`]: struct.Arena.html#method.set_min_alignment
+    pub fn min_alignment(mut self, align: usize) -> Self {
+        assert!(align.is_power_of_two(), "minimum alignment {} is not a power of two", align);
+        self.min_alignment = align;
+        self
+    }
+
+    /// Sets a quota on the memory handed out by the Arena. See
+    /// [`Arena::set_quota`].
+    ///
+    /// [`Arena::set_quota`]: struct.Arena.html#method.set_quota
+    pub fn quota(mut self, bytes: usize) -> Self {
+        self.quota = Some(bytes);
+        self
+    }
+
+    /// Sets whether to touch every page of the Arena's memory right after
+    /// allocating it. See [`Arena::prefault`].
+    ///
+    /// [`Arena::prefault`]: struct.Arena.html#method.prefault
+    pub fn prefault(mut self, prefault: bool) -> Self {
+        self.prefault = prefault;
+        self
+    }
+
+    /// Sets whether the Arena fills its memory with poison bytes, which it
+    /// does by default in debug builds only. See [`Arena`].
+    ///
+    /// [`Arena`]: struct.Arena.html#poisoning
+    #[cfg(feature = "poison")]
+    pub fn poison(mut self, poison: bool) -> Self {
+        self.poison = poison;
+        self
+    }
+
+    /// Sets the name the Arena is listed under in
+    /// [`registry::snapshot`].
+    ///
+    /// [`registry::snapshot`]: registry/fn.snapshot.html
+    #[cfg(feature = "registry")]
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Sets whether the Arena is added to the registry, which it is by
+    /// default. See [`registry::snapshot`].
+    ///
+    /// [`registry::snapshot`]: registry/fn.snapshot.html
+    #[cfg(feature = "registry")]
+    pub fn register(mut self, register: bool) -> Self {
+        self.register = register;
+        self
+    }
+
+    /// Allocates the Arena's memory and returns the configured Arena.
+    pub fn build(self) -> Result<Arena, AllocError> {
+        let mut arena = Arena::new(self.size, self.alignment)?;
+        arena.set_min_alignment(self.min_alignment);
+        #[cfg(feature = "poison")]
+        {
+            arena.poison = self.poison;
+        }
+        if let Some(quota) = self.quota {
+            arena.set_quota(quota);
+        }
+        if self.prefault {
+            arena.prefault();
+        }
+        #[cfg(feature = "registry")]
+        {
+            if self.register {
+                arena.used.register(self.name, self.size);
+            } else {
+                arena.used.unregister();
+            }
+        }
+        Ok(arena)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn builder_defaults() {
+        let a = ArenaBuilder::new(1024, 1024).build().unwrap();
+        assert_eq!(a.min_alignment(), 1);
+        assert_eq!(a.quota(), None);
+        let x = a.new_box(1u8).unwrap();
+        let y = a.new_box(2u8).unwrap();
+        assert_eq!(&*y as *const u8 as usize - &*x as *const u8 as usize, 1);
+        a.check_invariants();
+    }
+
+    #[test]
+    fn builder_prefault() {
+        let a = ArenaBuilder::new(1 << 16, 4096).prefault(true).build().unwrap();
+        assert_eq!(*a.new_box(7u32).unwrap(), 7);
+        a.check_invariants();
+    }
+
+    #[test]
+    #[cfg(feature = "poison")]
+    fn builder_poison() {
+        let mut a = ArenaBuilder::new(64, 64).poison(true).build().unwrap();
+        let p = a.new_uninit::<[u8; 8]>().unwrap().as_ptr() as *const u8;
+        assert_eq!(unsafe { *p }, 0xA5);
+        a.reset();
+        assert_eq!(unsafe { *p }, 0x5A);
+        let mut a = ArenaBuilder::new(64, 64).poison(false).build().unwrap();
+        let x = a.new_box([0x11u8; 8]).unwrap();
+        let p = &*x as *const [u8; 8] as *const u8;
+        drop(x);
+        a.reset();
+        assert_eq!(unsafe { *p }, 0x11);
+        assert_eq!(unsafe { *(a.new_uninit::<u8>().unwrap().as_ptr()) }, 0x11);
+    }
+
+    #[test]
+    #[should_panic(expected = "minimum alignment 48 is not a power of two")]
+    fn builder_invalid_min_alignment() {
+        let _ = ArenaBuilder::new(1024, 1024).min_alignment(48);
+    }
+}
@@ -0,0 +1,209 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Deduplicating byte slices in an arena.
+
+use core::hash::BuildHasher;
+use core::ptr;
+use std::collections::hash_map::RandomState;
+use std::collections::HashSet;
+
+use alloc::AllocError;
+use {Arena, ArenaBox};
+
+/// A set of byte slices stored in an `Arena`, which stores each distinct
+/// slice once.
+///
+/// Interning a slice looks it up by a hash of its contents, and compares
+/// the bytes of any slice with the same hash, so two slices are only ever
+/// merged if they are equal. A slice seen before comes back as the very
+/// same arena memory, so interned slices can be compared by address. Like
+/// a [`SymbolTable`], the interner keeps its index on the heap, and only
+/// the bytes go into the Arena.
+///
+/// Interned slices borrow the Arena rather than the interner, so each one
+/// is leaked like with [`ArenaBox::leak`]: with the `live-count` feature it
+/// counts as a live allocation for good, and keeps [`Arena::try_reset`]
+/// from freeing it.
+///
+/// [`SymbolTable`]: struct.SymbolTable.html
+/// [`ArenaBox::leak`]: struct.ArenaBox.html#method.leak
+/// [`Arena::try_reset`]: struct.Arena.html#method.try_reset
+///
+/// # Examples
+///
+/// ```
+/// # use memory_arena::*;
+/// let a = Arena::new(1024, 1024).unwrap();
+/// let mut blobs = BytesInterner::new(&a);
+/// let icon = blobs.intern(&[0x89, b'P', b'N', b'G']).unwrap();
+/// let (again, hit) = blobs.intern_dedup(&[0x89, b'P', b'N', b'G']).unwrap();
+/// assert!(hit && std::ptr::eq(icon, again));
+/// assert_eq!((blobs.len(), a.used()), (1, 4));
+/// ```
+pub struct BytesInterner<'a, S = RandomState> {
+    arena: &'a Arena,
+    set: HashSet<&'a [u8], S>,
+}
+
+impl<'a> BytesInterner<'a> {
+    /// Creates an empty interner that stores its slices in `arena`.
+    pub fn new(arena: &'a Arena) -> Self {
+        BytesInterner::with_hasher(arena, RandomState::new())
+    }
+}
+
+impl<'a, S: BuildHasher> BytesInterner<'a, S> {
+    /// Creates an empty interner that stores its slices in `arena`, and
+    /// hashes them with `hasher`.
+    pub fn with_hasher(arena: &'a Arena, hasher: S) -> Self {
+        BytesInterner {
+            arena,
+            set: HashSet::with_hasher(hasher),
+        }
+    }
+
+    /// Returns the number of distinct slices interned.
+    pub fn len(&self) -> usize {
+        self.set.len()
+    }
+
+    /// Returns `true` if no slices have been interned.
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty()
+    }
+
+    /// Returns the arena copy of `bytes`, copying `bytes` into the Arena if
+    /// they have not been interned before.
+    ///
+    /// Fails if there is not enough available memory in the Arena for the
+    /// copy, in which case the interner is left as it was.
+    pub fn intern(&mut self, bytes: &[u8]) -> Result<&'a [u8], AllocError> {
+        self.intern_dedup(bytes).map(|(copy, _)| copy)
+    }
+
+    /// Like [`BytesInterner::intern`], but also returns whether `bytes`
+    /// had been interned before, so that nothing new was stored.
+    ///
+    /// [`BytesInterner::intern`]: #method.intern
+    pub fn intern_dedup(&mut self, bytes: &[u8]) -> Result<(&'a [u8], bool), AllocError> {
+        if let Some(&copy) = self.set.get(bytes) {
+            return Ok((copy, true));
+        }
+        let copy: &'a [u8] = if bytes.is_empty() {
+            &[]
+        } else {
+            unsafe {
+                let p = self.arena.try_aligned_alloc(bytes.len(), 1)?;
+                ptr::copy_nonoverlapping(bytes.as_ptr(), p, bytes.len());
+                let copy = ptr::slice_from_raw_parts_mut(p, bytes.len());
+                ArenaBox::leak(ArenaBox::from_raw_in(copy, self.arena))
+            }
+        };
+        self.set.insert(copy);
+        Ok((copy, false))
+    }
+
+    /// Returns the arena copy of `bytes` if they have been interned.
+    pub fn get(&self, bytes: &[u8]) -> Option<&'a [u8]> {
+        self.set.get(bytes).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use core::hash::Hasher;
+    use std::vec::Vec;
+    use test_util::checked_arena;
+
+    /// Hashes every slice to the same value, so that all lookups collide.
+    #[derive(Default)]
+    struct Colliding;
+
+    impl Hasher for Colliding {
+        fn finish(&self) -> u64 {
+            42
+        }
+
+        fn write(&mut self, _: &[u8]) {}
+    }
+
+    impl BuildHasher for Colliding {
+        type Hasher = Colliding;
+
+        fn build_hasher(&self) -> Colliding {
+            Colliding
+        }
+    }
+
+    #[test]
+    fn bytes_interner_dedup() {
+        let a = checked_arena(1024, 1024);
+        let mut blobs = BytesInterner::new(&a);
+        let (mesh, hit) = blobs.intern_dedup(&[1, 2, 3, 4]).unwrap();
+        assert!(!hit);
+        let copy = [1, 2, 3, 4].to_vec();
+        let (again, hit) = blobs.intern_dedup(&copy).unwrap();
+        assert!(hit && ptr::eq(mesh, again));
+        assert_ne!(mesh.as_ptr(), copy.as_ptr());
+        let empty = blobs.intern(&[]).unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(blobs.intern_dedup(&[]).unwrap(), (empty, true));
+        assert_eq!(blobs.get(&[1, 2, 3, 4]).map(<[u8]>::as_ptr), Some(mesh.as_ptr()));
+        assert_eq!(blobs.get(&[1, 2, 3]), None);
+        assert_eq!((blobs.len(), a.used()), (2, 4));
+    }
+
+    #[test]
+    fn bytes_interner_collisions() {
+        let a = checked_arena(1024, 1024);
+        let mut blobs = BytesInterner::with_hasher(&a, Colliding);
+        // Blobs of the same length that all hash alike stay apart.
+        let inputs: Vec<[u8; 8]> = (0..16u8).map(|i| [i; 8]).collect();
+        let first: Vec<_> = inputs.iter().map(|b| blobs.intern(b).unwrap()).collect();
+        assert_eq!((blobs.len(), a.used()), (16, 16 * 8));
+        for (input, copy) in inputs.iter().zip(&first) {
+            assert_eq!(input, copy);
+            let (again, hit) = blobs.intern_dedup(input).unwrap();
+            assert!(hit && ptr::eq(again, *copy));
+        }
+        assert_eq!(a.used(), 16 * 8);
+    }
+
+    #[test]
+    fn bytes_interner_exhausted() {
+        let a = checked_arena(16, 16);
+        let mut blobs = BytesInterner::new(&a);
+        let x = blobs.intern(&[7; 10]).unwrap();
+        assert_eq!(blobs.intern_dedup(&[8; 7]), Err(AllocError::Exhausted));
+        assert_eq!((blobs.len(), blobs.get(&[8; 7])), (1, None));
+        // A full arena still finds what it already has.
+        let (y, hit) = blobs.intern_dedup(&[7; 10]).unwrap();
+        assert!(hit && ptr::eq(x, y));
+        assert!(!blobs.intern_dedup(&[9; 6]).unwrap().1);
+        assert_eq!((blobs.len(), a.remaining()), (2, 0));
+    }
+
+    #[cfg(feature = "live-count")]
+    #[test]
+    fn bytes_interner_try_reset() {
+        let a = checked_arena(1024, 1024);
+        let (x, y) = {
+            let mut blobs = BytesInterner::new(&a);
+            let x = blobs.intern(b"hello").unwrap();
+            assert!(blobs.intern_dedup(b"hello").unwrap().1);
+            (x, blobs.intern(&[]).unwrap())
+        };
+        // The slices outlive the interner, so the Arena must not reuse them.
+        assert_eq!(a.try_reset(), Err(::ResetError { live: 1 }));
+        let _ = a.alloc_slice_copy(b"XXXXX").unwrap();
+        assert_eq!((x, y), (&b"hello"[..], &[][..]));
+    }
+}
@@ -0,0 +1,204 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Child arenas, which have their own memory but are torn down along with
+//! their parent.
+
+use core::cell::Cell;
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem;
+use core::ops::Deref;
+use core::ptr;
+
+use alloc::AllocError;
+use arena_box::LiveGuard;
+use cleanup::Trigger;
+use Arena;
+
+/// The alignment of the buffer of a child arena.
+const CHILD_ALIGNMENT: usize = 16;
+
+struct ChildNode {
+    arena: Arena,
+    live: Cell<bool>,
+}
+
+/// An `Arena` that is registered with a parent `Arena`.
+///
+/// A child arena has its own independently allocated buffer, which is freed
+/// as soon as the `ChildArena` is dropped. If the child is still alive when
+/// the parent's allocations are released, for example because the
+/// `ChildArena` was leaked with `mem::forget`, the parent tears it down
+/// instead. Either way the child's buffer, and those of its own children,
+/// are freed exactly once.
+///
+/// `ChildArena` dereferences to `Arena`, so allocating from it works just
+/// like allocating from any other arena, and does not borrow the parent
+/// mutably.
+///
+/// # Examples
+///
+/// ```
+/// # use memory_arena::*;
+/// let session = Arena::new(1024, 1024).unwrap();
+/// let request = session.child(4096).unwrap();
+/// let parse = request.child(4096).unwrap();
+/// let token = parse.new_box(42).unwrap();
+/// assert_eq!(*token, 42);
+/// ```
+pub struct ChildArena<'p> {
+    node: *mut ChildNode,
+    // Counts the child as a live allocation of the parent, since resetting
+    // the parent tears the child down.
+    _live: LiveGuard<'p>,
+    phantom: PhantomData<&'p Arena>,
+}
+
+impl Arena {
+    /// Creates a child arena with a buffer of `size` bytes, aligned to 16
+    /// bytes. The child starts out with the same minimum alignment as this
+    /// Arena.
+    ///
+    /// The child does not take any memory from this Arena; see
+    /// [`ChildArena`] for how the two lifetimes interact.
+    ///
+    /// [`ChildArena`]: struct.ChildArena.html
+    pub fn child<'a>(&'a self, size: usize) -> Result<ChildArena<'a>, AllocError> {
+        let mut arena = Arena::new(size, CHILD_ALIGNMENT)?;
+        arena.set_min_alignment(self.min_alignment());
+        let node = unsafe {
+            ::alloc::aligned_alloc(mem::size_of::<ChildNode>(), mem::align_of::<ChildNode>())?
+        } as *mut ChildNode;
+        unsafe {
+            ptr::write(
+                node,
+                ChildNode {
+                    arena,
+                    live: Cell::new(true),
+                },
+            );
+        }
+        let teardown = move || unsafe {
+            if (*node).live.get() {
+                ptr::drop_in_place(node);
+            }
+            ::alloc::free(node as *mut u8, mem::size_of::<ChildNode>(), mem::align_of::<ChildNode>());
+        };
+        if let Err((_, e)) = self.cleanups.push(teardown, Trigger::Reset) {
+            unsafe {
+                ptr::drop_in_place(node);
+                ::alloc::free(node as *mut u8, mem::size_of::<ChildNode>(), mem::align_of::<ChildNode>());
+            }
+            return Err(e);
+        }
+        Ok(ChildArena {
+            node,
+            _live: LiveGuard::new(self),
+            phantom: PhantomData,
+        })
+    }
+}
+
+impl<'p> Deref for ChildArena<'p> {
+    type Target = Arena;
+
+    fn deref(&self) -> &Arena {
+        unsafe { &(*self.node).arena }
+    }
+}
+
+impl<'p> Drop for ChildArena<'p> {
+    fn drop(&mut self) {
+        // The node itself stays around until the parent runs the teardown
+        // callback, which only needs to free it from then on.
+        unsafe {
+            (*self.node).live.set(false);
+            ptr::drop_in_place(&mut (*self.node).arena);
+        }
+    }
+}
+
+impl<'p> fmt::Debug for ChildArena<'p> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ChildArena").field("node", &self.node).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use test_util::{checked_arena, live_allocations};
+
+    #[test]
+    fn child_allocations() {
+        let root = checked_arena(1024, 1024);
+        let child = root.child(256).unwrap();
+        let x = child.new_box(1u64).unwrap();
+        let y = root.new_box(2u64).unwrap();
+        let z = child.new_box(3u64).unwrap();
+        assert_eq!((*x, *y, *z), (1, 2, 3));
+        assert_eq!(root.used.get(), 8);
+        assert_eq!(child.used.get(), 16);
+        child.check_invariants();
+    }
+
+    #[test]
+    fn child_drop_early() {
+        let before = live_allocations();
+        let root = checked_arena(1024, 1024);
+        let child = root.child(256).unwrap();
+        let grandchild = child.child(256).unwrap();
+        let _ = grandchild.new_box(1u8).unwrap();
+        // The root, the child and grandchild buffers, their two nodes and
+        // their two teardown callbacks.
+        assert_eq!(live_allocations() - before, 7);
+        drop(grandchild);
+        assert_eq!(live_allocations() - before, 6);
+        drop(child);
+        // Dropping the child also frees the grandchild's node and callback,
+        // since they were registered with the child.
+        assert_eq!(live_allocations() - before, 3);
+        drop(root);
+        assert_eq!(live_allocations(), before);
+    }
+
+    #[test]
+    fn child_drop_middle_with_live_grandchild() {
+        let before = live_allocations();
+        let root = checked_arena(1024, 1024);
+        let child = root.child(256).unwrap();
+        let grandchild = child.child(256).unwrap();
+        let _ = grandchild.new_box(1u8).unwrap();
+        mem::forget(grandchild);
+        drop(child);
+        assert_eq!(live_allocations() - before, 3);
+        drop(root);
+        assert_eq!(live_allocations(), before);
+    }
+
+    #[test]
+    fn child_drop_root_with_live_grandchildren() {
+        let before = live_allocations();
+        let root = checked_arena(1024, 1024);
+        {
+            let child = root.child(256).unwrap();
+            for _ in 0..3 {
+                let grandchild = child.child(64).unwrap();
+                let _ = grandchild.new_box(1u8).unwrap();
+                mem::forget(grandchild);
+            }
+            mem::forget(child);
+        }
+        let sibling = root.child(256).unwrap();
+        drop(sibling);
+        drop(root);
+        assert_eq!(live_allocations(), before);
+    }
+}
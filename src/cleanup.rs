@@ -0,0 +1,177 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Cleanup callbacks registered with an `Arena`.
+//!
+//! Each callback lives in its own small node from the system allocator,
+//! rather than in the arena's memory, so that registering one never eats
+//! into the arena's capacity and the nodes survive anything that rewinds the
+//! arena. The nodes form an intrusive stack, which gives reverse
+//! registration order for free.
+
+use core::cell::Cell;
+#[cfg(feature = "alloc")]
+use core::mem;
+use core::ptr;
+
+#[cfg(feature = "alloc")]
+use alloc::AllocError;
+
+/// When a cleanup callback runs.
+#[cfg_attr(not(feature = "alloc"), allow(dead_code))]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Trigger {
+    /// Whenever the arena's allocations are released, by a reset or a drop.
+    Reset,
+    /// Only when the arena is dropped.
+    Drop,
+}
+
+/// The type-erased header of a cleanup node.
+pub(crate) struct Cleanup {
+    next: *mut Cleanup,
+    trigger: Trigger,
+    run: unsafe fn(*mut Cleanup),
+}
+
+#[cfg_attr(not(feature = "alloc"), allow(dead_code))]
+#[repr(C)]
+struct CleanupNode<F> {
+    header: Cleanup,
+    f: F,
+}
+
+/// Returns the size and alignment of the node for a callback of type `F`.
+#[cfg(feature = "alloc")]
+fn node_layout<F>() -> (usize, usize) {
+    let size = mem::size_of::<CleanupNode<F>>();
+    (size, mem::align_of::<CleanupNode<F>>().max(mem::size_of::<*const u8>()))
+}
+
+#[cfg(feature = "alloc")]
+/// Moves the callback out of its node, frees the node, and calls the
+/// callback.
+unsafe fn run<F: FnOnce()>(p: *mut Cleanup) {
+    let node = p as *mut CleanupNode<F>;
+    let f = ptr::read(&(*node).f);
+    let (size, align) = node_layout::<F>();
+    ::alloc::free(node as *mut u8, size, align);
+    f();
+}
+
+/// A stack of cleanup callbacks.
+pub(crate) struct Cleanups {
+    head: Cell<*mut Cleanup>,
+}
+
+impl Cleanups {
+    pub(crate) fn new() -> Self {
+        Cleanups {
+            head: Cell::new(ptr::null_mut()),
+        }
+    }
+
+    /// Pushes `f` onto the stack, handing it back along with the error if
+    /// no node could be allocated for it.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn push<F: FnOnce() + 'static>(
+        &self,
+        f: F,
+        trigger: Trigger,
+    ) -> Result<(), (F, AllocError)> {
+        let (size, align) = node_layout::<F>();
+        let node = match unsafe { ::alloc::aligned_alloc(size, align) } {
+            Ok(p) => p as *mut CleanupNode<F>,
+            Err(e) => return Err((f, e)),
+        };
+        unsafe {
+            ptr::write(
+                node,
+                CleanupNode {
+                    header: Cleanup {
+                        next: self.head.get(),
+                        trigger,
+                        run: run::<F>,
+                    },
+                    f,
+                },
+            );
+        }
+        self.head.set(node as *mut Cleanup);
+        Ok(())
+    }
+
+    /// Runs and removes every callback, most recently registered first.
+    ///
+    /// If a callback panics, the remaining ones still run while the panic
+    /// unwinds. A second panic during that unwinding aborts the process, as
+    /// usual.
+    pub(crate) fn run_all(&self) {
+        Pass::new(self, false).run();
+    }
+
+    /// Like `run_all`, but keeps the callbacks that only run on drop, in
+    /// their original order.
+    pub(crate) fn run_resets(&self) {
+        Pass::new(self, true).run();
+    }
+}
+
+/// A walk over the stack that runs the callbacks and sets aside the kept
+/// ones. Dropping it, normally or while a callback's panic unwinds, finishes
+/// the walk and puts the kept callbacks back.
+struct Pass<'c> {
+    cleanups: &'c Cleanups,
+    keep_drops: bool,
+    kept: *mut Cleanup,
+    kept_tail: *mut Cleanup,
+}
+
+impl<'c> Pass<'c> {
+    fn new(cleanups: &'c Cleanups, keep_drops: bool) -> Self {
+        Pass {
+            cleanups,
+            keep_drops,
+            kept: ptr::null_mut(),
+            kept_tail: ptr::null_mut(),
+        }
+    }
+
+    fn run(&mut self) {
+        loop {
+            let node = self.cleanups.head.get();
+            if node.is_null() {
+                return;
+            }
+            unsafe {
+                self.cleanups.head.set((*node).next);
+                if self.keep_drops && (*node).trigger == Trigger::Drop {
+                    (*node).next = ptr::null_mut();
+                    if self.kept_tail.is_null() {
+                        self.kept = node;
+                    } else {
+                        (*self.kept_tail).next = node;
+                    }
+                    self.kept_tail = node;
+                } else {
+                    ((*node).run)(node);
+                }
+            }
+        }
+    }
+}
+
+impl<'c> Drop for Pass<'c> {
+    fn drop(&mut self) {
+        self.run();
+        if !self.kept_tail.is_null() {
+            unsafe { (*self.kept_tail).next = self.cleanups.head.get() };
+            self.cleanups.head.set(self.kept);
+        }
+    }
+}
\ No newline at end of file
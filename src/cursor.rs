@@ -0,0 +1,194 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! `std::io` reading and seeking over bytes in an `Arena`, enabled by the
+//! `std` feature.
+
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+
+use ArenaBox;
+
+/// A cursor over an `ArenaBox<[u8]>`, implementing `Read`, `BufRead` and
+/// `Seek`.
+///
+/// This behaves like `std::io::Cursor`, but owns the arena box, so the
+/// bytes can be handed to APIs that want `impl Read + Seek` without copying
+/// them out of the Arena. As with `std::io::Cursor`, seeking past the end
+/// is allowed, and reads from there return no data.
+///
+/// # Examples
+///
+/// ```
+/// # use memory_arena::*;
+/// use std::io::{Read, Seek, SeekFrom};
+///
+/// let a = Arena::new(1024, 1024).unwrap();
+/// let bytes = a.new_box(*b"header:payload").unwrap();
+/// let mut c = ArenaCursor::new(ArenaBox::into_slice(bytes));
+/// c.seek(SeekFrom::Start(7)).unwrap();
+/// let mut payload = String::new();
+/// c.read_to_string(&mut payload).unwrap();
+/// assert_eq!(payload, "payload");
+/// ```
+#[derive(Debug)]
+pub struct ArenaCursor<'a> {
+    inner: ArenaBox<'a, [u8]>,
+    pos: u64,
+}
+
+impl<'a> ArenaCursor<'a> {
+    /// Creates a cursor at the start of `inner`.
+    pub fn new(inner: ArenaBox<'a, [u8]>) -> Self {
+        ArenaCursor { inner, pos: 0 }
+    }
+
+    /// Returns the current position.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Sets the position, which may be past the end of the bytes.
+    pub fn set_position(&mut self, pos: u64) {
+        self.pos = pos;
+    }
+
+    /// Returns a reference to the underlying bytes.
+    pub fn get_ref(&self) -> &ArenaBox<'a, [u8]> {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying bytes.
+    pub fn get_mut(&mut self) -> &mut ArenaBox<'a, [u8]> {
+        &mut self.inner
+    }
+
+    /// Returns the underlying bytes, discarding the position.
+    pub fn into_inner(self) -> ArenaBox<'a, [u8]> {
+        self.inner
+    }
+
+    fn remaining(&self) -> &[u8] {
+        let start = self.pos.min(self.inner.len() as u64) as usize;
+        &self.inner[start..]
+    }
+}
+
+impl<'a> Read for ArenaCursor<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.remaining().read(buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.remaining().read_exact(buf)?;
+        self.pos += buf.len() as u64;
+        Ok(())
+    }
+}
+
+impl<'a> BufRead for ArenaCursor<'a> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Ok(self.remaining())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt as u64;
+    }
+}
+
+impl<'a> Seek for ArenaCursor<'a> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(n) => {
+                self.pos = n;
+                return Ok(n);
+            }
+            SeekFrom::End(n) => (self.inner.len() as u64, n),
+            SeekFrom::Current(n) => (self.pos, n),
+        };
+        match base.checked_add_signed(offset) {
+            Some(n) => {
+                self.pos = n;
+                Ok(n)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use reserve::Reservation;
+    use std::string::String;
+    use std::vec::Vec;
+    use test_util::checked_arena;
+
+    #[test]
+    fn cursor_read_and_seek() {
+        let a = checked_arena(1024, 1024);
+        let mut w = Reservation::new(&a);
+        for i in 0..16u32 {
+            assert!(w.extend(&i.to_le_bytes()));
+        }
+        let mut c = ArenaCursor::new(w.finish());
+        let mut word = [0u8; 4];
+        c.read_exact(&mut word).unwrap();
+        assert_eq!(u32::from_le_bytes(word), 0);
+        assert_eq!(c.seek(SeekFrom::Current(8)).unwrap(), 12);
+        c.read_exact(&mut word).unwrap();
+        assert_eq!(u32::from_le_bytes(word), 3);
+        assert_eq!(c.seek(SeekFrom::End(-4)).unwrap(), 60);
+        c.read_exact(&mut word).unwrap();
+        assert_eq!(u32::from_le_bytes(word), 15);
+        assert_eq!(c.read(&mut word).unwrap(), 0);
+        assert_eq!(c.read_exact(&mut word).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(c.position(), 64);
+        c.set_position(20);
+        assert_eq!(c.stream_position().unwrap(), 20);
+        c.read_exact(&mut word).unwrap();
+        assert_eq!(u32::from_le_bytes(word), 5);
+        assert_eq!(c.into_inner().len(), 64);
+    }
+
+    #[test]
+    fn cursor_past_end() {
+        let a = checked_arena(1024, 1024);
+        let mut c = ArenaCursor::new(ArenaBox::into_slice(a.new_box(*b"abc").unwrap()));
+        assert_eq!(c.seek(SeekFrom::Start(10)).unwrap(), 10);
+        let mut buf = [0u8; 2];
+        assert_eq!(c.read(&mut buf).unwrap(), 0);
+        assert!(c.fill_buf().unwrap().is_empty());
+        assert_eq!(c.seek(SeekFrom::Current(-9)).unwrap(), 1);
+        assert_eq!(c.read(&mut buf).unwrap(), 2);
+        assert_eq!(&buf, b"bc");
+        let err = c.seek(SeekFrom::End(-4)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(c.position(), 3);
+        assert_eq!(&**c.get_ref(), b"abc");
+    }
+
+    #[test]
+    fn cursor_buf_read() {
+        let a = checked_arena(1024, 1024);
+        let mut c = ArenaCursor::new(ArenaBox::into_slice(a.new_box(*b"one\ntwo\nthree").unwrap()));
+        c.get_mut()[0] = b'O';
+        let lines: Vec<String> = c.by_ref().lines().map(Result::unwrap).collect();
+        assert_eq!(lines, ["One", "two", "three"]);
+        assert_eq!(c.position(), 13);
+    }
+}
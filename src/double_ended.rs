@@ -0,0 +1,488 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! An arena that allocates from both ends of its buffer.
+
+use core::cell::Cell;
+use core::fmt;
+use core::mem;
+use core::ptr;
+
+use alloc::AllocError;
+use ArenaBox;
+
+/// An arena with two bump cursors, one moving up from the start of the
+/// buffer and one moving down from the end.
+///
+/// This is the classic frame allocator: long-lived data is allocated from
+/// the front, per-frame scratch data from the back, and
+/// [`DoubleEndedArena::reset_back`] throws away the scratch data without
+/// touching the rest. An allocation fails when it would make the two ends
+/// overlap; they are allowed to meet exactly.
+///
+/// Boxes from either end borrow the arena, and resetting the back needs
+/// `&mut self`, so it can only happen once they are all gone. For front
+/// allocations to outlive the scratch data, [`DoubleEndedArena::split`]
+/// the arena into one handle for each end; only the back one is borrowed
+/// mutably by its reset.
+///
+/// [`DoubleEndedArena::reset_back`]: #method.reset_back
+/// [`DoubleEndedArena::split`]: #method.split
+///
+/// # Examples
+///
+/// ```
+/// # use memory_arena::*;
+/// let mut a = DoubleEndedArena::new(1024, 64).unwrap();
+/// let (front, mut back) = a.split();
+/// let level = front.new_box([7u32; 16]).unwrap();
+/// for frame in 0..3u64 {
+///     let scratch = back.new_box([frame; 32]).unwrap();
+///     assert_eq!(scratch[31], frame);
+///     drop(scratch);
+///     back.reset();
+/// }
+/// assert_eq!(level[15], 7);
+/// ```
+///
+/// The back cannot be reset while something still borrows it:
+///
+/// ```compile_fail,E0502
+/// # use memory_arena::*;
+/// let mut a = DoubleEndedArena::new(1024, 64).unwrap();
+/// let (_, mut back) = a.split();
+/// let scratch = back.new_box(1u64).unwrap();
+/// back.reset();
+/// assert_eq!(*scratch, 1);
+/// ```
+pub struct DoubleEndedArena {
+    mem: *mut u8,
+    size: usize,
+    alignment: usize,
+    // The end of the front allocations and the start of the back ones, as
+    // offsets from `mem`, with `front <= back` at all times.
+    front: Cell<usize>,
+    back: Cell<usize>,
+}
+
+impl DoubleEndedArena {
+    /// Creates an arena of `size` bytes, aligned to `alignment` bytes.
+    pub fn new(size: usize, alignment: usize) -> Result<Self, AllocError> {
+        let mem = unsafe { ::alloc::aligned_alloc(size, alignment)? };
+        Ok(DoubleEndedArena {
+            mem,
+            size,
+            alignment,
+            front: Cell::new(0),
+            back: Cell::new(size),
+        })
+    }
+
+    /// Allocates `size` bytes aligned to `alignment` from the front of the
+    /// arena.
+    ///
+    /// Zero-sized allocations always succeed with a dangling pointer that
+    /// is aligned to `alignment`, and leave the cursor where it is.
+    pub fn alloc_front(&self, size: usize, alignment: usize) -> Result<*mut u8, AllocError> {
+        ::alloc::check_alignment(alignment)?;
+        if size == 0 {
+            return Ok(ptr::without_provenance_mut(alignment));
+        }
+        let base = self.mem as usize;
+        let aligned = (base + self.front.get())
+            .checked_add(alignment - 1)
+            .ok_or(AllocError::Exhausted)?
+            & !(alignment - 1);
+        let end = (aligned - base).checked_add(size).ok_or(AllocError::Exhausted)?;
+        if end > self.back.get() {
+            return Err(AllocError::Exhausted);
+        }
+        self.front.set(end);
+        Ok(unsafe { self.mem.add(aligned - base) })
+    }
+
+    /// Allocates `size` bytes aligned to `alignment` from the back of the
+    /// arena, below everything allocated from the back so far.
+    ///
+    /// Zero-sized allocations always succeed with a dangling pointer that
+    /// is aligned to `alignment`, and leave the cursor where it is.
+    pub fn alloc_back(&self, size: usize, alignment: usize) -> Result<*mut u8, AllocError> {
+        ::alloc::check_alignment(alignment)?;
+        if size == 0 {
+            return Ok(ptr::without_provenance_mut(alignment));
+        }
+        let base = self.mem as usize;
+        let start = (base + self.back.get()).checked_sub(size).ok_or(AllocError::Exhausted)?
+            & !(alignment - 1);
+        if start < base + self.front.get() {
+            return Err(AllocError::Exhausted);
+        }
+        self.back.set(start - base);
+        Ok(unsafe { self.mem.add(start - base) })
+    }
+
+    /// Allocates memory from the front of the arena, places x into it,
+    /// and returns the resulting `ArenaBox`, wrapped in `Result::Ok`.
+    ///
+    /// If there is not enough available memory,
+    /// then the original value is returned, wrapped in `Result::Err`.
+    pub fn new_box_front<'a, T>(&'a self, x: T) -> Result<ArenaBox<'a, T>, T> {
+        match self.alloc_front(mem::size_of::<T>(), mem::align_of::<T>()) {
+            Err(_) => Err(x),
+            Ok(p) => unsafe {
+                let p = p as *mut T;
+                ptr::write(p, x);
+                Ok(ArenaBox::from_raw(p))
+            },
+        }
+    }
+
+    /// Allocates memory from the back of the arena, places x into it,
+    /// and returns the resulting `ArenaBox`, wrapped in `Result::Ok`.
+    ///
+    /// If there is not enough available memory,
+    /// then the original value is returned, wrapped in `Result::Err`.
+    pub fn new_box_back<'a, T>(&'a self, x: T) -> Result<ArenaBox<'a, T>, T> {
+        match self.alloc_back(mem::size_of::<T>(), mem::align_of::<T>()) {
+            Err(_) => Err(x),
+            Ok(p) => unsafe {
+                let p = p as *mut T;
+                ptr::write(p, x);
+                Ok(ArenaBox::from_raw(p))
+            },
+        }
+    }
+
+    /// Drops `b`, and returns its memory to the arena if it is the most
+    /// recent allocation from either end, like [`Arena::reclaim`].
+    ///
+    /// A box from the front that ends at the front cursor moves the cursor
+    /// back to where the box starts, and a box from the back that starts at
+    /// the back cursor moves the cursor up to where the box ends, and then
+    /// `true` is returned. Otherwise, dropping `b` behaves like a normal
+    /// drop, and `false` is returned. Padding is not given back, whether it
+    /// is below a front box or above a back one.
+    ///
+    /// [`Arena::reclaim`]: struct.Arena.html#method.reclaim
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// let a = DoubleEndedArena::new(1024, 64).unwrap();
+    /// let level = a.new_box_front([1u8; 16]).unwrap();
+    /// let scratch = a.new_box_back([2u8; 32]).unwrap();
+    /// assert!(a.reclaim(scratch));
+    /// assert!(a.reclaim(level));
+    /// assert_eq!(a.remaining(), 1024);
+    /// ```
+    pub fn reclaim<T: ?Sized>(&self, b: ArenaBox<'_, T>) -> bool {
+        let start = (&*b as *const T as *const u8 as usize).wrapping_sub(self.mem as usize);
+        let size = mem::size_of_val(&*b);
+        drop(b);
+        if size == 0 || start >= self.size {
+            false
+        } else if start + size == self.front.get() {
+            self.front.set(start);
+            true
+        } else if start == self.back.get() {
+            self.back.set(start + size);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Splits the arena into a handle for each end, so that the back can
+    /// be reset while boxes from the front are still alive.
+    pub fn split(&mut self) -> (DoubleEndedFront<'_>, DoubleEndedBack<'_>) {
+        (DoubleEndedFront { arena: self }, DoubleEndedBack { arena: self })
+    }
+
+    /// Frees everything allocated from the back of the arena, leaving the
+    /// front allocations as they are.
+    pub fn reset_back(&mut self) {
+        self.back.set(self.size);
+    }
+
+    /// Frees everything allocated from either end of the arena.
+    pub fn reset(&mut self) {
+        self.front.set(0);
+        self.back.set(self.size);
+    }
+
+    /// Returns the number of bytes allocated from the front, including
+    /// alignment padding.
+    pub fn used_front(&self) -> usize {
+        self.front.get()
+    }
+
+    /// Returns the number of bytes allocated from the back, including
+    /// alignment padding.
+    pub fn used_back(&self) -> usize {
+        self.size - self.back.get()
+    }
+
+    /// Returns the number of bytes left between the two ends.
+    pub fn remaining(&self) -> usize {
+        self.back.get() - self.front.get()
+    }
+
+    /// Returns the total size of the arena.
+    pub fn capacity(&self) -> usize {
+        self.size
+    }
+
+    /// Verifies the internal consistency of the arena, panicking with a
+    /// description of the first violated invariant, like
+    /// [`Arena::check_invariants`].
+    ///
+    /// This is meant for tests and fuzzing, and is only available with the
+    /// `check-invariants` feature.
+    ///
+    /// [`Arena::check_invariants`]: struct.Arena.html#method.check_invariants
+    #[cfg(any(test, feature = "check-invariants"))]
+    pub fn check_invariants(&self) {
+        let (front, back) = (self.front.get(), self.back.get());
+        assert!(!self.mem.is_null(), "arena invariant violated: base pointer is null");
+        assert!(
+            front <= back,
+            "arena invariant violated: front cursor ({}) is past back cursor ({})",
+            front,
+            back
+        );
+        assert!(
+            back <= self.size,
+            "arena invariant violated: back cursor ({}) exceeds size ({})",
+            back,
+            self.size
+        );
+    }
+}
+
+/// The front end of a split [`DoubleEndedArena`].
+///
+/// [`DoubleEndedArena`]: struct.DoubleEndedArena.html
+#[derive(Debug)]
+pub struct DoubleEndedFront<'a> {
+    arena: &'a DoubleEndedArena,
+}
+
+impl<'a> DoubleEndedFront<'a> {
+    /// Like [`DoubleEndedArena::alloc_front`].
+    ///
+    /// [`DoubleEndedArena::alloc_front`]: struct.DoubleEndedArena.html#method.alloc_front
+    pub fn alloc(&self, size: usize, alignment: usize) -> Result<*mut u8, AllocError> {
+        self.arena.alloc_front(size, alignment)
+    }
+
+    /// Like [`DoubleEndedArena::new_box_front`].
+    ///
+    /// [`DoubleEndedArena::new_box_front`]: struct.DoubleEndedArena.html#method.new_box_front
+    pub fn new_box<T>(&self, x: T) -> Result<ArenaBox<'_, T>, T> {
+        self.arena.new_box_front(x)
+    }
+
+    /// Like [`DoubleEndedArena::reclaim`].
+    ///
+    /// [`DoubleEndedArena::reclaim`]: struct.DoubleEndedArena.html#method.reclaim
+    pub fn reclaim<T: ?Sized>(&self, b: ArenaBox<'_, T>) -> bool {
+        self.arena.reclaim(b)
+    }
+}
+
+/// The back end of a split [`DoubleEndedArena`], which can be reset on its
+/// own.
+///
+/// [`DoubleEndedArena`]: struct.DoubleEndedArena.html
+#[derive(Debug)]
+pub struct DoubleEndedBack<'a> {
+    arena: &'a DoubleEndedArena,
+}
+
+impl<'a> DoubleEndedBack<'a> {
+    /// Like [`DoubleEndedArena::alloc_back`].
+    ///
+    /// [`DoubleEndedArena::alloc_back`]: struct.DoubleEndedArena.html#method.alloc_back
+    pub fn alloc(&self, size: usize, alignment: usize) -> Result<*mut u8, AllocError> {
+        self.arena.alloc_back(size, alignment)
+    }
+
+    /// Like [`DoubleEndedArena::new_box_back`].
+    ///
+    /// [`DoubleEndedArena::new_box_back`]: struct.DoubleEndedArena.html#method.new_box_back
+    pub fn new_box<T>(&self, x: T) -> Result<ArenaBox<'_, T>, T> {
+        self.arena.new_box_back(x)
+    }
+
+    /// Like [`DoubleEndedArena::reclaim`].
+    ///
+    /// [`DoubleEndedArena::reclaim`]: struct.DoubleEndedArena.html#method.reclaim
+    pub fn reclaim<T: ?Sized>(&self, b: ArenaBox<'_, T>) -> bool {
+        self.arena.reclaim(b)
+    }
+
+    /// Frees everything allocated from the back of the arena.
+    pub fn reset(&mut self) {
+        // Only this handle can allocate from the back while the arena is
+        // split, and `&mut self` means none of its boxes are left.
+        self.arena.back.set(self.arena.size);
+    }
+}
+
+impl Drop for DoubleEndedArena {
+    fn drop(&mut self) {
+        unsafe { ::alloc::free(self.mem, self.size, self.alignment) };
+    }
+}
+
+impl fmt::Debug for DoubleEndedArena {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DoubleEndedArena")
+            .field("capacity", &self.size)
+            .field("used_front", &self.used_front())
+            .field("used_back", &self.used_back())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use test_util::{checked, live_allocations, DropCounter};
+
+    #[test]
+    fn double_ended_reset_back() {
+        let before = live_allocations();
+        {
+            let mut a = checked(DoubleEndedArena::new(256, 64).unwrap());
+            {
+                let (front, mut back) = a.split();
+                let kept = [front.new_box(1u64).unwrap(), front.new_box(2u64).unwrap()];
+                for frame in 0..10u64 {
+                    let x = back.new_box([frame; 8]).unwrap();
+                    let y = back.new_box(frame as u8).unwrap();
+                    assert_eq!((x[7], *y), (frame, frame as u8));
+                    drop((x, y));
+                    back.reset();
+                    // Everything but the front is free again.
+                    assert!(back.new_box([0u8; 241]).is_err());
+                    drop(back.new_box([0u8; 240]).unwrap());
+                    back.reset();
+                }
+                assert_eq!((*kept[0], *kept[1]), (1, 2));
+            }
+            let p = a.alloc_front(8, 8).unwrap();
+            a.new_box_back(3u8).unwrap();
+            assert_eq!((a.used_front(), a.used_back()), (24, 1));
+            a.reset_back();
+            assert_eq!((a.used_front(), a.used_back()), (24, 0));
+            assert_eq!(p as usize, a.mem as usize + 16);
+            a.reset();
+            assert_eq!(a.remaining(), 256);
+        }
+        assert_eq!(live_allocations(), before);
+    }
+
+    #[test]
+    fn double_ended_alignment() {
+        let a = checked(DoubleEndedArena::new(256, 256).unwrap());
+        let base = a.mem as usize;
+        let b = a.alloc_back(3, 1).unwrap() as usize;
+        assert_eq!(b, base + 253);
+        // Going down, the padding for the alignment ends up above the
+        // allocation.
+        let b = a.alloc_back(8, 16).unwrap() as usize;
+        assert_eq!(b, base + 240);
+        let b = a.alloc_back(1, 64).unwrap() as usize;
+        assert_eq!(b, base + 192);
+        assert_eq!(a.used_back(), 64);
+        let x = a.new_box_back(0x1234u16).unwrap();
+        assert_eq!(&*x as *const u16 as usize, base + 190);
+        let f = a.alloc_front(1, 1).unwrap() as usize;
+        assert_eq!(f, base);
+        let f = a.alloc_front(4, 32).unwrap() as usize;
+        assert_eq!(f, base + 32);
+        let z = a.alloc_back(0, 128).unwrap() as usize;
+        assert_eq!(z % 128, 0);
+        assert_eq!(a.used_back(), 66);
+        assert_eq!(a.alloc_back(1, 3), Err(AllocError::InvalidAlignment { alignment: 3 }));
+    }
+
+    #[test]
+    fn double_ended_crossing() {
+        let mut a = checked(DoubleEndedArena::new(64, 64).unwrap());
+        let base = a.mem as usize;
+        a.alloc_front(20, 4).unwrap();
+        // 44 bytes are left, and the ends may meet exactly.
+        assert_eq!(a.alloc_back(45, 1), Err(AllocError::Exhausted));
+        assert_eq!(a.alloc_back(44, 1).unwrap() as usize, base + 20);
+        assert_eq!(a.remaining(), 0);
+        assert_eq!(a.alloc_front(1, 1), Err(AllocError::Exhausted));
+        assert_eq!(a.alloc_back(1, 1), Err(AllocError::Exhausted));
+        assert!(a.new_box_front(()).is_ok());
+        a.reset_back();
+        // The padding counts as well: at alignment 8, a 40-byte block from
+        // the back would start at 24, and one of 41 bytes at 16, which is
+        // below the front cursor.
+        assert_eq!(a.alloc_back(41, 8), Err(AllocError::Exhausted));
+        assert_eq!(a.alloc_back(40, 8).unwrap() as usize, base + 24);
+        assert_eq!(a.alloc_front(4, 1).unwrap() as usize, base + 20);
+        assert_eq!(a.alloc_front(1, 1), Err(AllocError::Exhausted));
+        assert_eq!(a.remaining(), 0);
+        assert!(a.new_box_back(0u8).is_err());
+        assert_eq!(a.alloc_back(usize::MAX, 1), Err(AllocError::Exhausted));
+        assert_eq!(a.alloc_front(usize::MAX, 1), Err(AllocError::Exhausted));
+    }
+
+    #[test]
+    fn double_ended_reclaim() {
+        let mut a = checked(DoubleEndedArena::new(256, 64).unwrap());
+        let drops = Cell::new(0);
+        {
+            let (front, back) = a.split();
+            let x = front.new_box((1u64, DropCounter(&drops))).unwrap();
+            let y = front.new_box(2u8).unwrap();
+            let z = back.new_box([3u8; 24]).unwrap();
+            let w = back.new_box(4u32).unwrap();
+            assert_eq!((front.arena.used_front(), front.arena.used_back()), (17, 28));
+            // Neither `x` nor `z` is at its cursor.
+            assert!(!front.reclaim(x));
+            assert!(!back.reclaim(z));
+            assert_eq!(drops.get(), 1);
+            assert_eq!((front.arena.used_front(), front.arena.used_back()), (17, 28));
+            assert!(back.reclaim(w));
+            assert_eq!(front.arena.used_back(), 24);
+            assert!(front.reclaim(y));
+            assert_eq!(front.arena.used_front(), 16);
+            let v = back.new_box((5u64, DropCounter(&drops))).unwrap();
+            assert_eq!(front.arena.used_back(), 40);
+            // A box from one end can be given back through the other.
+            assert!(front.reclaim(v));
+            assert_eq!((front.arena.used_back(), drops.get()), (24, 2));
+            assert!(!back.reclaim(back.new_box(()).unwrap()));
+            let other = checked(DoubleEndedArena::new(64, 64).unwrap());
+            assert!(!back.reclaim(other.new_box_back(6u8).unwrap()));
+            assert!(!front.reclaim(other.new_box_front(7u8).unwrap()));
+        }
+        assert_eq!((a.used_front(), a.used_back()), (16, 24));
+        a.reset();
+        let x = a.new_box_front([8u8; 256]).unwrap();
+        assert!(a.reclaim(x));
+        assert_eq!(a.remaining(), 256);
+    }
+
+    #[test]
+    #[should_panic(expected = "arena invariant violated: front cursor (65) is past back cursor (64)")]
+    fn double_ended_check_invariants() {
+        let a = DoubleEndedArena::new(64, 64).unwrap();
+        a.front.set(65);
+        a.check_invariants();
+    }
+}
@@ -0,0 +1,218 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Cloning trait objects into an arena.
+
+use core::alloc::Layout;
+use core::mem;
+use core::ptr;
+
+use alloc::AllocError;
+use {Arena, ArenaBox};
+
+/// A value that can be cloned into memory it is handed, so that trait
+/// objects can be cloned into an `Arena` with [`Arena::clone_dyn`].
+///
+/// This is implemented for every `Clone` type, and for slices of them and
+/// `str`. To make a trait object
+/// cloneable, add `ArenaDynClone` as a supertrait:
+///
+/// ```
+/// # use memory_arena::*;
+/// trait Shape: ArenaDynClone {
+///     fn area(&self) -> f64;
+/// }
+///
+/// #[derive(Clone)]
+/// struct Square(f64);
+///
+/// impl Shape for Square {
+///     fn area(&self) -> f64 {
+///         self.0 * self.0
+///     }
+/// }
+///
+/// let a = Arena::new(1024, 1024).unwrap();
+/// let s: &dyn Shape = &Square(2.0);
+/// let copy: ArenaBox<dyn Shape> = a.clone_dyn(s).unwrap();
+/// assert_eq!(copy.area(), 4.0);
+/// ```
+///
+/// [`Arena::clone_dyn`]: struct.Arena.html#method.clone_dyn
+pub trait ArenaDynClone {
+    /// Writes a clone of `self` to `dst`, which must be valid for writes of
+    /// `Layout::for_value(self)`.
+    #[doc(hidden)]
+    unsafe fn clone_to(&self, dst: *mut u8);
+}
+
+impl<T: Clone> ArenaDynClone for T {
+    unsafe fn clone_to(&self, dst: *mut u8) {
+        ptr::write(dst as *mut T, self.clone());
+    }
+}
+
+impl<T: Clone> ArenaDynClone for [T] {
+    unsafe fn clone_to(&self, dst: *mut u8) {
+        // Drops the elements cloned so far if a later clone panics.
+        struct Partial<T>(*mut T, usize);
+
+        impl<T> Drop for Partial<T> {
+            fn drop(&mut self) {
+                unsafe { ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.0, self.1)) }
+            }
+        }
+
+        let mut done = Partial(dst as *mut T, 0);
+        for x in self {
+            ptr::write(done.0.add(done.1), x.clone());
+            done.1 += 1;
+        }
+        mem::forget(done);
+    }
+}
+
+impl ArenaDynClone for str {
+    unsafe fn clone_to(&self, dst: *mut u8) {
+        ptr::copy_nonoverlapping(self.as_ptr(), dst, self.len());
+    }
+}
+
+impl Arena {
+    /// Clones `value`, which may be a trait object, into the Arena.
+    ///
+    /// The clone is written straight into the Arena's memory, without a
+    /// temporary heap allocation, and the returned box keeps the vtable of
+    /// `value`. If `clone` panics, the memory taken for the clone stays in
+    /// the Arena unused.
+    pub fn clone_dyn<'a, T: ?Sized + ArenaDynClone>(
+        &'a self,
+        value: &T,
+    ) -> Result<ArenaBox<'a, T>, AllocError> {
+        let layout = Layout::for_value(value);
+        let dst = if layout.size() == 0 {
+            layout.align() as *mut u8
+        } else {
+            self.try_aligned_alloc(layout.size(), layout.align())?
+        };
+        unsafe {
+            value.clone_to(dst);
+            // Keep the metadata of the pointer to `value`, and replace its
+            // address.
+            let mut p = value as *const T as *mut T;
+            *(&mut p as *mut *mut T as *mut *mut u8) = dst;
+            Ok(ArenaBox::from_raw_in(p, self))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use core::cell::Cell;
+    use std::string::{String, ToString};
+    use std::vec::Vec;
+    use std::panic;
+    use test_util::checked_arena;
+
+    trait Shape: ArenaDynClone {
+        fn describe(&self) -> String;
+    }
+
+    #[derive(Clone)]
+    struct Circle(u32);
+
+    #[derive(Clone)]
+    struct Label(String, [u64; 3]);
+
+    #[derive(Clone)]
+    struct Nothing;
+
+    impl Shape for Circle {
+        fn describe(&self) -> String {
+            std::format!("circle {}", self.0)
+        }
+    }
+
+    impl Shape for Label {
+        fn describe(&self) -> String {
+            std::format!("label {} {}", self.0, self.1.len())
+        }
+    }
+
+    impl Shape for Nothing {
+        fn describe(&self) -> String {
+            "nothing".to_string()
+        }
+    }
+
+    #[test]
+    fn clone_dyn_generations() {
+        let originals: [&dyn Shape; 3] = [&Circle(3), &Label("x".to_string(), [0; 3]), &Nothing];
+        let first = checked_arena(1024, 1024);
+        let gen1: Vec<ArenaBox<dyn Shape>> =
+            originals.iter().map(|s| first.clone_dyn(*s).unwrap()).collect();
+        let second = checked_arena(1024, 1024);
+        let gen2: Vec<ArenaBox<dyn Shape>> =
+            gen1.iter().map(|s| second.clone_dyn(&**s).unwrap()).collect();
+        drop(gen1);
+        let described: Vec<String> = gen2.iter().map(|s| s.describe()).collect();
+        assert_eq!(described, ["circle 3", "label x 3", "nothing"]);
+    }
+
+    struct Counted<'c>(&'c Cell<usize>, bool);
+
+    impl<'c> Clone for Counted<'c> {
+        fn clone(&self) -> Self {
+            assert!(!self.1, "clone failed");
+            Counted(self.0, false)
+        }
+    }
+
+    impl<'c> Drop for Counted<'c> {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn clone_dyn_drops() {
+        let drops = Cell::new(0);
+        let a = checked_arena(1024, 1024);
+        let c = a.clone_dyn(&[1u8, 2, 3][..]).unwrap();
+        assert_eq!(&*c, &[1, 2, 3]);
+        let s = a.clone_dyn("text").unwrap();
+        assert_eq!(&*s, "text");
+        let original = Counted(&drops, false);
+        let x = a.clone_dyn(&original).unwrap();
+        drop(x);
+        assert_eq!(drops.get(), 1);
+        drop(original);
+        assert_eq!(drops.get(), 2);
+    }
+
+    #[test]
+    fn clone_dyn_slice_panic() {
+        let drops = Cell::new(0);
+        let a = checked_arena(1024, 1024);
+        let items = [Counted(&drops, false), Counted(&drops, false), Counted(&drops, true)];
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            let _ = a.clone_dyn(&items[..]);
+        }));
+        assert!(result.is_err());
+        assert_eq!(drops.get(), 2);
+    }
+
+    #[test]
+    fn clone_dyn_exhausted() {
+        let a = checked_arena(8, 8);
+        let l: &dyn Shape = &Label(String::new(), [0; 3]);
+        assert!(a.clone_dyn(l).is_err());
+    }
+}
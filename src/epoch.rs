@@ -0,0 +1,488 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! An arena that frees its allocations a whole epoch at a time.
+
+use core::alloc::Layout;
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem::{self, MaybeUninit};
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::AllocError;
+use Arena;
+
+/// The alignment of the buffer of each region.
+const REGION_ALIGNMENT: usize = 16;
+
+/// Hands out a distinct id to every `EpochArena`, so that boxes can be
+/// checked against the arena they came from.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+struct Region {
+    arena: Arena,
+    epoch: u64,
+    next: *mut Region,
+}
+
+/// An arena made of one bump region per epoch, which releases old epochs
+/// as a whole.
+///
+/// Allocations always go to the region of the current epoch. `advance`
+/// opens a new epoch with a fresh region, and `retain_last` releases the
+/// regions of all but the most recent epochs, dropping the values in them.
+/// Released regions are kept in a pool for later epochs, up to a configurable
+/// number, and freed beyond that, so memory use is bounded by the number of
+/// retained epochs plus the size of the pool.
+///
+/// Values are accessed through the `EpochArena` with an [`EpochBox`]
+/// handle, which records the epoch the value was allocated in. Accessing a
+/// value whose epoch has been released returns `None` instead of the
+/// value, so data from a released epoch can never be observed.
+///
+/// [`EpochBox`]: struct.EpochBox.html
+///
+/// # Examples
+///
+/// ```
+/// # use memory_arena::*;
+/// let mut a = EpochArena::new(1024, 2).unwrap();
+/// let old = a.new_box(1u32).unwrap();
+/// a.advance().unwrap();
+/// let new = a.new_box(2u32).unwrap();
+/// assert_eq!(a.get(&old), Some(&1));
+/// a.retain_last(1);
+/// assert_eq!(a.get(&old), None);
+/// assert_eq!(a.get(&new), Some(&2));
+/// ```
+pub struct EpochArena {
+    id: usize,
+    region_size: usize,
+    /// The regions of the retained epochs, newest first.
+    current: *mut Region,
+    /// Released regions available for reuse.
+    pool: *mut Region,
+    pooled: usize,
+    max_pooled: usize,
+    oldest: u64,
+}
+
+impl EpochArena {
+    /// Creates an `EpochArena` whose epochs each get a region of
+    /// `region_size` bytes, keeping up to `max_pooled` released regions
+    /// around for reuse. Epoch 0 is opened right away.
+    pub fn new(region_size: usize, max_pooled: usize) -> Result<Self, AllocError> {
+        let mut a = EpochArena {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            region_size,
+            current: ptr::null_mut(),
+            pool: ptr::null_mut(),
+            pooled: 0,
+            max_pooled,
+            oldest: 0,
+        };
+        a.current = a.region(0)?;
+        Ok(a)
+    }
+
+    /// Takes a region from the pool, or allocates a new one.
+    fn region(&mut self, epoch: u64) -> Result<*mut Region, AllocError> {
+        if !self.pool.is_null() {
+            let r = self.pool;
+            unsafe {
+                self.pool = (*r).next;
+                (*r).epoch = epoch;
+                (*r).next = ptr::null_mut();
+            }
+            self.pooled -= 1;
+            return Ok(r);
+        }
+        let arena = Arena::new(self.region_size, REGION_ALIGNMENT)?;
+        unsafe {
+            let r = ::alloc::aligned_alloc(mem::size_of::<Region>(), mem::align_of::<Region>())?
+                as *mut Region;
+            ptr::write(
+                r,
+                Region {
+                    arena,
+                    epoch,
+                    next: ptr::null_mut(),
+                },
+            );
+            Ok(r)
+        }
+    }
+
+    /// Returns the number of the current epoch.
+    pub fn epoch(&self) -> u64 {
+        unsafe { (*self.current).epoch }
+    }
+
+    /// Returns the number of the oldest epoch that has not been released.
+    pub fn oldest_epoch(&self) -> u64 {
+        self.oldest
+    }
+
+    fn current(&self) -> &Arena {
+        unsafe { &(*self.current).arena }
+    }
+
+    /// Allocates uninitialized memory for `layout` in the current epoch.
+    pub fn alloc(&self, layout: Layout) -> Result<EpochBox<[MaybeUninit<u8>]>, AllocError> {
+        let p = if layout.size() == 0 {
+            ptr::without_provenance_mut(layout.align())
+        } else {
+            self.current().try_aligned_alloc(layout.size(), layout.align())?
+        };
+        let p = ptr::slice_from_raw_parts_mut(p as *mut MaybeUninit<u8>, layout.size());
+        Ok(self.handle(p))
+    }
+
+    /// Places `x` in the current epoch.
+    ///
+    /// The value is dropped when its epoch is released. If the region of
+    /// the current epoch is full, `x` is returned, wrapped in
+    /// `Result::Err`, and `advance` can be used to continue in a fresh
+    /// region. Zero-sized values take up no room in the region.
+    pub fn new_box<T: 'static>(&self, x: T) -> Result<EpochBox<T>, T> {
+        let arena = self.current();
+        let p = match arena.aligned_alloc(mem::size_of::<T>(), mem::align_of::<T>()) {
+            Some(p) => p as *mut T,
+            None => return Err(x),
+        };
+        let drop_value = move || unsafe { ptr::drop_in_place(p) };
+        if mem::needs_drop::<T>() && arena.on_reset(drop_value).is_err() {
+            return Err(x);
+        }
+        unsafe { ptr::write(p, x) };
+        Ok(self.handle(p))
+    }
+
+    fn handle<T: ?Sized>(&self, p: *mut T) -> EpochBox<T> {
+        EpochBox {
+            ptr: unsafe { NonNull::new_unchecked(p) },
+            epoch: self.epoch(),
+            owner: self.id,
+            phantom: PhantomData,
+        }
+    }
+
+    fn check<T: ?Sized>(&self, b: &EpochBox<T>) -> bool {
+        assert_eq!(b.owner, self.id, "EpochBox used with a different EpochArena");
+        b.epoch >= self.oldest
+    }
+
+    /// Returns a reference to the value of `b`, or `None` if its epoch has
+    /// been released.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `b` was allocated from a different `EpochArena`.
+    pub fn get<'s, T: ?Sized>(&'s self, b: &EpochBox<T>) -> Option<&'s T> {
+        if self.check(b) {
+            Some(unsafe { &*b.ptr.as_ptr() })
+        } else {
+            None
+        }
+    }
+
+    /// Returns a mutable reference to the value of `b`, or `None` if its
+    /// epoch has been released.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `b` was allocated from a different `EpochArena`.
+    pub fn get_mut<'s, T: ?Sized>(&'s mut self, b: &mut EpochBox<T>) -> Option<&'s mut T> {
+        if self.check(b) {
+            Some(unsafe { &mut *b.ptr.as_ptr() })
+        } else {
+            None
+        }
+    }
+
+    /// Opens a new epoch, which subsequent allocations go to.
+    pub fn advance(&mut self) -> Result<(), AllocError> {
+        let epoch = self.epoch() + 1;
+        let r = self.region(epoch)?;
+        unsafe { (*r).next = self.current };
+        self.current = r;
+        Ok(())
+    }
+
+    /// Releases every epoch except for the `k` most recent ones, counting
+    /// the current one, and drops the values in them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `k` is zero, since the current epoch cannot be released.
+    pub fn retain_last(&mut self, k: usize) {
+        assert!(k > 0, "the current epoch cannot be released");
+        let mut last = self.current;
+        for _ in 1..k {
+            let next = unsafe { (*last).next };
+            if next.is_null() {
+                return;
+            }
+            last = next;
+        }
+        let mut r = unsafe { mem::replace(&mut (*last).next, ptr::null_mut()) };
+        self.oldest = unsafe { (*last).epoch };
+        while !r.is_null() {
+            unsafe {
+                let next = (*r).next;
+                if self.pooled < self.max_pooled {
+                    (*r).arena.clear();
+                    (*r).next = self.pool;
+                    self.pool = r;
+                    self.pooled += 1;
+                } else {
+                    free_region(r);
+                }
+                r = next;
+            }
+        }
+    }
+
+    /// Verifies the internal consistency of the arena, panicking with a
+    /// description of the first violated invariant, like
+    /// [`Arena::check_invariants`].
+    ///
+    /// This is meant for tests and fuzzing, and is only available with the
+    /// `check-invariants` feature.
+    ///
+    /// [`Arena::check_invariants`]: struct.Arena.html#method.check_invariants
+    #[cfg(any(test, feature = "check-invariants"))]
+    pub fn check_invariants(&self) {
+        assert!(!self.current.is_null(), "arena invariant violated: no current epoch");
+        // The retained epochs count down by one from the current one, to
+        // the oldest.
+        let mut last = None;
+        let mut r = self.current;
+        while !r.is_null() {
+            let region = unsafe { &*r };
+            if let Some(last) = last {
+                assert!(
+                    region.epoch + 1 == last,
+                    "arena invariant violated: epoch {} is retained after epoch {}",
+                    region.epoch,
+                    last
+                );
+            }
+            region.arena.check_invariants();
+            last = Some(region.epoch);
+            r = region.next;
+        }
+        assert!(
+            last == Some(self.oldest),
+            "arena invariant violated: the last retained epoch is {:?}, not the oldest ({})",
+            last,
+            self.oldest
+        );
+        let mut pooled = 0;
+        let mut r = self.pool;
+        while !r.is_null() {
+            let region = unsafe { &*r };
+            assert!(
+                region.arena.used() == 0,
+                "arena invariant violated: a pooled region has {} bytes in use",
+                region.arena.used()
+            );
+            pooled += 1;
+            r = region.next;
+        }
+        assert!(
+            pooled == self.pooled && pooled <= self.max_pooled,
+            "arena invariant violated: {} regions pooled, counted as {}, at most {}",
+            pooled,
+            self.pooled,
+            self.max_pooled
+        );
+    }
+}
+
+unsafe fn free_region(r: *mut Region) {
+    ptr::drop_in_place(r);
+    ::alloc::free(r as *mut u8, mem::size_of::<Region>(), mem::align_of::<Region>());
+}
+
+impl Drop for EpochArena {
+    fn drop(&mut self) {
+        for list in &[self.current, self.pool] {
+            let mut r = *list;
+            while !r.is_null() {
+                unsafe {
+                    let next = (*r).next;
+                    free_region(r);
+                    r = next;
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for EpochArena {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EpochArena")
+            .field("epoch", &self.epoch())
+            .field("oldest_epoch", &self.oldest)
+            .field("region_size", &self.region_size)
+            .field("pooled", &self.pooled)
+            .finish()
+    }
+}
+
+/// A handle to a value allocated in an `EpochArena`.
+///
+/// The value is reached through [`EpochArena::get`] and
+/// [`EpochArena::get_mut`], which check that its epoch is still retained.
+/// Dropping the handle does not drop the value; that happens when its
+/// epoch is released.
+///
+/// [`EpochArena::get`]: struct.EpochArena.html#method.get
+/// [`EpochArena::get_mut`]: struct.EpochArena.html#method.get_mut
+pub struct EpochBox<T: ?Sized> {
+    ptr: NonNull<T>,
+    epoch: u64,
+    owner: usize,
+    phantom: PhantomData<T>,
+}
+
+impl<T: ?Sized> EpochBox<T> {
+    /// Returns the epoch the value was allocated in.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for EpochBox<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EpochBox").field("epoch", &self.epoch).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use core::cell::Cell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+    use std::vec::Vec;
+    use test_util::{checked, live_allocations};
+
+    #[derive(Debug)]
+    struct Tracked(u64, Rc<Cell<usize>>);
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            self.1.set(self.1.get() + 1);
+        }
+    }
+
+    #[test]
+    fn epoch_retention() {
+        let before = live_allocations();
+        let drops = Rc::new(Cell::new(0));
+        let mut a = checked(EpochArena::new(4096, 2).unwrap());
+        let mut window: VecDeque<Vec<EpochBox<Tracked>>> = VecDeque::new();
+        let mut released = Vec::new();
+        let mut allocated = 0;
+        let mut peak = 0;
+        for round in 0..200u64 {
+            let boxes = (0..round % 7 + 1)
+                .map(|i| a.new_box(Tracked(round * 100 + i, drops.clone())).unwrap())
+                .collect();
+            allocated += round as usize % 7 + 1;
+            window.push_back(boxes);
+            let k = (round % 4 + 1) as usize;
+            a.retain_last(k);
+            while window.len() > k {
+                released.extend(window.pop_front().unwrap());
+            }
+            assert_eq!(a.oldest_epoch(), round + 1 - window.len() as u64);
+            for (e, boxes) in window.iter().enumerate() {
+                for (i, b) in boxes.iter().enumerate() {
+                    let epoch = a.oldest_epoch() + e as u64;
+                    assert_eq!(b.epoch(), epoch);
+                    assert_eq!(a.get(b).unwrap().0, epoch * 100 + i as u64);
+                }
+            }
+            for b in &released {
+                assert!(a.get(b).is_none());
+            }
+            assert_eq!(drops.get() + window.iter().map(Vec::len).sum::<usize>(), allocated);
+            peak = peak.max(live_allocations() - before);
+            a.advance().unwrap();
+        }
+        // At most four retained regions and two pooled ones, two system
+        // allocations each, plus the drop callbacks of the retained values.
+        assert!(peak <= 6 * 2 + 4 * 7, "peak of {} live allocations", peak);
+        drop(a);
+        assert_eq!(drops.get(), allocated);
+        assert_eq!(live_allocations(), before);
+    }
+
+    #[test]
+    fn epoch_get_mut_and_alloc() {
+        let mut a = checked(EpochArena::new(1024, 0).unwrap());
+        let mut b = a.new_box([0u8; 16]).unwrap();
+        a.get_mut(&mut b).unwrap()[3] = 7;
+        let raw = a.alloc(Layout::new::<u64>()).unwrap();
+        let bytes = a.get(&raw).unwrap();
+        assert_eq!(bytes.len(), 8);
+        assert!((bytes.as_ptr() as usize).is_multiple_of(8));
+        a.advance().unwrap();
+        assert_eq!(a.get(&b).unwrap()[3], 7);
+        a.retain_last(1);
+        assert!(a.get_mut(&mut b).is_none());
+        assert!(a.get(&raw).is_none());
+    }
+
+    #[test]
+    fn epoch_region_full() {
+        let mut a = checked(EpochArena::new(16, 1).unwrap());
+        let _ = a.new_box(1u64).unwrap();
+        let _ = a.new_box(2u64).unwrap();
+        assert_eq!(a.new_box(3u64).unwrap_err(), 3);
+        assert_eq!(a.alloc(Layout::new::<u8>()).unwrap_err(), AllocError::Exhausted);
+        a.advance().unwrap();
+        assert!(a.new_box(3u64).is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "EpochBox used with a different EpochArena")]
+    fn epoch_wrong_arena() {
+        let a = checked(EpochArena::new(1024, 0).unwrap());
+        let b = checked(EpochArena::new(1024, 0).unwrap());
+        let x = a.new_box(1u8).unwrap();
+        let _ = b.get(&x);
+    }
+
+    #[test]
+    #[should_panic(expected = "arena invariant violated: 2 regions pooled, counted as 1, at most 2")]
+    fn epoch_check_invariants_pool() {
+        let mut a = EpochArena::new(64, 2).unwrap();
+        a.advance().unwrap();
+        a.advance().unwrap();
+        a.retain_last(1);
+        a.pooled = 1;
+        a.check_invariants();
+    }
+
+    #[test]
+    #[should_panic(expected = "arena invariant violated: the last retained epoch is Some(1), not the oldest (0)")]
+    fn epoch_check_invariants_oldest() {
+        let mut a = EpochArena::new(64, 0).unwrap();
+        a.advance().unwrap();
+        a.advance().unwrap();
+        a.retain_last(2);
+        a.oldest = 0;
+        a.check_invariants();
+    }
+}
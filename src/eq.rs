@@ -0,0 +1,393 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Deep structural comparison of values that live in different arenas.
+
+use {
+    Arena, ArenaArc, ArenaBox, ArenaLinkedList, ArenaRc, ArenaString, ArenaVec, Handle,
+    SmallArenaVec, TaggedArenaPtr,
+};
+
+/// Structural equality between two values that may live in different
+/// arenas.
+///
+/// `arena` is the Arena that `self` lives in, and `other_arena` the one
+/// that `other` lives in. Types that point into their Arena with plain
+/// pointers compare what they point to and ignore the arenas; the arenas
+/// are passed along for types that have to be resolved against them.
+/// Shared pointers such as `ArenaArc` compare by value, not identity. A
+/// [`Handle`] is resolved against its own arena, so `arena` and
+/// `other_arena` have to be the right ones for handles.
+///
+/// [`Handle`]: struct.Handle.html
+///
+/// Structs made of fields that implement `ArenaEq` can implement it with
+/// [`impl_arena_eq!`].
+///
+/// [`impl_arena_eq!`]: macro.impl_arena_eq.html
+pub trait ArenaEq {
+    /// Returns `true` if `self`, in `arena`, is structurally equal to
+    /// `other`, in `other_arena`.
+    fn arena_eq(&self, arena: &Arena, other: &Self, other_arena: &Arena) -> bool;
+}
+
+/// Returns `true` if `a`, in `arena_a`, is structurally equal to `b`, in
+/// `arena_b`.
+///
+/// # Examples
+///
+/// ```
+/// # use memory_arena::*;
+/// # #[cfg(feature = "alloc")] {
+/// let a = Arena::new(1024, 1024).unwrap();
+/// let b = Arena::new(1024, 1024).unwrap();
+/// let x = a.new_box(a.new_box(7u32).unwrap()).unwrap();
+/// let y = b.new_box(b.new_box(7u32).unwrap()).unwrap();
+/// assert!(arena_eq(&x, &a, &y, &b));
+/// # }
+/// ```
+pub fn arena_eq<T: ArenaEq + ?Sized>(a: &T, arena_a: &Arena, b: &T, arena_b: &Arena) -> bool {
+    a.arena_eq(arena_a, b, arena_b)
+}
+
+/// Implements `ArenaEq` for a struct by comparing the listed fields in
+/// order.
+///
+/// Every field of the struct should be listed; fields that are left out
+/// are not compared.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate memory_arena;
+/// # use memory_arena::*;
+/// # fn main() {
+/// # #[cfg(feature = "alloc")] {
+/// struct Node<'a> {
+///     value: u32,
+///     next: Option<ArenaBox<'a, Node<'a>>>,
+/// }
+/// impl_arena_eq!(Node<'a> { value, next });
+///
+/// let a = Arena::new(1024, 1024).unwrap();
+/// let b = Arena::new(1024, 1024).unwrap();
+/// let tail = a.new_box(Node { value: 2, next: None }).ok().unwrap();
+/// let x = Node { value: 1, next: Some(tail) };
+/// let tail = b.new_box(Node { value: 3, next: None }).ok().unwrap();
+/// let y = Node { value: 1, next: Some(tail) };
+/// assert!(!arena_eq(&x, &a, &y, &b));
+/// # }
+/// # }
+/// ```
+#[macro_export]
+macro_rules! impl_arena_eq {
+    ($name:ident $(<$($lt:lifetime),+>)* { $($field:ident),* $(,)* }) => {
+        impl $(<$($lt),+>)* $crate::ArenaEq for $name $(<$($lt),+>)* {
+            #[allow(unused_variables)]
+            fn arena_eq(
+                &self,
+                arena: &$crate::Arena,
+                other: &Self,
+                other_arena: &$crate::Arena,
+            ) -> bool {
+                true $(&& $crate::ArenaEq::arena_eq(
+                    &self.$field, arena, &other.$field, other_arena
+                ))*
+            }
+        }
+    };
+}
+
+macro_rules! impl_arena_eq_partial_eq {
+    ($($t:ty),*) => {
+        $(
+            impl ArenaEq for $t {
+                fn arena_eq(&self, _: &Arena, other: &Self, _: &Arena) -> bool {
+                    self == other
+                }
+            }
+        )*
+    };
+}
+
+impl_arena_eq_partial_eq!(
+    (), bool, char, str, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64
+);
+
+#[cfg(feature = "std")]
+impl_arena_eq_partial_eq!(::std::string::String);
+
+macro_rules! impl_arena_eq_tuple {
+    ($($name:ident $idx:tt),+) => {
+        impl<$($name: ArenaEq),+> ArenaEq for ($($name,)+) {
+            fn arena_eq(&self, arena: &Arena, other: &Self, other_arena: &Arena) -> bool {
+                $(self.$idx.arena_eq(arena, &other.$idx, other_arena))&&+
+            }
+        }
+    };
+}
+
+impl_arena_eq_tuple!(A 0);
+impl_arena_eq_tuple!(A 0, B 1);
+impl_arena_eq_tuple!(A 0, B 1, C 2);
+impl_arena_eq_tuple!(A 0, B 1, C 2, D 3);
+
+impl<T: ArenaEq> ArenaEq for [T] {
+    fn arena_eq(&self, arena: &Arena, other: &Self, other_arena: &Arena) -> bool {
+        self.len() == other.len()
+            && self.iter().zip(other).all(|(x, y)| x.arena_eq(arena, y, other_arena))
+    }
+}
+
+impl<T: ArenaEq, const N: usize> ArenaEq for [T; N] {
+    fn arena_eq(&self, arena: &Arena, other: &Self, other_arena: &Arena) -> bool {
+        self[..].arena_eq(arena, &other[..], other_arena)
+    }
+}
+
+impl<T: ArenaEq> ArenaEq for Option<T> {
+    fn arena_eq(&self, arena: &Arena, other: &Self, other_arena: &Arena) -> bool {
+        match (self, other) {
+            (Some(x), Some(y)) => x.arena_eq(arena, y, other_arena),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<T: ArenaEq, E: ArenaEq> ArenaEq for Result<T, E> {
+    fn arena_eq(&self, arena: &Arena, other: &Self, other_arena: &Arena) -> bool {
+        match (self, other) {
+            (Ok(x), Ok(y)) => x.arena_eq(arena, y, other_arena),
+            (Err(x), Err(y)) => x.arena_eq(arena, y, other_arena),
+            _ => false,
+        }
+    }
+}
+
+impl<T: ArenaEq + ?Sized> ArenaEq for &T {
+    fn arena_eq(&self, arena: &Arena, other: &Self, other_arena: &Arena) -> bool {
+        (**self).arena_eq(arena, *other, other_arena)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: ArenaEq> ArenaEq for ::std::vec::Vec<T> {
+    fn arena_eq(&self, arena: &Arena, other: &Self, other_arena: &Arena) -> bool {
+        self[..].arena_eq(arena, &other[..], other_arena)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: ArenaEq + ?Sized> ArenaEq for ::std::boxed::Box<T> {
+    fn arena_eq(&self, arena: &Arena, other: &Self, other_arena: &Arena) -> bool {
+        (**self).arena_eq(arena, &**other, other_arena)
+    }
+}
+
+impl<'a, T: ArenaEq + ?Sized> ArenaEq for ArenaBox<'a, T> {
+    fn arena_eq(&self, arena: &Arena, other: &Self, other_arena: &Arena) -> bool {
+        (**self).arena_eq(arena, &**other, other_arena)
+    }
+}
+
+impl<'a, T: ArenaEq> ArenaEq for ArenaArc<'a, T> {
+    fn arena_eq(&self, arena: &Arena, other: &Self, other_arena: &Arena) -> bool {
+        (**self).arena_eq(arena, &**other, other_arena)
+    }
+}
+
+impl<'a, T: ArenaEq> ArenaEq for ArenaRc<'a, T> {
+    fn arena_eq(&self, arena: &Arena, other: &Self, other_arena: &Arena) -> bool {
+        (**self).arena_eq(arena, &**other, other_arena)
+    }
+}
+
+impl<'a, T: ArenaEq> ArenaEq for ArenaVec<'a, T> {
+    fn arena_eq(&self, arena: &Arena, other: &Self, other_arena: &Arena) -> bool {
+        self.as_slice().arena_eq(arena, other.as_slice(), other_arena)
+    }
+}
+
+impl<'a> ArenaEq for ArenaString<'a> {
+    fn arena_eq(&self, _: &Arena, other: &Self, _: &Arena) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+/// Compares the values the handles resolve to, `self` in `arena` and
+/// `other` in `other_arena`. A handle that does not resolve, because it is
+/// stale or from another Arena, is not equal to anything.
+impl<T: ArenaEq> ArenaEq for Handle<T> {
+    fn arena_eq(&self, arena: &Arena, other: &Self, other_arena: &Arena) -> bool {
+        match (arena.try_get(*self), other_arena.try_get(*other)) {
+            (Some(x), Some(y)) => x.arena_eq(arena, y, other_arena),
+            _ => false,
+        }
+    }
+}
+
+impl<'a, T: ArenaEq, const N: usize> ArenaEq for SmallArenaVec<'a, T, N> {
+    fn arena_eq(&self, arena: &Arena, other: &Self, other_arena: &Arena) -> bool {
+        self.as_slice().arena_eq(arena, other.as_slice(), other_arena)
+    }
+}
+
+impl<'a, T: ArenaEq + 'a> ArenaEq for ArenaLinkedList<'a, T> {
+    fn arena_eq(&self, arena: &Arena, other: &Self, other_arena: &Arena) -> bool {
+        self.len() == other.len()
+            && self.iter().zip(other.iter()).all(|(x, y)| (**x).arena_eq(arena, &**y, other_arena))
+    }
+}
+
+/// Compares the tags and the pointed-to values.
+impl<'a, T: ArenaEq + 'a, const BITS: u32> ArenaEq for TaggedArenaPtr<'a, T, BITS> {
+    fn arena_eq(&self, arena: &Arena, other: &Self, other_arena: &Arena) -> bool {
+        self.tag() == other.tag() && self.get().arena_eq(arena, other.get(), other_arena)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use core::fmt::Write;
+    use test_util::checked_arena;
+    use {ArenaRope, ListNode};
+
+    struct Node<'a> {
+        value: u32,
+        name: ArenaBox<'a, str>,
+        next: Option<ArenaBox<'a, Node<'a>>>,
+    }
+    impl_arena_eq!(Node<'a> { value, name, next });
+
+    fn name_in(arena: &Arena, value: u32) -> ArenaBox<'_, str> {
+        let mut rope = ArenaRope::new(arena);
+        write!(rope, "node {}", value).unwrap();
+        rope.finish(arena).unwrap()
+    }
+
+    fn build<'a>(arena: &'a Arena, values: &[u32]) -> Option<ArenaBox<'a, Node<'a>>> {
+        let mut head = None;
+        for &value in values.iter().rev() {
+            let node = Node {
+                value,
+                name: name_in(arena, value),
+                next: head,
+            };
+            head = Some(arena.new_box(node).ok().unwrap());
+        }
+        head
+    }
+
+    fn clone_list<'a>(
+        list: &Option<ArenaBox<Node>>,
+        arena: &'a Arena,
+    ) -> Option<ArenaBox<'a, Node<'a>>> {
+        list.as_ref().map(|node| {
+            let next = clone_list(&node.next, arena);
+            let mut rope = ArenaRope::new(arena);
+            rope.push_str(&node.name).unwrap();
+            let node = Node {
+                value: node.value,
+                name: rope.finish(arena).unwrap(),
+                next,
+            };
+            arena.new_box(node).ok().unwrap()
+        })
+    }
+
+    #[test]
+    fn eq_cloned_list() {
+        let a = checked_arena(8192, 4096);
+        let b = checked_arena(8192, 4096);
+        let original = build(&a, &[1, 2, 3, 4]);
+        let mut copy = clone_list(&original, &b);
+        assert!(arena_eq(&original, &a, &copy, &b));
+
+        copy.as_mut().unwrap().next.as_mut().unwrap().value = 20;
+        assert!(!arena_eq(&original, &a, &copy, &b));
+        copy.as_mut().unwrap().next.as_mut().unwrap().value = 2;
+        assert!(arena_eq(&original, &a, &copy, &b));
+
+        copy.as_mut().unwrap().next.as_mut().unwrap().next = None;
+        assert!(!arena_eq(&original, &a, &copy, &b));
+    }
+
+    #[test]
+    fn eq_std_and_shared() {
+        let a = checked_arena(4096, 4096);
+        let b = checked_arena(4096, 4096);
+        let x = a.new_arc(a.new_box([1u8, 2, 3]).unwrap()).ok().unwrap();
+        let y = b.new_arc(b.new_box([1u8, 2, 3]).unwrap()).ok().unwrap();
+        assert!(!ArenaArc::ptr_eq(&x, &y));
+        assert!(arena_eq(&(x.clone(), Some(1.5f64)), &a, &(y.clone(), Some(1.5)), &b));
+        assert!(!arena_eq(&(x, Some(1.5f64)), &a, &(y, None), &b));
+
+        let nodes_a = [ListNode::new(1u32), ListNode::new(2)];
+        let nodes_b = [ListNode::new(1u32), ListNode::new(2)];
+        let mut list_a = ArenaLinkedList::new();
+        let mut list_b = ArenaLinkedList::new();
+        list_a.push_back(&nodes_a[0]);
+        list_a.push_back(&nodes_a[1]);
+        list_b.push_back(&nodes_b[0]);
+        assert!(!arena_eq(&list_a, &a, &list_b, &b));
+        list_b.push_back(&nodes_b[1]);
+        assert!(arena_eq(&list_a, &a, &list_b, &b));
+    }
+
+    #[test]
+    fn eq_rc_vec_string() {
+        let a = checked_arena(4096, 4096);
+        let b = checked_arena(4096, 4096);
+        let x = a.new_rc(a.new_box(5u32).unwrap()).ok().unwrap();
+        let y = b.new_rc(b.new_box(5u32).unwrap()).ok().unwrap();
+        assert!(!ArenaRc::ptr_eq(&x, &y));
+        assert!(arena_eq(&x, &a, &y, &b));
+        let z = b.new_rc(b.new_box(6u32).unwrap()).ok().unwrap();
+        assert!(!arena_eq(&x, &a, &z, &b));
+
+        let mut v = ArenaVec::with_capacity_in(4, &a).unwrap();
+        let mut w = ArenaVec::with_capacity_in(8, &b).unwrap();
+        for i in 0..3u16 {
+            v.push(i);
+            w.push(i);
+        }
+        assert!(arena_eq(&v, &a, &w, &b));
+        w.push(3);
+        assert!(!arena_eq(&v, &a, &w, &b));
+
+        let mut s = ArenaString::with_capacity_in(8, &a).unwrap();
+        let mut t = ArenaString::with_capacity_in(16, &b).unwrap();
+        s.push_str("arena");
+        t.push_str("arena");
+        assert!(arena_eq(&s, &a, &t, &b));
+        t.push('s');
+        assert!(!arena_eq(&s, &a, &t, &b));
+    }
+
+    #[test]
+    fn eq_handles() {
+        let mut a = checked_arena(4096, 4096);
+        let mut b = checked_arena(4096, 4096);
+        // Different offsets in the two arenas, but the same values.
+        let _ = b.insert(0u64).unwrap();
+        let x = a.insert((1u32, a.insert(2u8).unwrap())).unwrap();
+        let inner = b.insert(2u8).unwrap();
+        let y = b.insert((1u32, inner)).unwrap();
+        assert_ne!(x.offset(), y.offset());
+        assert!(arena_eq(&x, &a, &y, &b));
+        // Resolving against the wrong arenas does not match.
+        assert!(!arena_eq(&x, &b, &y, &a));
+        *b.get_mut(inner) = 3;
+        assert!(!arena_eq(&x, &a, &y, &b));
+        a.reset();
+        assert!(!arena_eq(&x, &a, &y, &b));
+    }
+}
@@ -0,0 +1,136 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Arenas mapped at a fixed virtual address.
+
+use alloc::AllocError;
+use arena::Backing;
+use regions::Regions;
+use Arena;
+
+impl Arena {
+    /// Creates an Arena of `size` bytes whose memory is mapped at exactly
+    /// the virtual address `addr`.
+    ///
+    /// The memory starts out zeroed, so two runs that make the same
+    /// allocations at the same address produce byte-identical arenas, even
+    /// where the values contain absolute pointers into the Arena. This is
+    /// what record and replay debugging or content-addressed caching of
+    /// arena images need.
+    ///
+    /// An image of the Arena is its first `used` bytes, starting at `addr`.
+    /// To load it again, possibly in another run of the program, create a
+    /// new Arena at the same address, claim the image's size with
+    /// `alloc_at_offset(0, ..)`, and copy the bytes in; the pointers stored
+    /// in the image then point to the same places as before.
+    ///
+    /// Existing mappings are never replaced: if any of the memory at `addr`
+    /// is already in use, this fails with `AllocError::AddressUnavailable`.
+    /// On Unix the memory comes from `mmap`, and on Windows from
+    /// `VirtualAlloc`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `addr` is not a multiple of the page size.
+    pub fn new_at(addr: usize, size: usize) -> Result<Arena, AllocError> {
+        assert!(
+            addr.is_multiple_of(::alloc::page_size()),
+            "address {:#x} is not page-aligned",
+            addr
+        );
+        let mem = unsafe { ::alloc::map(Some(addr), size)? };
+        Ok(Arena::from_parts(mem, size, Backing::Mapped(size), Regions::new()))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use core::alloc::Layout;
+    use core::ptr::{self, NonNull};
+    use core::slice;
+    use std::vec::Vec;
+
+    const SIZE: usize = 1 << 16;
+
+    /// Returns an address at which `SIZE` bytes are free right now.
+    fn free_address() -> usize {
+        unsafe {
+            let p = ::libc::mmap(
+                ptr::null_mut(),
+                SIZE,
+                ::libc::PROT_NONE,
+                ::libc::MAP_PRIVATE | ::libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            assert_ne!(p, ::libc::MAP_FAILED);
+            ::libc::munmap(p, SIZE);
+            p as usize
+        }
+    }
+
+    #[derive(Debug)]
+    struct Node {
+        value: u64,
+        next: Option<NonNull<Node>>,
+    }
+
+    /// Builds a small linked list and returns an image of the Arena.
+    fn build_image(addr: usize) -> Vec<u8> {
+        let a = Arena::new_at(addr, SIZE).unwrap();
+        assert_eq!(a.mem as usize, addr);
+        let mut next = None;
+        for value in 0..10 {
+            let node = a.new_box(Node { value, next }).unwrap();
+            next = Some(NonNull::from(&*node));
+            ::core::mem::forget(node);
+        }
+        unsafe { slice::from_raw_parts(a.mem, a.used.get()).to_vec() }
+    }
+
+    #[test]
+    fn new_at_reproducible() {
+        let addr = free_address();
+        let first = build_image(addr);
+        let second = build_image(addr);
+        assert!(!first.is_empty());
+        assert_eq!(first, second);
+
+        let a = Arena::new_at(addr, SIZE).unwrap();
+        let p = a.alloc_at_offset(0, Layout::array::<u8>(first.len()).unwrap()).unwrap();
+        unsafe { ptr::copy_nonoverlapping(first.as_ptr(), p.as_ptr(), first.len()) };
+        let head = unsafe { &*((addr + first.len() - ::core::mem::size_of::<Node>()) as *const Node) };
+        let mut values = Vec::new();
+        let mut node = Some(head);
+        while let Some(n) = node {
+            values.push(n.value);
+            node = n.next.map(|p| unsafe { &*p.as_ptr() });
+        }
+        assert_eq!(values, [9, 8, 7, 6, 5, 4, 3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn new_at_unavailable() {
+        let addr = free_address();
+        let a = Arena::new_at(addr, SIZE).unwrap();
+        assert_eq!(
+            Arena::new_at(addr, SIZE).err(),
+            Some(AllocError::AddressUnavailable { addr })
+        );
+        drop(a);
+        assert!(Arena::new_at(addr, SIZE).is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "is not page-aligned")]
+    fn new_at_misaligned() {
+        let _ = Arena::new_at(free_address() + 1, SIZE);
+    }
+}
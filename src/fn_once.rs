@@ -0,0 +1,324 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! One-shot closures stored in an arena.
+//!
+//! Calling a `FnOnce` moves it, which an `ArenaBox<dyn FnOnce()>` can't do
+//! on stable Rust. `ArenaFnOnce` instead keeps the closure next to a call
+//! thunk for its concrete type, which moves the closure out of the arena
+//! and calls it. On nightly, the `fn_traits` feature makes arena boxes of
+//! closures callable directly, like `Box`.
+
+#[cfg(feature = "fn_traits")]
+use core::alloc::{AllocError, Allocator, Layout};
+use core::fmt;
+#[cfg(feature = "fn_traits")]
+use core::marker::Tuple;
+use core::mem::ManuallyDrop;
+use core::ptr::{self, NonNull};
+#[cfg(feature = "fn_traits")]
+use rust_alloc::boxed::Box;
+
+use arena_box::LiveGuard;
+use {Arena, ArenaBox};
+
+/// A closure that can be called once with its arguments given as a tuple.
+///
+/// This is implemented for every `FnOnce` of up to four arguments, so that
+/// [`ArenaFnOnce`] can take the arguments as a single type parameter.
+///
+/// [`ArenaFnOnce`]: struct.ArenaFnOnce.html
+pub trait FnOnceArgs<Args> {
+    /// The return type of the closure.
+    type Output;
+
+    /// Calls the closure with the arguments unpacked from `args`.
+    fn call_with(self, args: Args) -> Self::Output;
+}
+
+macro_rules! fn_once_args {
+    ($($arg:ident: $ty:ident),*) => {
+        impl<F, R, $($ty),*> FnOnceArgs<($($ty,)*)> for F
+        where
+            F: FnOnce($($ty),*) -> R,
+        {
+            type Output = R;
+
+            #[inline]
+            fn call_with(self, ($($arg,)*): ($($ty,)*)) -> R {
+                self($($arg),*)
+            }
+        }
+    };
+}
+
+fn_once_args!();
+fn_once_args!(a: A);
+fn_once_args!(a: A, b: B);
+fn_once_args!(a: A, b: B, c: C);
+fn_once_args!(a: A, b: B, c: C, d: D);
+
+/// A `FnOnce` closure stored in an `Arena`, taking `Args` as a tuple and
+/// returning `R`.
+///
+/// [`ArenaFnOnce::call_once`] consumes the value, so it can only be called
+/// once; dropping it without calling it drops the closure's captures. The
+/// closure's memory stays in the Arena either way.
+///
+/// [`ArenaFnOnce::call_once`]: #method.call_once
+///
+/// # Examples
+///
+/// ```
+/// # use memory_arena::*;
+/// # #[cfg(feature = "alloc")] {
+/// let a = Arena::new(1024, 1024).unwrap();
+/// let name = String::from("undo");
+/// let f = a.alloc_fn_once(move |n: usize| name.repeat(n)).ok().unwrap();
+/// assert_eq!(f.call_once((2,)), "undoundo");
+/// # }
+/// ```
+///
+/// Calling it again does not compile:
+///
+/// ```compile_fail,E0382
+/// # use memory_arena::*;
+/// let a = Arena::new(1024, 1024).unwrap();
+/// let f = a.alloc_fn_once(|| 1).ok().unwrap();
+/// f.call_once(());
+/// f.call_once(());
+/// ```
+pub struct ArenaFnOnce<'a, Args, R> {
+    data: NonNull<u8>,
+    call: unsafe fn(*mut u8, Args) -> R,
+    drop: unsafe fn(*mut u8),
+    live: LiveGuard<'a>,
+}
+
+unsafe fn call_thunk<F: FnOnceArgs<Args>, Args>(p: *mut u8, args: Args) -> F::Output {
+    ptr::read(p as *mut F).call_with(args)
+}
+
+unsafe fn drop_thunk<F>(p: *mut u8) {
+    ptr::drop_in_place(p as *mut F)
+}
+
+impl<'a, Args, R> ArenaFnOnce<'a, Args, R> {
+    /// Calls the closure, consuming it.
+    pub fn call_once(self, args: Args) -> R {
+        let this = ManuallyDrop::new(self);
+        // Release the count of live allocations once the call is over, even
+        // if it panics.
+        let _live = unsafe { ptr::read(&this.live) };
+        unsafe { (this.call)(this.data.as_ptr(), args) }
+    }
+}
+
+impl<'a, Args, R> Drop for ArenaFnOnce<'a, Args, R> {
+    fn drop(&mut self) {
+        unsafe { (self.drop)(self.data.as_ptr()) }
+    }
+}
+
+impl<'a, Args, R> fmt::Debug for ArenaFnOnce<'a, Args, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ArenaFnOnce").field("data", &self.data).finish()
+    }
+}
+
+impl Arena {
+    /// Moves the closure `f` into the Arena, so that it can be called once
+    /// later through the returned [`ArenaFnOnce`].
+    ///
+    /// If there is not enough available memory in the Arena, then the
+    /// closure is returned, wrapped in `Result::Err`.
+    ///
+    /// [`ArenaFnOnce`]: struct.ArenaFnOnce.html
+    pub fn alloc_fn_once<'a, Args, F>(
+        &'a self,
+        f: F,
+    ) -> Result<ArenaFnOnce<'a, Args, F::Output>, F>
+    where
+        F: FnOnceArgs<Args>,
+    {
+        let (p, live) = ArenaBox::into_raw_parts(self.new_box(f)?);
+        Ok(ArenaFnOnce {
+            data: unsafe { NonNull::new_unchecked(p as *mut u8) },
+            call: call_thunk::<F, Args>,
+            drop: drop_thunk::<F>,
+            live,
+        })
+    }
+}
+
+/// An allocator that never frees anything, for a `Box` that moves a
+/// closure out of the Arena when it is called.
+#[cfg(feature = "fn_traits")]
+struct InPlace;
+
+#[cfg(feature = "fn_traits")]
+unsafe impl Allocator for InPlace {
+    fn allocate(&self, _: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        Err(AllocError)
+    }
+
+    unsafe fn deallocate(&self, _: NonNull<u8>, _: Layout) {}
+}
+
+/// Calls the boxed closure, consuming the box, like `Box<F>` does. This is
+/// what makes an `ArenaBox<dyn FnOnce(..)>` callable at all.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate memory_arena;
+/// # use memory_arena::*;
+/// # fn main() {
+/// struct World {
+///     log: Vec<String>,
+/// }
+///
+/// let a = Arena::new(1024, 1024).unwrap();
+/// let mut world = World { log: Vec::new() };
+/// let name = String::from("spawn");
+/// let job = a.new_box(move |w: &mut World| w.log.push(name)).ok().unwrap();
+/// let job = arena_box_unsize!(job, dyn FnOnce(&mut World));
+/// job(&mut world);
+/// assert_eq!(world.log, ["spawn"]);
+/// # }
+/// ```
+#[cfg(feature = "fn_traits")]
+impl<'a, Args: Tuple, F: FnOnce<Args> + ?Sized> FnOnce<Args> for ArenaBox<'a, F> {
+    type Output = F::Output;
+
+    extern "rust-call" fn call_once(self, args: Args) -> F::Output {
+        // `Box` knows how to move an unsized closure out to call it. Its
+        // allocator leaves the memory alone afterwards, and the count of
+        // live allocations is released once the call is over.
+        let (raw, _live) = ArenaBox::into_raw_parts(self);
+        let f = unsafe { Box::from_raw_in(raw, InPlace) };
+        <Box<F, InPlace> as FnOnce<Args>>::call_once(f, args)
+    }
+}
+
+#[cfg(feature = "fn_traits")]
+impl<'a, Args: Tuple, F: FnMut<Args> + ?Sized> FnMut<Args> for ArenaBox<'a, F> {
+    extern "rust-call" fn call_mut(&mut self, args: Args) -> F::Output {
+        (**self).call_mut(args)
+    }
+}
+
+#[cfg(feature = "fn_traits")]
+impl<'a, Args: Tuple, F: Fn<Args> + ?Sized> Fn<Args> for ArenaBox<'a, F> {
+    extern "rust-call" fn call(&self, args: Args) -> F::Output {
+        (**self).call(args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use core::cell::Cell;
+    use std::string::String;
+    #[cfg(feature = "fn_traits")]
+    use std::string::ToString;
+    use std::vec::Vec;
+    use test_util::{checked_arena, DropCounter};
+
+    #[test]
+    fn fn_once_call() {
+        let a = checked_arena(1024, 1024);
+        let drops = Cell::new(0);
+        let log = Cell::new(Vec::new());
+        let counter = DropCounter(&drops);
+        let s = String::from("state");
+        let f = a
+            .alloc_fn_once(move || {
+                let _counter = counter;
+                s
+            })
+            .ok()
+            .unwrap();
+        assert_eq!(drops.get(), 0);
+        assert_eq!(f.call_once(()), "state");
+        assert_eq!(drops.get(), 1);
+
+        let log_ref = &log;
+        let push = a
+            .alloc_fn_once(move |x: u32| {
+                let mut v = log_ref.take();
+                v.push(x);
+                log_ref.set(v);
+            })
+            .ok()
+            .unwrap();
+        push.call_once((7,));
+        assert_eq!(log.take(), [7]);
+
+        let add = a.alloc_fn_once(|x: u8, y: u16| u32::from(x) + u32::from(y)).ok().unwrap();
+        assert_eq!(add.call_once((1, 2)), 3);
+    }
+
+    #[test]
+    fn fn_once_drop_uncalled() {
+        let a = checked_arena(1024, 1024);
+        let drops = Cell::new(0);
+        let counter = DropCounter(&drops);
+        let f = a.alloc_fn_once(move || drop(counter)).ok().unwrap();
+        drop(f);
+        assert_eq!(drops.get(), 1);
+    }
+
+    #[cfg(feature = "fn_traits")]
+    #[test]
+    fn fn_once_arena_box_call() {
+        let a = checked_arena(1024, 1024);
+        let drops = Cell::new(0);
+        let mut jobs = Vec::new();
+        for i in 0..3 {
+            let name = i.to_string();
+            let counter = DropCounter(&drops);
+            let job = a
+                .new_box(move |log: &mut Vec<String>| {
+                    let _counter = counter;
+                    log.push(name)
+                })
+                .ok()
+                .unwrap();
+            jobs.push(::arena_box_unsize!(job, dyn FnOnce(&mut Vec<String>)));
+        }
+        let mut log = Vec::new();
+        let uncalled = jobs.remove(1);
+        for job in jobs {
+            job(&mut log);
+        }
+        assert_eq!(log, ["0", "2"]);
+        assert_eq!(drops.get(), 2);
+        drop(uncalled);
+        assert_eq!(drops.get(), 3);
+
+        let mut count = 0;
+        {
+            let mut inc = ::arena_box_unsize!(a.new_box(|| count += 1).ok().unwrap(), dyn FnMut() + '_);
+            inc();
+            inc();
+        }
+        assert_eq!(count, 2);
+        let twice = ::arena_box_unsize!(a.new_box(|x: u32| x * 2).ok().unwrap(), dyn Fn(u32) -> u32);
+        assert_eq!((twice(2), twice(3)), (4, 6));
+        a.check_invariants();
+    }
+
+    #[test]
+    fn fn_once_exhausted() {
+        let a = checked_arena(8, 8);
+        let big = [0u64; 4];
+        assert!(a.alloc_fn_once(move || big.len()).is_err());
+    }
+}
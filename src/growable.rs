@@ -0,0 +1,447 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! An arena that grows by allocating further chunks when it runs full.
+
+use core::alloc::Layout;
+#[cfg(any(test, feature = "check-invariants"))]
+use core::cell::RefCell;
+use core::cell::Cell;
+use core::fmt;
+use core::mem::{self, MaybeUninit};
+#[cfg(any(test, feature = "check-invariants"))]
+use core::ops::Range;
+use core::pin::Pin;
+use core::ptr;
+#[cfg(any(test, feature = "check-invariants"))]
+use rust_alloc::vec::Vec;
+
+use alloc::AllocError;
+use {Arena, ArenaBox};
+
+/// The alignment of the buffer of each chunk.
+const CHUNK_ALIGNMENT: usize = 16;
+
+struct Chunk {
+    arena: Arena,
+    /// The chunk allocated before this one.
+    prev: *mut Chunk,
+}
+
+/// An arena made of a list of chunks, which allocates a new chunk whenever
+/// the current one cannot satisfy a request.
+///
+/// Every chunk has the size given to [`GrowableArena::new`], except that an
+/// allocation too large for one gets a dedicated chunk of its own, and the
+/// current chunk stays in use for later allocations. All chunks stay
+/// allocated until the `GrowableArena` is dropped, which frees each of
+/// them once.
+///
+/// # Address stability
+///
+/// Allocations never move. Growing only ever adds chunks, and never
+/// relocates, resizes or frees the chunks that are already there, so every
+/// reference and pointer into a `GrowableArena` stays valid for as long as
+/// the arena does. [`GrowableArena::new_pinned_box`] relies on this, and
+/// any future feature that grows or shrinks allocations in place has to
+/// keep it that way.
+///
+/// [`GrowableArena::new`]: #method.new
+/// [`GrowableArena::new_pinned_box`]: #method.new_pinned_box
+///
+/// # Examples
+///
+/// ```
+/// # use memory_arena::*;
+/// let a = GrowableArena::new(64).unwrap();
+/// let boxes: Vec<_> = (0..32u64).map(|i| a.new_box(i).unwrap()).collect();
+/// assert!(a.chunk_count() > 1);
+/// assert_eq!(*boxes[31], 31);
+/// ```
+pub struct GrowableArena {
+    chunk_size: usize,
+    /// The chunk allocations are made from, which is the head of the list.
+    current: Cell<*mut Chunk>,
+    chunks: Cell<usize>,
+    /// The address range of every allocation, oldest first, for
+    /// `live_addresses`.
+    #[cfg(any(test, feature = "check-invariants"))]
+    allocations: RefCell<Vec<Range<usize>>>,
+}
+
+impl GrowableArena {
+    /// Creates a `GrowableArena` whose chunks are `chunk_size` bytes,
+    /// aligned to 16 bytes. The first chunk is allocated right away.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero.
+    pub fn new(chunk_size: usize) -> Result<Self, AllocError> {
+        assert!(chunk_size > 0, "chunk size must not be zero");
+        let a = GrowableArena {
+            chunk_size,
+            current: Cell::new(ptr::null_mut()),
+            chunks: Cell::new(0),
+            #[cfg(any(test, feature = "check-invariants"))]
+            allocations: RefCell::new(Vec::new()),
+        };
+        let chunk = a.new_chunk(chunk_size, CHUNK_ALIGNMENT, ptr::null_mut())?;
+        a.current.set(chunk);
+        Ok(a)
+    }
+
+    /// Allocates a chunk that comes after `prev` in the list.
+    fn new_chunk(&self, size: usize, alignment: usize, prev: *mut Chunk) -> Result<*mut Chunk, AllocError> {
+        let arena = Arena::new(size, alignment)?;
+        let chunk = unsafe { ::alloc::aligned_alloc(mem::size_of::<Chunk>(), mem::align_of::<Chunk>())? }
+            as *mut Chunk;
+        unsafe { ptr::write(chunk, Chunk { arena, prev }) };
+        self.chunks.set(self.chunks.get() + 1);
+        Ok(chunk)
+    }
+
+    fn current(&self) -> &Arena {
+        unsafe { &(*self.current.get()).arena }
+    }
+
+    /// Returns the size of a regular chunk.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Returns the number of chunks allocated so far, including dedicated
+    /// ones.
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.get()
+    }
+
+    /// Allocates `size` bytes aligned to `alignment`, and returns them
+    /// along with the chunk they are in.
+    fn alloc_in_chunk(&self, size: usize, alignment: usize) -> Result<(*mut u8, &Arena), AllocError> {
+        let (p, arena) = self.find_room(size, alignment)?;
+        #[cfg(any(test, feature = "check-invariants"))]
+        {
+            if size != 0 {
+                self.allocations.borrow_mut().push(p as usize..p as usize + size);
+            }
+        }
+        Ok((p, arena))
+    }
+
+    /// Does the work of `alloc_in_chunk`, allocating a new chunk if the
+    /// current one is full.
+    fn find_room(&self, size: usize, alignment: usize) -> Result<(*mut u8, &Arena), AllocError> {
+        if let Ok(p) = self.current().try_aligned_alloc(size, alignment) {
+            return Ok((p, self.current()));
+        }
+        // An empty chunk needs padding in front of allocations that are
+        // more aligned than the chunk itself.
+        let padded = size.checked_add(alignment.saturating_sub(CHUNK_ALIGNMENT));
+        let chunk = match padded {
+            Some(padded) if padded <= self.chunk_size => {
+                let chunk = self.new_chunk(self.chunk_size, CHUNK_ALIGNMENT, self.current.get())?;
+                self.current.set(chunk);
+                chunk
+            }
+            // A dedicated chunk goes behind the current one, which stays
+            // in use.
+            _ => unsafe {
+                let current = self.current.get();
+                let chunk = self.new_chunk(size, alignment.max(CHUNK_ALIGNMENT), (*current).prev)?;
+                (*current).prev = chunk;
+                chunk
+            },
+        };
+        let arena = unsafe { &(*chunk).arena };
+        Ok((arena.try_aligned_alloc(size, alignment)?, arena))
+    }
+
+    /// Allocates uninitialized memory for `layout`, from a new chunk if
+    /// necessary.
+    pub fn alloc(&self, layout: Layout) -> Result<ArenaBox<'_, [MaybeUninit<u8>]>, AllocError> {
+        let (p, arena) = self.alloc_in_chunk(layout.size(), layout.align())?;
+        let p = ptr::slice_from_raw_parts_mut(p as *mut MaybeUninit<u8>, layout.size());
+        Ok(unsafe { ArenaBox::from_raw_in(p, arena) })
+    }
+
+    /// Places `x` in the arena, and returns the resulting `ArenaBox`,
+    /// wrapped in `Result::Ok`.
+    ///
+    /// If a new chunk is needed and cannot be allocated, then the original
+    /// value is returned, wrapped in `Result::Err`.
+    pub fn new_box<T>(&self, x: T) -> Result<ArenaBox<'_, T>, T> {
+        match self.alloc_in_chunk(mem::size_of::<T>(), mem::align_of::<T>()) {
+            Err(_) => Err(x),
+            Ok((p, arena)) => unsafe {
+                let p = p as *mut T;
+                ptr::write(p, x);
+                Ok(ArenaBox::from_raw_in(p, arena))
+            },
+        }
+    }
+
+    /// Places `x` in the arena, and returns it pinned.
+    ///
+    /// The value is never moved until it is dropped, since the memory of a
+    /// `GrowableArena` never moves.
+    ///
+    /// # Safety
+    ///
+    /// The box must be dropped before the arena is, rather than leaked: the
+    /// arena frees its chunks without running the destructors of the values
+    /// in them. See [`ArenaBox::into_pin`].
+    ///
+    /// [`ArenaBox::into_pin`]: struct.ArenaBox.html#method.into_pin
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// let a = GrowableArena::new(64).unwrap();
+    /// let x = unsafe { a.new_pinned_box(5) }.unwrap();
+    /// assert_eq!(*x, 5);
+    /// ```
+    pub unsafe fn new_pinned_box<T>(&self, x: T) -> Result<Pin<ArenaBox<'_, T>>, T> {
+        self.new_box(x).map(|b| ArenaBox::into_pin(b))
+    }
+
+    /// Copies `src` into the arena and returns the copy.
+    pub fn alloc_slice_copy<T: Copy>(&self, src: &[T]) -> Result<ArenaBox<'_, [T]>, AllocError> {
+        let (p, arena) = self.alloc_in_chunk(mem::size_of_val(src), mem::align_of::<T>())?;
+        unsafe {
+            let p = p as *mut T;
+            ptr::copy_nonoverlapping(src.as_ptr(), p, src.len());
+            Ok(ArenaBox::from_raw_in(ptr::slice_from_raw_parts_mut(p, src.len()), arena))
+        }
+    }
+
+    /// Copies `s` into the arena and returns the copy.
+    pub fn alloc_str(&self, s: &str) -> Result<ArenaBox<'_, str>, AllocError> {
+        let (bytes, live) = ArenaBox::into_raw_parts(self.alloc_slice_copy(s.as_bytes())?);
+        Ok(unsafe { ArenaBox::from_raw_parts(bytes as *mut str, live) })
+    }
+
+    /// Returns the address range of each chunk, newest first.
+    ///
+    /// This is for checking that growing the arena leaves the memory of
+    /// earlier allocations where it was.
+    #[doc(hidden)]
+    pub fn chunk_ranges(&self) -> ChunkRanges<'_> {
+        ChunkRanges {
+            next: self.current.get(),
+            phantom: ::core::marker::PhantomData,
+        }
+    }
+
+    /// Returns the address range of every allocation, oldest first, not
+    /// counting zero-sized ones.
+    ///
+    /// No memory is handed out twice before the arena is dropped, so every
+    /// allocation counts as live, even once its box is gone, and a later
+    /// snapshot starts with the ranges of an earlier one, unchanged. This
+    /// is for checking that growing the arena, or any future in-place grow
+    /// or shrink, leaves earlier allocations where they were. It is only
+    /// available in tests and with the `check-invariants` feature.
+    #[doc(hidden)]
+    #[cfg(any(test, feature = "check-invariants"))]
+    pub fn live_addresses(&self) -> Vec<Range<usize>> {
+        self.allocations.borrow().clone()
+    }
+
+    /// Verifies the internal consistency of the arena, panicking with a
+    /// description of the first violated invariant, like
+    /// [`Arena::check_invariants`].
+    ///
+    /// This is meant for tests and fuzzing, and is only available with the
+    /// `check-invariants` feature.
+    ///
+    /// [`Arena::check_invariants`]: struct.Arena.html#method.check_invariants
+    #[cfg(any(test, feature = "check-invariants"))]
+    pub fn check_invariants(&self) {
+        let mut count = 0;
+        let mut chunk = self.current.get();
+        while !chunk.is_null() {
+            let c = unsafe { &*chunk };
+            c.arena.check_invariants();
+            count += 1;
+            chunk = c.prev;
+        }
+        assert!(
+            count == self.chunks.get(),
+            "arena invariant violated: {} chunks counted, but {} in the list",
+            self.chunks.get(),
+            count
+        );
+        // Every allocation is in a chunk, and none of them overlap.
+        let chunks: Vec<_> = self.chunk_ranges().collect();
+        let mut live = self.live_addresses();
+        for r in &live {
+            assert!(
+                chunks.iter().any(|c| c.start <= r.start && r.end <= c.end),
+                "arena invariant violated: allocation at {:#x} is outside every chunk",
+                r.start
+            );
+        }
+        live.sort_by_key(|r| r.start);
+        for w in live.windows(2) {
+            assert!(
+                w[0].end <= w[1].start,
+                "arena invariant violated: allocations at {:#x} and {:#x} overlap",
+                w[0].start,
+                w[1].start
+            );
+        }
+    }
+}
+
+impl Drop for GrowableArena {
+    fn drop(&mut self) {
+        let mut chunk = self.current.get();
+        while !chunk.is_null() {
+            unsafe {
+                let prev = (*chunk).prev;
+                ptr::drop_in_place(chunk);
+                ::alloc::free(chunk as *mut u8, mem::size_of::<Chunk>(), mem::align_of::<Chunk>());
+                chunk = prev;
+            }
+        }
+    }
+}
+
+impl fmt::Debug for GrowableArena {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GrowableArena")
+            .field("chunk_size", &self.chunk_size)
+            .field("chunks", &self.chunks.get())
+            .finish()
+    }
+}
+
+/// An iterator over the address ranges of the chunks of a
+/// `GrowableArena`.
+#[doc(hidden)]
+pub struct ChunkRanges<'a> {
+    next: *mut Chunk,
+    phantom: ::core::marker::PhantomData<&'a GrowableArena>,
+}
+
+impl<'a> Iterator for ChunkRanges<'a> {
+    type Item = ::core::ops::Range<usize>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next.is_null() {
+            return None;
+        }
+        let chunk = unsafe { &*self.next };
+        self.next = chunk.prev;
+        let start = chunk.arena.mem as usize;
+        Some(start..start + chunk.arena.capacity())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use core::cell::Cell;
+    use std::vec::Vec;
+    use test_util::{checked, live_allocations, DropCounter};
+
+    #[test]
+    fn growable_across_chunks() {
+        let a = checked(GrowableArena::new(64).unwrap());
+        let boxes: Vec<_> = (0..40u32).map(|i| a.new_box([i; 3]).unwrap()).collect();
+        // Five 12-byte arrays fit in each chunk.
+        assert_eq!(a.chunk_count(), 8);
+        for (i, b) in boxes.iter().enumerate() {
+            assert_eq!(**b, [i as u32; 3]);
+        }
+        let s = a.alloc_str("text").unwrap();
+        assert_eq!(&*s, "text");
+        assert_eq!(a.chunk_count(), 8);
+    }
+
+    #[test]
+    fn growable_dedicated_chunk() {
+        let a = checked(GrowableArena::new(64).unwrap());
+        let small = a.new_box(1u8).unwrap();
+        let big = a.alloc_slice_copy(&[7u64; 100]).unwrap();
+        assert_eq!((big.len(), big[99]), (100, 7));
+        assert_eq!(a.chunk_count(), 2);
+        // The regular chunk is still used for small allocations.
+        let next = a.new_box(2u8).unwrap();
+        assert_eq!(&*next as *const u8 as usize, &*small as *const u8 as usize + 1);
+        assert_eq!(a.chunk_count(), 2);
+
+        let aligned = a.alloc(Layout::from_size_align(64, 64).unwrap()).unwrap();
+        assert_eq!(aligned.as_ptr() as usize % 64, 0);
+        assert_eq!(a.chunk_count(), 3);
+    }
+
+    #[test]
+    fn growable_addresses_stable() {
+        let a = checked(GrowableArena::new(128).unwrap());
+        let early: Vec<_> = (0..8u64).map(|i| unsafe { a.new_pinned_box(i * 11) }.unwrap()).collect();
+        let addresses: Vec<_> = early.iter().map(|b| &**b as *const u64 as usize).collect();
+        let snapshot = a.live_addresses();
+        assert_eq!(snapshot.iter().map(|r| r.start).collect::<Vec<_>>(), addresses);
+        let chunks: Vec<_> = a.chunk_ranges().collect();
+        assert_eq!(chunks.len(), 1);
+        let mut later = Vec::new();
+        for i in 0..200u64 {
+            later.push(a.new_box([i; 4]).unwrap());
+        }
+        let _big = a.alloc_slice_copy(&[0u8; 1000]).unwrap();
+        assert!(a.chunk_count() > 10);
+        for (i, b) in early.iter().enumerate() {
+            assert_eq!(&**b as *const u64 as usize, addresses[i]);
+            assert_eq!(**b, i as u64 * 11);
+        }
+        // The first chunk is still the same memory, now at the end.
+        assert_eq!(a.chunk_ranges().last(), Some(chunks[0].clone()));
+        assert!(addresses.iter().all(|p| chunks[0].contains(p)));
+        let now = a.live_addresses();
+        assert_eq!(now.len(), 8 + 200 + 1);
+        assert_eq!(now[..8], snapshot[..]);
+    }
+
+    #[test]
+    fn growable_drop_frees_chunks() {
+        let drops = Cell::new(0);
+        let before = live_allocations();
+        {
+            let a = checked(GrowableArena::new(32).unwrap());
+            for _ in 0..20 {
+                drop(a.new_box(DropCounter(&drops)).unwrap());
+            }
+            let _big = a.alloc_slice_copy(&[1u8; 100]).unwrap();
+            assert_eq!(live_allocations() - before, 2 * a.chunk_count() as isize);
+        }
+        assert_eq!(live_allocations(), before);
+        assert_eq!(drops.get(), 20);
+    }
+
+    #[test]
+    #[should_panic(expected = "arena invariant violated: 3 chunks counted, but 2 in the list")]
+    fn growable_check_invariants_chunks() {
+        let a = GrowableArena::new(16).unwrap();
+        let _ = a.alloc_slice_copy(&[0u8; 32]).unwrap();
+        a.chunks.set(3);
+        a.check_invariants();
+    }
+
+    #[test]
+    #[should_panic(expected = "arena invariant violated: allocations at")]
+    fn growable_check_invariants_overlap() {
+        let a = GrowableArena::new(64).unwrap();
+        let x = a.new_box(1u64).unwrap();
+        let p = &*x as *const u64 as usize;
+        a.allocations.borrow_mut().push(p + 4..p + 12);
+        a.check_invariants();
+    }
+}
@@ -0,0 +1,250 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Shared ownership of an arena, for boxes without a lifetime.
+
+use core::fmt;
+use core::mem;
+use core::ops::{Deref, DerefMut};
+use rust_alloc::rc::Rc;
+
+use alloc::AllocError;
+use arena_box::LiveGuard;
+use {Arena, ArenaBox};
+
+/// A reference-counted handle to an `Arena`, whose boxes keep the Arena
+/// alive.
+///
+/// An [`OwnedArenaBox`] holds a clone of the handle instead of borrowing
+/// the Arena, so it has no lifetime parameter, and can be stored in
+/// long-lived structures, or anywhere a `'static` bound applies. The Arena
+/// and its memory are freed once every handle and every box are gone.
+/// Cloning a handle is cheap, and allocating through it works just like
+/// allocating from the Arena, which it dereferences to.
+///
+/// [`OwnedArenaBox`]: struct.OwnedArenaBox.html
+///
+/// # Examples
+///
+/// ```
+/// # use memory_arena::*;
+/// use std::collections::HashMap;
+///
+/// struct Cache {
+///     entries: HashMap<u32, OwnedArenaBox<str>>,
+/// }
+///
+/// let arena = ArenaHandle::new(1024, 1024).unwrap();
+/// let mut cache = Cache { entries: HashMap::new() };
+/// cache.entries.insert(1, arena.alloc_str("one").unwrap());
+/// drop(arena);
+/// assert_eq!(&*cache.entries[&1], "one");
+/// ```
+#[derive(Clone)]
+pub struct ArenaHandle {
+    arena: Rc<Arena>,
+}
+
+impl ArenaHandle {
+    /// Creates a handle to a new `Arena` of `size` bytes, aligned to
+    /// `alignment`; see [`Arena::new`].
+    ///
+    /// [`Arena::new`]: struct.Arena.html#method.new
+    pub fn new(size: usize, alignment: usize) -> Result<Self, AllocError> {
+        Ok(Arena::new(size, alignment)?.into())
+    }
+
+    /// Returns the Arena if this is the only handle and no boxes are left,
+    /// and the handle otherwise.
+    pub fn try_unwrap(this: Self) -> Result<Arena, Self> {
+        Rc::try_unwrap(this.arena).map_err(|arena| ArenaHandle { arena })
+    }
+
+    /// Returns the number of handles and boxes that share the Arena.
+    pub fn owner_count(this: &Self) -> usize {
+        Rc::strong_count(&this.arena)
+    }
+
+    /// Turns a box from this Arena into one that keeps the Arena alive.
+    fn own<T: ?Sized>(&self, b: ArenaBox<'_, T>) -> OwnedArenaBox<T> {
+        let (raw, live) = ArenaBox::into_raw_parts(b);
+        // The box can claim any lifetime, since the handle stored next to
+        // it keeps the Arena alive for as long as the box exists.
+        let live = unsafe { mem::transmute::<LiveGuard<'_>, LiveGuard<'static>>(live) };
+        OwnedArenaBox {
+            value: unsafe { ArenaBox::from_raw_parts(raw, live) },
+            arena: self.clone(),
+        }
+    }
+
+    /// Moves `x` into the Arena, like [`Arena::new_box`].
+    ///
+    /// [`Arena::new_box`]: struct.Arena.html#method.new_box
+    pub fn new_box<T>(&self, x: T) -> Result<OwnedArenaBox<T>, T> {
+        self.arena.new_box(x).map(|b| self.own(b))
+    }
+
+    /// Copies `s` into the Arena, like [`Arena::alloc_slice_copy`].
+    ///
+    /// [`Arena::alloc_slice_copy`]: struct.Arena.html#method.alloc_slice_copy
+    pub fn alloc_slice_copy<T: Copy>(&self, s: &[T]) -> Result<OwnedArenaBox<[T]>, AllocError> {
+        self.arena.alloc_slice_copy(s).map(|b| self.own(b))
+    }
+
+    /// Copies `s` into the Arena, like [`Arena::alloc_str`].
+    ///
+    /// [`Arena::alloc_str`]: struct.Arena.html#method.alloc_str
+    pub fn alloc_str(&self, s: &str) -> Result<OwnedArenaBox<str>, AllocError> {
+        self.arena.alloc_str(s).map(|b| self.own(b))
+    }
+}
+
+impl From<Arena> for ArenaHandle {
+    fn from(arena: Arena) -> Self {
+        ArenaHandle {
+            arena: Rc::new(arena),
+        }
+    }
+}
+
+impl Deref for ArenaHandle {
+    type Target = Arena;
+
+    fn deref(&self) -> &Arena {
+        &self.arena
+    }
+}
+
+impl fmt::Debug for ArenaHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ArenaHandle").field("arena", &*self.arena).finish()
+    }
+}
+
+/// A box in an `Arena` that shares ownership of the Arena, through an
+/// [`ArenaHandle`].
+///
+/// [`ArenaHandle`]: struct.ArenaHandle.html
+pub struct OwnedArenaBox<T: ?Sized> {
+    // Fields are dropped in order, so the value is always dropped before
+    // the handle can free the Arena's memory.
+    value: ArenaBox<'static, T>,
+    arena: ArenaHandle,
+}
+
+impl<T: ?Sized> OwnedArenaBox<T> {
+    /// Returns the handle to the Arena the value lives in.
+    pub fn arena(b: &Self) -> &ArenaHandle {
+        &b.arena
+    }
+}
+
+impl<T: ?Sized> Deref for OwnedArenaBox<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T: ?Sized> DerefMut for OwnedArenaBox<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for OwnedArenaBox<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for OwnedArenaBox<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use core::any::Any;
+    use core::cell::RefCell;
+    use std::boxed::Box;
+    use std::string::{String, ToString};
+    use std::vec::Vec;
+    use test_util::live_allocations;
+
+    /// Something that only takes `'static` values.
+    struct Store(Vec<Box<dyn Any>>);
+
+    impl Store {
+        fn put<T: 'static>(&mut self, x: T) {
+            self.0.push(Box::new(x));
+        }
+    }
+
+    struct Logged(u32, Rc<RefCell<Vec<u32>>>);
+
+    impl Drop for Logged {
+        fn drop(&mut self) {
+            self.1.borrow_mut().push(self.0);
+        }
+    }
+
+    #[test]
+    fn owned_outlives_handle() {
+        let live = live_allocations();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut store = Store(Vec::new());
+        {
+            let arena = ArenaHandle::new(1024, 64).unwrap();
+            for i in 0..3 {
+                store.put(arena.new_box(Logged(i, log.clone())).ok().unwrap());
+            }
+            store.put(arena.alloc_str(&5.to_string()).unwrap());
+            store.put(arena.alloc_slice_copy(&[1u16, 2, 3]).unwrap());
+            assert_eq!(ArenaHandle::owner_count(&arena), 6);
+        }
+        assert_eq!(live_allocations(), live + 1);
+        let s = store.0[3].downcast_ref::<OwnedArenaBox<str>>().unwrap();
+        assert_eq!(&**s, "5");
+        assert_eq!(OwnedArenaBox::arena(s).used(), 3 * 16 + 1 + 1 + 6);
+
+        store.0.truncate(1);
+        assert_eq!(*log.borrow(), [1, 2]);
+        assert_eq!(live_allocations(), live + 1);
+        // The last box drops its value before the Arena's memory is freed.
+        let b = store.0.pop().unwrap().downcast::<OwnedArenaBox<Logged>>().ok().unwrap();
+        assert_eq!(b.0, 0);
+        drop(b);
+        assert_eq!(*log.borrow(), [1, 2, 0]);
+        assert_eq!(live_allocations(), live);
+    }
+
+    #[test]
+    fn owned_try_unwrap() {
+        let arena = ArenaHandle::new(1024, 64).unwrap();
+        let mut b = arena.new_box(String::from("x")).unwrap();
+        b.push('y');
+        let arena = ArenaHandle::try_unwrap(arena).unwrap_err();
+        assert_eq!(format!("{}", b), "xy");
+        drop(b);
+        let mut arena = ArenaHandle::try_unwrap(arena).unwrap();
+        arena.reset();
+        assert_eq!(arena.used(), 0);
+    }
+
+    #[test]
+    fn owned_exhausted() {
+        let arena = ArenaHandle::new(8, 8).unwrap();
+        assert_eq!(arena.new_box([0u8; 9]).unwrap_err(), [0; 9]);
+        assert_eq!(ArenaHandle::owner_count(&arena), 1);
+    }
+}
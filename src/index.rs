@@ -0,0 +1,412 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Values in an arena addressed by small `Copy` handles rather than by
+//! references.
+
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::marker::PhantomData;
+use core::mem;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+#[cfg(feature = "live-count")]
+use arena_box::LiveGuard;
+use Arena;
+
+/// Hands out the tags that tell the handles of different Arenas apart.
+static NEXT_TAG: AtomicU32 = AtomicU32::new(0);
+
+/// Returns a tag that no Arena has had for a long time.
+fn next_tag() -> u32 {
+    NEXT_TAG.fetch_add(1, Ordering::Relaxed)
+}
+
+fn stamp(tag: u32, generation: u32) -> u64 {
+    (tag as u64) << 32 | generation as u64
+}
+
+/// Returns the handle stamp of a new Arena, with a fresh tag and
+/// generation zero.
+pub(crate) fn first_stamp() -> u64 {
+    stamp(next_tag(), 0)
+}
+
+/// A handle to a value of type `T` placed in an `Arena` with
+/// [`Arena::insert`].
+///
+/// A handle is the offset of the value in the Arena's memory, together
+/// with a tag for the Arena and the Arena's generation, in twelve bytes.
+/// It has no lifetime and is
+/// `Copy`, `Send` and `Sync` whatever `T` is, so it can be stored in
+/// long-lived graphs, used as a map key or sent to another thread, and
+/// only gives access to the value through the Arena it came from.
+///
+/// Unlike a `Symbol`, whose table tag is only checked in debug builds, a
+/// handle is checked in release builds too, since reading another Arena's
+/// memory as a `T` would be undefined behavior. A reset or rewind, or the
+/// end of an [`Arena::scope`], starts a new [`Arena::generation`], which
+/// makes the handles from before it stale: [`Arena::try_get`] returns
+/// `None` for them, and for handles from a different Arena, and
+/// [`Arena::get`] panics. The check is a single
+/// 64-bit compare of the tag and generation.
+///
+/// The generation is a `u32`, and wraps around to zero after 2<sup>32</sup>
+/// resets. When it does, the Arena takes a new tag, so handles from the
+/// generation zero before are still told apart. Tags come from a global
+/// 32-bit counter, one per Arena and per wraparound, so a stale handle can
+/// only be mistaken for a current one once that counter has wrapped around
+/// too.
+///
+/// [`Arena::insert`]: struct.Arena.html#method.insert
+/// [`Arena::scope`]: struct.Arena.html#method.scope
+/// [`Arena::generation`]: struct.Arena.html#method.generation
+/// [`Arena::try_get`]: struct.Arena.html#method.try_get
+/// [`Arena::get`]: struct.Arena.html#method.get
+pub struct Handle<T> {
+    offset: u32,
+    tag: u32,
+    generation: u32,
+    phantom: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    /// Returns the offset of the value in the Arena's memory.
+    pub fn offset(self) -> u32 {
+        self.offset
+    }
+
+    /// Returns the generation of the Arena the handle was made in.
+    pub fn generation(self) -> u32 {
+        self.generation
+    }
+
+    fn stamp(self) -> u64 {
+        stamp(self.tag, self.generation)
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.offset, self.stamp()) == (other.offset, other.stamp())
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.offset, self.stamp()).hash(state)
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Handle")
+            .field("offset", &self.offset)
+            .field("tag", &self.tag)
+            .field("generation", &self.generation)
+            .finish()
+    }
+}
+
+impl Arena {
+    /// Returns the generation of the Arena's handles, which goes up by one,
+    /// wrapping around, with every reset, rewind and scope.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// let mut a = Arena::new(1024, 1024).unwrap();
+    /// let h = a.insert(1u32).unwrap();
+    /// a.reset();
+    /// assert_eq!((h.generation(), a.generation()), (0, 1));
+    /// assert!(a.try_get(h).is_none());
+    /// # }
+    /// ```
+    pub fn generation(&self) -> u32 {
+        self.handle_stamp.get() as u32
+    }
+
+    /// Starts a new generation, after the cursor moved back over memory
+    /// that handles might point to.
+    pub(crate) fn next_generation(&self) {
+        let old = self.handle_stamp.get();
+        let generation = (old as u32).wrapping_add(1);
+        let tag = if generation == 0 { next_tag() } else { (old >> 32) as u32 };
+        self.handle_stamp.set(stamp(tag, generation));
+    }
+
+    /// Places `x` in the Arena, and returns a handle to it.
+    ///
+    /// The value is only reachable through the handle, with
+    /// [`Arena::get`] and [`Arena::get_mut`], and like a leaked `ArenaBox`
+    /// it is never dropped; with the `live-count` feature it also counts
+    /// as a live allocation for good, since [`Arena::try_get`] borrows it
+    /// for as long as the Arena. It is always placed in the Arena's main
+    /// buffer, within its first 4 GiB, so that its offset fits in a `u32`.
+    /// If there is no room for it there, then `x` is returned, wrapped in
+    /// `Result::Err`.
+    ///
+    /// [`Arena::get`]: #method.get
+    /// [`Arena::get_mut`]: #method.get_mut
+    /// [`Arena::try_get`]: #method.try_get
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// # #[cfg(feature = "alloc")] {
+    /// struct Node {
+    ///     name: &'static str,
+    ///     next: Option<Handle<Node>>,
+    /// }
+    ///
+    /// let mut a = Arena::new(1024, 1024).unwrap();
+    /// let tail = a.insert(Node { name: "tail", next: None }).ok().unwrap();
+    /// let head = a.insert(Node { name: "head", next: Some(tail) }).ok().unwrap();
+    /// let next = a.get(head).next.unwrap();
+    /// a.get_mut(next).name = "last";
+    /// assert_eq!(a.get(tail).name, "last");
+    /// # }
+    /// ```
+    pub fn insert<T>(&self, x: T) -> Result<Handle<T>, T> {
+        let before = self.checkpoint();
+        let p = match self.try_alloc_main(mem::size_of::<T>(), mem::align_of::<T>()) {
+            Ok(p) => p,
+            Err(_) => return Err(x),
+        };
+        let offset = if mem::size_of::<T>() == 0 {
+            0
+        } else {
+            p as usize - self.mem as usize
+        };
+        if offset > u32::MAX as usize {
+            self.roll_back(before, self.checkpoint());
+            return Err(x);
+        }
+        unsafe { (p as *mut T).write(x) };
+        #[cfg(feature = "live-count")]
+        mem::forget(LiveGuard::new(self));
+        let stamp = self.handle_stamp.get();
+        Ok(Handle {
+            offset: offset as u32,
+            tag: (stamp >> 32) as u32,
+            generation: stamp as u32,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Returns a pointer to the value of `h`, if it is one of this Arena's
+    /// handles from the current generation.
+    #[inline]
+    fn handle_ptr<T>(&self, h: Handle<T>) -> Option<*mut T> {
+        if h.stamp() != self.handle_stamp.get() {
+            return None;
+        }
+        if mem::size_of::<T>() == 0 {
+            return Some(NonNull::dangling().as_ptr());
+        }
+        debug_assert!(h.offset as usize + mem::size_of::<T>() <= self.used.get());
+        Some(unsafe { self.mem.add(h.offset as usize) as *mut T })
+    }
+
+    /// Returns the value of a handle from [`Arena::insert`], or `None` if
+    /// the handle comes from a different Arena, or from an earlier
+    /// generation of this one.
+    ///
+    /// [`Arena::insert`]: #method.insert
+    pub fn try_get<T>(&self, h: Handle<T>) -> Option<&T> {
+        self.handle_ptr(h).map(|p| unsafe { &*p })
+    }
+
+    /// Like [`Arena::try_get`], but returns the value mutably.
+    ///
+    /// [`Arena::try_get`]: #method.try_get
+    pub fn try_get_mut<T>(&mut self, h: Handle<T>) -> Option<&mut T> {
+        self.handle_ptr(h).map(|p| unsafe { &mut *p })
+    }
+
+    /// Returns the value of a handle from [`Arena::insert`].
+    ///
+    /// [`Arena::insert`]: #method.insert
+    ///
+    /// # Panics
+    ///
+    /// Panics if `h` comes from a different Arena, or from before this
+    /// Arena was last reset or rewound.
+    pub fn get<T>(&self, h: Handle<T>) -> &T {
+        self.try_get(h).expect("Handle used with a different Arena, or after a reset")
+    }
+
+    /// Returns the value of a handle from [`Arena::insert`], mutably.
+    ///
+    /// [`Arena::insert`]: #method.insert
+    ///
+    /// # Panics
+    ///
+    /// Panics if `h` comes from a different Arena, or from before this
+    /// Arena was last reset or rewound.
+    pub fn get_mut<T>(&mut self, h: Handle<T>) -> &mut T {
+        self.try_get_mut(h).expect("Handle used with a different Arena, or after a reset")
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use std::collections::HashMap;
+    use std::panic;
+    use std::string::String;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::vec::Vec;
+    use test_util::checked_arena;
+
+    #[test]
+    fn handles_in_hash_map() {
+        let a = checked_arena(4096, 4096);
+        let mut by_name = HashMap::new();
+        for (i, name) in ["alpha", "beta", "gamma"].iter().enumerate() {
+            let h = a.insert(String::from(*name)).unwrap();
+            by_name.insert(h, i);
+        }
+        let mut names: Vec<_> = by_name.iter().map(|(&h, &i)| (i, a.get(h).as_str())).collect();
+        names.sort();
+        assert_eq!(names, [(0, "alpha"), (1, "beta"), (2, "gamma")]);
+        assert_eq!(mem::size_of::<Handle<String>>(), 12);
+
+        // Handles can cross threads, and be resolved back home.
+        let (tx, rx) = mpsc::channel();
+        let keys: Vec<_> = by_name.keys().copied().collect();
+        thread::spawn(move || keys.into_iter().for_each(|h| tx.send(h).unwrap()));
+        let mut total = 0;
+        for h in rx {
+            total += a.get(h).len();
+        }
+        assert_eq!(total, 5 + 4 + 5);
+    }
+
+    #[test]
+    fn handles_mixed_types() {
+        let mut a = checked_arena(1024, 1024);
+        let byte = a.insert(7u8).unwrap();
+        let word = a.insert(0x1234_5678u32).unwrap();
+        let unit = a.insert(()).unwrap();
+        let pair = a.insert((1.5f64, 'x')).unwrap();
+        assert_eq!((byte.offset(), word.offset(), pair.offset()), (0, 4, 8));
+        *a.get_mut(word) += 1;
+        a.get_mut(pair).1 = 'y';
+        assert_eq!((*a.get(byte), *a.get(word)), (7, 0x1234_5679));
+        assert_eq!((*a.get(unit), *a.get(pair)), ((), (1.5, 'y')));
+        assert_eq!(a.used(), 8 + 16);
+    }
+
+    #[test]
+    fn handles_full() {
+        let a = checked_arena(8, 8);
+        assert!(a.insert(1u64).is_ok());
+        assert_eq!(a.insert(2u8), Err(2));
+        assert_eq!(a.used(), 8);
+    }
+
+    #[cfg(feature = "live-count")]
+    #[test]
+    fn handles_try_reset() {
+        let a = checked_arena(64, 64);
+        let h = a.insert(*b"hello").unwrap();
+        let r = a.get(h);
+        // `r` borrows the Arena, not a box, so the value has to stay.
+        assert_eq!(a.try_reset(), Err(::ResetError { live: 1 }));
+        let _ = a.alloc_slice_copy(b"XXXXX").unwrap();
+        assert_eq!(r, b"hello");
+        assert!(a.try_get(h).is_some());
+    }
+
+    #[test]
+    fn handles_wrong_arena() {
+        let a = checked_arena(64, 64);
+        let b = checked_arena(64, 64);
+        let h = a.insert(5u32).unwrap();
+        let _ = b.insert(6u32).unwrap();
+        assert_eq!(h.generation(), b.generation());
+        assert!(b.try_get(h).is_none());
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| *b.get(h)));
+        assert!(result.is_err());
+        assert_eq!(*a.get(h), 5);
+    }
+
+    #[test]
+    fn handles_after_reset() {
+        let mut a = checked_arena(64, 64);
+        let h = a.insert(5u32).unwrap();
+        a.reset();
+        let h2 = a.insert(6u32).unwrap();
+        assert_eq!(h.offset(), h2.offset());
+        assert_eq!((h.generation(), h2.generation()), (0, 1));
+        assert_ne!(h, h2);
+        assert!(a.try_get(h).is_none() && a.try_get_mut(h).is_none());
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| *a.get(h)));
+        assert!(result.is_err());
+        assert_eq!(*a.get(h2), 6);
+        *a.try_get_mut(h2).unwrap() += 1;
+        assert_eq!(a.try_get(h2), Some(&7));
+
+        let cp = a.checkpoint();
+        a.rewind(cp);
+        assert_eq!(a.generation(), 2);
+        assert!(a.try_get(h2).is_none());
+    }
+
+    #[test]
+    fn handles_escaping_scope() {
+        let mut a = checked_arena(64, 64);
+        let escaped = a.scope(|a| a.insert(5u64).unwrap());
+        assert_eq!((a.used(), a.generation()), (0, 1));
+        assert!(a.try_get(escaped).is_none() && a.try_get_mut(escaped).is_none());
+        // Even a value of another type at the same offset cannot be read
+        // through it.
+        let s = a.insert("str").unwrap();
+        assert_eq!(s.offset(), escaped.offset());
+        assert!(a.try_get(escaped).is_none());
+        assert_eq!(*a.get(s), "str");
+    }
+
+    #[test]
+    fn handles_generation_wraps() {
+        let mut a = checked_arena(64, 64);
+        let first = a.insert(1u8).unwrap();
+        let tag = (a.handle_stamp.get() >> 32) as u32;
+        a.handle_stamp.set(stamp(tag, u32::MAX - 1));
+        a.reset();
+        let last = a.insert(2u8).unwrap();
+        assert_eq!(last.generation(), u32::MAX);
+        a.reset();
+        // The generation wraps around to zero, and a new tag keeps the
+        // handles from the first generation zero stale.
+        assert_eq!(a.generation(), 0);
+        let wrapped = a.insert(3u8).unwrap();
+        assert_eq!((first.offset(), first.generation()), (wrapped.offset(), 0));
+        assert!(a.try_get(first).is_none() && a.try_get(last).is_none());
+        assert_eq!(*a.get(wrapped), 3);
+        a.reset();
+        assert_eq!(a.generation(), 1);
+    }
+}
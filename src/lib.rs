@@ -7,16 +7,183 @@
 // except according to those terms.
 
 #![no_std]
+#![cfg_attr(any(feature = "allocator_api", feature = "fn_traits"), feature(allocator_api))]
+#![cfg_attr(feature = "coerce_unsized", feature(coerce_unsized, unsize))]
+#![cfg_attr(feature = "sanitize", feature(cfg_sanitize))]
+#![cfg_attr(
+    feature = "fn_traits",
+    feature(fn_traits, tuple_trait, unboxed_closures)
+)]
 
+#[cfg(feature = "alloc")]
+extern crate alloc as rust_alloc;
+#[cfg(feature = "libc")]
 extern crate libc;
+#[cfg(feature = "futures")]
+extern crate futures_core;
+#[cfg(feature = "tokio")]
+extern crate tokio;
+#[cfg(feature = "postcard")]
+extern crate postcard;
+#[cfg(feature = "bincode")]
+extern crate bincode;
+#[cfg(loom)]
+extern crate loom;
+
+#[cfg(any(test, feature = "std", feature = "tokio"))]
+#[macro_use]
+extern crate std;
+#[cfg(all(test, feature = "futures"))]
+extern crate futures;
+#[cfg(any(feature = "serde", all(test, any(feature = "postcard", feature = "bincode"))))]
+extern crate serde;
 
 mod coerce_unsized;
 mod nonzero;
 mod unsize;
 mod unique;
 mod alloc;
+#[cfg(feature = "allocator_api")]
+mod allocator_api;
+mod cleanup;
 mod arena_box;
 mod arena;
+#[cfg(feature = "alloc")]
+mod backing;
+mod arc;
+#[cfg(feature = "alloc")]
+mod builder;
+#[cfg(feature = "alloc")]
+mod child;
+#[cfg(feature = "libc")]
+mod pages;
+mod rc;
+mod reclaiming;
+mod regions;
+mod sanitize;
+mod valgrind;
+#[cfg(feature = "registry")]
+pub mod registry;
+#[cfg(feature = "dyn-clone")]
+mod dyn_clone;
+#[cfg(feature = "alloc")]
+mod epoch;
+mod eq;
+#[cfg(feature = "fixed-address")]
+mod fixed;
+mod fn_once;
+#[cfg(feature = "alloc")]
+mod growable;
+#[cfg(feature = "alloc")]
+mod handle;
+mod index;
+#[cfg(feature = "oom-abort")]
+mod oom;
+mod linked_list;
+mod rope;
+mod slice_builder;
+mod small_vec;
+mod soa;
+mod string;
+mod tagged;
+#[cfg(feature = "alloc")]
+mod typed;
+mod vec;
+#[cfg(any(all(test, feature = "std"), feature = "postcard", feature = "bincode"))]
+mod reserve;
+#[cfg(feature = "postcard")]
+mod postcard_flavor;
+#[cfg(feature = "bincode")]
+mod bincode_writer;
+#[cfg(feature = "std")]
+mod bytes_interner;
+#[cfg(feature = "std")]
+mod cursor;
+#[cfg(feature = "std")]
+mod os_str;
+#[cfg(feature = "std")]
+mod sharded;
+#[cfg(feature = "testing")]
+mod mock;
+#[cfg(feature = "std")]
+mod symbol;
+#[cfg(feature = "std")]
+mod writer;
+#[cfg(feature = "alloc")]
+mod atomic;
+#[cfg(feature = "alloc")]
+mod double_ended;
+#[cfg(feature = "alloc")]
+mod sync;
+#[cfg(feature = "serde")]
+mod serde_impl;
+#[cfg(feature = "futures")]
+mod stream;
+#[cfg(feature = "tokio")]
+mod tokio_io;
+#[cfg(test)]
+mod test_util;
+#[cfg(test)]
+mod zst_tests;
 
-pub use arena::Arena;
+pub use alloc::AllocError;
+pub use arena::{Arena, ArenaCheckpoint, ArenaFull, ArenaStats, PlacementError};
+#[cfg(feature = "live-count")]
+pub use arena::ResetError;
+pub use arc::ArenaArc;
 pub use arena_box::ArenaBox;
+#[cfg(feature = "alloc")]
+pub use backing::{BackingMemory, SystemMemory};
+#[cfg(feature = "alloc")]
+pub use builder::ArenaBuilder;
+#[cfg(feature = "alloc")]
+pub use child::ChildArena;
+pub use rc::{ArenaRc, ArenaWeak};
+pub use reclaiming::{ReclaimingArena, ReclaimingBox};
+pub use regions::BorrowedArena;
+#[cfg(feature = "dyn-clone")]
+pub use dyn_clone::ArenaDynClone;
+#[cfg(feature = "alloc")]
+pub use epoch::{EpochArena, EpochBox};
+pub use eq::{arena_eq, ArenaEq};
+pub use fn_once::{ArenaFnOnce, FnOnceArgs};
+#[cfg(feature = "alloc")]
+pub use growable::GrowableArena;
+#[cfg(feature = "alloc")]
+pub use handle::{ArenaHandle, OwnedArenaBox};
+pub use index::Handle;
+#[cfg(feature = "oom-abort")]
+pub use oom::set_oom_handler;
+#[cfg(feature = "testing")]
+pub use mock::{MockArena, MockCall};
+pub use linked_list::{ArenaLinkedList, ListCursorMut, ListIter, ListNode};
+pub use rope::{ArenaRope, Chunks};
+pub use slice_builder::SliceBuilder;
+pub use small_vec::SmallArenaVec;
+#[doc(hidden)]
+pub use soa::{soa_iter, soa_result, SoaColumn};
+pub use string::ArenaString;
+pub use tagged::{TaggedArenaPtr, TaggedArenaRef};
+#[cfg(feature = "alloc")]
+pub use typed::TypedArena;
+pub use vec::ArenaVec;
+#[cfg(feature = "postcard")]
+pub use postcard_flavor::PostcardFlavor;
+#[cfg(feature = "bincode")]
+pub use bincode_writer::BincodeWriter;
+#[cfg(feature = "std")]
+pub use bytes_interner::BytesInterner;
+#[cfg(feature = "std")]
+pub use cursor::ArenaCursor;
+#[cfg(feature = "std")]
+pub use sharded::ShardedArena;
+#[cfg(feature = "alloc")]
+pub use double_ended::{DoubleEndedArena, DoubleEndedBack, DoubleEndedFront};
+#[cfg(feature = "alloc")]
+pub use sync::SyncArena;
+#[cfg(feature = "std")]
+pub use symbol::{Symbol, SymbolTable, Symbols};
+#[cfg(feature = "std")]
+pub use writer::ArenaWriter;
+#[cfg(feature = "serde")]
+pub use serde_impl::{ArenaSeed, ArenaSeqSeed};
@@ -0,0 +1,632 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! An intrusive doubly-linked list of nodes that live in an `Arena`.
+//!
+//! The list does not own its nodes; it links `&'a ListNode<T>` references,
+//! typically borrowed from `ArenaBox`es, through pointers stored in the
+//! nodes themselves. That makes unlinking a node O(1) given only a
+//! reference to it, which is what LRU lists and schedulers need.
+//!
+//! Every node records which list it is linked into, and a list only
+//! unlinks its own nodes. Dropping a list unlinks all of its nodes, and the
+//! list is invariant in `'a`, so every node it links lives for all of `'a`.
+//! A node whose list was leaked with `mem::forget` stays linked into that
+//! list for good, and no other list will take it.
+//!
+//! # Examples
+//!
+//! ```
+//! # use memory_arena::*;
+//! # #[cfg(feature = "alloc")] {
+//! let a = Arena::new(1024, 1024).unwrap();
+//! let x = a.new_box(ListNode::new(1)).unwrap();
+//! let y = a.new_box(ListNode::new(2)).unwrap();
+//! let z = a.new_box(ListNode::new(3)).unwrap();
+//! let mut list = ArenaLinkedList::new();
+//! list.push_back(&x);
+//! list.push_back(&y);
+//! list.push_front(&z);
+//! list.unlink(&x);
+//! let values: Vec<i32> = list.iter().map(|n| **n).collect();
+//! assert_eq!(values, [3, 2]);
+//! # }
+//! ```
+
+use core::cell::Cell;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Hands out a distinct id to every `ArenaLinkedList`, so that nodes can be
+/// checked against the list they are linked into. Zero means unlinked.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// A value together with the links of an `ArenaLinkedList`.
+pub struct ListNode<T> {
+    prev: Cell<Option<*const ListNode<T>>>,
+    next: Cell<Option<*const ListNode<T>>>,
+    list: Cell<usize>,
+    value: T,
+}
+
+impl<T> ListNode<T> {
+    /// Creates an unlinked node holding `value`.
+    pub fn new(value: T) -> Self {
+        ListNode {
+            prev: Cell::new(None),
+            next: Cell::new(None),
+            list: Cell::new(0),
+            value,
+        }
+    }
+
+    /// Returns `true` if the node is currently linked into a list.
+    pub fn is_linked(&self) -> bool {
+        self.list.get() != 0
+    }
+
+    /// Returns the value of the node.
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T> Deref for ListNode<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for ListNode<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ListNode")
+            .field("value", &self.value)
+            .field("linked", &self.is_linked())
+            .finish()
+    }
+}
+
+/// Turns a link back into a reference. Links only ever point to nodes that
+/// were handed to a list as `&'a ListNode<T>`.
+fn node<'a, T>(p: *const ListNode<T>) -> &'a ListNode<T> {
+    unsafe { &*p }
+}
+
+/// An intrusive doubly-linked list of `&'a ListNode<T>`s.
+///
+/// See the [module documentation](index.html) for an overview.
+pub struct ArenaLinkedList<'a, T: 'a> {
+    head: Option<&'a ListNode<T>>,
+    tail: Option<&'a ListNode<T>>,
+    len: usize,
+    id: usize,
+    // Invariant in `'a`, so that a list of long-lived nodes can not take a
+    // short-lived one.
+    phantom: PhantomData<fn(&'a ()) -> &'a ()>,
+}
+
+impl<'a, T: 'a> ArenaLinkedList<'a, T> {
+    /// Creates an empty list.
+    pub fn new() -> Self {
+        ArenaLinkedList {
+            head: None,
+            tail: None,
+            len: 0,
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns the number of linked nodes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if no nodes are linked.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the first node, if any.
+    pub fn front(&self) -> Option<&'a ListNode<T>> {
+        self.head
+    }
+
+    /// Returns the last node, if any.
+    pub fn back(&self) -> Option<&'a ListNode<T>> {
+        self.tail
+    }
+
+    /// Links `node` in between `prev` and `next`, which must be adjacent.
+    fn link(
+        &mut self,
+        node: &'a ListNode<T>,
+        prev: Option<&'a ListNode<T>>,
+        next: Option<&'a ListNode<T>>,
+    ) {
+        assert!(!node.is_linked(), "node is already linked into a list");
+        node.list.set(self.id);
+        node.prev.set(prev.map(|p| p as *const _));
+        node.next.set(next.map(|n| n as *const _));
+        match prev {
+            Some(prev) => prev.next.set(Some(node)),
+            None => self.head = Some(node),
+        }
+        match next {
+            Some(next) => next.prev.set(Some(node)),
+            None => self.tail = Some(node),
+        }
+        self.len += 1;
+    }
+
+    /// Links `node` at the front of the list.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `node` is already linked into a list.
+    pub fn push_front(&mut self, node: &'a ListNode<T>) {
+        let head = self.head;
+        self.link(node, None, head);
+    }
+
+    /// Links `node` at the back of the list.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `node` is already linked into a list.
+    pub fn push_back(&mut self, node: &'a ListNode<T>) {
+        let tail = self.tail;
+        self.link(node, tail, None);
+    }
+
+    /// Unlinks and returns the first node, if any.
+    pub fn pop_front(&mut self) -> Option<&'a ListNode<T>> {
+        let head = self.head?;
+        self.unlink(head);
+        Some(head)
+    }
+
+    /// Unlinks and returns the last node, if any.
+    pub fn pop_back(&mut self) -> Option<&'a ListNode<T>> {
+        let tail = self.tail?;
+        self.unlink(tail);
+        Some(tail)
+    }
+
+    /// Unlinks `node` from the list in O(1).
+    ///
+    /// Unlinking a node that is not linked at all does nothing, and panics
+    /// in debug builds.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `node` is linked into a different list.
+    pub fn unlink(&mut self, node: &'a ListNode<T>) {
+        debug_assert!(node.is_linked(), "node is not linked into a list");
+        if !node.is_linked() {
+            return;
+        }
+        assert!(node.list.get() == self.id, "node is linked into a different list");
+        let prev = node.prev.get().map(self::node);
+        let next = node.next.get().map(self::node);
+        match prev {
+            Some(prev) => prev.next.set(node.next.get()),
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => next.prev.set(node.prev.get()),
+            None => self.tail = prev,
+        }
+        node.prev.set(None);
+        node.next.set(None);
+        node.list.set(0);
+        self.len -= 1;
+    }
+
+    /// Returns an iterator over the nodes, front to back.
+    pub fn iter(&self) -> ListIter<'a, T> {
+        ListIter {
+            front: self.head,
+            back: self.tail,
+            len: self.len,
+            list: self.id,
+        }
+    }
+
+    /// Returns a cursor positioned at the first node.
+    pub fn cursor_front_mut(&mut self) -> ListCursorMut<'_, 'a, T> {
+        ListCursorMut {
+            current: self.head,
+            list: self,
+        }
+    }
+
+    /// Returns a cursor positioned at the last node.
+    pub fn cursor_back_mut(&mut self) -> ListCursorMut<'_, 'a, T> {
+        ListCursorMut {
+            current: self.tail,
+            list: self,
+        }
+    }
+}
+
+impl<'a, T: 'a> Drop for ArenaLinkedList<'a, T> {
+    fn drop(&mut self) {
+        // Nodes can outlive the list, and must not keep pointing at each
+        // other once it is gone.
+        while self.pop_front().is_some() {}
+    }
+}
+
+impl<'a, T: 'a> Default for ArenaLinkedList<'a, T> {
+    fn default() -> Self {
+        ArenaLinkedList::new()
+    }
+}
+
+impl<'a, T: fmt::Debug + 'a> fmt::Debug for ArenaLinkedList<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter().map(|n| &n.value)).finish()
+    }
+}
+
+impl<'a, T: 'a> IntoIterator for &ArenaLinkedList<'a, T> {
+    type Item = &'a ListNode<T>;
+    type IntoIter = ListIter<'a, T>;
+
+    fn into_iter(self) -> ListIter<'a, T> {
+        self.iter()
+    }
+}
+
+/// An iterator over the nodes of an `ArenaLinkedList`.
+///
+/// The iterator does not borrow the list, so nodes can be unlinked while
+/// iterating, as long as the iterator has already moved past them. The
+/// iterator stops at a node that is no longer linked into the list.
+#[derive(Clone)]
+pub struct ListIter<'a, T: 'a> {
+    front: Option<&'a ListNode<T>>,
+    back: Option<&'a ListNode<T>>,
+    len: usize,
+    list: usize,
+}
+
+impl<'a, T: 'a> ListIter<'a, T> {
+    /// Follows the link `get` of `n`, unless `n` has left the list since
+    /// the iterator was made, in which case the link may point to a node
+    /// that does not live for `'a`.
+    fn follow<F>(&self, n: &'a ListNode<T>, get: F) -> Option<&'a ListNode<T>>
+    where
+        F: FnOnce(&ListNode<T>) -> Option<*const ListNode<T>>,
+    {
+        if n.list.get() == self.list {
+            get(n).map(node)
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a, T: 'a> Iterator for ListIter<'a, T> {
+    type Item = &'a ListNode<T>;
+
+    fn next(&mut self) -> Option<&'a ListNode<T>> {
+        if self.len == 0 {
+            return None;
+        }
+        let n = self.front?;
+        self.len -= 1;
+        self.front = self.follow(n, |n| n.next.get());
+        Some(n)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T: 'a> DoubleEndedIterator for ListIter<'a, T> {
+    fn next_back(&mut self) -> Option<&'a ListNode<T>> {
+        if self.len == 0 {
+            return None;
+        }
+        let n = self.back?;
+        self.len -= 1;
+        self.back = self.follow(n, |n| n.prev.get());
+        Some(n)
+    }
+}
+
+impl<'a, T: 'a> ExactSizeIterator for ListIter<'a, T> {}
+
+/// A cursor over an `ArenaLinkedList` that can link and unlink nodes at
+/// its position.
+///
+/// Besides pointing at a node, the cursor can be at a "ghost" position past
+/// either end of the list, where `current` returns `None`. Moving past the
+/// ghost position wraps around to the other end, as with the cursors of
+/// `std::collections::LinkedList`.
+pub struct ListCursorMut<'l, 'a: 'l, T: 'a> {
+    list: &'l mut ArenaLinkedList<'a, T>,
+    current: Option<&'a ListNode<T>>,
+}
+
+impl<'l, 'a: 'l, T: 'a> ListCursorMut<'l, 'a, T> {
+    /// Returns the node at the cursor, or `None` at the ghost position.
+    pub fn current(&self) -> Option<&'a ListNode<T>> {
+        self.current
+    }
+
+    /// Moves to the next node.
+    pub fn move_next(&mut self) {
+        self.current = match self.current {
+            Some(n) => n.next.get().map(node),
+            None => self.list.head,
+        };
+    }
+
+    /// Moves to the previous node.
+    pub fn move_prev(&mut self) {
+        self.current = match self.current {
+            Some(n) => n.prev.get().map(node),
+            None => self.list.tail,
+        };
+    }
+
+    /// Links `n` after the cursor, or at the front of the list at the
+    /// ghost position. The cursor does not move.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is already linked into a list.
+    pub fn insert_after(&mut self, n: &'a ListNode<T>) {
+        let (prev, next) = match self.current {
+            Some(c) => (Some(c), c.next.get().map(node)),
+            None => (None, self.list.head),
+        };
+        self.list.link(n, prev, next);
+    }
+
+    /// Links `n` before the cursor, or at the back of the list at the ghost
+    /// position. The cursor does not move.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is already linked into a list.
+    pub fn insert_before(&mut self, n: &'a ListNode<T>) {
+        let (prev, next) = match self.current {
+            Some(c) => (c.prev.get().map(node), Some(c)),
+            None => (self.list.tail, None),
+        };
+        self.list.link(n, prev, next);
+    }
+
+    /// Unlinks and returns the node at the cursor, and moves to the next
+    /// one. Does nothing at the ghost position.
+    pub fn remove_current(&mut self) -> Option<&'a ListNode<T>> {
+        let c = self.current?;
+        self.current = c.next.get().map(node);
+        self.list.unlink(c);
+        Some(c)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use std::vec::Vec;
+    use test_util::checked_arena;
+    use ArenaBox;
+
+    fn values<T: Copy>(list: &ArenaLinkedList<T>) -> Vec<T> {
+        list.iter().map(|n| **n).collect()
+    }
+
+    /// Checks that the links agree with each other in both directions.
+    fn check_integrity<T>(list: &ArenaLinkedList<T>) {
+        let forward: Vec<_> = list.iter().map(|n| n as *const ListNode<T>).collect();
+        let mut backward: Vec<_> = list.iter().rev().map(|n| n as *const ListNode<T>).collect();
+        backward.reverse();
+        assert_eq!(forward, backward);
+        assert_eq!(forward.len(), list.len());
+        let mut prev = None;
+        for n in list {
+            assert!(n.is_linked());
+            assert_eq!(n.prev.get(), prev);
+            prev = Some(n as *const _);
+        }
+        assert_eq!(list.back().map(|n| n as *const _), prev);
+    }
+
+    #[test]
+    fn list_interleaved() {
+        let a = checked_arena(4096, 4096);
+        let nodes: Vec<ArenaBox<ListNode<u32>>> =
+            (0..8).map(|i| a.new_box(ListNode::new(i)).unwrap()).collect();
+        let mut list = ArenaLinkedList::new();
+        list.push_back(&nodes[0]);
+        list.push_back(&nodes[1]);
+        list.push_front(&nodes[2]);
+        list.unlink(&nodes[0]);
+        list.push_back(&nodes[3]);
+        list.push_front(&nodes[0]);
+        assert_eq!(values(&list), [0, 2, 1, 3]);
+        list.unlink(&nodes[3]);
+        list.unlink(&nodes[0]);
+        assert_eq!(values(&list), [2, 1]);
+        assert!(!nodes[0].is_linked());
+        assert_eq!(list.pop_front().map(|n| **n), Some(2));
+        assert_eq!(list.pop_back().map(|n| **n), Some(1));
+        assert!(list.is_empty());
+        assert_eq!(list.pop_front().map(|n| **n), None);
+        check_integrity(&list);
+    }
+
+    #[test]
+    fn list_cursor() {
+        let a = checked_arena(4096, 4096);
+        let nodes: Vec<ArenaBox<ListNode<u32>>> =
+            (0..6).map(|i| a.new_box(ListNode::new(i)).unwrap()).collect();
+        let mut list = ArenaLinkedList::new();
+        for n in &nodes[..3] {
+            list.push_back(n);
+        }
+        {
+            let mut c = list.cursor_front_mut();
+            c.move_next();
+            assert_eq!(c.current().map(|n| **n), Some(1));
+            c.insert_before(&nodes[3]);
+            c.insert_after(&nodes[4]);
+            assert_eq!(c.remove_current().map(|n| **n), Some(1));
+            assert_eq!(c.current().map(|n| **n), Some(4));
+            c.move_next();
+            c.move_next();
+            assert!(c.current().is_none());
+            c.insert_before(&nodes[5]);
+            c.move_prev();
+            assert_eq!(c.current().map(|n| **n), Some(5));
+        }
+        assert_eq!(values(&list), [0, 3, 4, 2, 5]);
+        let mut c = list.cursor_back_mut();
+        while c.remove_current().is_some() {
+            c.move_prev();
+            c.move_prev();
+        }
+        check_integrity(&list);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "node is not linked into a list")]
+    fn list_double_unlink() {
+        let a = checked_arena(1024, 1024);
+        let n = a.new_box(ListNode::new(0)).unwrap();
+        let mut list = ArenaLinkedList::new();
+        list.push_back(&n);
+        list.unlink(&n);
+        list.unlink(&n);
+    }
+
+    #[test]
+    #[should_panic(expected = "node is already linked into a list")]
+    fn list_double_link() {
+        let a = checked_arena(1024, 1024);
+        let n = a.new_box(ListNode::new(0)).unwrap();
+        let mut list = ArenaLinkedList::new();
+        list.push_back(&n);
+        list.push_front(&n);
+    }
+
+    #[test]
+    fn list_drop_unlinks() {
+        let a = checked_arena(1024, 1024);
+        let n1 = a.new_box(ListNode::new(1)).unwrap();
+        let n2 = a.new_box(ListNode::new(2)).unwrap();
+        let mut c = ArenaLinkedList::new();
+        {
+            let short = ListNode::new(0);
+            let mut b = ArenaLinkedList::new();
+            b.push_back(&n1);
+            b.push_back(&short);
+            b.push_back(&n2);
+            let mut it = b.iter();
+            drop(b);
+            assert!(!n1.is_linked() && !short.is_linked() && !n2.is_linked());
+            assert_eq!((n1.next.get(), n2.prev.get()), (None, None));
+            // The iterator outlives the list, but must not follow the links
+            // the nodes get in another one.
+            c.push_back(&n2);
+            c.push_back(&n1);
+            assert_eq!(it.next().map(|n| **n), Some(1));
+            assert_eq!(it.next().map(|n| **n), None);
+        }
+        assert_eq!(values(&c), [2, 1]);
+        c.unlink(&n1);
+        check_integrity(&c);
+    }
+
+    #[test]
+    #[should_panic(expected = "node is linked into a different list")]
+    fn list_unlink_foreign() {
+        let a = checked_arena(1024, 1024);
+        let n1 = a.new_box(ListNode::new(1)).unwrap();
+        let n2 = a.new_box(ListNode::new(2)).unwrap();
+        let mut b = ArenaLinkedList::new();
+        b.push_back(&n1);
+        b.push_back(&n2);
+        ::core::mem::forget(b);
+        let mut c = ArenaLinkedList::new();
+        c.unlink(&n1);
+    }
+
+    #[test]
+    fn list_random_against_model() {
+        const NODES: usize = 64;
+        let a = checked_arena(NODES * 64, 4096);
+        let nodes: Vec<ArenaBox<ListNode<usize>>> =
+            (0..NODES).map(|i| a.new_box(ListNode::new(i)).unwrap()).collect();
+        let mut list = ArenaLinkedList::new();
+        let mut model: Vec<usize> = Vec::new();
+        // xorshift64, so the sequence is the same on every run.
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut rand = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as usize
+        };
+        for _ in 0..5000 {
+            let i = rand() % NODES;
+            match rand() % 5 {
+                0 | 1 if !model.contains(&i) => {
+                    if rand() % 2 == 0 {
+                        list.push_front(&nodes[i]);
+                        model.insert(0, i);
+                    } else {
+                        list.push_back(&nodes[i]);
+                        model.push(i);
+                    }
+                }
+                2 if model.contains(&i) => {
+                    list.unlink(&nodes[i]);
+                    model.retain(|&j| j != i);
+                }
+                3 => {
+                    let (popped, expected) = if rand() % 2 == 0 {
+                        let expected = if model.is_empty() { None } else { Some(model.remove(0)) };
+                        (list.pop_front(), expected)
+                    } else {
+                        (list.pop_back(), model.pop())
+                    };
+                    assert_eq!(popped.map(|n| **n), expected);
+                }
+                4 if !model.is_empty() && !model.contains(&i) => {
+                    let at = rand() % model.len();
+                    let mut c = list.cursor_front_mut();
+                    for _ in 0..at {
+                        c.move_next();
+                    }
+                    c.insert_after(&nodes[i]);
+                    model.insert(at + 1, i);
+                }
+                _ => {}
+            }
+            assert_eq!(values(&list), model);
+            check_integrity(&list);
+        }
+        for (i, n) in nodes.iter().enumerate() {
+            assert_eq!(n.is_linked(), model.contains(&i));
+        }
+    }
+}
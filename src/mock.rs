@@ -0,0 +1,253 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A test double for an Arena, whose allocations fail when the test says
+//! so.
+
+use core::alloc::Layout;
+use core::cell::{Cell, RefCell};
+use core::ptr;
+use rust_alloc::vec::Vec;
+
+use alloc::AllocError;
+use {Arena, ArenaBox, ArenaCheckpoint};
+
+/// An allocation requested from a [`MockArena`], and whether it was
+/// granted.
+///
+/// [`MockArena`]: struct.MockArena.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MockCall {
+    pub layout: Layout,
+    pub succeeded: bool,
+}
+
+/// An Arena whose allocations succeed or fail as scripted, for testing how
+/// code copes with running out of memory at each point it allocates.
+///
+/// The memory comes from the `Arena` it wraps, which should be big enough
+/// for everything the test allocates: the failures come from the script,
+/// not from the Arena filling up. Every allocation is recorded, whether it
+/// succeeded or not, and can be inspected with [`MockArena::calls`]. With
+/// the `allocator_api` feature, `&MockArena` is also an `Allocator`, so
+/// standard collections can be tested the same way.
+///
+/// [`MockArena::calls`]: #method.calls
+///
+/// # Examples
+///
+/// ```
+/// # use memory_arena::*;
+/// # use std::alloc::Layout;
+/// let mut m = MockArena::new(Arena::new(1024, 1024).unwrap());
+/// m.succeed_times(2);
+/// let cp = m.checkpoint();
+/// let x = m.new_box(1u32).unwrap();
+/// let y = m.new_box(2u32).unwrap();
+/// assert_eq!(m.new_box(3u32).err(), Some(3));
+/// # drop((x, y));
+/// m.rewind(cp);
+/// assert_eq!(m.used(), 0);
+/// assert_eq!(m.calls().len(), 3);
+/// assert_eq!(m.calls()[2], MockCall { layout: Layout::new::<u32>(), succeeded: false });
+/// ```
+pub struct MockArena {
+    arena: Arena,
+    successes: Cell<Option<usize>>,
+    max_size: Cell<Option<usize>>,
+    calls: RefCell<Vec<MockCall>>,
+}
+
+impl MockArena {
+    /// Creates a mock that allocates from `arena`, and for now lets every
+    /// allocation succeed.
+    pub fn new(arena: Arena) -> MockArena {
+        MockArena {
+            arena,
+            successes: Cell::new(None),
+            max_size: Cell::new(None),
+            calls: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Lets the next `n` allocations succeed, and fails every one after
+    /// them.
+    pub fn succeed_times(&self, n: usize) {
+        self.successes.set(Some(n));
+    }
+
+    /// Fails every allocation of more than `size` bytes.
+    pub fn fail_larger_than(&self, size: usize) {
+        self.max_size.set(Some(size));
+    }
+
+    /// Lets every allocation succeed again, as far as the Arena has room.
+    pub fn succeed_always(&self) {
+        self.successes.set(None);
+        self.max_size.set(None);
+    }
+
+    /// Returns the allocations requested so far, in order.
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.calls.borrow().clone()
+    }
+
+    /// Returns the number of bytes of the Arena in use.
+    pub fn used(&self) -> usize {
+        self.arena.used()
+    }
+
+    /// Allocates memory for `layout`, unless the script says to fail.
+    ///
+    /// Scripted failures are reported as `AllocError::Exhausted`.
+    pub fn alloc(&self, layout: Layout) -> Result<*mut u8, AllocError> {
+        let allowed = self.successes.get() != Some(0)
+            && self.max_size.get().is_none_or(|max| layout.size() <= max);
+        let result = if allowed {
+            self.arena.try_aligned_alloc(layout.size(), layout.align())
+        } else {
+            Err(AllocError::Exhausted)
+        };
+        if result.is_ok() {
+            if let Some(n) = self.successes.get() {
+                self.successes.set(Some(n - 1));
+            }
+        }
+        self.calls.borrow_mut().push(MockCall {
+            layout,
+            succeeded: result.is_ok(),
+        });
+        result
+    }
+
+    /// Moves `x` into the Arena, like [`Arena::new_box`], unless the script
+    /// says to fail.
+    ///
+    /// [`Arena::new_box`]: struct.Arena.html#method.new_box
+    pub fn new_box<T>(&self, x: T) -> Result<ArenaBox<'_, T>, T> {
+        match self.alloc(Layout::new::<T>()) {
+            Err(_) => Err(x),
+            Ok(p) => unsafe {
+                let p = p as *mut T;
+                ptr::write(p, x);
+                Ok(ArenaBox::from_raw_in(p, &self.arena))
+            },
+        }
+    }
+
+    /// Like [`Arena::checkpoint`].
+    ///
+    /// [`Arena::checkpoint`]: struct.Arena.html#method.checkpoint
+    pub fn checkpoint(&self) -> ArenaCheckpoint {
+        self.arena.checkpoint()
+    }
+
+    /// Like [`Arena::rewind`]. The script and the recorded calls are kept.
+    ///
+    /// [`Arena::rewind`]: struct.Arena.html#method.rewind
+    pub fn rewind(&mut self, cp: ArenaCheckpoint) {
+        self.arena.rewind(cp)
+    }
+
+    /// Returns the Arena the mock allocates from.
+    pub fn into_inner(self) -> Arena {
+        self.arena
+    }
+}
+
+/// Allocates through [`MockArena::alloc`], so the script applies and the
+/// allocation is recorded. Growing always moves the block to a new
+/// allocation, which is recorded too.
+///
+/// [`MockArena::alloc`]: struct.MockArena.html#method.alloc
+#[cfg(feature = "allocator_api")]
+unsafe impl core::alloc::Allocator for &MockArena {
+    fn allocate(&self, layout: Layout) -> Result<ptr::NonNull<[u8]>, core::alloc::AllocError> {
+        match self.alloc(layout) {
+            Ok(p) => Ok(unsafe {
+                ptr::NonNull::new_unchecked(ptr::slice_from_raw_parts_mut(p, layout.size()))
+            }),
+            Err(_) => Err(core::alloc::AllocError),
+        }
+    }
+
+    unsafe fn deallocate(&self, _ptr: ptr::NonNull<u8>, _layout: Layout) {}
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    /// Boxes each of `values`, rolling the Arena back if any allocation
+    /// fails.
+    fn insert_all(m: &mut MockArena, values: &[u64]) -> Result<u64, u64> {
+        let cp = m.checkpoint();
+        let result = values.iter().try_fold(0, |sum, &v| Ok(sum + *m.new_box(v)?));
+        if result.is_err() {
+            m.rewind(cp);
+        }
+        result
+    }
+
+    #[test]
+    fn mock_rollback_at_each_failure() {
+        let values = [1, 2, 3, 4, 5];
+        for fail_at in 0..values.len() {
+            let mut m = MockArena::new(Arena::new(1024, 1024).unwrap());
+            let _ = m.new_box(0u8).unwrap();
+            let used = m.used();
+            m.succeed_times(fail_at);
+            assert_eq!(insert_all(&mut m, &values), Err(values[fail_at]));
+            assert_eq!(m.used(), used);
+            let calls = m.calls();
+            assert_eq!(calls.len(), fail_at + 2);
+            assert!(calls[..fail_at + 1].iter().all(|c| c.succeeded));
+            let failed = MockCall { layout: Layout::new::<u64>(), succeeded: false };
+            assert_eq!(calls[fail_at + 1], failed);
+            // The memory given back is reused once allocations succeed.
+            m.succeed_always();
+            let first = m.new_box(9u64).unwrap();
+            assert_eq!(&*first as *const u64 as usize - m.arena.mem as usize, 8);
+            drop(first);
+            m.into_inner().check_invariants();
+        }
+    }
+
+    #[test]
+    fn mock_fail_larger_than() {
+        let m = MockArena::new(Arena::new(1024, 1024).unwrap());
+        m.fail_larger_than(8);
+        assert!(m.new_box(1u64).is_ok());
+        assert_eq!(m.new_box([1u64; 2]).err(), Some([1; 2]));
+        assert!(m.new_box(()).is_ok());
+        assert_eq!(m.used(), 8);
+        let sizes: Vec<_> = m.calls().iter().map(|c| (c.layout.size(), c.succeeded)).collect();
+        assert_eq!(sizes, [(8, true), (16, false), (0, true)]);
+    }
+
+    #[cfg(feature = "allocator_api")]
+    #[test]
+    fn mock_allocator_vec() {
+        let m = MockArena::new(Arena::new(1 << 16, 4096).unwrap());
+        m.succeed_times(3);
+        let mut v: Vec<u32, &MockArena> = Vec::new_in(&m);
+        let mut pushed = 0;
+        while v.try_reserve(1).is_ok() {
+            v.push(pushed);
+            pushed += 1;
+        }
+        // Three allocations succeeded, and the fourth growth failed.
+        let calls = m.calls();
+        assert_eq!(calls.len(), 4);
+        assert_eq!(calls.iter().filter(|c| c.succeeded).count(), 3);
+        assert!(calls.windows(2).all(|w| w[0].layout.size() < w[1].layout.size()));
+        assert_eq!(pushed as usize, v.capacity());
+        assert!(v.iter().copied().eq(0..pushed));
+    }
+}
@@ -22,6 +22,11 @@
 use coerce_unsized::CoerceUnsized;
 
 /// Unsafe trait to indicate what types are usable with the NonZero struct
+///
+/// # Safety
+///
+/// `is_zero` must report exactly the values that `NonZero` forbids.
+#[allow(dead_code)]
 pub unsafe trait Zeroable {
     /// Whether this value is zero
     fn is_zero(&self) -> bool;
@@ -77,7 +82,7 @@ impl<T: Zeroable> NonZero<T> {
         NonZero(inner)
     }
 
-    /// Creates an instance of NonZero with the provided value.
+    // /// Creates an instance of NonZero with the provided value.
     // #[inline]
     // pub fn new(inner: T) -> Option<Self> {
     //     if inner.is_zero() {
@@ -95,6 +100,14 @@ impl<T: Zeroable> NonZero<T> {
 
 impl<T: Zeroable + CoerceUnsized<U>, U: Zeroable> CoerceUnsized<NonZero<U>> for NonZero<T> {}
 
+#[cfg(feature = "coerce_unsized")]
+impl<T, U> ::core::ops::CoerceUnsized<NonZero<U>> for NonZero<T>
+where
+    T: Zeroable + ::core::ops::CoerceUnsized<U>,
+    U: Zeroable,
+{
+}
+
 impl<'a, T: ?Sized> From<&'a mut T> for NonZero<*mut T> {
     fn from(reference: &'a mut T) -> Self {
         NonZero(reference)
@@ -0,0 +1,117 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Infallible allocation for builds that treat running out of arena memory
+//! as fatal.
+//!
+//! Every `Result` returned by the fallible API is a branch at the call
+//! site, and on a microcontroller those add up in flash. With the
+//! `oom-abort` feature, [`Arena::new_box_unchecked_oom`] returns the box
+//! directly and diverts failures to a single out-of-line handler instead;
+//! `examples/oom_abort.rs` measures the difference. The fallible API stays
+//! available alongside it.
+//!
+//! [`Arena::new_box_unchecked_oom`]: ../struct.Arena.html#method.new_box_unchecked_oom
+
+use core::alloc::Layout;
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use {Arena, ArenaBox};
+
+/// The handler installed with `set_oom_handler`, or null for the default.
+static HANDLER: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+fn default_handler(layout: Layout) -> ! {
+    panic!(
+        "memory arena out of memory: cannot allocate {} bytes aligned to {} bytes",
+        layout.size(),
+        layout.align()
+    )
+}
+
+/// Installs the function called when an infallible arena allocation, such
+/// as [`Arena::new_box_unchecked_oom`], runs out of memory.
+///
+/// The handler gets the layout of the failed allocation and must not
+/// return. The default one panics with the size and alignment, which
+/// aborts in `panic = "abort"` builds.
+///
+/// [`Arena::new_box_unchecked_oom`]: struct.Arena.html#method.new_box_unchecked_oom
+pub fn set_oom_handler(handler: fn(Layout) -> !) {
+    HANDLER.store(handler as *mut (), Ordering::Release);
+}
+
+#[cold]
+#[inline(never)]
+fn oom(layout: Layout) -> ! {
+    let handler = HANDLER.load(Ordering::Acquire);
+    if handler.is_null() {
+        default_handler(layout)
+    } else {
+        let handler: fn(Layout) -> ! = unsafe { mem::transmute(handler) };
+        handler(layout)
+    }
+}
+
+impl Arena {
+    /// Like [`Arena::new_box`], but returns the box directly and calls the
+    /// out-of-memory handler if there is not enough available memory in the
+    /// Arena.
+    ///
+    /// See [`set_oom_handler`] for the handler.
+    ///
+    /// [`Arena::new_box`]: #method.new_box
+    /// [`set_oom_handler`]: fn.set_oom_handler.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let x = a.new_box_unchecked_oom(5);
+    /// assert_eq!(*x, 5);
+    /// ```
+    #[inline]
+    pub fn new_box_unchecked_oom<'a, T>(&'a self, x: T) -> ArenaBox<'a, T> {
+        match self.new_box(x) {
+            Ok(b) => b,
+            Err(x) => {
+                mem::forget(x);
+                oom(Layout::new::<T>())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use test_util::checked_arena;
+
+    fn test_handler(layout: Layout) -> ! {
+        panic!("test oom handler: {} bytes", layout.size())
+    }
+
+    #[test]
+    fn oom_success() {
+        let a = checked_arena(64, 64);
+        let x = a.new_box_unchecked_oom([1u32; 16]);
+        assert_eq!(x[15], 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "test oom handler: 24 bytes")]
+    fn oom_handler_called() {
+        set_oom_handler(test_handler);
+        let a = checked_arena(16, 16);
+        let _x = a.new_box_unchecked_oom([0u64; 3]);
+    }
+}
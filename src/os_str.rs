@@ -0,0 +1,140 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Platform strings and paths in an `Arena`, enabled by the `std` feature.
+
+use std::ffi::OsStr;
+use std::path::Path;
+
+use alloc::AllocError;
+use {Arena, ArenaBox};
+
+impl Arena {
+    /// Copies `s` into the Arena and returns the copy.
+    ///
+    /// The copy is made of the string's encoded bytes, which are arbitrary
+    /// bytes on Unix, and WTF-8 on Windows, so strings that are not valid
+    /// Unicode are copied exactly. An empty string takes up no memory. If
+    /// there is not enough memory left for the copy, nothing is allocated
+    /// and the error is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// use std::ffi::OsStr;
+    ///
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let var = a.alloc_os_str(OsStr::new("PATH")).unwrap();
+    /// assert_eq!(&*var, "PATH");
+    /// ```
+    pub fn alloc_os_str<'a>(&'a self, s: &OsStr) -> Result<ArenaBox<'a, OsStr>, AllocError> {
+        let bytes = self.alloc_slice_copy(s.as_encoded_bytes())?;
+        let (bytes, live) = ArenaBox::into_raw_parts(bytes);
+        // The bytes come from `as_encoded_bytes`, on this same platform.
+        unsafe {
+            let s = OsStr::from_encoded_bytes_unchecked(&*bytes) as *const OsStr;
+            Ok(ArenaBox::from_raw_parts(s as *mut OsStr, live))
+        }
+    }
+
+    /// Copies `p` into the Arena and returns the copy, like
+    /// [`Arena::alloc_os_str`].
+    ///
+    /// [`Arena::alloc_os_str`]: #method.alloc_os_str
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// use std::path::Path;
+    ///
+    /// let a = Arena::new(1024, 1024).unwrap();
+    /// let dir = Path::new("/usr/local");
+    /// let paths: Vec<_> = ["bin", "lib"]
+    ///     .iter()
+    ///     .map(|name| a.alloc_path(&dir.join(name)).unwrap())
+    ///     .collect();
+    /// assert_eq!(paths[1].file_name().unwrap(), "lib");
+    /// ```
+    pub fn alloc_path<'a>(&'a self, p: &Path) -> Result<ArenaBox<'a, Path>, AllocError> {
+        let (s, live) = ArenaBox::into_raw_parts(self.alloc_os_str(p.as_os_str())?);
+        unsafe {
+            let p = Path::new(&*s) as *const Path;
+            Ok(ArenaBox::from_raw_parts(p as *mut Path, live))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use std::path::PathBuf;
+    use test_util::checked_arena;
+
+    #[test]
+    fn alloc_os_str_round_trip() {
+        let a = checked_arena(64, 64);
+        let s = a.alloc_os_str(OsStr::new("h\u{e9}llo")).unwrap();
+        assert_eq!(&*s, OsStr::new("h\u{e9}llo"));
+        assert_eq!(s.to_str(), Some("h\u{e9}llo"));
+        let empty = a.alloc_os_str(OsStr::new("")).unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(a.used(), 6);
+        assert_eq!(a.alloc_os_str(OsStr::new(&"x".repeat(59))).err(), Some(AllocError::Exhausted));
+        assert_eq!(a.used(), 6);
+    }
+
+    #[test]
+    fn alloc_path_round_trip() {
+        let a = checked_arena(1024, 1024);
+        let mut original = PathBuf::from("walk");
+        for part in &["src", "deep", "file.rs"] {
+            original.push(part);
+        }
+        let p = a.alloc_path(&original).unwrap();
+        assert_eq!(&*p, original.as_path());
+        assert_eq!(p.extension(), Some(OsStr::new("rs")));
+        assert_eq!(p.components().count(), 4);
+        assert_eq!(p.parent(), original.parent());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn alloc_path_not_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let a = checked_arena(1024, 1024);
+        let raw = OsStr::from_bytes(b"caf\xe9/\xff\xfe.txt");
+        assert!(raw.to_str().is_none());
+        let s = a.alloc_os_str(raw).unwrap();
+        assert_eq!(&*s, raw);
+        assert_eq!(s.as_bytes(), raw.as_bytes());
+        let p = a.alloc_path(Path::new(raw)).unwrap();
+        assert_eq!(&*p, Path::new(raw));
+        assert_eq!(p.file_name().unwrap().as_bytes(), b"\xff\xfe.txt");
+        assert_eq!(p.extension(), Some(OsStr::new("txt")));
+        assert_eq!(a.used(), 2 * raw.len());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn alloc_path_unpaired_surrogate() {
+        use std::ffi::OsString;
+        use std::os::windows::ffi::{OsStrExt, OsStringExt};
+
+        let a = checked_arena(1024, 1024);
+        let raw = OsString::from_wide(&[0x61, 0xd800, 0x5c, 0x62]);
+        assert!(raw.to_str().is_none());
+        let p = a.alloc_path(Path::new(&raw)).unwrap();
+        assert_eq!(p.as_os_str(), raw.as_os_str());
+        assert!(p.as_os_str().encode_wide().eq(raw.encode_wide()));
+        assert_eq!(p.file_name(), Some(OsStr::new("b")));
+    }
+}
@@ -0,0 +1,320 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Arenas made of whole virtual memory pages.
+
+use alloc::AllocError;
+use arena::Backing;
+use regions::Regions;
+use Arena;
+
+impl Arena {
+    /// Returns the size of a virtual memory page, as reported by the
+    /// operating system the first time it is asked.
+    pub fn page_size() -> usize {
+        ::alloc::page_size()
+    }
+
+    /// Creates an Arena of exactly `pages` virtual memory pages, mapped
+    /// directly from the operating system rather than taken from the heap.
+    ///
+    /// The buffer is page-aligned and zeroed, and page-level operations
+    /// such as [`Arena::protect_read_only`] accept it.
+    ///
+    /// [`Arena::protect_read_only`]: #method.protect_read_only
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// let a = Arena::new_pages(4).unwrap();
+    /// assert!(a.is_page_backed());
+    /// assert!(a.new_box([0u8; 1024]).is_ok());
+    /// ```
+    pub fn new_pages(pages: usize) -> Result<Arena, AllocError> {
+        let size = match pages.checked_mul(Arena::page_size()) {
+            Some(size) => size,
+            None => return Err(AllocError::Exhausted),
+        };
+        let mem = unsafe { ::alloc::map(None, size)? };
+        Ok(Arena::from_parts(mem, size, Backing::Mapped(size), Regions::new()))
+    }
+
+    /// Creates an Arena of `virtual_size` bytes of reserved address space,
+    /// whose pages only take up memory once they are first written to.
+    ///
+    /// This makes it cheap to create a huge Arena that most of the time
+    /// only uses a little of its capacity: the operating system commits the
+    /// pages on demand, as allocations touch them. On Linux, no memory or
+    /// swap is set aside for the reservation either (`MAP_NORESERVE`), so
+    /// running out of memory when touching a page can get the process
+    /// killed rather than failing here. `used` and `capacity` work as for
+    /// any other Arena, and the whole reservation is unmapped when the
+    /// Arena is dropped.
+    ///
+    /// If `alignment` is not a power of two or is larger than a page,
+    /// `AllocError::InvalidAlignment` is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// let a = Arena::new_reserved(1 << 30, 64).unwrap();
+    /// assert_eq!(a.capacity(), 1 << 30);
+    /// let x = a.new_box([1u64; 512]).unwrap();
+    /// assert_eq!(a.used(), 4096);
+    /// ```
+    #[cfg(unix)]
+    pub fn new_reserved(virtual_size: usize, alignment: usize) -> Result<Arena, AllocError> {
+        ::alloc::check_alignment(alignment)?;
+        if alignment > Arena::page_size() {
+            return Err(AllocError::InvalidAlignment { alignment });
+        }
+        let mem = unsafe { ::alloc::map_reserved(virtual_size)? };
+        Ok(Arena::from_parts(mem, virtual_size, Backing::Mapped(virtual_size), Regions::new()))
+    }
+
+    /// Creates an Arena of `size` bytes, rounded up to a multiple of
+    /// `alignment`, that is directly followed by an inaccessible guard page.
+    ///
+    /// The buffer is mapped from the operating system with one extra page,
+    /// and placed so that it ends where the guard page starts. Any write
+    /// past the end of the Arena, such as an overrun of its last
+    /// allocation, faults right away instead of corrupting other memory.
+    /// Allocating from the Arena works as usual. Since the buffer does not
+    /// start on a page boundary, it does not count as page-backed.
+    ///
+    /// If `alignment` is not a power of two or is larger than a page,
+    /// `AllocError::InvalidAlignment` is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// let a = Arena::new_guarded(100, 8).unwrap();
+    /// assert_eq!(a.capacity(), 104);
+    /// let b = a.alloc_slice_copy(&[1u8; 104]).unwrap();
+    /// assert!(a.new_box(0u8).is_err());
+    /// // Writing to `b.as_ptr().add(104)` would fault.
+    /// ```
+    pub fn new_guarded(size: usize, alignment: usize) -> Result<Arena, AllocError> {
+        ::alloc::check_alignment(alignment)?;
+        let page = Arena::page_size();
+        if alignment > page {
+            return Err(AllocError::InvalidAlignment { alignment });
+        }
+        let round_up = |n: usize, to: usize| n.checked_add(to - 1).map(|n| n & !(to - 1));
+        let size = round_up(size, alignment).ok_or(AllocError::Exhausted)?;
+        let data = round_up(size, page).ok_or(AllocError::Exhausted)?;
+        let len = data.checked_add(page).ok_or(AllocError::Exhausted)?;
+        unsafe {
+            let base = ::alloc::map(None, len)?;
+            if let Err(e) = ::alloc::guard(base.add(data), page) {
+                ::alloc::unmap(base, len);
+                return Err(e);
+            }
+            let offset = data - size;
+            let backing = Backing::Guarded { offset, len };
+            Ok(Arena::from_parts(base.add(offset), size, backing, Regions::new()))
+        }
+    }
+
+    /// Returns `true` if the Arena's buffer consists of whole pages mapped
+    /// for it alone, as with [`Arena::new_pages`].
+    ///
+    /// [`Arena::new_pages`]: #method.new_pages
+    pub fn is_page_backed(&self) -> bool {
+        matches!(self.backing, Backing::Mapped(_))
+    }
+
+    /// Makes the whole buffer of a page-backed Arena read-only, so that any
+    /// write to it faults.
+    ///
+    /// This fails with `AllocError::NotPageBacked` for arenas whose memory
+    /// comes from the heap, since their pages may be shared with other
+    /// allocations.
+    ///
+    /// # Safety
+    ///
+    /// Nothing may write to the Arena's memory until [`Arena::unprotect`]
+    /// is called, and that includes allocating from the Arena and dropping
+    /// boxes whose values have destructors that write.
+    ///
+    /// [`Arena::unprotect`]: #method.unprotect
+    pub unsafe fn protect_read_only(&self) -> Result<(), AllocError> {
+        self.protect(true)
+    }
+
+    /// Makes the buffer of a page-backed Arena writable again after
+    /// [`Arena::protect_read_only`].
+    ///
+    /// # Safety
+    ///
+    /// The Arena must not have been made read-only for the sake of code
+    /// that still relies on that.
+    ///
+    /// [`Arena::protect_read_only`]: #method.protect_read_only
+    pub unsafe fn unprotect(&self) -> Result<(), AllocError> {
+        self.protect(false)
+    }
+
+    unsafe fn protect(&self, read_only: bool) -> Result<(), AllocError> {
+        match self.backing {
+            Backing::Mapped(size) => ::alloc::protect(self.mem, size, read_only),
+            _ => Err(AllocError::NotPageBacked),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use test_util::{checked_arena, live_allocations};
+
+    #[test]
+    fn new_pages_aligned() {
+        let page = Arena::page_size();
+        assert!(page.is_power_of_two());
+        assert_eq!(Arena::page_size(), page);
+        let live = live_allocations();
+        {
+            let a = Arena::new_pages(3).unwrap();
+            assert!(a.is_page_backed());
+            assert!((a.mem as usize).is_multiple_of(page));
+            assert_eq!(a.take_remaining().len(), 3 * page);
+            a.check_invariants();
+        }
+        assert_eq!(live_allocations(), live);
+    }
+
+    #[test]
+    fn new_pages_protect() {
+        let a = Arena::new_pages(1).unwrap();
+        let x = a.new_box(7u64).unwrap();
+        unsafe {
+            assert_eq!(a.protect_read_only(), Ok(()));
+            assert_eq!(*x, 7);
+            assert_eq!(a.unprotect(), Ok(()));
+        }
+        drop(x);
+        let heap = checked_arena(4096, 4096);
+        assert!(!heap.is_page_backed());
+        assert_eq!(unsafe { heap.protect_read_only() }, Err(AllocError::NotPageBacked));
+    }
+
+    /// Returns the resident set size of the process, in pages.
+    #[cfg(target_os = "linux")]
+    fn resident_pages() -> usize {
+        let statm = ::std::fs::read_to_string("/proc/self/statm").unwrap();
+        statm.split_whitespace().nth(1).unwrap().parse().unwrap()
+    }
+
+    #[test]
+    #[cfg_attr(feature = "sanitize", ignore = "ASAN shadow memory is resident too")]
+    #[cfg(unix)]
+    fn new_reserved_commits_lazily() {
+        let live = live_allocations();
+        {
+            #[cfg(target_os = "linux")]
+            let before = resident_pages();
+            let a = Arena::new_reserved(512 << 20, 4096).unwrap();
+            assert_eq!((a.capacity(), a.used()), (512 << 20, 0));
+            assert!(a.is_page_backed());
+            assert_eq!(a.mem as usize % 4096, 0);
+            let x = a.alloc_slice_copy(&[3u8; 4000]).unwrap();
+            let y = a.new_box([5u64; 256]).unwrap();
+            assert_eq!((x[3999], y[255]), (3, 5));
+            assert_eq!(a.used(), 4000 + 2048);
+            // Other tests run at the same time, so only check that nowhere
+            // near the whole reservation became resident.
+            #[cfg(target_os = "linux")]
+            assert!(resident_pages().saturating_sub(before) < (64 << 20) / Arena::page_size());
+            a.check_invariants();
+        }
+        assert_eq!(live_allocations(), live);
+        let page = Arena::page_size();
+        let err = Arena::new_reserved(1 << 20, page * 2).err();
+        assert_eq!(err, Some(AllocError::InvalidAlignment { alignment: page * 2 }));
+        assert_eq!(Arena::new_reserved(0, 8).err(), Some(AllocError::ZeroSizeAlloc));
+    }
+
+    #[test]
+    fn new_guarded_allocates() {
+        let live = live_allocations();
+        {
+            let page = Arena::page_size();
+            let a = Arena::new_guarded(page + 10, 16).unwrap();
+            assert_eq!(a.capacity(), page + 16);
+            assert!(!a.is_page_backed());
+            assert_eq!((a.mem as usize + a.capacity()) % page, 0);
+            let x = a.new_box(1u64).unwrap();
+            let rest = a.alloc_slice_copy(&vec![7u8; page + 8]).unwrap();
+            assert_eq!((*x, rest[page + 7]), (1, 7));
+            assert!(a.new_box(0u8).is_err());
+            assert_eq!(unsafe { a.protect_read_only() }, Err(AllocError::NotPageBacked));
+            a.check_invariants();
+            let empty = Arena::new_guarded(0, 1).unwrap();
+            assert!(empty.new_box(0u8).is_err());
+        }
+        assert_eq!(live_allocations(), live);
+        let page = Arena::page_size();
+        let err = Arena::new_guarded(16, page * 2).err();
+        assert_eq!(err, Some(AllocError::InvalidAlignment { alignment: page * 2 }));
+        assert_eq!(Arena::new_guarded(usize::MAX, 1).err(), Some(AllocError::Exhausted));
+    }
+
+    #[test]
+    #[cfg_attr(feature = "sanitize", ignore = "ASAN catches the fault first")]
+    #[cfg(unix)]
+    fn new_guarded_overrun_faults() {
+        // Like `new_pages_write_faults`, the fault happens in a child.
+        let a = Arena::new_guarded(24, 8).unwrap();
+        let mut b = a.alloc_slice_copy(&[0u8; 24]).unwrap();
+        let end = unsafe { b.as_mut_ptr().add(b.len()) };
+        unsafe {
+            ::core::ptr::write_volatile(end.sub(1), 1);
+            match ::libc::fork() {
+                0 => {
+                    ::core::ptr::write_volatile(end, 1);
+                    ::libc::_exit(0);
+                }
+                pid => {
+                    let mut status = 0;
+                    assert_eq!(::libc::waitpid(pid, &mut status, 0), pid);
+                    assert!(::libc::WIFSIGNALED(status));
+                }
+            }
+        }
+        assert_eq!(b[23], 1);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "sanitize", ignore = "ASAN catches the fault first")]
+    #[cfg(unix)]
+    fn new_pages_write_faults() {
+        // A write to a read-only page must fault; check in a child process
+        // so the fault does not take down the test runner. The child only
+        // makes system calls, since other test threads may hold locks.
+        let a = Arena::new_pages(1).unwrap();
+        unsafe {
+            match ::libc::fork() {
+                0 => {
+                    a.protect_read_only().unwrap();
+                    ::core::ptr::write_volatile(a.mem, 1);
+                    ::libc::_exit(0);
+                }
+                pid => {
+                    let mut status = 0;
+                    assert_eq!(::libc::waitpid(pid, &mut status, 0), pid);
+                    assert!(::libc::WIFSIGNALED(status));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A `postcard` serialization flavor that writes into an `Arena`, enabled
+//! by the `postcard` feature.
+
+use postcard::ser_flavors::Flavor;
+use postcard::{Error, Result};
+
+use reserve::Reservation;
+use {Arena, ArenaBox};
+
+/// A `postcard` flavor that appends the serialized bytes to an `Arena`.
+///
+/// The flavor reserves all of the Arena's remaining memory while it is
+/// alive, so nothing else can be allocated from the Arena until it is
+/// finalized or dropped. Finalizing it returns the unused memory to the
+/// Arena and the output as an `ArenaBox<[u8]>`. Running out of memory is
+/// reported as `postcard::Error::SerializeBufferFull`.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate memory_arena;
+/// # extern crate postcard;
+/// # use memory_arena::*;
+/// # fn main() {
+/// let a = Arena::new(1024, 1024).unwrap();
+/// let bytes = postcard::serialize_with_flavor(&(1u8, 2u32), PostcardFlavor::new(&a)).unwrap();
+/// assert_eq!(&*bytes, &[1, 2]);
+/// # }
+/// ```
+pub struct PostcardFlavor<'a> {
+    buf: Reservation<'a>,
+}
+
+impl<'a> PostcardFlavor<'a> {
+    /// Creates a flavor that writes into `arena`.
+    pub fn new(arena: &'a Arena) -> Self {
+        PostcardFlavor {
+            buf: Reservation::new(arena),
+        }
+    }
+}
+
+impl<'a> Flavor for PostcardFlavor<'a> {
+    type Output = ArenaBox<'a, [u8]>;
+
+    fn try_extend(&mut self, data: &[u8]) -> Result<()> {
+        if self.buf.extend(data) {
+            Ok(())
+        } else {
+            Err(Error::SerializeBufferFull)
+        }
+    }
+
+    fn try_push(&mut self, data: u8) -> Result<()> {
+        self.try_extend(&[data])
+    }
+
+    fn finalize(self) -> Result<Self::Output> {
+        Ok(self.buf.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use serde::{Deserialize, Serialize};
+    use test_util::checked_arena;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Message<'m> {
+        id: u32,
+        name: &'m str,
+        payload: [u16; 4],
+        reply: Option<bool>,
+    }
+
+    const MESSAGE: Message<'static> = Message {
+        id: 1234,
+        name: "arena",
+        payload: [1, 2, 300, 40000],
+        reply: Some(true),
+    };
+
+    #[test]
+    fn postcard_round_trip() {
+        let a = checked_arena(1024, 1024);
+        let bytes = postcard::serialize_with_flavor(&MESSAGE, PostcardFlavor::new(&a)).unwrap();
+        assert_eq!(a.used.get(), bytes.len());
+        let decoded: Message = postcard::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, MESSAGE);
+        assert!(a.new_box(0u8).is_ok());
+    }
+
+    #[test]
+    fn postcard_exhausted() {
+        let a = checked_arena(8, 8);
+        let _ = a.new_box(0u8).unwrap();
+        let result = postcard::serialize_with_flavor(&MESSAGE, PostcardFlavor::new(&a));
+        assert_eq!(result.unwrap_err(), Error::SerializeBufferFull);
+        assert_eq!(a.used.get(), 1);
+    }
+}
@@ -0,0 +1,338 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Single-threaded reference-counted pointers to a value in an arena.
+
+use core::borrow::Borrow;
+use core::cell::Cell;
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem::ManuallyDrop;
+use core::ops::Deref;
+use core::ptr::NonNull;
+
+use {Arena, ArenaBox};
+
+struct RcInner<T> {
+    strong: Cell<usize>,
+    weak: Cell<usize>,
+    value: ManuallyDrop<T>,
+}
+
+/// A single-threaded reference-counted pointer to a value in an arena.
+///
+/// `ArenaRc<T>` is the arena counterpart of `std::rc::Rc<T>`, for values
+/// with several owners, such as the nodes of a graph. The counts are kept
+/// next to the value, and the value is dropped when the last `ArenaRc` to
+/// it goes away. The memory stays in the arena, like that of an
+/// `ArenaBox`, so an [`ArenaWeak`] can always tell whether the value is
+/// still there. As with `Rc`, a cycle of `ArenaRc`s keeps its values from
+/// ever being dropped, but the memory is still released with the arena.
+///
+/// The lifetime `'a` makes sure the arena outlives all clones:
+///
+/// ```compile_fail,E0597
+/// # use memory_arena::*;
+/// let x;
+/// {
+///     let a = Arena::new(1024, 1024).unwrap();
+///     x = a.new_rc(1u32).unwrap();
+/// }
+/// assert_eq!(*x, 1);
+/// ```
+///
+/// [`ArenaWeak`]: struct.ArenaWeak.html
+///
+/// # Examples
+///
+/// ```
+/// # use memory_arena::*;
+/// # #[cfg(feature = "alloc")] {
+/// let a = Arena::new(1024, 1024).unwrap();
+/// let leaf = a.new_rc(String::from("leaf")).unwrap();
+/// let parents = [leaf.clone(), leaf.clone()];
+/// assert_eq!(ArenaRc::strong_count(&leaf), 3);
+/// let weak = ArenaRc::downgrade(&leaf);
+/// drop((leaf, parents));
+/// assert!(weak.upgrade().is_none());
+/// # }
+/// ```
+pub struct ArenaRc<'a, T> {
+    ptr: NonNull<RcInner<T>>,
+    phantom: PhantomData<(&'a (), RcInner<T>)>,
+}
+
+/// A weak reference to a value managed by [`ArenaRc`], which does not keep
+/// the value alive.
+///
+/// [`ArenaRc`]: struct.ArenaRc.html
+pub struct ArenaWeak<'a, T> {
+    ptr: NonNull<RcInner<T>>,
+    phantom: PhantomData<(&'a (), RcInner<T>)>,
+}
+
+impl<'a, T> ArenaRc<'a, T> {
+    fn inner(&self) -> &RcInner<T> {
+        unsafe { self.ptr.as_ref() }
+    }
+
+    /// Returns the number of `ArenaRc`s to the value of `this`, including
+    /// itself.
+    pub fn strong_count(this: &Self) -> usize {
+        this.inner().strong.get()
+    }
+
+    /// Returns the number of `ArenaWeak`s to the value of `this`.
+    pub fn weak_count(this: &Self) -> usize {
+        this.inner().weak.get()
+    }
+
+    /// Returns `true` if both point to the same value.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.ptr == other.ptr
+    }
+
+    /// Returns a weak reference to the value of `this`.
+    pub fn downgrade(this: &Self) -> ArenaWeak<'a, T> {
+        let weak = &this.inner().weak;
+        weak.set(weak.get().checked_add(1).expect("ArenaWeak count overflow"));
+        ArenaWeak {
+            ptr: this.ptr,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns a mutable reference to the value if `this` is the only
+    /// reference to it, strong or weak.
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        if this.inner().strong.get() == 1 && this.inner().weak.get() == 0 {
+            Some(unsafe { &mut (*this.ptr.as_ptr()).value })
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a, T> Clone for ArenaRc<'a, T> {
+    fn clone(&self) -> Self {
+        let strong = &self.inner().strong;
+        strong.set(strong.get().checked_add(1).expect("ArenaRc count overflow"));
+        ArenaRc {
+            ptr: self.ptr,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, T> Drop for ArenaRc<'a, T> {
+    fn drop(&mut self) {
+        let strong = &self.inner().strong;
+        strong.set(strong.get() - 1);
+        if strong.get() == 0 {
+            unsafe { ManuallyDrop::drop(&mut (*self.ptr.as_ptr()).value) }
+        }
+    }
+}
+
+impl<'a, T> Deref for ArenaRc<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner().value
+    }
+}
+
+impl<'a, T> AsRef<T> for ArenaRc<'a, T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<'a, T> Borrow<T> for ArenaRc<'a, T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<'a, T: fmt::Debug> fmt::Debug for ArenaRc<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: fmt::Display> fmt::Display for ArenaRc<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<'a, T> ArenaWeak<'a, T> {
+    fn inner(&self) -> &RcInner<T> {
+        unsafe { self.ptr.as_ref() }
+    }
+
+    /// Returns an `ArenaRc` to the value, or `None` if it has been dropped
+    /// already.
+    pub fn upgrade(&self) -> Option<ArenaRc<'a, T>> {
+        let strong = &self.inner().strong;
+        if strong.get() == 0 {
+            return None;
+        }
+        strong.set(strong.get().checked_add(1).expect("ArenaRc count overflow"));
+        Some(ArenaRc {
+            ptr: self.ptr,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Returns the number of `ArenaRc`s to the value, which is zero once it
+    /// has been dropped.
+    pub fn strong_count(&self) -> usize {
+        self.inner().strong.get()
+    }
+
+    /// Returns `true` if both point to the same value.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.ptr == other.ptr
+    }
+}
+
+impl<'a, T> Clone for ArenaWeak<'a, T> {
+    fn clone(&self) -> Self {
+        let weak = &self.inner().weak;
+        weak.set(weak.get().checked_add(1).expect("ArenaWeak count overflow"));
+        ArenaWeak {
+            ptr: self.ptr,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, T> Drop for ArenaWeak<'a, T> {
+    fn drop(&mut self) {
+        let weak = &self.inner().weak;
+        weak.set(weak.get() - 1);
+    }
+}
+
+impl<'a, T> fmt::Debug for ArenaWeak<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("(ArenaWeak)")
+    }
+}
+
+impl Arena {
+    /// Places `x` in the Arena and returns the first `ArenaRc` to it.
+    ///
+    /// If there is not enough available memory in the Arena, then the
+    /// original value is returned, wrapped in `Result::Err`.
+    pub fn new_rc<'a, T>(&'a self, x: T) -> Result<ArenaRc<'a, T>, T> {
+        let inner = RcInner {
+            strong: Cell::new(1),
+            weak: Cell::new(0),
+            value: ManuallyDrop::new(x),
+        };
+        match self.new_box(inner) {
+            Ok(b) => Ok(ArenaRc {
+                ptr: unsafe { NonNull::new_unchecked(ArenaBox::into_raw(b)) },
+                phantom: PhantomData,
+            }),
+            Err(inner) => Err(ManuallyDrop::into_inner(inner.value)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use core::cell::RefCell;
+    use std::vec::Vec;
+    use test_util::checked_arena;
+
+    #[derive(Debug)]
+    struct Logged<'l>(u32, &'l RefCell<Vec<u32>>);
+
+    impl<'l> Drop for Logged<'l> {
+        fn drop(&mut self) {
+            self.1.borrow_mut().push(self.0);
+        }
+    }
+
+    #[test]
+    fn rc_drop_order() {
+        let log = RefCell::new(Vec::new());
+        let a = checked_arena(1024, 1024);
+        let x = a.new_rc(Logged(1, &log)).unwrap();
+        let y = a.new_rc(Logged(2, &log)).unwrap();
+        let x2 = x.clone();
+        let y2 = y.clone();
+        assert_eq!(ArenaRc::strong_count(&x), 2);
+        assert!(ArenaRc::ptr_eq(&x, &x2) && !ArenaRc::ptr_eq(&x, &y));
+        drop(x);
+        drop(y2);
+        assert!(log.borrow().is_empty());
+        drop(y);
+        assert_eq!(*log.borrow(), [2]);
+        assert_eq!(x2.0, 1);
+        drop(x2);
+        assert_eq!(*log.borrow(), [2, 1]);
+    }
+
+    #[test]
+    fn rc_weak() {
+        let log = RefCell::new(Vec::new());
+        let a = checked_arena(1024, 1024);
+        let mut x = a.new_rc(Logged(1, &log)).unwrap();
+        let w = ArenaRc::downgrade(&x);
+        let w2 = w.clone();
+        assert_eq!(ArenaRc::weak_count(&x), 2);
+        assert!(ArenaRc::get_mut(&mut x).is_none());
+        let up = w.upgrade().unwrap();
+        assert_eq!((up.0, w.strong_count()), (1, 2));
+        drop(up);
+        drop(x);
+        assert_eq!(*log.borrow(), [1]);
+        assert!(w.upgrade().is_none() && w2.upgrade().is_none());
+        assert_eq!(w.strong_count(), 0);
+        assert!(w.ptr_eq(&w2));
+
+        let mut y = a.new_rc(5u32).unwrap();
+        *ArenaRc::get_mut(&mut y).unwrap() += 1;
+        assert_eq!(*y, 6);
+    }
+
+    #[test]
+    fn rc_cycle() {
+        struct Node<'a, 'l> {
+            next: RefCell<Option<ArenaRc<'a, Node<'a, 'l>>>>,
+            _logged: Logged<'l>,
+        }
+
+        let log = RefCell::new(Vec::new());
+        {
+            let a = checked_arena(1024, 1024);
+            let n1 = a.new_rc(Node { next: RefCell::new(None), _logged: Logged(1, &log) });
+            let n1 = n1.ok().unwrap();
+            let n2 = Node { next: RefCell::new(Some(n1.clone())), _logged: Logged(2, &log) };
+            let n2 = a.new_rc(n2).ok().unwrap();
+            *n1.next.borrow_mut() = Some(n2.clone());
+            drop((n1, n2));
+            a.check_invariants();
+        }
+        // The cycle keeps both values alive until the arena is gone, and
+        // then they are never dropped.
+        assert!(log.borrow().is_empty());
+    }
+
+    #[test]
+    fn rc_exhausted() {
+        let a = checked_arena(8, 8);
+        assert_eq!(a.new_rc(5u64).unwrap_err(), 5);
+    }
+}
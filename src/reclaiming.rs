@@ -0,0 +1,265 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! An arena whose boxes give their memory back when they are dropped on
+//! top of the cursor.
+
+use core::fmt;
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+
+#[cfg(feature = "alloc")]
+use alloc::AllocError;
+use arena::ArenaCheckpoint;
+use {Arena, ArenaBox};
+
+/// An `Arena` whose boxes move the cursor back when the most recent
+/// allocation is dropped.
+///
+/// This suits loops that allocate a temporary, look at it and throw it
+/// away: with a plain Arena every temporary stays allocated until the next
+/// reset, while here a temporary that is dropped before anything else is
+/// allocated takes no room at all afterwards, alignment padding included.
+/// A [`ReclaimingBox`] dropped while later allocations are still in use
+/// keeps its memory, like an `ArenaBox`, and only that box loses out.
+///
+/// `ReclaimingArena` dereferences to `Arena`. Allocations made through the
+/// `Arena` methods it does not have itself are never reclaimed, and keep
+/// boxes allocated before them from being reclaimed while they sit above
+/// them.
+///
+/// [`ReclaimingBox`]: struct.ReclaimingBox.html
+///
+/// # Examples
+///
+/// ```
+/// # use memory_arena::*;
+/// # #[cfg(feature = "alloc")] {
+/// let a = ReclaimingArena::new(64, 64).unwrap();
+/// for i in 0..1000u64 {
+///     let scratch = a.new_box([i; 4]).unwrap();
+///     assert_eq!(scratch[3], i);
+/// }
+/// assert_eq!(a.used(), 0);
+/// # }
+/// ```
+pub struct ReclaimingArena {
+    arena: Arena,
+}
+
+impl ReclaimingArena {
+    /// Creates a `ReclaimingArena` with a new `Arena` of `size` bytes,
+    /// aligned to `alignment`; see [`Arena::new`].
+    ///
+    /// [`Arena::new`]: struct.Arena.html#method.new
+    #[cfg(feature = "alloc")]
+    pub fn new(size: usize, alignment: usize) -> Result<Self, AllocError> {
+        Ok(Arena::new(size, alignment)?.into())
+    }
+
+    /// Returns the underlying `Arena`.
+    pub fn into_inner(self) -> Arena {
+        self.arena
+    }
+
+    /// Records the cursor around `alloc`, for the box it returns to roll
+    /// back to.
+    fn track<'a, T: ?Sized, E, F>(&'a self, alloc: F) -> Result<ReclaimingBox<'a, T>, E>
+    where
+        F: FnOnce(&'a Arena) -> Result<ArenaBox<'a, T>, E>,
+    {
+        let before = self.arena.checkpoint();
+        let value = alloc(&self.arena)?;
+        Ok(ReclaimingBox {
+            value: ManuallyDrop::new(value),
+            arena: &self.arena,
+            before,
+            after: self.arena.checkpoint(),
+        })
+    }
+
+    /// Moves `x` into the Arena, like [`Arena::new_box`].
+    ///
+    /// [`Arena::new_box`]: struct.Arena.html#method.new_box
+    pub fn new_box<T>(&self, x: T) -> Result<ReclaimingBox<'_, T>, T> {
+        self.track(|a| a.new_box(x))
+    }
+
+    /// Copies `s` into the Arena, like [`Arena::alloc_slice_copy`].
+    ///
+    /// [`Arena::alloc_slice_copy`]: struct.Arena.html#method.alloc_slice_copy
+    pub fn alloc_slice_copy<T: Copy>(
+        &self,
+        s: &[T],
+    ) -> Result<ReclaimingBox<'_, [T]>, ::alloc::AllocError> {
+        self.track(|a| a.alloc_slice_copy(s))
+    }
+
+    /// Copies `s` into the Arena, like [`Arena::alloc_str`].
+    ///
+    /// [`Arena::alloc_str`]: struct.Arena.html#method.alloc_str
+    pub fn alloc_str(&self, s: &str) -> Result<ReclaimingBox<'_, str>, ::alloc::AllocError> {
+        self.track(|a| a.alloc_str(s))
+    }
+
+    /// Makes all of the memory available again, like [`Arena::reset`].
+    ///
+    /// [`Arena::reset`]: struct.Arena.html#method.reset
+    pub fn reset(&mut self) {
+        self.arena.reset()
+    }
+}
+
+impl From<Arena> for ReclaimingArena {
+    fn from(arena: Arena) -> Self {
+        ReclaimingArena { arena }
+    }
+}
+
+impl Deref for ReclaimingArena {
+    type Target = Arena;
+
+    fn deref(&self) -> &Arena {
+        &self.arena
+    }
+}
+
+impl fmt::Debug for ReclaimingArena {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ReclaimingArena").field("arena", &self.arena).finish()
+    }
+}
+
+/// A box from a [`ReclaimingArena`], which gives its memory back to the
+/// Arena when it is dropped, if it is still the most recent allocation.
+///
+/// Besides the value, the box remembers where the cursor was before and
+/// after the allocation.
+///
+/// [`ReclaimingArena`]: struct.ReclaimingArena.html
+pub struct ReclaimingBox<'a, T: ?Sized> {
+    value: ManuallyDrop<ArenaBox<'a, T>>,
+    arena: &'a Arena,
+    before: ArenaCheckpoint,
+    after: ArenaCheckpoint,
+}
+
+impl<'a, T: ?Sized> ReclaimingBox<'a, T> {
+    /// Turns the box into a plain `ArenaBox`, whose memory stays allocated
+    /// until the Arena is reset.
+    pub fn into_box(b: Self) -> ArenaBox<'a, T> {
+        let mut b = ManuallyDrop::new(b);
+        unsafe { ManuallyDrop::take(&mut b.value) }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for ReclaimingBox<'a, T> {
+    fn drop(&mut self) {
+        unsafe { ManuallyDrop::drop(&mut self.value) };
+        self.arena.roll_back(self.before, self.after);
+    }
+}
+
+impl<'a, T: ?Sized> Deref for ReclaimingBox<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for ReclaimingBox<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for ReclaimingBox<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn reclaiming_loop() {
+        let a = ReclaimingArena::new(64, 64).unwrap();
+        let keep = a.new_box(1u8).unwrap();
+        for i in 0..100_000u64 {
+            // The u64 needs 7 bytes of padding after `keep`, which are given
+            // back too.
+            let x = a.new_box(i).unwrap();
+            let s = a.alloc_str("scratch").unwrap();
+            assert_eq!((*x, &*s), (i, "scratch"));
+            drop(s);
+            drop(x);
+            assert_eq!(a.used(), 1);
+        }
+        assert_eq!(*keep, 1);
+        drop(keep);
+        assert_eq!(a.used(), 0);
+        a.check_invariants();
+    }
+
+    #[test]
+    fn reclaiming_interleaved() {
+        let a = ReclaimingArena::new(64, 64).unwrap();
+        let x = a.new_box([1u8; 8]).unwrap();
+        let y = a.new_box([2u8; 8]).unwrap();
+        // `x` is not on top, so its memory stays allocated.
+        drop(x);
+        assert_eq!(a.used(), 16);
+        drop(y);
+        assert_eq!(a.used(), 8);
+
+        // A plain allocation on top keeps `z` from being reclaimed, but not
+        // what comes after it.
+        let z = a.alloc_slice_copy(&[3u16; 4]).unwrap();
+        let plain = Arena::new_box(&a, 4u32).unwrap();
+        let w = a.new_box(5u32).unwrap();
+        drop(w);
+        assert_eq!(a.used(), 20);
+        drop(z);
+        assert_eq!(a.used(), 20);
+        assert_eq!(*plain, 4);
+
+        let kept = ReclaimingBox::into_box(a.new_box(6u64).unwrap());
+        assert_eq!((*kept, a.used()), (6, 32));
+        let v: Vec<_> = (0..3).map(|i| a.new_box(i).unwrap()).collect();
+        assert_eq!(a.used(), 44);
+        // Only the last element is on top when it is dropped.
+        drop(v);
+        assert_eq!(a.used(), 40);
+        a.check_invariants();
+    }
+
+    #[test]
+    fn reclaiming_min_alignment() {
+        let mut arena = Arena::new(256, 64).unwrap();
+        arena.set_min_alignment(16);
+        let a = ReclaimingArena::from(arena);
+        let x = a.new_box(1u8).unwrap();
+        let y = a.new_box(2u8).unwrap();
+        let padding = a.min_alignment_padding();
+        assert!(padding > 0);
+        drop(y);
+        assert_eq!((a.used(), a.min_alignment_padding()), (1, 0));
+        let z = a.new_box(3u8).unwrap();
+        assert_eq!(a.used(), 17);
+        drop(z);
+        drop(x);
+        assert_eq!(a.used(), 0);
+        let a = a.into_inner();
+        a.check_invariants();
+    }
+}
@@ -0,0 +1,463 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Arenas over memory they do not own, possibly made of several regions.
+
+use core::cell::Cell;
+use core::fmt;
+use core::marker::PhantomData;
+#[cfg(feature = "alloc")]
+use core::mem;
+use core::ops::Deref;
+use core::ptr;
+use core::slice;
+
+#[cfg(feature = "alloc")]
+use alloc::AllocError;
+use arena::Backing;
+use {Arena, ArenaCheckpoint};
+
+pub(crate) struct Region {
+    mem: *mut u8,
+    size: usize,
+    used: Cell<usize>,
+}
+
+/// The regions of an Arena after the first one, in priority order.
+pub(crate) struct Regions {
+    ptr: *mut Region,
+    len: usize,
+}
+
+impl Regions {
+    pub(crate) fn new() -> Self {
+        Regions {
+            ptr: ptr::null_mut(),
+            len: 0,
+        }
+    }
+
+    fn as_slice(&self) -> &[Region] {
+        if self.len == 0 {
+            return &[];
+        }
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of bytes across all regions.
+    pub(crate) fn size(&self) -> usize {
+        self.as_slice().iter().map(|r| r.size).sum()
+    }
+
+    /// Returns the number of bytes used across all regions.
+    pub(crate) fn used(&self) -> usize {
+        self.as_slice().iter().map(|r| r.used.get()).sum()
+    }
+
+    pub(crate) fn largest_remaining(&self) -> usize {
+        self.as_slice().iter().map(|r| r.size - r.used.get()).max().unwrap_or(0)
+    }
+
+    /// Allocates from the first region that has room.
+    pub(crate) fn alloc(&self, size: usize, alignment: usize) -> Option<*mut u8> {
+        self.as_slice().iter().find_map(|r| {
+            let unaligned_p = r.mem as usize + r.used.get();
+            let aligned_p = (unaligned_p + alignment - 1) & !(alignment - 1);
+            let end = (aligned_p - r.mem as usize).checked_add(size)?;
+            if end > r.size {
+                return None;
+            }
+            r.used.set(end);
+            Some(aligned_p as *mut u8)
+        })
+    }
+
+    /// Returns `true` if `addr` is in the used part of a region.
+    pub(crate) fn contains(&self, addr: usize) -> bool {
+        self.contains_range(addr, addr)
+    }
+
+    /// Returns `true` if `first..=last` is in the used part of one region.
+    pub(crate) fn contains_range(&self, first: usize, last: usize) -> bool {
+        self.as_slice().iter().any(|r| {
+            let offset = first.wrapping_sub(r.mem as usize);
+            offset < r.used.get() && last - first < r.used.get() - offset
+        })
+    }
+
+    pub(crate) fn clear(&self) {
+        for r in self.as_slice() {
+            r.used.set(0);
+        }
+    }
+
+    /// Fills the used part of every region with `byte`.
+    #[cfg(feature = "poison")]
+    pub(crate) fn fill_used(&self, byte: u8) {
+        for r in self.as_slice() {
+            unsafe { ptr::write_bytes(r.mem, byte, r.used.get()) };
+        }
+    }
+
+    #[cfg(any(test, feature = "check-invariants"))]
+    pub(crate) fn check_invariants(&self) {
+        for (i, r) in self.as_slice().iter().enumerate() {
+            assert!(
+                r.used.get() <= r.size,
+                "arena invariant violated: used ({}) exceeds size ({}) of region {}",
+                r.used.get(),
+                r.size,
+                i + 1
+            );
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Drop for Regions {
+    fn drop(&mut self) {
+        if !self.ptr.is_null() {
+            let size = self.len * mem::size_of::<Region>();
+            unsafe { ::alloc::free(self.ptr as *mut u8, size, mem::align_of::<Region>()) };
+        }
+    }
+}
+
+/// An `Arena` over memory borrowed for `'buf`.
+///
+/// `BorrowedArena` dereferences to `Arena`, so it allocates like any other
+/// Arena, except that the memory is given back to its owner rather than
+/// freed when the `BorrowedArena` is dropped. It does not dereference
+/// mutably, since the Arena could then be swapped out and outlive the
+/// buffer; `reset`, `rewind` and `scope` are forwarded instead.
+pub struct BorrowedArena<'buf> {
+    arena: Arena,
+    phantom: PhantomData<&'buf mut [u8]>,
+}
+
+impl Arena {
+    /// Creates an Arena that allocates out of `buf`, for targets where
+    /// memory cannot be allocated from the system.
+    ///
+    /// Nothing is assumed about the alignment of `buf`: the first
+    /// allocation is padded as needed, like any other. The buffer is
+    /// borrowed until the `BorrowedArena` is dropped, and is never freed.
+    /// Unlike [`Arena::new`], this is available without the `libc` feature.
+    ///
+    /// [`Arena::new`]: #method.new
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// let mut buf = [0u8; 64];
+    /// let a = Arena::from_buffer(&mut buf[1..]);
+    /// let x = a.new_box(7u32).unwrap();
+    /// assert_eq!(&*x as *const u32 as usize % 4, 0);
+    /// ```
+    ///
+    /// The following example will not compile, because the ArenaBox
+    /// cannot outlive the buffer it was allocated from.
+    ///
+    /// ```compile_fail
+    /// # use memory_arena::*;
+    /// let x = {
+    ///     let mut buf = [0u8; 64];
+    ///     let a = Arena::from_buffer(&mut buf);
+    ///     let x: ArenaBox<u32> = a.new_box(7).unwrap();
+    ///     x
+    /// };
+    /// ```
+    ///
+    /// The buffer cannot be used while the Arena has it either.
+    ///
+    /// ```compile_fail
+    /// # use memory_arena::*;
+    /// let mut buf = [0u8; 64];
+    /// let a = Arena::from_buffer(&mut buf);
+    /// let x = a.new_box(7u32).unwrap();
+    /// buf[0] = 1;
+    /// drop(x);
+    /// ```
+    pub fn from_buffer(buf: &mut [u8]) -> BorrowedArena<'_> {
+        BorrowedArena {
+            arena: Arena::from_parts(buf.as_mut_ptr(), buf.len(), Backing::Borrowed, Regions::new()),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Creates an Arena over several separate buffers.
+    ///
+    /// Allocations are placed in the first buffer that has room for them,
+    /// so the buffers should be given in order of preference, such as fast
+    /// memory first. A single allocation never spans two buffers, and one
+    /// that is too large for what is left in each of them fails with
+    /// `AllocError::NoRegionFits`, even if there is enough memory left in
+    /// total.
+    ///
+    /// The buffers are moved out of `regions`, which is left holding empty
+    /// slices, and are borrowed until the `BorrowedArena` is dropped.
+    /// [`Arena::take_remaining`] and [`Arena::alloc_at_offset`] only operate
+    /// on the first buffer. This needs the `libc` feature, to allocate the
+    /// list of buffers; [`Arena::from_buffer`] does not.
+    ///
+    /// [`Arena::take_remaining`]: #method.take_remaining
+    /// [`Arena::alloc_at_offset`]: #method.alloc_at_offset
+    /// [`Arena::from_buffer`]: #method.from_buffer
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// let mut fast = [0u8; 64];
+    /// let mut slow = [0u8; 1024];
+    /// let a = Arena::from_regions(&mut [&mut fast[..], &mut slow[..]]).unwrap();
+    /// let small = a.new_box(1u64).unwrap();
+    /// let large = a.new_box([0u64; 16]).unwrap();
+    /// # let _ = (small, large);
+    /// ```
+    #[cfg(feature = "alloc")]
+    pub fn from_regions<'buf>(
+        regions: &mut [&'buf mut [u8]],
+    ) -> Result<BorrowedArena<'buf>, AllocError> {
+        let mut rest = Regions::new();
+        if regions.len() > 1 {
+            let len = regions.len() - 1;
+            let size = len * mem::size_of::<Region>();
+            rest.ptr = unsafe { ::alloc::aligned_alloc(size, mem::align_of::<Region>())? }
+                as *mut Region;
+            rest.len = len;
+            for (i, buf) in regions[1..].iter_mut().enumerate() {
+                let buf = mem::take(buf);
+                let region = Region {
+                    mem: buf.as_mut_ptr(),
+                    size: buf.len(),
+                    used: Cell::new(0),
+                };
+                unsafe { ptr::write(rest.ptr.add(i), region) };
+            }
+        }
+        let (mem, size) = match regions.first_mut() {
+            Some(buf) => {
+                let buf = mem::take(buf);
+                (buf.as_mut_ptr(), buf.len())
+            }
+            None => (ptr::dangling_mut(), 0),
+        };
+        Ok(BorrowedArena {
+            arena: Arena::from_parts(mem, size, Backing::Borrowed, rest),
+            phantom: PhantomData,
+        })
+    }
+}
+
+impl<'buf> BorrowedArena<'buf> {
+    /// Makes all of the memory available again, like [`Arena::reset`].
+    ///
+    /// [`Arena::reset`]: struct.Arena.html#method.reset
+    pub fn reset(&mut self) {
+        self.arena.reset()
+    }
+
+    /// Makes the memory allocated since `cp` was taken available again,
+    /// like [`Arena::rewind`].
+    ///
+    /// [`Arena::rewind`]: struct.Arena.html#method.rewind
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cp` was taken from a different Arena, or from this one
+    /// before a reset or rewind to an earlier position.
+    pub fn rewind(&mut self, cp: ArenaCheckpoint) {
+        self.arena.rewind(cp)
+    }
+
+    /// Calls `f` with the Arena, then makes what `f` allocated available
+    /// again, like [`Arena::scope`].
+    ///
+    /// [`Arena::scope`]: struct.Arena.html#method.scope
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use memory_arena::*;
+    /// let mut buf = [0u8; 128];
+    /// let mut a = Arena::from_buffer(&mut buf);
+    /// for pass in 0..3u64 {
+    ///     let sum = a.scope(|a| a.new_box([pass; 8]).unwrap().iter().sum::<u64>());
+    ///     assert_eq!((sum, a.used()), (8 * pass, 0));
+    /// }
+    /// ```
+    pub fn scope<F, R>(&mut self, f: F) -> R
+    where
+        F: for<'s> FnOnce(&'s Arena) -> R,
+    {
+        self.arena.scope(f)
+    }
+}
+
+impl<'buf> Deref for BorrowedArena<'buf> {
+    type Target = Arena;
+
+    fn deref(&self) -> &Arena {
+        &self.arena
+    }
+}
+
+impl<'buf> fmt::Debug for BorrowedArena<'buf> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BorrowedArena").field("regions", &(self.arena.regions.len + 1)).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use test_util::live_allocations;
+
+    #[cfg(feature = "alloc")]
+    fn inside(p: *const u8, buf: *const [u8]) -> bool {
+        let start = buf as *const u8 as usize;
+        (start..start + buf.len()).contains(&(p as usize))
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn regions_spill() {
+        let before = live_allocations();
+        let mut fast = [0u8; 64];
+        let mut slow = [0u8; 256];
+        let (fast_p, slow_p) = (&fast[..] as *const [u8], &slow[..] as *const [u8]);
+        {
+            let mut regions = [&mut fast[..], &mut slow[..]];
+            let a = Arena::from_regions(&mut regions).unwrap();
+            assert!(regions.iter().all(|r| r.is_empty()));
+            let first: std::vec::Vec<_> = (0..7).map(|i| a.new_box(i as u64).unwrap()).collect();
+            for b in &first {
+                assert!(inside(&**b as *const u64 as *const u8, fast_p));
+            }
+            let spilled = a.new_box([7u64; 4]).unwrap();
+            assert!(inside(&*spilled as *const [u64; 4] as *const u8, slow_p));
+            assert!(a.contains_value(&*spilled) && a.contains_value(&*first[6]));
+            assert!(!a.contains((&*spilled as *const [u64; 4]).wrapping_add(1) as *const u8));
+            // Small allocations still go to the first region while it has
+            // room.
+            let last = a.new_box(8u64).unwrap();
+            assert!(inside(&*last as *const u64 as *const u8, fast_p));
+            let next = a.new_box(9u64).unwrap();
+            assert!(inside(&*next as *const u64 as *const u8, slow_p));
+            assert!(a.new_box([0u8; 512]).is_err());
+            assert_eq!(
+                a.try_aligned_alloc(256, 1),
+                Err(AllocError::NoRegionFits { size: 256, largest: 216 })
+            );
+            assert!(a.try_aligned_alloc(216, 1).is_ok());
+            assert_eq!(*spilled, [7; 4]);
+            a.check_invariants();
+        }
+        assert_eq!(&fast[..8], &0u64.to_ne_bytes());
+        assert_eq!(&slow[..8], &7u64.to_ne_bytes());
+        assert_eq!(live_allocations(), before);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn regions_quota_and_clear() {
+        let mut a_buf = [0u8; 16];
+        let mut b_buf = [0u8; 16];
+        let mut a = Arena::from_regions(&mut [&mut a_buf[..], &mut b_buf[..]]).unwrap();
+        let _ = a.new_box([0u8; 16]).unwrap();
+        // `set_quota` is only reachable through an owned Arena, so for the
+        // test reach into the BorrowedArena.
+        a.arena.set_quota(24);
+        let _ = a.new_box([0u8; 8]).unwrap();
+        assert_eq!(
+            a.try_aligned_alloc(1, 1),
+            Err(AllocError::QuotaExceeded { quota: 24, used: 24 })
+        );
+        a.arena.clear_quota();
+        a.clear();
+        assert_eq!(a.regions.used(), 0);
+        assert!(a.new_box([0u8; 16]).is_ok());
+        assert!(a.new_box([0u8; 16]).is_ok());
+    }
+
+    #[test]
+    fn regions_from_buffer() {
+        let before = live_allocations();
+        let mut buf = [0u8; 35];
+        let start = buf.as_ptr() as usize;
+        // Start at an odd address, so the first allocation has to be
+        // padded.
+        let offset = 1 + start % 2;
+        {
+            let a = Arena::from_buffer(&mut buf[offset..]);
+            assert_eq!(a.capacity(), 34 - start % 2);
+            let word = a.new_box(2u64).unwrap();
+            let word_p = &*word as *const u64 as usize;
+            assert_eq!(word_p % 8, 0);
+            assert!(word_p > start + offset);
+            assert_eq!(a.used(), word_p + 8 - (start + offset));
+            let byte = a.new_box(1u8).unwrap();
+            assert_eq!(&*byte as *const u8 as usize, word_p + 8);
+            assert_eq!(a.new_box([0u8; 32]).err(), Some([0u8; 32]));
+            a.check_invariants();
+        }
+        assert_eq!(buf[offset..].iter().filter(|&&b| b != 0).count(), 2);
+        assert_eq!(live_allocations(), before);
+    }
+
+    #[test]
+    fn regions_from_buffer_reuse() {
+        let mut buf = [0u8; 64];
+        let mut a = Arena::from_buffer(&mut buf);
+        let full = a.remaining();
+        let _ = a.new_box(1u8).unwrap();
+        a.reset();
+        assert_eq!((a.used(), a.remaining()), (0, full));
+        let first = a.new_box(2u8).unwrap();
+        let first_p = &*first as *const u8;
+        drop(first);
+        let cp = a.checkpoint();
+        let _ = a.new_box(3u8).unwrap();
+        a.rewind(cp);
+        assert_eq!(a.used(), 1);
+        let again = a.new_box(4u8).unwrap();
+        assert_eq!(&*again as *const u8 as usize, first_p as usize + 1);
+        drop(again);
+        let sum = a.scope(|a| *a.new_box(5u8).unwrap() + *a.new_box(6u8).unwrap());
+        assert_eq!((sum, a.used()), (11, 2));
+        a.check_invariants();
+    }
+
+    #[test]
+    #[should_panic(expected = "checkpoint is from a different arena")]
+    fn regions_from_buffer_rewind_foreign() {
+        let (mut buf, mut other) = ([0u8; 16], [0u8; 16]);
+        let mut a = Arena::from_buffer(&mut buf);
+        let b = Arena::from_buffer(&mut other);
+        a.rewind(b.checkpoint());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn regions_single_and_empty() {
+        let mut buf = [0u8; 32];
+        let a = Arena::from_regions(&mut [&mut buf[..]]).unwrap();
+        let _ = a.new_box(1u64).unwrap();
+        assert_eq!(a.try_aligned_alloc(64, 1), Err(AllocError::Exhausted));
+        a.check_invariants();
+        let none = Arena::from_regions(&mut []).unwrap();
+        assert_eq!(none.new_box(1u8), Err(1));
+    }
+}
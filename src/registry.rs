@@ -0,0 +1,199 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A global list of live arenas, for diagnostics.
+//!
+//! With the `registry` feature, every `Arena` registers itself when it is
+//! created, unless it opts out with [`ArenaBuilder::register`] or
+//! [`Arena::unregister`], and [`snapshot`] collects the current usage of
+//! all of them, say for a diagnostics endpoint.
+//!
+//! An `Arena` can't be read from other threads, so each registered Arena
+//! mirrors its usage into a shared entry on the heap, which the registry
+//! only holds weakly. A snapshot therefore never touches an Arena itself,
+//! and an Arena that is dropped during a snapshot simply has its entry
+//! skipped.
+//!
+//! [`ArenaBuilder::register`]: ../struct.ArenaBuilder.html#method.register
+//! [`Arena::unregister`]: ../struct.Arena.html#method.unregister
+//! [`snapshot`]: fn.snapshot.html
+
+use core::cell::Cell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::string::String;
+use std::sync::{Arc, RwLock, Weak};
+use std::vec::Vec;
+
+use Arena;
+
+struct Entry {
+    name: Option<String>,
+    capacity: usize,
+    used: AtomicUsize,
+}
+
+static REGISTRY: RwLock<Vec<Weak<Entry>>> = RwLock::new(Vec::new());
+
+/// The usage of one registered `Arena` at the time of a [`snapshot`].
+///
+/// [`snapshot`]: fn.snapshot.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatsSnapshot {
+    /// The name given with `ArenaBuilder::name`, if any.
+    pub name: Option<String>,
+    /// The size of the Arena's buffer in bytes.
+    pub capacity: usize,
+    /// The number of bytes handed out or lost to padding.
+    pub used: usize,
+}
+
+/// Returns the current usage of every live, registered `Arena`.
+pub fn snapshot() -> Vec<StatsSnapshot> {
+    let registry = REGISTRY.read().unwrap_or_else(|e| e.into_inner());
+    registry
+        .iter()
+        .filter_map(Weak::upgrade)
+        .map(|entry| StatsSnapshot {
+            name: entry.name.clone(),
+            capacity: entry.capacity,
+            used: entry.used.load(Ordering::Relaxed),
+        })
+        .collect()
+}
+
+/// The number of used bytes of an `Arena`, which is also published to the
+/// Arena's registry entry, if it has one.
+pub(crate) struct Used {
+    local: Cell<usize>,
+    entry: Option<Arc<Entry>>,
+}
+
+impl Used {
+    pub(crate) fn new(used: usize) -> Self {
+        Used {
+            local: Cell::new(used),
+            entry: None,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn get(&self) -> usize {
+        self.local.get()
+    }
+
+    #[inline]
+    pub(crate) fn set(&self, used: usize) {
+        self.local.set(used);
+        if let Some(ref entry) = self.entry {
+            entry.used.store(used, Ordering::Relaxed);
+        }
+    }
+
+    /// Adds an entry for an Arena of `capacity` bytes to the registry,
+    /// replacing the current one.
+    pub(crate) fn register(&mut self, name: Option<String>, capacity: usize) {
+        self.unregister();
+        let entry = Arc::new(Entry {
+            name,
+            capacity,
+            used: AtomicUsize::new(self.get()),
+        });
+        let mut registry = REGISTRY.write().unwrap_or_else(|e| e.into_inner());
+        registry.retain(|w| w.strong_count() > 0);
+        registry.push(Arc::downgrade(&entry));
+        self.entry = Some(entry);
+    }
+
+    /// Removes the entry from the registry, if there is one.
+    pub(crate) fn unregister(&mut self) {
+        if self.entry.take().is_some() {
+            // The entry is dead now and skipped by snapshots anyway, so only
+            // prune it if that does not have to wait.
+            if let Ok(mut registry) = REGISTRY.try_write() {
+                registry.retain(|w| w.strong_count() > 0);
+            }
+        }
+    }
+}
+
+impl Arena {
+    /// Removes the Arena from the registry, so that it no longer shows up
+    /// in [`registry::snapshot`].
+    ///
+    /// [`registry::snapshot`]: registry/fn.snapshot.html
+    pub fn unregister(&mut self) {
+        self.used.unregister();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use std::thread;
+    use ArenaBuilder;
+
+    fn ours(prefix: &str) -> Vec<StatsSnapshot> {
+        let mut stats: Vec<_> = snapshot()
+            .into_iter()
+            .filter(|s| s.name.as_ref().is_some_and(|n| n.starts_with(prefix)))
+            .collect();
+        stats.sort_by(|a, b| a.name.cmp(&b.name));
+        stats
+    }
+
+    #[test]
+    fn registry_snapshot() {
+        let a = ArenaBuilder::new(1024, 64).name("registry-snapshot-a").build().unwrap();
+        let b = ArenaBuilder::new(2048, 64).name("registry-snapshot-b").build().unwrap();
+        let c = ArenaBuilder::new(4096, 64).name("registry-snapshot-c").build().unwrap();
+        let _x = a.new_box([0u8; 100]).unwrap();
+        let _y = c.new_box([0u8; 300]).unwrap();
+        let stats = ours("registry-snapshot-");
+        let usage: Vec<_> = stats.iter().map(|s| (s.capacity, s.used)).collect();
+        assert_eq!(usage, [(1024, 100), (2048, 0), (4096, 300)]);
+        drop(b);
+        let stats = ours("registry-snapshot-");
+        let names: Vec<_> = stats.iter().map(|s| s.name.clone().unwrap()).collect();
+        assert_eq!(names, ["registry-snapshot-a", "registry-snapshot-c"]);
+    }
+
+    #[test]
+    fn registry_opt_out() {
+        let mut a = ArenaBuilder::new(1024, 64).name("registry-opt-out-a").build().unwrap();
+        let b = ArenaBuilder::new(1024, 64)
+            .name("registry-opt-out-b")
+            .register(false)
+            .build()
+            .unwrap();
+        assert_eq!(ours("registry-opt-out-").len(), 1);
+        a.unregister();
+        assert!(ours("registry-opt-out-").is_empty());
+        drop(b);
+    }
+
+    #[test]
+    fn registry_concurrent_drops() {
+        thread::scope(|s| {
+            for i in 0..4 {
+                s.spawn(move || {
+                    for j in 0..100 {
+                        let name = std::format!("registry-concurrent-{}-{}", i, j);
+                        let a = ArenaBuilder::new(64, 64).name(&name).build().unwrap();
+                        assert!(a.new_box(j).is_ok());
+                        drop(a);
+                    }
+                });
+            }
+            for _ in 0..100 {
+                let _ = snapshot();
+            }
+        });
+        assert!(ours("registry-concurrent-").is_empty());
+    }
+}
@@ -0,0 +1,70 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! An append-only byte buffer over the remaining memory of an `Arena`, used
+//! by the serialization adapters.
+
+use core::mem::MaybeUninit;
+use core::ptr;
+
+use {Arena, ArenaBox};
+
+/// Bytes appended to the memory obtained from `Arena::take_remaining`.
+///
+/// The Arena cannot hand out any other memory while a `Reservation` is
+/// alive. Finishing it returns the unused tail to the Arena; dropping it
+/// returns everything.
+pub(crate) struct Reservation<'a> {
+    arena: &'a Arena,
+    buf: Option<ArenaBox<'a, [MaybeUninit<u8>]>>,
+    len: usize,
+}
+
+impl<'a> Reservation<'a> {
+    pub(crate) fn new(arena: &'a Arena) -> Self {
+        Reservation {
+            arena,
+            buf: Some(arena.take_remaining()),
+            len: 0,
+        }
+    }
+
+    /// Appends `bytes`, or leaves the buffer untouched and returns `false`
+    /// if they do not fit.
+    pub(crate) fn extend(&mut self, bytes: &[u8]) -> bool {
+        let buf = self.buf.as_mut().unwrap();
+        if bytes.len() > buf.len() - self.len {
+            return false;
+        }
+        unsafe {
+            let dst = buf.as_mut_ptr().add(self.len) as *mut u8;
+            ptr::copy_nonoverlapping(bytes.as_ptr(), dst, bytes.len());
+        }
+        self.len += bytes.len();
+        true
+    }
+
+    /// Returns the bytes appended so far as a box, and the rest of the
+    /// reserved memory to the Arena.
+    pub(crate) fn finish(mut self) -> ArenaBox<'a, [u8]> {
+        let buf = self.buf.take().unwrap();
+        let unused = buf.len() - self.len;
+        let kept = self.arena.give_back(buf, unused);
+        let (p, live) = ArenaBox::into_raw_parts(kept);
+        unsafe { ArenaBox::from_raw_parts(p as *mut [u8], live) }
+    }
+}
+
+impl<'a> Drop for Reservation<'a> {
+    fn drop(&mut self) {
+        if let Some(buf) = self.buf.take() {
+            let len = buf.len();
+            let _ = self.arena.give_back(buf, len);
+        }
+    }
+}
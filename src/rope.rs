@@ -0,0 +1,317 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A string builder for strings of unknown length, made of fixed-size
+//! chunks in an `Arena`.
+
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem::{self, MaybeUninit};
+use core::ptr::{self, NonNull};
+use core::slice;
+use core::str;
+
+use alloc::AllocError;
+use arena_box::LiveGuard;
+use {Arena, ArenaBox};
+
+/// The number of bytes of text each chunk holds.
+const CHUNK_CAPACITY: usize = 240;
+
+struct Chunk {
+    next: Option<NonNull<Chunk>>,
+    len: usize,
+    bytes: [MaybeUninit<u8>; CHUNK_CAPACITY],
+}
+
+impl Chunk {
+    fn as_str(&self) -> &str {
+        unsafe {
+            let bytes = slice::from_raw_parts(self.bytes.as_ptr() as *const u8, self.len);
+            str::from_utf8_unchecked(bytes)
+        }
+    }
+}
+
+/// A string built up from chunks allocated in an `Arena` as it grows.
+///
+/// Unlike a string with a fixed capacity, an `ArenaRope` never needs to know
+/// its final length: whenever the current chunk is full, another one is
+/// allocated from the Arena and linked to it. Each chunk always holds
+/// complete UTF-8 characters. Once done, the pieces can either be copied
+/// into one contiguous string with [`ArenaRope::finish`], or consumed one at
+/// a time with [`ArenaRope::iter_chunks`].
+///
+/// The chunks stay allocated until the Arena is dropped.
+///
+/// [`ArenaRope::finish`]: #method.finish
+/// [`ArenaRope::iter_chunks`]: #method.iter_chunks
+///
+/// # Examples
+///
+/// ```
+/// # use memory_arena::*;
+/// # #[cfg(feature = "alloc")] {
+/// use std::fmt::Write;
+///
+/// let a = Arena::new(4096, 4096).unwrap();
+/// let mut rope = ArenaRope::new(&a);
+/// rope.push_str("hello").unwrap();
+/// rope.push(',').unwrap();
+/// write!(rope, " {}!", "world").unwrap();
+/// assert_eq!(&*rope.finish(&a).unwrap(), "hello, world!");
+/// # }
+/// ```
+pub struct ArenaRope<'a> {
+    arena: &'a Arena,
+    _live: LiveGuard<'a>,
+    head: Option<NonNull<Chunk>>,
+    tail: Option<NonNull<Chunk>>,
+    len: usize,
+}
+
+impl<'a> ArenaRope<'a> {
+    /// Creates an empty rope whose chunks are allocated from `arena`.
+    ///
+    /// No memory is allocated until the first non-empty push.
+    pub fn new(arena: &'a Arena) -> Self {
+        ArenaRope {
+            arena,
+            _live: LiveGuard::new(arena),
+            head: None,
+            tail: None,
+            len: 0,
+        }
+    }
+
+    /// Returns the length of the string in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the string is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn new_chunk(&self) -> Result<NonNull<Chunk>, AllocError> {
+        let size = mem::size_of::<Chunk>();
+        let p = self.arena.try_aligned_alloc(size, mem::align_of::<Chunk>())? as *mut Chunk;
+        unsafe {
+            ptr::write(
+                p,
+                Chunk {
+                    next: None,
+                    len: 0,
+                    bytes: [MaybeUninit::uninit(); CHUNK_CAPACITY],
+                },
+            );
+            Ok(NonNull::new_unchecked(p))
+        }
+    }
+
+    /// Appends `s` to the end of the string.
+    ///
+    /// If the Arena runs out of memory for a new chunk, the string is left
+    /// as it was before the call, and the error is returned.
+    pub fn push_str(&mut self, mut s: &str) -> Result<(), AllocError> {
+        let rollback = self.tail.map(|tail| (tail, unsafe { tail.as_ref().len }));
+        let old_len = self.len;
+        while !s.is_empty() {
+            // Fill up the last chunk with as many whole characters as fit,
+            // and start a new one when not even the next character does.
+            let mut n = 0;
+            if let Some(tail) = self.tail {
+                n = s.len().min(CHUNK_CAPACITY - unsafe { tail.as_ref().len });
+                while !s.is_char_boundary(n) {
+                    n -= 1;
+                }
+            }
+            if n == 0 {
+                let chunk = match self.new_chunk() {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        self.rollback(rollback, old_len);
+                        return Err(e);
+                    }
+                };
+                match self.tail {
+                    Some(mut tail) => unsafe { tail.as_mut().next = Some(chunk) },
+                    None => self.head = Some(chunk),
+                }
+                self.tail = Some(chunk);
+                continue;
+            }
+            let chunk = unsafe { self.tail.unwrap().as_mut() };
+            unsafe {
+                let dst = chunk.bytes.as_mut_ptr().add(chunk.len) as *mut u8;
+                ptr::copy_nonoverlapping(s.as_ptr(), dst, n);
+            }
+            chunk.len += n;
+            self.len += n;
+            s = &s[n..];
+        }
+        Ok(())
+    }
+
+    fn rollback(&mut self, tail: Option<(NonNull<Chunk>, usize)>, len: usize) {
+        match tail {
+            Some((mut tail, tail_len)) => unsafe {
+                let chunk = tail.as_mut();
+                chunk.next = None;
+                chunk.len = tail_len;
+                self.tail = Some(tail);
+            },
+            None => {
+                self.head = None;
+                self.tail = None;
+            }
+        }
+        self.len = len;
+    }
+
+    /// Appends `c` to the end of the string.
+    pub fn push(&mut self, c: char) -> Result<(), AllocError> {
+        self.push_str(c.encode_utf8(&mut [0; 4]))
+    }
+
+    /// Returns an iterator over the chunks of the string, in order.
+    pub fn iter_chunks(&self) -> Chunks<'_> {
+        Chunks {
+            next: self.head,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Copies the string into a single contiguous allocation in `arena`,
+    /// which may or may not be the Arena the chunks live in.
+    pub fn finish<'b>(&self, arena: &'b Arena) -> Result<ArenaBox<'b, str>, AllocError> {
+        let p = if self.len == 0 {
+            ptr::dangling_mut()
+        } else {
+            arena.try_aligned_alloc(self.len, 1)?
+        };
+        let mut offset = 0;
+        for chunk in self.iter_chunks() {
+            unsafe { ptr::copy_nonoverlapping(chunk.as_ptr(), p.add(offset), chunk.len()) };
+            offset += chunk.len();
+        }
+        let s = ptr::slice_from_raw_parts_mut(p, self.len) as *mut str;
+        Ok(unsafe { ArenaBox::from_raw_in(s, arena) })
+    }
+}
+
+impl<'a> fmt::Write for ArenaRope<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s).map_err(|_| fmt::Error)
+    }
+}
+
+impl<'a> fmt::Debug for ArenaRope<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter_chunks()).finish()
+    }
+}
+
+/// An iterator over the chunks of an `ArenaRope`.
+///
+/// Created by [`ArenaRope::iter_chunks`].
+///
+/// [`ArenaRope::iter_chunks`]: struct.ArenaRope.html#method.iter_chunks
+#[derive(Clone)]
+pub struct Chunks<'r> {
+    next: Option<NonNull<Chunk>>,
+    phantom: PhantomData<&'r Chunk>,
+}
+
+impl<'r> Iterator for Chunks<'r> {
+    type Item = &'r str;
+
+    fn next(&mut self) -> Option<&'r str> {
+        let chunk: &'r Chunk = unsafe { &*self.next?.as_ptr() };
+        self.next = chunk.next;
+        Some(chunk.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use core::fmt::Write;
+    use std::string::String;
+    use test_util::checked_arena;
+
+    #[test]
+    fn rope_multi_chunk() {
+        let a = checked_arena(64 * 1024, 4096);
+        let mut rope = ArenaRope::new(&a);
+        let mut reference = String::new();
+        for i in 0..500 {
+            let piece = match i % 4 {
+                0 => "ascii ",
+                1 => "\u{e9}t\u{e9} ",
+                2 => "\u{1f980}",
+                _ => "\u{4e2d}\u{6587}",
+            };
+            rope.push_str(piece).unwrap();
+            reference.push_str(piece);
+            write!(rope, "{}", i).unwrap();
+            write!(reference, "{}", i).unwrap();
+            rope.push('|').unwrap();
+            reference.push('|');
+        }
+        assert_eq!(rope.len(), reference.len());
+        assert!(rope.iter_chunks().count() > 1);
+        let mut pieces = String::new();
+        for chunk in rope.iter_chunks() {
+            assert!(chunk.len() <= CHUNK_CAPACITY);
+            pieces.push_str(chunk);
+        }
+        assert_eq!(pieces, reference);
+        assert_eq!(&*rope.finish(&a).unwrap(), &reference[..]);
+    }
+
+    #[test]
+    fn rope_long_push() {
+        let a = checked_arena(64 * 1024, 4096);
+        let other = checked_arena(8192, 4096);
+        let mut rope = ArenaRope::new(&a);
+        let reference: String = (0..3000).map(|i| ['a', '\u{e9}', '\u{1f980}'][i % 3]).collect();
+        rope.push_str(&reference).unwrap();
+        assert_eq!(&*rope.finish(&other).unwrap(), &reference[..]);
+    }
+
+    #[test]
+    fn rope_empty() {
+        let a = checked_arena(1024, 1024);
+        let mut rope = ArenaRope::new(&a);
+        rope.push_str("").unwrap();
+        assert!(rope.is_empty());
+        assert_eq!(rope.iter_chunks().count(), 0);
+        assert_eq!(&*rope.finish(&a).unwrap(), "");
+        assert_eq!(a.used.get(), 0);
+    }
+
+    #[test]
+    fn rope_exhausted() {
+        let a = checked_arena(2 * mem::size_of::<Chunk>(), 4096);
+        let mut rope = ArenaRope::new(&a);
+        rope.push_str("start").unwrap();
+        let long: String = (0..CHUNK_CAPACITY).map(|_| 'x').collect();
+        rope.push_str(&long).unwrap();
+        assert_eq!(rope.push_str(&long), Err(AllocError::Exhausted));
+        assert_eq!(rope.len(), 5 + CHUNK_CAPACITY);
+        let mut expected = String::from("start");
+        expected.push_str(&long);
+        let pieces: String = rope.iter_chunks().collect();
+        assert_eq!(pieces, expected);
+        assert!(write!(rope, "{}", long).is_err());
+        assert_eq!(rope.len(), 5 + CHUNK_CAPACITY);
+    }
+}
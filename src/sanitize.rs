@@ -0,0 +1,55 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! AddressSanitizer annotations of the memory an `Arena` has not handed
+//! out, enabled by the `sanitize` feature.
+//!
+//! To ASAN, an Arena's buffer is a single heap allocation, so an overflow
+//! from one arena allocation into the next goes unnoticed. With the
+//! feature, and only in builds with `-Zsanitizer=address`, the memory past
+//! the cursor is poisoned, and exactly the bytes of each allocation are
+//! unpoisoned as it is handed out, like pool allocators built for ASAN do.
+//! Everywhere else these functions do nothing.
+
+// `cfg(sanitize)` needs a nightly feature, which the `sanitize` feature
+// turns on, so it is only looked at when the feature is enabled.
+#[cfg_attr(feature = "sanitize", cfg(sanitize = "address"))]
+#[cfg_attr(not(feature = "sanitize"), cfg(any()))]
+mod imp {
+    extern "C" {
+        fn __asan_poison_memory_region(addr: *const u8, size: usize);
+        fn __asan_unpoison_memory_region(addr: *const u8, size: usize);
+    }
+
+    #[inline]
+    pub(crate) fn poison(p: *const u8, len: usize) {
+        if len != 0 {
+            unsafe { __asan_poison_memory_region(p, len) }
+        }
+    }
+
+    #[inline]
+    pub(crate) fn unpoison(p: *const u8, len: usize) {
+        if len != 0 {
+            unsafe { __asan_unpoison_memory_region(p, len) }
+        }
+    }
+}
+
+#[cfg_attr(feature = "sanitize", cfg(not(sanitize = "address")))]
+mod imp {
+    #[inline(always)]
+    pub(crate) fn poison(_: *const u8, _: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn unpoison(_: *const u8, _: usize) {}
+}
+
+/// Tells ASAN that the `len` bytes at `p` must not be accessed, or that
+/// they may be again.
+pub(crate) use self::imp::{poison, unpoison};
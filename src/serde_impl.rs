@@ -0,0 +1,390 @@
+//! `serde` support for `ArenaBox`, enabled by the `serde` feature.
+//!
+//! An `ArenaBox<T>` serializes exactly like the `T` it points to, the same
+//! way `Box<T>` does, including for unsized targets like `str` and `[T]`.
+//!
+//! Deserializing needs the Arena to allocate from, which a `Deserialize`
+//! impl has no way to get at, so it goes through the seeds [`ArenaSeed`]
+//! and [`ArenaSeqSeed`] instead.
+//!
+//! [`ArenaSeed`]: struct.ArenaSeed.html
+//! [`ArenaSeqSeed`]: struct.ArenaSeqSeed.html
+
+use core::fmt;
+use core::marker::PhantomData;
+
+use serde::de::{self, DeserializeSeed, Deserializer, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+
+use alloc::AllocError;
+use string::ArenaString;
+use vec::ArenaVec;
+use {Arena, ArenaBox};
+
+impl<'a, T: Serialize + ?Sized> Serialize for ArenaBox<'a, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (**self).serialize(serializer)
+    }
+}
+
+/// A `DeserializeSeed` that deserializes a `T` into an `Arena`, producing an
+/// `ArenaBox<T>`.
+///
+/// Besides sized `T: Deserialize`, it can deserialize `str`, to an
+/// `ArenaBox<str>`, and `[T]`, to an `ArenaBox<[T]>`, without going through
+/// a `String` or `Vec` on the heap. Sequences of values that need a seed of
+/// their own, like nested arena boxes, are deserialized with
+/// [`ArenaSeqSeed`].
+///
+/// If the Arena runs out of memory, deserialization fails with a custom
+/// error from the deserializer.
+///
+/// [`ArenaSeqSeed`]: struct.ArenaSeqSeed.html
+///
+/// # Examples
+///
+/// ```
+/// # extern crate memory_arena;
+/// # extern crate bincode;
+/// # use memory_arena::*;
+/// # fn main() {
+/// use bincode::config;
+///
+/// let a = Arena::new(1024, 1024).unwrap();
+/// let bytes = [5, b'a', b'r', b'e', b'n', b'a', 2, 7, 9];
+/// let (name, len) =
+///     bincode::serde::seed_decode_from_slice(ArenaSeed::<str>::new(&a), &bytes, config::standard())
+///         .unwrap();
+/// assert_eq!(&*name, "arena");
+/// let (numbers, _) = bincode::serde::seed_decode_from_slice(
+///     ArenaSeed::<[u8]>::new(&a),
+///     &bytes[len..],
+///     config::standard(),
+/// )
+/// .unwrap();
+/// assert_eq!(&*numbers, &[7, 9]);
+/// # }
+/// ```
+pub struct ArenaSeed<'a, T: ?Sized> {
+    arena: &'a Arena,
+    marker: PhantomData<fn() -> ArenaBox<'a, T>>,
+}
+
+impl<'a, T: ?Sized> ArenaSeed<'a, T> {
+    /// Creates a seed that deserializes into `arena`.
+    pub fn new(arena: &'a Arena) -> Self {
+        ArenaSeed {
+            arena,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Clone for ArenaSeed<'a, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T: ?Sized> Copy for ArenaSeed<'a, T> {}
+
+impl<'de, 'a, T: Deserialize<'de>> DeserializeSeed<'de> for ArenaSeed<'a, T> {
+    type Value = ArenaBox<'a, T>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        let x = T::deserialize(deserializer)?;
+        self.arena
+            .new_box(x)
+            .map_err(|_| de::Error::custom(AllocError::Exhausted))
+    }
+}
+
+impl<'de, 'a> DeserializeSeed<'de> for ArenaSeed<'a, str> {
+    type Value = ArenaBox<'a, str>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_str(self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for ArenaSeed<'a, str> {
+    type Value = ArenaBox<'a, str>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a string")
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+        let mut string = ArenaString::with_capacity_in(s.len(), self.arena).map_err(E::custom)?;
+        string.push_str(s);
+        Ok(string.into_boxed_str())
+    }
+}
+
+impl<'de, 'a, T: Deserialize<'de>> DeserializeSeed<'de> for ArenaSeed<'a, [T]> {
+    type Value = ArenaBox<'a, [T]>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        ArenaSeqSeed::new(self.arena, PhantomData::<T>).deserialize(deserializer)
+    }
+}
+
+/// A `DeserializeSeed` that deserializes a sequence into an `Arena`,
+/// deserializing each element with a clone of `seed`.
+///
+/// The elements are collected into an `ArenaBox<[S::Value]>`. Room for as
+/// many elements as the deserializer says there are is allocated up front,
+/// so with a deserializer that knows the length, the elements are never
+/// moved, even if they allocate from the Arena themselves.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate memory_arena;
+/// # extern crate bincode;
+/// # use memory_arena::*;
+/// # fn main() {
+/// let a = Arena::new(1024, 1024).unwrap();
+/// let bytes = [2, 1, b'a', 2, b'b', b'c'];
+/// let seed = ArenaSeqSeed::new(&a, ArenaSeed::<str>::new(&a));
+/// let (names, _) =
+///     bincode::serde::seed_decode_from_slice(seed, &bytes, bincode::config::standard()).unwrap();
+/// assert_eq!(&*names[0], "a");
+/// assert_eq!(&*names[1], "bc");
+/// # }
+/// ```
+#[derive(Clone, Copy)]
+pub struct ArenaSeqSeed<'a, S> {
+    arena: &'a Arena,
+    seed: S,
+}
+
+impl<'a, S> ArenaSeqSeed<'a, S> {
+    /// Creates a seed that deserializes a sequence into `arena`, with
+    /// `seed` for the elements.
+    pub fn new(arena: &'a Arena, seed: S) -> Self {
+        ArenaSeqSeed { arena, seed }
+    }
+}
+
+impl<'de, 'a, S: DeserializeSeed<'de> + Clone> DeserializeSeed<'de> for ArenaSeqSeed<'a, S> {
+    type Value = ArenaBox<'a, [S::Value]>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, 'a, S: DeserializeSeed<'de> + Clone> Visitor<'de> for ArenaSeqSeed<'a, S> {
+    type Value = ArenaBox<'a, [S::Value]>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a sequence")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut v = ArenaVec::with_capacity_in(seq.size_hint().unwrap_or(0), self.arena)
+            .map_err(de::Error::custom)?;
+        while let Some(x) = seq.next_element_seed(self.seed.clone())? {
+            v.try_reserve(1).map_err(de::Error::custom)?;
+            v.push(x);
+        }
+        Ok(v.into_boxed_slice())
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    extern crate bincode;
+
+    #[allow(unused_imports)]
+    use super::*;
+    use self::bincode::config;
+    use rust_alloc::boxed::Box;
+    use test_util::{checked_arena, heap_allocations};
+
+    #[derive(Serialize)]
+    struct Node<P> {
+        name: P,
+        weight: u32,
+    }
+
+    #[derive(Serialize)]
+    struct Config<P, C, L> {
+        title: P,
+        root: C,
+        leaves: L,
+    }
+
+    #[derive(Serialize)]
+    struct Section<S, L, T> {
+        heading: S,
+        lines: L,
+        tags: T,
+    }
+
+    #[derive(Serialize)]
+    struct Document<S, C> {
+        title: S,
+        sections: C,
+    }
+
+    type ArenaSection<'a> = Section<ArenaBox<'a, str>, ArenaBox<'a, [ArenaBox<'a, str>]>, ArenaBox<'a, [u32]>>;
+    type ArenaDocument<'a> = Document<ArenaBox<'a, str>, ArenaBox<'a, [ArenaSection<'a>]>>;
+
+    // A derived `Deserialize` cannot deserialize into an Arena, so the seeds
+    // for the document are written out, the way users of `ArenaSeed` would.
+    #[derive(Clone, Copy)]
+    struct SectionSeed<'a>(&'a Arena);
+
+    #[derive(Clone, Copy)]
+    struct DocumentSeed<'a>(&'a Arena);
+
+    fn field<'de, A: SeqAccess<'de>, S: DeserializeSeed<'de>>(
+        seq: &mut A,
+        i: usize,
+        seed: S,
+    ) -> Result<S::Value, A::Error> {
+        seq.next_element_seed(seed)?
+            .ok_or_else(|| de::Error::invalid_length(i, &"more fields"))
+    }
+
+    impl<'de, 'a> DeserializeSeed<'de> for SectionSeed<'a> {
+        type Value = ArenaSection<'a>;
+
+        fn deserialize<D: Deserializer<'de>>(self, d: D) -> Result<Self::Value, D::Error> {
+            d.deserialize_struct("Section", &["heading", "lines", "tags"], self)
+        }
+    }
+
+    impl<'de, 'a> Visitor<'de> for SectionSeed<'a> {
+        type Value = ArenaSection<'a>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a section")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            Ok(Section {
+                heading: field(&mut seq, 0, ArenaSeed::<str>::new(self.0))?,
+                lines: field(&mut seq, 1, ArenaSeqSeed::new(self.0, ArenaSeed::<str>::new(self.0)))?,
+                tags: field(&mut seq, 2, ArenaSeed::<[u32]>::new(self.0))?,
+            })
+        }
+    }
+
+    impl<'de, 'a> DeserializeSeed<'de> for DocumentSeed<'a> {
+        type Value = ArenaDocument<'a>;
+
+        fn deserialize<D: Deserializer<'de>>(self, d: D) -> Result<Self::Value, D::Error> {
+            d.deserialize_struct("Document", &["title", "sections"], self)
+        }
+    }
+
+    impl<'de, 'a> Visitor<'de> for DocumentSeed<'a> {
+        type Value = ArenaDocument<'a>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a document")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            Ok(Document {
+                title: field(&mut seq, 0, ArenaSeed::<str>::new(self.0))?,
+                sections: field(&mut seq, 1, ArenaSeqSeed::new(self.0, SectionSeed(self.0)))?,
+            })
+        }
+    }
+
+    /// Returns the encoding of a document with a few sections, built on
+    /// the heap.
+    fn encoded_document() -> ([u8; 1024], usize) {
+        let section = |heading: &str, lines: &[&str], tags: &[u32]| Section {
+            heading: Box::<str>::from(heading),
+            lines: lines.iter().map(|&l| Box::<str>::from(l)).collect::<Box<[_]>>(),
+            tags: Box::<[u32]>::from(tags),
+        };
+        encode(&Document {
+            title: Box::<str>::from("request"),
+            sections: Box::<[_]>::from([
+                section("headers", &["host: example.com", "accept: */*"], &[1, 2]),
+                section("body", &["{}"], &[]),
+                section("trailers", &[], &[300, 70000, 5]),
+            ]),
+        })
+    }
+
+    fn encode<T: Serialize>(value: &T) -> ([u8; 1024], usize) {
+        let mut buf = [0; 1024];
+        let len = bincode::serde::encode_into_slice(value, &mut buf, config::standard()).unwrap();
+        (buf, len)
+    }
+
+    #[test]
+    fn serialize_like_box() {
+        let a = checked_arena(1024, 1024);
+        let mut title = a.new_string();
+        title.push_str("arena config");
+        let leaf = |name: &'static str, weight| Node { name, weight };
+        let arena_config = Config {
+            title: title.into_boxed_str(),
+            root: a.new_box(Node { name: a.new_box(7u8).unwrap(), weight: 1 }).ok().unwrap(),
+            leaves: ArenaBox::<[_]>::from(a.new_box([leaf("a", 2), leaf("bc", 3)]).ok().unwrap()),
+        };
+        let box_config = Config {
+            title: Box::<str>::from("arena config"),
+            root: Box::new(Node { name: Box::new(7u8), weight: 1 }),
+            leaves: Box::<[_]>::from([leaf("a", 2), leaf("bc", 3)]),
+        };
+        let (arena_bytes, arena_len) = encode(&arena_config);
+        let (box_bytes, box_len) = encode(&box_config);
+        assert!(arena_len > 0);
+        assert_eq!(arena_bytes[..arena_len], box_bytes[..box_len]);
+    }
+
+    #[test]
+    fn deserialize_into_arena() {
+        let before = heap_allocations();
+        let (bytes, len) = encoded_document();
+        assert!(heap_allocations() > before);
+        let a = checked_arena(4096, 4096);
+        let before = heap_allocations();
+        let (doc, read) =
+            bincode::serde::seed_decode_from_slice(DocumentSeed(&a), &bytes[..len], config::standard())
+                .unwrap();
+        assert_eq!(heap_allocations(), before);
+        assert_eq!(read, len);
+        assert_eq!(&*doc.title, "request");
+        assert_eq!(doc.sections.len(), 3);
+        assert_eq!(&*doc.sections[0].lines[0], "host: example.com");
+        assert_eq!(&*doc.sections[2].tags, &[300, 70000, 5]);
+        let start = a.mem as usize;
+        let in_arena = |p: *const u8| (p as usize) >= start && (p as usize) < start + a.used();
+        assert!(in_arena(doc.sections[1].heading.as_ptr()));
+        assert!(in_arena(doc.sections[0].lines[1].as_ptr()));
+        // Serializing the arena document again gives back the same bytes.
+        let (again, again_len) = encode(&doc);
+        assert_eq!(again[..again_len], bytes[..len]);
+    }
+
+    #[test]
+    fn deserialize_exhausted() {
+        let (bytes, len) = encoded_document();
+        for size in &[0, 8, 64, 128] {
+            let a = checked_arena(*size, 8);
+            let result =
+                bincode::serde::seed_decode_from_slice(DocumentSeed(&a), &bytes[..len], config::standard());
+            assert!(result.is_err());
+        }
+        let a = checked_arena(8, 8);
+        let result = bincode::serde::seed_decode_from_slice(ArenaSeed::<u64>::new(&a), &[1], config::standard());
+        assert_eq!(*result.unwrap().0, 1);
+        let result = bincode::serde::seed_decode_from_slice(ArenaSeed::<u64>::new(&a), &[2], config::standard());
+        // Without its `alloc` feature, bincode drops the message of custom
+        // errors.
+        assert!(matches!(
+            result,
+            Err(bincode::error::DecodeError::Serde(bincode::serde::DecodeError::CustomError))
+        ));
+    }
+}
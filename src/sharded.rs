@@ -0,0 +1,470 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! An arena that many threads can allocate from at once, enabled by the
+//! `std` feature.
+
+use core::fmt;
+use core::mem;
+use core::ptr;
+use core::slice;
+
+use alloc::AllocError;
+use atomic::{AtomicUsize, Ordering};
+use ArenaBox;
+
+/// Shard boundaries are kept this far apart, so that the cursors of two
+/// shards never share a cache line with each other's memory.
+const SHARD_ALIGNMENT: usize = 64;
+
+// Only the shard a thread starts at depends on this counter, so it stays a
+// plain atomic under loom as well.
+static NEXT_THREAD: ::core::sync::atomic::AtomicUsize = ::core::sync::atomic::AtomicUsize::new(0);
+
+thread_local! {
+    /// A small number that is distinct for each thread, used to pick its
+    /// home shard.
+    static THREAD_INDEX: usize = NEXT_THREAD.fetch_add(1, ::core::sync::atomic::Ordering::Relaxed);
+}
+
+#[repr(align(64))]
+struct Shard {
+    cursor: AtomicUsize,
+    end: usize,
+}
+
+/// An arena that can be shared between threads, with one bump cursor per
+/// shard.
+///
+/// The buffer is split into `shards` equal regions. Each thread allocates
+/// from its own home shard with an atomic bump of that shard's cursor, so
+/// threads on different shards never contend with each other. When the home
+/// shard is full, allocations fall back to the shard with the most memory
+/// left.
+///
+/// # Examples
+///
+/// ```
+/// # use memory_arena::*;
+/// let a = ShardedArena::new(1 << 16, 64, 4).unwrap();
+/// std::thread::scope(|s| {
+///     for i in 0..4u64 {
+///         let a = &a;
+///         s.spawn(move || {
+///             let x = a.new_box(i).unwrap();
+///             assert_eq!(*x, i);
+///         });
+///     }
+/// });
+/// assert_eq!(a.used(), 4 * 8);
+/// ```
+pub struct ShardedArena {
+    mem: *mut u8,
+    size: usize,
+    alignment: usize,
+    shards: *mut Shard,
+    shard_count: usize,
+    shard_size: usize,
+}
+
+unsafe impl Send for ShardedArena {}
+unsafe impl Sync for ShardedArena {}
+
+impl ShardedArena {
+    /// Creates an arena of `size` bytes, aligned to `alignment` bytes,
+    /// split into `shards` shards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub fn new(size: usize, alignment: usize, shards: usize) -> Result<Self, AllocError> {
+        assert!(shards > 0, "a ShardedArena needs at least one shard");
+        let shards_size = shards
+            .checked_mul(mem::size_of::<Shard>())
+            .ok_or(AllocError::OutOfMemory)?;
+        let mem = unsafe { ::alloc::aligned_alloc(size, alignment)? };
+        let shards_p = unsafe { ::alloc::aligned_alloc(shards_size, mem::align_of::<Shard>()) };
+        let shards_p = match shards_p {
+            Ok(p) => p as *mut Shard,
+            Err(e) => {
+                unsafe { ::alloc::free(mem, size, alignment) };
+                return Err(e);
+            }
+        };
+        let per_shard = (size / shards) & !(SHARD_ALIGNMENT - 1);
+        for i in 0..shards {
+            let end = if i + 1 == shards { size } else { (i + 1) * per_shard };
+            let shard = Shard {
+                cursor: AtomicUsize::new(i * per_shard),
+                end,
+            };
+            unsafe { ptr::write(shards_p.add(i), shard) };
+        }
+        Ok(ShardedArena {
+            mem,
+            size,
+            alignment,
+            shards: shards_p,
+            shard_count: shards,
+            shard_size: per_shard,
+        })
+    }
+
+    fn shards(&self) -> &[Shard] {
+        unsafe { slice::from_raw_parts(self.shards, self.shard_count) }
+    }
+
+    /// Bumps the cursor of `shard`, or returns `None` if it is full.
+    fn alloc_in(&self, shard: &Shard, size: usize, alignment: usize) -> Option<*mut u8> {
+        let base = self.mem as usize;
+        let mut cursor = shard.cursor.load(Ordering::Relaxed);
+        loop {
+            let aligned = (base + cursor).checked_add(alignment - 1)? & !(alignment - 1);
+            let new = (aligned - base).checked_add(size)?;
+            if new > shard.end {
+                return None;
+            }
+            // Every bump of a cursor is an atomic read-modify-write, which
+            // is all it takes for the handed out ranges to be disjoint.
+            match shard.cursor.compare_exchange_weak(
+                cursor,
+                new,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Some(aligned as *mut u8),
+                Err(actual) => cursor = actual,
+            }
+        }
+    }
+
+    fn aligned_alloc(&self, size: usize, alignment: usize) -> Option<*mut u8> {
+        assert!(alignment.is_power_of_two());
+        let shards = self.shards();
+        let home = THREAD_INDEX.with(|&i| i) % shards.len();
+        if let Some(p) = self.alloc_in(&shards[home], size, alignment) {
+            return Some(p);
+        }
+        // Steal from the emptiest shard. Other threads may fill it up in
+        // the meantime, so keep trying for as long as any shard has room.
+        loop {
+            let (shard, left) = shards
+                .iter()
+                .map(|s| (s, s.end - s.cursor.load(Ordering::Relaxed)))
+                .max_by_key(|&(_, left)| left)?;
+            if left < size {
+                return None;
+            }
+            if let Some(p) = self.alloc_in(shard, size, alignment) {
+                return Some(p);
+            }
+            if shard.end - shard.cursor.load(Ordering::Relaxed) == left {
+                // Nothing changed, so the shard is too fragmented by alignment
+                // for this request.
+                return self.alloc_anywhere(size, alignment);
+            }
+        }
+    }
+
+    fn alloc_anywhere(&self, size: usize, alignment: usize) -> Option<*mut u8> {
+        self.shards().iter().find_map(|s| self.alloc_in(s, size, alignment))
+    }
+
+    /// Allocates memory from the arena, places x into it,
+    /// and returns the resulting `ArenaBox`, wrapped in `Result::Ok`.
+    ///
+    /// If there is not enough available memory in any shard,
+    /// then the original value is returned, wrapped in `Result::Err`.
+    pub fn new_box<'a, T>(&'a self, x: T) -> Result<ArenaBox<'a, T>, T> {
+        let size = mem::size_of::<T>();
+        let p = if size == 0 {
+            ptr::dangling_mut()
+        } else {
+            match self.aligned_alloc(size, mem::align_of::<T>()) {
+                Some(p) => p as *mut T,
+                None => return Err(x),
+            }
+        };
+        unsafe {
+            ptr::write(p, x);
+            Ok(ArenaBox::from_raw(p))
+        }
+    }
+
+    /// Copies `src` into the arena and returns the copy.
+    pub fn alloc_slice_copy<'a, T: Copy>(
+        &'a self,
+        src: &[T],
+    ) -> Result<ArenaBox<'a, [T]>, AllocError> {
+        let size = mem::size_of_val(src);
+        let p = if size == 0 {
+            ptr::dangling_mut()
+        } else {
+            self.aligned_alloc(size, mem::align_of::<T>()).ok_or(AllocError::Exhausted)? as *mut T
+        };
+        unsafe {
+            ptr::copy_nonoverlapping(src.as_ptr(), p, src.len());
+            Ok(ArenaBox::from_raw(ptr::slice_from_raw_parts_mut(p, src.len())))
+        }
+    }
+
+    /// Returns the number of shards.
+    pub fn shard_count(&self) -> usize {
+        self.shard_count
+    }
+
+    /// Returns the number of bytes handed out from shard `i`, including
+    /// alignment padding.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is not less than `shard_count()`.
+    pub fn shard_used(&self, i: usize) -> usize {
+        self.shards()[i].cursor.load(Ordering::Relaxed) - i * self.shard_size
+    }
+
+    /// Returns the number of bytes handed out from all shards together,
+    /// including alignment padding.
+    pub fn used(&self) -> usize {
+        (0..self.shard_count).map(|i| self.shard_used(i)).sum()
+    }
+
+    /// Returns the total size of the arena.
+    pub fn capacity(&self) -> usize {
+        self.size
+    }
+
+    /// Verifies the internal consistency of the arena, panicking with a
+    /// description of the first violated invariant, like
+    /// [`Arena::check_invariants`].
+    ///
+    /// This is meant for tests and fuzzing, and is only available with the
+    /// `check-invariants` feature.
+    ///
+    /// [`Arena::check_invariants`]: struct.Arena.html#method.check_invariants
+    #[cfg(any(test, feature = "check-invariants"))]
+    pub fn check_invariants(&self) {
+        assert!(!self.mem.is_null(), "arena invariant violated: base pointer is null");
+        // The shards are back to back, and each cursor is within its shard.
+        let mut start = 0;
+        for (i, shard) in self.shards().iter().enumerate() {
+            let cursor = shard.cursor.load(Ordering::Relaxed);
+            assert!(
+                start <= cursor && cursor <= shard.end,
+                "arena invariant violated: cursor ({}) of shard {} is outside {}..{}",
+                cursor,
+                i,
+                start,
+                shard.end
+            );
+            start = shard.end;
+        }
+        assert!(
+            start == self.size,
+            "arena invariant violated: shards end at {}, not at size ({})",
+            start,
+            self.size
+        );
+    }
+}
+
+impl Drop for ShardedArena {
+    fn drop(&mut self) {
+        unsafe {
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.shards, self.shard_count));
+            let shards_size = self.shard_count * mem::size_of::<Shard>();
+            ::alloc::free(self.shards as *mut u8, shards_size, mem::align_of::<Shard>());
+            ::alloc::free(self.mem, self.size, self.alignment);
+        }
+    }
+}
+
+impl fmt::Debug for ShardedArena {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ShardedArena")
+            .field("capacity", &self.size)
+            .field("used", &self.used())
+            .field("shards", &self.shard_count)
+            .finish()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use std::thread;
+    use std::vec::Vec;
+    use test_util::{checked, live_allocations};
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn sharded_send_sync() {
+        assert_send_sync::<ShardedArena>();
+        #[cfg(not(feature = "live-count"))]
+        assert_send_sync::<ArenaBox<u64>>();
+    }
+
+    #[test]
+    fn sharded_many_threads() {
+        const THREADS: usize = 32;
+        const PER_THREAD: usize = 500;
+        let a = checked(ShardedArena::new(THREADS * PER_THREAD * 16, 64, 8).unwrap());
+        let mut ranges: Vec<(usize, usize)> = thread::scope(|s| {
+            let handles: Vec<_> = (0..THREADS)
+                .map(|t| {
+                    let a = &a;
+                    s.spawn(move || {
+                        let boxes: Vec<_> = (0..PER_THREAD)
+                            .map(|i| {
+                                if i % 3 == 0 {
+                                    let b = a.alloc_slice_copy(&[t as u8; 5]).unwrap();
+                                    let p = b.as_ptr() as usize;
+                                    assert_eq!(*b, [t as u8; 5]);
+                                    (p, p + 5)
+                                } else {
+                                    let b = a.new_box((t * PER_THREAD + i) as u64).unwrap();
+                                    let p = &*b as *const u64 as usize;
+                                    assert!(p.is_multiple_of(8));
+                                    assert_eq!(*b, (t * PER_THREAD + i) as u64);
+                                    (p, p + 8)
+                                }
+                            })
+                            .collect();
+                        boxes
+                    })
+                })
+                .collect();
+            handles.into_iter().flat_map(|h| h.join().unwrap()).collect()
+        });
+        ranges.sort();
+        for w in ranges.windows(2) {
+            assert!(w[0].1 <= w[1].0, "{:?} overlaps {:?}", w[0], w[1]);
+        }
+        let base = a.mem as usize;
+        assert!(ranges[0].0 >= base && ranges[ranges.len() - 1].1 <= base + a.capacity());
+        assert!(a.used() >= THREADS * PER_THREAD * 7);
+    }
+
+    #[test]
+    fn sharded_steal_and_exhaust() {
+        let before = live_allocations();
+        {
+            let a = checked(ShardedArena::new(4 * 64, 64, 4).unwrap());
+            // A single thread fills its home shard and then the others.
+            for i in 0..32u64 {
+                assert_eq!(*a.new_box(i).unwrap(), i);
+            }
+            assert_eq!(a.new_box(32u64), Err(32));
+            assert_eq!(a.alloc_slice_copy(&[1u8]).unwrap_err(), AllocError::Exhausted);
+            for i in 0..a.shard_count() {
+                assert_eq!(a.shard_used(i), 64);
+            }
+            assert_eq!(a.used(), a.capacity());
+            assert!(a.new_box(()).is_ok());
+        }
+        assert_eq!(live_allocations(), before);
+    }
+
+    #[test]
+    fn sharded_uneven_split() {
+        let a = checked(ShardedArena::new(1000, 8, 3).unwrap());
+        let mut n = 0;
+        while a.new_box(0u64).is_ok() {
+            n += 1;
+        }
+        // 320 bytes in each of the first two shards, 360 in the last.
+        assert_eq!(n, 125);
+        assert_eq!(a.shard_used(2), 360);
+    }
+
+    #[test]
+    fn sharded_size_overflow() {
+        let before = live_allocations();
+        let shards = (1 << (usize::BITS - 4)) + 1;
+        assert_eq!(ShardedArena::new(64, 64, shards).err(), Some(AllocError::OutOfMemory));
+        assert_eq!(live_allocations(), before);
+        let a = checked(ShardedArena::new(256, 64, 2).unwrap());
+        let _ = a.new_box(1u8).unwrap();
+        for &size in &[usize::MAX, usize::MAX - 1, usize::MAX - 64] {
+            for shard in a.shards() {
+                assert_eq!(a.alloc_in(shard, size, 64), None);
+            }
+            assert_eq!(a.aligned_alloc(size, 8), None);
+        }
+        assert_eq!(a.used(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "arena invariant violated: cursor (200) of shard 0 is outside 0..128")]
+    fn sharded_check_invariants() {
+        let a = ShardedArena::new(256, 64, 2).unwrap();
+        a.shards()[0].cursor.store(200, Ordering::Relaxed);
+        a.check_invariants();
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use loom::sync::Arc;
+    use loom::thread;
+    use std::vec::Vec;
+
+    /// Has each of `threads` threads make `n` allocations of `SIZE` bytes
+    /// from an arena of two 64-byte shards, and checks that the ranges are
+    /// disjoint and that each lies inside one shard. Returns the number of
+    /// allocations that succeeded.
+    fn race<const SIZE: usize>(threads: usize, n: usize) -> usize {
+        let a = Arc::new(checked(ShardedArena::new(128, 64, 2).unwrap()));
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                let a = a.clone();
+                thread::spawn(move || {
+                    (0..n)
+                        .filter_map(|_| a.new_box([0u8; SIZE]).ok())
+                        .map(|b| (b.as_ptr() as usize, b.as_ptr() as usize + SIZE))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let mut ranges: Vec<_> = handles.into_iter().flat_map(|h| h.join().unwrap()).collect();
+        ranges.sort();
+        for w in ranges.windows(2) {
+            assert!(w[0].1 <= w[1].0, "{:?} overlaps {:?}", w[0], w[1]);
+        }
+        let base = a.mem as usize;
+        for &(start, end) in &ranges {
+            let shard = (start - base) / 64;
+            assert!(shard < 2 && end <= base + (shard + 1) * 64);
+        }
+        for i in 0..a.shard_count() {
+            assert!(a.shard_used(i) <= 64);
+        }
+        ranges.len()
+    }
+
+    #[test]
+    fn loom_sharded_two_threads() {
+        loom::model(|| {
+            // Only four of the six fit, two in each shard, so both threads
+            // move on from the home shard.
+            assert_eq!(race::<32>(2, 3), 4);
+        });
+    }
+
+    #[test]
+    fn loom_sharded_three_threads() {
+        loom::model(|| {
+            // One allocation fits in each shard, and the third fails.
+            assert_eq!(race::<48>(3, 1), 2);
+        });
+    }
+}
@@ -0,0 +1,220 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Arena slices built one item at a time, in place.
+
+use core::fmt;
+use core::ptr;
+use core::slice;
+
+use alloc::AllocError;
+use arena::GrowingSlice;
+use {Arena, ArenaBox};
+
+/// A slice being built at the end of an `Arena`'s memory, one item at a
+/// time, that never moves.
+///
+/// Unlike an [`ArenaVec`], the items are never copied to a bigger
+/// allocation: the slice only grows by extending the Arena's most recent
+/// allocation. Once something else is allocated from the Arena, pushing
+/// past the room already made fails with `AllocError::Interleaved`, and
+/// the builder can only be finished with the items it has. A slice that
+/// ends up in one of the Arena's extra regions cannot grow beyond it
+/// either.
+///
+/// Dropping the builder drops the items, and gives their memory back if
+/// nothing was allocated after them.
+///
+/// [`ArenaVec`]: struct.ArenaVec.html
+///
+/// # Examples
+///
+/// ```
+/// # use memory_arena::*;
+/// # #[cfg(feature = "alloc")] {
+/// let a = Arena::new(1024, 1024).unwrap();
+/// let mut b = a.slice_builder();
+/// for word in "to be or not to be".split(' ') {
+///     b.try_push(word.len()).unwrap();
+/// }
+/// assert_eq!(&*b.finish(), [2, 2, 2, 3, 2, 2]);
+/// # }
+/// ```
+pub struct SliceBuilder<'a, T> {
+    slice: GrowingSlice<'a, T>,
+}
+
+impl Arena {
+    /// Returns a builder for a slice of `T` at the end of the Arena's
+    /// memory, which does not take any memory until the first item is
+    /// pushed.
+    pub fn slice_builder<T>(&self) -> SliceBuilder<'_, T> {
+        SliceBuilder {
+            slice: GrowingSlice::new(self),
+        }
+    }
+}
+
+impl<'a, T> SliceBuilder<'a, T> {
+    /// Returns the number of items pushed so far.
+    pub fn len(&self) -> usize {
+        self.slice.len
+    }
+
+    /// Returns `true` if no items have been pushed.
+    pub fn is_empty(&self) -> bool {
+        self.slice.len == 0
+    }
+
+    /// Returns the items pushed so far.
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.slice.ptr, self.slice.len) }
+    }
+
+    /// Returns the items pushed so far, mutably.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.slice.ptr, self.slice.len) }
+    }
+
+    /// Appends `x` to the slice, growing it in place if needed.
+    ///
+    /// If there is no room for `x`, it is returned along with the reason:
+    /// `AllocError::Interleaved` if another allocation now follows the
+    /// slice, or the error the Arena ran into otherwise.
+    pub fn try_push(&mut self, x: T) -> Result<(), (T, AllocError)> {
+        let s = &mut self.slice;
+        if s.len == s.cap {
+            if let Err(e) = s.grow_in_place() {
+                return Err((x, e));
+            }
+        }
+        unsafe { ptr::write(s.ptr.add(s.len), x) };
+        s.len += 1;
+        Ok(())
+    }
+
+    /// Returns the slice as an arena box, and gives any room left over at
+    /// the end back to the Arena if possible.
+    pub fn finish(self) -> ArenaBox<'a, [T]> {
+        self.slice.finish()
+    }
+}
+
+impl<'a, T: fmt::Debug> fmt::Debug for SliceBuilder<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.as_slice()).finish()
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use core::cell::Cell;
+    use std::rc::Rc;
+    use std::string::{String, ToString};
+    use test_util::checked_arena;
+
+    #[derive(Debug)]
+    struct Counted(Rc<Cell<usize>>);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn slice_builder_grows_in_place() {
+        let a = checked_arena(4096, 4096);
+        let _ = a.new_box(1u8).unwrap();
+        let mut b = a.slice_builder::<u64>();
+        assert!(b.is_empty());
+        b.try_push(0).unwrap();
+        let start = b.as_slice().as_ptr();
+        assert_eq!(start as usize % 8, 0);
+        for i in 1..300 {
+            b.try_push(i).unwrap();
+            assert_eq!(b.as_slice().as_ptr(), start);
+        }
+        b.as_mut_slice()[0] = 7;
+        let s = b.finish();
+        assert_eq!(s.len(), 300);
+        assert_eq!(s[0], 7);
+        assert!(s[1..].iter().enumerate().all(|(i, &x)| x == i as u64 + 1));
+        // The u64s start after the padding that aligns them, and nothing is
+        // left over after them.
+        assert_eq!(a.used(), 8 + 300 * 8);
+        a.check_invariants();
+    }
+
+    #[test]
+    fn slice_builder_drop_types() {
+        let drops = Rc::new(Cell::new(0));
+        let a = checked_arena(4096, 4096);
+        let mut b = a.slice_builder();
+        for i in 0..20 {
+            b.try_push((i.to_string(), Counted(drops.clone()))).unwrap();
+        }
+        let s = b.finish();
+        assert_eq!(s[13].0, "13");
+        assert_eq!(drops.get(), 0);
+        drop(s);
+        assert_eq!(drops.get(), 20);
+
+        // An abandoned builder drops its items and gives back its memory.
+        let used = a.used();
+        let mut b = a.slice_builder();
+        for _ in 0..10 {
+            b.try_push(Counted(drops.clone())).unwrap();
+        }
+        drop(b);
+        assert_eq!(drops.get(), 30);
+        assert_eq!(a.used(), used);
+        a.check_invariants();
+    }
+
+    #[test]
+    fn slice_builder_interleaved() {
+        let a = checked_arena(4096, 4096);
+        let mut b = a.slice_builder::<String>();
+        b.try_push("a".to_string()).unwrap();
+        let cap = b.slice.cap;
+        for _ in 1..cap {
+            b.try_push("b".to_string()).unwrap();
+        }
+        let other = a.new_box(5u32).unwrap();
+        let (x, e) = b.try_push("c".to_string()).unwrap_err();
+        assert_eq!((&*x, e), ("c", AllocError::Interleaved));
+        assert_eq!(b.len(), cap);
+        let s = b.finish();
+        assert_eq!(s.len(), cap);
+        assert_eq!(*other, 5);
+
+        let mut b = a.slice_builder::<[u8; 1000]>();
+        let (_, e) = loop {
+            if let Err(e) = b.try_push([0; 1000]) {
+                break e;
+            }
+        };
+        assert_eq!(e, AllocError::Exhausted);
+        assert!(b.len() >= 3);
+        a.check_invariants();
+    }
+
+    #[test]
+    fn slice_builder_zero_sized() {
+        let a = checked_arena(16, 16);
+        let mut b = a.slice_builder::<()>();
+        for _ in 0..1000 {
+            b.try_push(()).unwrap();
+        }
+        assert_eq!(b.finish().len(), 1000);
+        assert_eq!(a.used(), 0);
+    }
+}
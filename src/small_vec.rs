@@ -0,0 +1,367 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A vector that keeps its first few elements inline and spills to an
+//! arena after that.
+
+use core::fmt;
+use core::mem::{self, MaybeUninit};
+use core::ops::{Deref, DerefMut};
+use core::ptr::{self, NonNull};
+use core::slice;
+
+use arena_box::LiveGuard;
+use {Arena, ArenaBox};
+
+/// The smallest capacity of a spilled buffer.
+const MIN_SPILL_CAPACITY: usize = 4;
+
+struct Spill<'a, T> {
+    ptr: NonNull<T>,
+    cap: usize,
+    _live: LiveGuard<'a>,
+}
+
+/// A vector that stores up to `N` elements inline, and moves them to a
+/// buffer in an `Arena` once it grows beyond that.
+///
+/// Lists that usually stay short, such as the children of a syntax tree
+/// node, then take no arena memory at all. Once spilled, the vector keeps
+/// using arena buffers, doubling the capacity whenever it is full; the
+/// memory of an outgrown buffer stays in the Arena until it is reset.
+///
+/// # Examples
+///
+/// ```
+/// # use memory_arena::*;
+/// # #[cfg(feature = "alloc")] {
+/// let a = Arena::new(1024, 1024).unwrap();
+/// let mut v: SmallArenaVec<u32, 2> = SmallArenaVec::new(&a);
+/// v.push(1).unwrap();
+/// v.push(2).unwrap();
+/// assert!(!v.spilled());
+/// v.push(3).unwrap();
+/// assert!(v.spilled());
+/// assert_eq!(v.as_slice(), &[1, 2, 3]);
+/// let b = v.into_boxed_slice(&a).unwrap();
+/// assert_eq!(&*b, &[1, 2, 3]);
+/// # }
+/// ```
+pub struct SmallArenaVec<'a, T, const N: usize> {
+    arena: &'a Arena,
+    len: usize,
+    inline: [MaybeUninit<T>; N],
+    spill: Option<Spill<'a, T>>,
+}
+
+impl<'a, T, const N: usize> SmallArenaVec<'a, T, N> {
+    /// Creates an empty vector that spills into `arena`.
+    pub fn new(arena: &'a Arena) -> Self {
+        SmallArenaVec {
+            arena,
+            len: 0,
+            inline: [const { MaybeUninit::uninit() }; N],
+            spill: None,
+        }
+    }
+
+    /// Returns the number of elements.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if there are no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of elements the vector can hold without
+    /// allocating.
+    pub fn capacity(&self) -> usize {
+        match self.spill {
+            Some(ref s) => s.cap,
+            None if mem::size_of::<T>() == 0 => usize::MAX,
+            None => N,
+        }
+    }
+
+    /// Returns `true` if the elements have moved into the Arena.
+    pub fn spilled(&self) -> bool {
+        self.spill.is_some()
+    }
+
+    fn as_ptr(&self) -> *const T {
+        match self.spill {
+            Some(ref s) => s.ptr.as_ptr(),
+            None => self.inline.as_ptr() as *const T,
+        }
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut T {
+        match self.spill {
+            Some(ref s) => s.ptr.as_ptr(),
+            None => self.inline.as_mut_ptr() as *mut T,
+        }
+    }
+
+    /// Returns the elements as a slice.
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.as_ptr(), self.len) }
+    }
+
+    /// Returns the elements as a mutable slice.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.as_mut_ptr(), self.len) }
+    }
+
+    /// Moves the elements into a new arena buffer with twice the capacity,
+    /// or at least `MIN_SPILL_CAPACITY`.
+    fn grow(&mut self) -> bool {
+        // Zero-sized elements never run out of room, until `len` does.
+        if mem::size_of::<T>() == 0 {
+            return false;
+        }
+        let cap = self.capacity().saturating_mul(2).max(MIN_SPILL_CAPACITY);
+        let size = match cap.checked_mul(mem::size_of::<T>()) {
+            Some(size) => size,
+            None => return false,
+        };
+        let p = match self.arena.aligned_alloc(size, mem::align_of::<T>()) {
+            Some(p) => p as *mut T,
+            None => return false,
+        };
+        unsafe {
+            ptr::copy_nonoverlapping(self.as_ptr(), p, self.len);
+            self.spill = Some(Spill {
+                ptr: NonNull::new_unchecked(p),
+                cap,
+                _live: LiveGuard::new(self.arena),
+            });
+        }
+        true
+    }
+
+    /// Appends `x` to the end of the vector.
+    ///
+    /// If the vector is full and there is not enough available memory in
+    /// the Arena for a bigger buffer, then `x` is returned, wrapped in
+    /// `Result::Err`.
+    pub fn push(&mut self, x: T) -> Result<(), T> {
+        if self.len == self.capacity() && !self.grow() {
+            return Err(x);
+        }
+        unsafe { ptr::write(self.as_mut_ptr().add(self.len), x) };
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Removes the last element and returns it, or `None` if the vector is
+    /// empty.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        Some(unsafe { ptr::read(self.as_ptr().add(self.len)) })
+    }
+
+    /// Returns an iterator over the elements.
+    pub fn iter(&self) -> slice::Iter<'_, T> {
+        self.as_slice().iter()
+    }
+
+    /// Returns an iterator that allows modifying each element.
+    pub fn iter_mut(&mut self) -> slice::IterMut<'_, T> {
+        self.as_mut_slice().iter_mut()
+    }
+
+    /// Moves the elements into an arena box in `arena`.
+    ///
+    /// If the vector has spilled into `arena` itself, its buffer is reused
+    /// as is; otherwise the elements are moved into a new allocation of
+    /// exactly the right size. If there is not enough available memory for
+    /// that, then the vector is returned, wrapped in `Result::Err`.
+    pub fn into_boxed_slice<'b>(mut self, arena: &'b Arena) -> Result<ArenaBox<'b, [T]>, Self> {
+        let p = match self.spill {
+            Some(ref s) if ptr::eq(self.arena, arena) => s.ptr.as_ptr(),
+            _ if self.len == 0 || mem::size_of::<T>() == 0 => ptr::dangling_mut(),
+            _ => {
+                let size = self.len * mem::size_of::<T>();
+                let p = match arena.aligned_alloc(size, mem::align_of::<T>()) {
+                    Some(p) => p as *mut T,
+                    None => return Err(self),
+                };
+                unsafe { ptr::copy_nonoverlapping(self.as_ptr(), p, self.len) };
+                p
+            }
+        };
+        let len = self.len;
+        // The elements belong to the box now.
+        self.len = 0;
+        Ok(unsafe { ArenaBox::from_raw_in(ptr::slice_from_raw_parts_mut(p, len), arena) })
+    }
+}
+
+impl<'a, T, const N: usize> Drop for SmallArenaVec<'a, T, N> {
+    fn drop(&mut self) {
+        unsafe { ptr::drop_in_place(self.as_mut_slice()) }
+    }
+}
+
+impl<'a, T, const N: usize> Deref for SmallArenaVec<'a, T, N> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<'a, T, const N: usize> DerefMut for SmallArenaVec<'a, T, N> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+impl<'s, 'a, T, const N: usize> IntoIterator for &'s SmallArenaVec<'a, T, N> {
+    type Item = &'s T;
+    type IntoIter = slice::Iter<'s, T>;
+
+    fn into_iter(self) -> slice::Iter<'s, T> {
+        self.iter()
+    }
+}
+
+impl<'s, 'a, T, const N: usize> IntoIterator for &'s mut SmallArenaVec<'a, T, N> {
+    type Item = &'s mut T;
+    type IntoIter = slice::IterMut<'s, T>;
+
+    fn into_iter(self) -> slice::IterMut<'s, T> {
+        self.iter_mut()
+    }
+}
+
+impl<'a, T: fmt::Debug, const N: usize> fmt::Debug for SmallArenaVec<'a, T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_slice(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use core::cell::Cell;
+    use std::vec::Vec;
+    use test_util::{checked_arena, DropCounter};
+
+    #[test]
+    fn small_vec_inline() {
+        let a = checked_arena(1024, 1024);
+        let drops = Cell::new(0);
+        {
+            let mut v: SmallArenaVec<DropCounter, 4> = SmallArenaVec::new(&a);
+            for _ in 0..4 {
+                v.push(DropCounter(&drops)).unwrap();
+            }
+            assert!(!v.spilled());
+            drop(v.pop());
+            assert_eq!(drops.get(), 1);
+        }
+        assert_eq!(drops.get(), 4);
+        assert_eq!(a.used.get(), 0);
+    }
+
+    #[test]
+    fn small_vec_spill() {
+        let a = checked_arena(64 * 1024, 4096);
+        let drops = Cell::new(0);
+        let mut model = Vec::new();
+        {
+            let mut v: SmallArenaVec<(u32, DropCounter), 3> = SmallArenaVec::new(&a);
+            for i in 0..100 {
+                v.push((i, DropCounter(&drops))).unwrap();
+                model.push(i);
+                if i % 7 == 6 {
+                    drop(v.pop());
+                    model.pop();
+                }
+                let values: Vec<u32> = v.iter().map(|x| x.0).collect();
+                assert_eq!(values, model);
+            }
+            assert!(v.spilled());
+            assert_eq!(drops.get(), 100 / 7);
+        }
+        assert_eq!(drops.get(), 100);
+    }
+
+    #[test]
+    fn small_vec_boundary() {
+        let a = checked_arena(64 * 1024, 4096);
+        for round in 0..8 {
+            let mut v: SmallArenaVec<u64, 2> = SmallArenaVec::new(&a);
+            for i in 0..round {
+                v.push(i).unwrap();
+            }
+            assert_eq!(v.spilled(), round > 2);
+            for x in &mut v {
+                *x *= 10;
+            }
+            let expected: Vec<u64> = (0..round).map(|i| i * 10).collect();
+            assert_eq!(v.as_slice(), &expected[..]);
+        }
+    }
+
+    #[test]
+    fn small_vec_into_boxed_slice() {
+        let a = checked_arena(1024, 1024);
+        let other = checked_arena(1024, 1024);
+        let drops = Cell::new(0);
+        let mut v: SmallArenaVec<DropCounter, 2> = SmallArenaVec::new(&a);
+        v.push(DropCounter(&drops)).unwrap();
+        let b = v.into_boxed_slice(&a).unwrap();
+        assert_eq!(b.len(), 1);
+        assert_eq!(drops.get(), 0);
+        drop(b);
+        assert_eq!(drops.get(), 1);
+
+        let mut v: SmallArenaVec<DropCounter, 1> = SmallArenaVec::new(&a);
+        for _ in 0..5 {
+            v.push(DropCounter(&drops)).unwrap();
+        }
+        let used = a.used.get();
+        let b = v.into_boxed_slice(&a).unwrap();
+        assert_eq!(a.used.get(), used);
+        let mut v: SmallArenaVec<DropCounter, 1> = SmallArenaVec::new(&a);
+        v.push(DropCounter(&drops)).unwrap();
+        v.push(DropCounter(&drops)).unwrap();
+        let c = v.into_boxed_slice(&other).unwrap();
+        assert_eq!((b.len(), c.len()), (5, 2));
+        drop((b, c));
+        assert_eq!(drops.get(), 8);
+    }
+
+    #[test]
+    fn small_vec_exhausted() {
+        let a = checked_arena(16, 16);
+        let mut v: SmallArenaVec<u64, 1> = SmallArenaVec::new(&a);
+        v.push(1).unwrap();
+        assert_eq!(v.push(2), Err(2));
+        assert_eq!(v.as_slice(), &[1]);
+    }
+
+    #[test]
+    fn small_vec_zero_sized_full() {
+        let a = checked_arena(16, 16);
+        let mut v: SmallArenaVec<(), 0> = SmallArenaVec::new(&a);
+        v.len = usize::MAX;
+        assert_eq!(v.push(()), Err(()));
+        assert!(!v.spilled());
+        assert_eq!(v.as_slice().len(), usize::MAX);
+        assert_eq!(a.used.get(), 0);
+    }
+}
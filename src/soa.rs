@@ -0,0 +1,199 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Structure-of-arrays allocation with the `alloc_soa!` macro.
+
+use core::mem;
+use core::ptr;
+
+use alloc::AllocError;
+use {Arena, ArenaBox};
+
+/// Splits the structs yielded by an `ExactSizeIterator` into one arena
+/// slice per field, in a single pass.
+///
+/// `alloc_soa!(arena, iter => a, b, c)` reserves a slice for each of the
+/// fields `a`, `b` and `c` in `arena`, sized by the iterator's `len()`,
+/// then moves each field of every item into its slice. It evaluates to a
+/// `Result` holding the tuple of `ArenaBox<[_]>` slices, or the error if
+/// one of the slices could not be reserved. The item type must not
+/// implement `Drop`, since its fields are moved out one by one; fields
+/// that are not listed are dropped.
+///
+/// If the iterator panics, or yields more items than it said it would, the
+/// elements written so far are dropped. If it yields fewer, the slices
+/// are shorter.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate memory_arena;
+/// # use memory_arena::*;
+/// # fn main() {
+/// # #[cfg(feature = "alloc")] {
+/// struct Particle {
+///     x: f32,
+///     mass: f32,
+///     name: String,
+/// }
+///
+/// let a = Arena::new(4096, 64).unwrap();
+/// let particles = (0..3).map(|i| Particle {
+///     x: i as f32,
+///     mass: 1.0,
+///     name: format!("p{}", i),
+/// });
+/// let (x, names) = alloc_soa!(&a, particles => x, name).unwrap();
+/// assert_eq!(&*x, &[0.0, 1.0, 2.0]);
+/// assert_eq!(names[2], "p2");
+/// # }
+/// # }
+/// ```
+#[macro_export]
+macro_rules! alloc_soa {
+    ($arena:expr, $iter:expr => $($field:ident),+ $(,)*) => {
+        (|| {
+            let arena: &$crate::Arena = $arena;
+            let (iter, len) = $crate::soa_iter($iter);
+            $( let mut $field = $crate::SoaColumn::new(arena, len)?; )+
+            for item in iter {
+                $( $field.push(item.$field); )+
+            }
+            $crate::soa_result(($( $field.finish(), )+))
+        })()
+    };
+}
+
+/// Returns the iterator of an `alloc_soa!` allocation, along with its
+/// length.
+#[doc(hidden)]
+pub fn soa_iter<I>(iter: I) -> (I::IntoIter, usize)
+where
+    I: IntoIterator,
+    I::IntoIter: ExactSizeIterator,
+{
+    let iter = iter.into_iter();
+    let len = iter.len();
+    (iter, len)
+}
+
+/// Wraps the columns of an `alloc_soa!` allocation in `Ok`, which also
+/// tells type inference the error type of the `?`s before it.
+#[doc(hidden)]
+pub fn soa_result<T>(columns: T) -> Result<T, AllocError> {
+    Ok(columns)
+}
+
+/// One column of an `alloc_soa!` allocation, which owns the elements
+/// written to it so far.
+#[doc(hidden)]
+pub struct SoaColumn<'a, T> {
+    arena: &'a Arena,
+    ptr: *mut T,
+    len: usize,
+    cap: usize,
+}
+
+impl<'a, T> SoaColumn<'a, T> {
+    pub fn new(arena: &'a Arena, cap: usize) -> Result<Self, AllocError> {
+        let size = cap.checked_mul(mem::size_of::<T>()).ok_or(AllocError::Exhausted)?;
+        let ptr = if size == 0 {
+            ptr::dangling_mut()
+        } else {
+            arena.try_aligned_alloc(size, mem::align_of::<T>())? as *mut T
+        };
+        Ok(SoaColumn {
+            arena,
+            ptr,
+            len: 0,
+            cap,
+        })
+    }
+
+    #[inline]
+    pub fn push(&mut self, x: T) {
+        assert!(self.len < self.cap, "iterator yielded more items than its length");
+        unsafe { ptr::write(self.ptr.add(self.len), x) };
+        self.len += 1;
+    }
+
+    pub fn finish(self) -> ArenaBox<'a, [T]> {
+        let this = mem::ManuallyDrop::new(self);
+        let slice = ptr::slice_from_raw_parts_mut(this.ptr, this.len);
+        unsafe { ArenaBox::from_raw_in(slice, this.arena) }
+    }
+}
+
+impl<'a, T> Drop for SoaColumn<'a, T> {
+    fn drop(&mut self) {
+        unsafe { ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.ptr, self.len)) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use core::cell::Cell;
+    use std::panic;
+    use test_util::{checked_arena, DropCounter};
+
+    struct Row<'c> {
+        id: u32,
+        weight: f64,
+        tracker: DropCounter<'c>,
+    }
+
+    fn rows(drops: &Cell<usize>, n: u32) -> impl ExactSizeIterator<Item = Row<'_>> {
+        (0..n).map(move |id| Row {
+            id,
+            weight: f64::from(id) / 2.0,
+            tracker: DropCounter(drops),
+        })
+    }
+
+    #[test]
+    fn soa_columns() {
+        let a = checked_arena(4096, 64);
+        let drops = Cell::new(0);
+        let (ids, weights, trackers) = alloc_soa!(&a, rows(&drops, 10) => id, weight, tracker).unwrap();
+        assert_eq!(ids.len(), 10);
+        for i in 0..10 {
+            assert_eq!(ids[i], i as u32);
+            assert_eq!(weights[i], ids[i] as f64 / 2.0);
+        }
+        assert_eq!(drops.get(), 0);
+        drop(trackers);
+        assert_eq!(drops.get(), 10);
+
+        let (ids,) = alloc_soa!(&a, rows(&drops, 5) => id).unwrap();
+        assert_eq!(&*ids, &[0, 1, 2, 3, 4]);
+        assert_eq!(drops.get(), 15);
+    }
+
+    #[test]
+    fn soa_panic() {
+        let a = checked_arena(4096, 64);
+        let drops = Cell::new(0);
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            let iter = rows(&drops, 10).inspect(|r| assert!(r.id < 6, "row 6"));
+            let _ = alloc_soa!(&a, iter => id, tracker);
+        }));
+        assert!(result.is_err());
+        // Six rows were written, and the seventh was dropped by the panic.
+        assert_eq!(drops.get(), 7);
+    }
+
+    #[test]
+    fn soa_exhausted() {
+        let a = checked_arena(64, 64);
+        let drops = Cell::new(0);
+        let result = alloc_soa!(&a, rows(&drops, 10) => id, weight);
+        assert_eq!(result.err(), Some(AllocError::Exhausted));
+    }
+}
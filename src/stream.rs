@@ -0,0 +1,94 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! `futures_core::Stream` support for `ArenaBox`, enabled by the `futures`
+//! feature.
+//!
+//! An `ArenaBox<S>` is a `Stream` whenever `S` is an `Unpin` stream, just
+//! like `Box<S>`. Streams that are not `Unpin` can be pinned in place with
+//! the unsafe [`ArenaBox::into_pin`], and the resulting `Pin<ArenaBox<S>>`
+//! is a `Stream` through the blanket impl for `Pin<P>` in `futures-core`.
+//!
+//! [`ArenaBox::into_pin`]: ../arena_box/struct.ArenaBox.html#method.into_pin
+
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use futures_core::Stream;
+
+use ArenaBox;
+
+impl<'a, S: Stream + Unpin + ?Sized> Stream for ArenaBox<'a, S> {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        S::poll_next(Pin::new(&mut **self), cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (**self).size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use test_util::checked_arena;
+    use core::future::Future;
+    use core::marker::PhantomPinned;
+    use core::task::Waker;
+    use futures::stream::{self, StreamExt};
+    use std::vec::Vec;
+
+    fn block_on<F: Future>(f: F) -> F::Output {
+        let mut f = ::core::pin::pin!(f);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = f.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    struct Countdown {
+        n: u32,
+        _pinned: PhantomPinned,
+    }
+
+    impl Stream for Countdown {
+        type Item = u32;
+
+        fn poll_next(self: Pin<&mut Self>, _: &mut Context) -> Poll<Option<u32>> {
+            let this = unsafe { self.get_unchecked_mut() };
+            if this.n == 0 {
+                return Poll::Ready(None);
+            }
+            this.n -= 1;
+            Poll::Ready(Some(this.n))
+        }
+    }
+
+    #[test]
+    fn arena_box_stream() {
+        let a = checked_arena(1024, 1024);
+        let s = a.new_box(stream::iter(1..5)).unwrap();
+        assert_eq!(s.size_hint(), (4, Some(4)));
+        let v: Vec<i32> = block_on(s.collect());
+        assert_eq!(v, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn pinned_arena_box_stream() {
+        let a = checked_arena(1024, 1024);
+        let s = a.new_box(Countdown { n: 3, _pinned: PhantomPinned }).unwrap();
+        let v: Vec<u32> = block_on(unsafe { ArenaBox::into_pin(s) }.collect());
+        assert_eq!(v, [2, 1, 0]);
+    }
+}
@@ -0,0 +1,290 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A growable string whose buffer lives in an arena.
+
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use core::str;
+
+use alloc::AllocError;
+use vec::ArenaVec;
+use {Arena, ArenaBox};
+
+/// A `String`-like string whose bytes are stored in an `Arena`.
+///
+/// It grows like an [`ArenaVec`], and like it has fallible `try_` methods
+/// next to ones that panic when the Arena runs out of memory. Its contents
+/// are always valid UTF-8.
+///
+/// [`ArenaVec`]: struct.ArenaVec.html
+///
+/// # Examples
+///
+/// ```
+/// # use memory_arena::*;
+/// # #[cfg(feature = "alloc")] {
+/// use std::fmt::Write;
+///
+/// let a = Arena::new(1024, 1024).unwrap();
+/// let mut s = a.new_string();
+/// s.push_str("node");
+/// write!(s, "_{}", 7).unwrap();
+/// s.push('!');
+/// assert_eq!(&*s, "node_7!");
+/// let b = s.into_boxed_str();
+/// assert_eq!(&*b, "node_7!");
+/// # }
+/// ```
+pub struct ArenaString<'a> {
+    bytes: ArenaVec<'a, u8>,
+}
+
+impl<'a> ArenaString<'a> {
+    /// Creates an empty string that allocates from `arena`.
+    ///
+    /// No memory is allocated until something is pushed.
+    pub fn new_in(arena: &'a Arena) -> Self {
+        ArenaString {
+            bytes: ArenaVec::new_in(arena),
+        }
+    }
+
+    /// Creates an empty string with room for `capacity` bytes in `arena`.
+    ///
+    /// If there is not enough available memory in the Arena, the error is
+    /// returned.
+    pub fn with_capacity_in(capacity: usize, arena: &'a Arena) -> Result<Self, AllocError> {
+        Ok(ArenaString {
+            bytes: ArenaVec::with_capacity_in(capacity, arena)?,
+        })
+    }
+
+    /// Returns the length of the string, in bytes.
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Returns `true` if the string is empty.
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Returns the number of bytes the string can hold without allocating.
+    pub fn capacity(&self) -> usize {
+        self.bytes.capacity()
+    }
+
+    /// Returns the string as a `&str`.
+    pub fn as_str(&self) -> &str {
+        unsafe { str::from_utf8_unchecked(self.bytes.as_slice()) }
+    }
+
+    /// Returns the string as a `&mut str`.
+    pub fn as_mut_str(&mut self) -> &mut str {
+        unsafe { str::from_utf8_unchecked_mut(self.bytes.as_mut_slice()) }
+    }
+
+    /// Makes room for at least `additional` more bytes.
+    ///
+    /// If there is not enough available memory in the Arena, the error is
+    /// returned and the string is left as it was.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), AllocError> {
+        self.bytes.try_reserve(additional)
+    }
+
+    /// Appends `s` to the end of the string.
+    ///
+    /// If there is not enough available memory in the Arena to grow the
+    /// string, the error is returned and nothing is appended.
+    pub fn try_push_str(&mut self, s: &str) -> Result<(), AllocError> {
+        self.bytes.extend_from_slice(s.as_bytes())
+    }
+
+    /// Appends `c` to the end of the string.
+    ///
+    /// If there is not enough available memory in the Arena to grow the
+    /// string, the error is returned and nothing is appended.
+    pub fn try_push(&mut self, c: char) -> Result<(), AllocError> {
+        self.try_push_str(c.encode_utf8(&mut [0; 4]))
+    }
+
+    /// Appends `s` to the end of the string.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is not enough available memory in the Arena to grow
+    /// the string.
+    pub fn push_str(&mut self, s: &str) {
+        if self.try_push_str(s).is_err() {
+            panic!("arena does not have enough memory left to grow the string");
+        }
+    }
+
+    /// Appends `c` to the end of the string.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is not enough available memory in the Arena to grow
+    /// the string.
+    pub fn push(&mut self, c: char) {
+        self.push_str(c.encode_utf8(&mut [0; 4]))
+    }
+
+    /// Removes the last character and returns it, or `None` if the string
+    /// is empty.
+    pub fn pop(&mut self) -> Option<char> {
+        let c = self.as_str().chars().next_back()?;
+        for _ in 0..c.len_utf8() {
+            self.bytes.pop();
+        }
+        Some(c)
+    }
+
+    /// Removes every character, keeping the buffer.
+    pub fn clear(&mut self) {
+        self.bytes.clear()
+    }
+
+    /// Converts the string into an arena box of its contents.
+    ///
+    /// Like with [`ArenaVec::into_boxed_slice`], the buffer is reused as
+    /// is.
+    ///
+    /// [`ArenaVec::into_boxed_slice`]: struct.ArenaVec.html#method.into_boxed_slice
+    pub fn into_boxed_str(self) -> ArenaBox<'a, str> {
+        let (bytes, live) = ArenaBox::into_raw_parts(self.bytes.into_boxed_slice());
+        unsafe { ArenaBox::from_raw_parts(bytes as *mut str, live) }
+    }
+}
+
+impl Arena {
+    /// Creates an empty [`ArenaString`] that allocates from the Arena.
+    ///
+    /// [`ArenaString`]: struct.ArenaString.html
+    pub fn new_string(&self) -> ArenaString<'_> {
+        ArenaString::new_in(self)
+    }
+}
+
+impl<'a> Deref for ArenaString<'a> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<'a> DerefMut for ArenaString<'a> {
+    fn deref_mut(&mut self) -> &mut str {
+        self.as_mut_str()
+    }
+}
+
+impl<'a> fmt::Write for ArenaString<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.try_push_str(s).map_err(|_| fmt::Error)
+    }
+
+    fn write_char(&mut self, c: char) -> fmt::Result {
+        self.try_push(c).map_err(|_| fmt::Error)
+    }
+}
+
+impl<'a> PartialEq<str> for ArenaString<'a> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl<'a, 'b> PartialEq<&'b str> for ArenaString<'a> {
+    fn eq(&self, other: &&'b str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl<'a> fmt::Display for ArenaString<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl<'a> fmt::Debug for ArenaString<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use core::fmt::Write;
+    use std::string::String;
+    use test_util::checked_arena;
+
+    #[test]
+    fn string_write() {
+        let a = checked_arena(64 * 1024, 4096);
+        let mut s = a.new_string();
+        let mut model = String::new();
+        for i in 0..500 {
+            write!(s, "{}:{:x};", i, i * 31).unwrap();
+            write!(model, "{}:{:x};", i, i * 31).unwrap();
+        }
+        assert!(s.len() > 4096);
+        assert_eq!(s, model.as_str());
+        let b = s.into_boxed_str();
+        assert_eq!(&*b, model);
+    }
+
+    #[test]
+    fn string_reallocate() {
+        let a = checked_arena(64 * 1024, 4096);
+        let mut s = ArenaString::with_capacity_in(1, &a).unwrap();
+        let mut model = String::new();
+        let mut moves = 0;
+        for i in 0..300u32 {
+            let before = (s.as_ptr(), s.capacity());
+            let c = ['a', 'é', '語', '🦀'][i as usize % 4];
+            s.push(c);
+            model.push(c);
+            // Something else allocated after the buffer keeps it from
+            // growing in place.
+            let _ = a.new_box(i).unwrap();
+            if s.capacity() != before.1 && s.as_ptr() != before.0 {
+                moves += 1;
+            }
+        }
+        assert!(moves >= 5);
+        assert_eq!(s.as_str(), model);
+        assert_eq!(s.pop(), model.pop());
+        assert_eq!(s.pop(), model.pop());
+        s.as_mut_str().make_ascii_uppercase();
+        model.make_ascii_uppercase();
+        assert_eq!(&*s.into_boxed_str(), model);
+    }
+
+    #[test]
+    fn string_exhausted() {
+        let a = checked_arena(8, 8);
+        let mut s = ArenaString::new_in(&a);
+        s.push_str("abcd");
+        assert_eq!(s.try_push_str("efghi"), Err(AllocError::Exhausted));
+        assert_eq!(s, "abcd");
+        s.try_push('é').unwrap();
+        // A multi-byte character is appended whole or not at all.
+        assert_eq!(s.try_push('語'), Err(AllocError::Exhausted));
+        assert_eq!(write!(s, "{}", 123), Err(fmt::Error));
+        assert_eq!(s, "abcdé");
+        assert!(s.pop() == Some('é') && s.len() == 4);
+        s.clear();
+        assert!(s.is_empty());
+        assert_eq!(a.new_string().pop(), None);
+    }
+}
@@ -0,0 +1,265 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Interning strings in an arena as compact `u32` symbols.
+
+use core::iter::Enumerate;
+use core::ptr;
+use core::slice;
+use core::str;
+#[cfg(debug_assertions)]
+use core::sync::atomic::{AtomicU32, Ordering};
+use std::collections::HashMap;
+use std::vec::Vec;
+
+use alloc::AllocError;
+use {Arena, ArenaBox};
+
+/// Gives every `SymbolTable` its own tag in debug builds.
+#[cfg(debug_assertions)]
+static NEXT_TABLE: AtomicU32 = AtomicU32::new(0);
+
+/// A string interned in a [`SymbolTable`].
+///
+/// Symbols are numbered in interning order, starting at zero, and two
+/// symbols from the same table are equal exactly if their strings are. In
+/// release builds a symbol is just a `u32`; debug builds also tag it with
+/// its table, so that resolving it against another table panics.
+///
+/// [`SymbolTable`]: struct.SymbolTable.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol {
+    index: u32,
+    #[cfg(debug_assertions)]
+    table: u32,
+}
+
+impl Symbol {
+    /// Returns the number of the symbol, which is its position in
+    /// interning order.
+    pub fn as_u32(self) -> u32 {
+        self.index
+    }
+}
+
+/// A table of strings interned in an `Arena`, which hands out a
+/// [`Symbol`] for each distinct string.
+///
+/// Each distinct string is copied into the Arena once; interning it again
+/// returns the same symbol without using any more of the Arena. Resolved
+/// strings borrow the Arena rather than the table, so each copy is leaked
+/// like with [`ArenaBox::leak`], and with the `live-count` feature keeps
+/// [`Arena::try_reset`] from freeing it.
+///
+/// [`Symbol`]: struct.Symbol.html
+/// [`ArenaBox::leak`]: struct.ArenaBox.html#method.leak
+/// [`Arena::try_reset`]: struct.Arena.html#method.try_reset
+///
+/// # Examples
+///
+/// ```
+/// # use memory_arena::*;
+/// let a = Arena::new(1024, 1024).unwrap();
+/// let mut symbols = SymbolTable::new(&a);
+/// let x = symbols.intern("x").unwrap();
+/// let y = symbols.intern("y").unwrap();
+/// assert_eq!(symbols.intern("x").unwrap(), x);
+/// assert_ne!(x, y);
+/// assert_eq!(symbols.resolve(y), "y");
+/// ```
+pub struct SymbolTable<'a> {
+    arena: &'a Arena,
+    map: HashMap<&'a str, Symbol>,
+    strings: Vec<&'a str>,
+    #[cfg(debug_assertions)]
+    tag: u32,
+}
+
+impl<'a> SymbolTable<'a> {
+    /// Creates an empty table that stores its strings in `arena`.
+    pub fn new(arena: &'a Arena) -> Self {
+        SymbolTable {
+            arena,
+            map: HashMap::new(),
+            strings: Vec::new(),
+            #[cfg(debug_assertions)]
+            tag: NEXT_TABLE.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// Returns the number of distinct strings interned.
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    /// Returns `true` if no strings have been interned.
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    fn symbol(&self, index: usize) -> Symbol {
+        Symbol {
+            index: index as u32,
+            #[cfg(debug_assertions)]
+            table: self.tag,
+        }
+    }
+
+    /// Returns the symbol for `s`, copying `s` into the Arena if it has not
+    /// been interned before.
+    ///
+    /// Fails if there is not enough available memory in the Arena for the
+    /// copy.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the table would hold more than `u32::MAX` strings.
+    pub fn intern(&mut self, s: &str) -> Result<Symbol, AllocError> {
+        if let Some(&symbol) = self.map.get(s) {
+            return Ok(symbol);
+        }
+        assert!(self.strings.len() < u32::MAX as usize, "too many symbols");
+        let copy: &'a str = if s.is_empty() {
+            ""
+        } else {
+            unsafe {
+                let p = self.arena.try_aligned_alloc(s.len(), 1)?;
+                ptr::copy_nonoverlapping(s.as_ptr(), p, s.len());
+                let copy = ptr::slice_from_raw_parts_mut(p, s.len()) as *mut str;
+                ArenaBox::leak(ArenaBox::from_raw_in(copy, self.arena))
+            }
+        };
+        let symbol = self.symbol(self.strings.len());
+        self.strings.push(copy);
+        self.map.insert(copy, symbol);
+        Ok(symbol)
+    }
+
+    /// Returns the symbol for `s` if it has been interned.
+    pub fn get(&self, s: &str) -> Option<Symbol> {
+        self.map.get(s).cloned()
+    }
+
+    /// Returns the string of `symbol`.
+    ///
+    /// # Panics
+    ///
+    /// Panics in debug builds if `symbol` comes from a different table.
+    pub fn resolve(&self, symbol: Symbol) -> &'a str {
+        #[cfg(debug_assertions)]
+        assert_eq!(symbol.table, self.tag, "Symbol used with a different SymbolTable");
+        self.strings[symbol.index as usize]
+    }
+
+    /// Returns an iterator over the symbols and their strings, in interning
+    /// order.
+    pub fn iter(&self) -> Symbols<'_, 'a> {
+        Symbols {
+            table: self,
+            inner: self.strings.iter().enumerate(),
+        }
+    }
+}
+
+impl<'t, 'a> IntoIterator for &'t SymbolTable<'a> {
+    type Item = (Symbol, &'a str);
+    type IntoIter = Symbols<'t, 'a>;
+
+    fn into_iter(self) -> Symbols<'t, 'a> {
+        self.iter()
+    }
+}
+
+/// An iterator over the symbols of a [`SymbolTable`], created by
+/// [`SymbolTable::iter`].
+///
+/// [`SymbolTable`]: struct.SymbolTable.html
+/// [`SymbolTable::iter`]: struct.SymbolTable.html#method.iter
+pub struct Symbols<'t, 'a: 't> {
+    table: &'t SymbolTable<'a>,
+    inner: Enumerate<slice::Iter<'t, &'a str>>,
+}
+
+impl<'t, 'a> Iterator for Symbols<'t, 'a> {
+    type Item = (Symbol, &'a str);
+
+    fn next(&mut self) -> Option<(Symbol, &'a str)> {
+        self.inner.next().map(|(i, s)| (self.table.symbol(i), *s))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'t, 'a> ExactSizeIterator for Symbols<'t, 'a> {}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use test_util::checked_arena;
+
+    #[test]
+    fn symbol_dedup() {
+        let a = checked_arena(1024, 1024);
+        let mut t = SymbolTable::new(&a);
+        let foo = t.intern("foo").unwrap();
+        let used = a.used.get();
+        assert_eq!(t.intern("foo").unwrap(), foo);
+        assert_eq!(a.used.get(), used);
+        let bar = t.intern("bar").unwrap();
+        assert_eq!(a.used.get(), used + 3);
+        let empty = t.intern("").unwrap();
+        assert_eq!(a.used.get(), used + 3);
+        assert_ne!(foo, bar);
+        assert_eq!((foo.as_u32(), bar.as_u32(), empty.as_u32()), (0, 1, 2));
+        assert_eq!((t.resolve(foo), t.resolve(bar), t.resolve(empty)), ("foo", "bar", ""));
+        assert_eq!(t.get("bar"), Some(bar));
+        assert_eq!(t.get("baz"), None);
+        assert_eq!(t.len(), 3);
+        let all: std::vec::Vec<_> = t.iter().collect();
+        assert_eq!(all, [(foo, "foo"), (bar, "bar"), (empty, "")]);
+    }
+
+    #[test]
+    fn symbol_exhausted() {
+        let a = checked_arena(8, 8);
+        let mut t = SymbolTable::new(&a);
+        assert!(t.intern("eight ch").is_ok());
+        assert!(t.intern("more").is_err());
+        assert_eq!(t.len(), 1);
+    }
+
+    #[cfg(feature = "live-count")]
+    #[test]
+    fn symbol_try_reset() {
+        let a = checked_arena(1024, 1024);
+        let s = {
+            let mut t = SymbolTable::new(&a);
+            let foo = t.intern("foo").unwrap();
+            assert_eq!(t.intern("foo").unwrap(), foo);
+            t.resolve(foo)
+        };
+        // The string outlives the table, so the Arena must not reuse it.
+        assert_eq!(a.try_reset(), Err(::ResetError { live: 1 }));
+        let _ = a.alloc_slice_copy(b"XXX").unwrap();
+        assert_eq!(s, "foo");
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "Symbol used with a different SymbolTable")]
+    fn symbol_other_table() {
+        let a = checked_arena(1024, 1024);
+        let mut t1 = SymbolTable::new(&a);
+        let t2 = SymbolTable::new(&a);
+        let s = t1.intern("x").unwrap();
+        let _ = t2.resolve(s);
+    }
+}
@@ -0,0 +1,338 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! An arena with a single atomic cursor, which threads can share.
+
+use core::fmt;
+use core::mem;
+use core::ptr;
+
+use alloc::AllocError;
+use atomic::{AtomicUsize, Ordering};
+use ArenaBox;
+
+/// An arena that can be shared between threads, with one atomic bump
+/// cursor.
+///
+/// Every allocation is a compare-and-swap of the cursor, which only moves
+/// it if the allocation fits, so running out of memory never leaves the
+/// cursor past the end. Under heavy contention from many threads,
+/// [`ShardedArena`] spreads the allocations over several cursors instead.
+///
+/// [`ShardedArena`]: struct.ShardedArena.html
+///
+/// # Examples
+///
+/// ```
+/// # use memory_arena::*;
+/// let a = SyncArena::new(1 << 16, 64).unwrap();
+/// std::thread::scope(|s| {
+///     for i in 0..4u64 {
+///         let a = &a;
+///         s.spawn(move || {
+///             let x = a.new_box(i).unwrap();
+///             assert_eq!(*x, i);
+///         });
+///     }
+/// });
+/// assert_eq!(a.used(), 4 * 8);
+/// ```
+pub struct SyncArena {
+    mem: *mut u8,
+    size: usize,
+    alignment: usize,
+    cursor: AtomicUsize,
+}
+
+unsafe impl Send for SyncArena {}
+unsafe impl Sync for SyncArena {}
+
+impl SyncArena {
+    /// Creates an arena of `size` bytes, aligned to `alignment` bytes.
+    pub fn new(size: usize, alignment: usize) -> Result<Self, AllocError> {
+        let mem = unsafe { ::alloc::aligned_alloc(size, alignment)? };
+        Ok(SyncArena {
+            mem,
+            size,
+            alignment,
+            cursor: AtomicUsize::new(0),
+        })
+    }
+
+    /// Bumps the cursor, or returns `None` if the arena is full.
+    ///
+    /// Zero-sized allocations always succeed with a dangling pointer that
+    /// is aligned to `alignment`, and leave the cursor where it is.
+    fn aligned_alloc(&self, size: usize, alignment: usize) -> Option<*mut u8> {
+        assert!(alignment.is_power_of_two());
+        if size == 0 {
+            return Some(ptr::without_provenance_mut(alignment));
+        }
+        let base = self.mem as usize;
+        let mut cursor = self.cursor.load(Ordering::Relaxed);
+        loop {
+            let aligned = (base + cursor + alignment - 1) & !(alignment - 1);
+            let new = (aligned - base).checked_add(size)?;
+            if new > self.size {
+                return None;
+            }
+            // Every bump of the cursor is an atomic read-modify-write, which
+            // is all it takes for the handed out ranges to be disjoint.
+            match self.cursor.compare_exchange_weak(
+                cursor,
+                new,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Some(aligned as *mut u8),
+                Err(actual) => cursor = actual,
+            }
+        }
+    }
+
+    /// Allocates memory from the arena, places x into it,
+    /// and returns the resulting `ArenaBox`, wrapped in `Result::Ok`.
+    ///
+    /// If there is not enough available memory,
+    /// then the original value is returned, wrapped in `Result::Err`.
+    pub fn new_box<'a, T>(&'a self, x: T) -> Result<ArenaBox<'a, T>, T> {
+        match self.aligned_alloc(mem::size_of::<T>(), mem::align_of::<T>()) {
+            None => Err(x),
+            Some(p) => unsafe {
+                let p = p as *mut T;
+                ptr::write(p, x);
+                Ok(ArenaBox::from_raw(p))
+            },
+        }
+    }
+
+    /// Copies `src` into the arena and returns the copy.
+    pub fn alloc_slice_copy<'a, T: Copy>(
+        &'a self,
+        src: &[T],
+    ) -> Result<ArenaBox<'a, [T]>, AllocError> {
+        let size = mem::size_of_val(src);
+        let p = self.aligned_alloc(size, mem::align_of::<T>()).ok_or(AllocError::Exhausted)?;
+        unsafe {
+            let p = p as *mut T;
+            ptr::copy_nonoverlapping(src.as_ptr(), p, src.len());
+            Ok(ArenaBox::from_raw(ptr::slice_from_raw_parts_mut(p, src.len())))
+        }
+    }
+
+    /// Returns the number of bytes handed out, including alignment padding.
+    pub fn used(&self) -> usize {
+        self.cursor.load(Ordering::Relaxed)
+    }
+
+    /// Returns the total size of the arena.
+    pub fn capacity(&self) -> usize {
+        self.size
+    }
+}
+
+impl Drop for SyncArena {
+    fn drop(&mut self) {
+        unsafe { ::alloc::free(self.mem, self.size, self.alignment) };
+    }
+}
+
+impl fmt::Debug for SyncArena {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SyncArena")
+            .field("capacity", &self.size)
+            .field("used", &self.used())
+            .finish()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use std::thread;
+    use std::vec::Vec;
+    use test_util::live_allocations;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn sync_send_sync() {
+        assert_send_sync::<SyncArena>();
+    }
+
+    #[test]
+    fn sync_many_threads() {
+        const THREADS: usize = 32;
+        const PER_THREAD: usize = 500;
+        let a = SyncArena::new(THREADS * PER_THREAD * 16, 64).unwrap();
+        let mut ranges: Vec<(usize, usize)> = thread::scope(|s| {
+            let handles: Vec<_> = (0..THREADS)
+                .map(|t| {
+                    let a = &a;
+                    s.spawn(move || {
+                        (0..PER_THREAD)
+                            .map(|i| {
+                                let v = (t * PER_THREAD + i) as u64;
+                                if i % 2 == 0 {
+                                    let b = a.alloc_slice_copy(&[v, !v]).unwrap();
+                                    let p = b.as_ptr() as usize;
+                                    assert_eq!(*b, [v, !v]);
+                                    (p, p + 16)
+                                } else {
+                                    let b = a.new_box(v).unwrap();
+                                    let p = &*b as *const u64 as usize;
+                                    assert!(p.is_multiple_of(8));
+                                    assert_eq!(*b, v);
+                                    (p, p + 8)
+                                }
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles.into_iter().flat_map(|h| h.join().unwrap()).collect()
+        });
+        ranges.sort();
+        for w in ranges.windows(2) {
+            assert!(w[0].1 <= w[1].0, "{:?} overlaps {:?}", w[0], w[1]);
+        }
+        let base = a.mem as usize;
+        assert!(ranges[0].0 >= base && ranges[ranges.len() - 1].1 <= base + a.capacity());
+        // Everything is a multiple of 8 bytes, so nothing needs padding.
+        let total: usize = ranges.iter().map(|&(start, end)| end - start).sum();
+        assert_eq!(a.used(), total);
+        assert_eq!(total, THREADS * PER_THREAD * 12);
+    }
+
+    #[test]
+    fn sync_exhaust_concurrently() {
+        const THREADS: usize = 16;
+        let before = live_allocations();
+        {
+            let a = SyncArena::new(4096 + 24, 64).unwrap();
+            let counts: Vec<usize> = thread::scope(|s| {
+                let handles: Vec<_> = (0..THREADS)
+                    .map(|_| {
+                        let a = &a;
+                        s.spawn(move || {
+                            let mut n = 0;
+                            while a.new_box([0u8; 16]).is_ok() {
+                                n += 1;
+                            }
+                            n
+                        })
+                    })
+                    .collect();
+                handles.into_iter().map(|h| h.join().unwrap()).collect()
+            });
+            let boxes: usize = counts.iter().sum();
+            assert_eq!(boxes, (4096 + 24) / 16);
+            assert_eq!(a.used(), boxes * 16);
+            assert!(a.new_box([0u8; 16]).is_err());
+            assert_eq!(a.alloc_slice_copy(&[1u8; 64]).unwrap_err(), AllocError::Exhausted);
+            assert!(a.new_box(()).is_ok());
+        }
+        assert_eq!(live_allocations(), before);
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use loom::sync::Arc;
+    use loom::thread;
+    use std::vec::Vec;
+
+    /// Allocations made by one thread, which returns the address ranges it
+    /// got.
+    type Allocs = fn(&SyncArena) -> Vec<(usize, usize)>;
+
+    /// Runs each of `threads` on a thread of its own, on an arena of `size`
+    /// bytes, and checks that the ranges they got are disjoint and inside
+    /// the arena. Returns the number of ranges and the cursor at the end.
+    fn race(size: usize, threads: &[Allocs]) -> (usize, usize) {
+        let a = Arc::new(SyncArena::new(size, 64).unwrap());
+        let handles: Vec<_> = threads
+            .iter()
+            .map(|&allocs| {
+                let a = a.clone();
+                thread::spawn(move || allocs(&a))
+            })
+            .collect();
+        let mut ranges: Vec<_> = handles.into_iter().flat_map(|h| h.join().unwrap()).collect();
+        ranges.sort();
+        for w in ranges.windows(2) {
+            assert!(w[0].1 <= w[1].0, "{:?} overlaps {:?}", w[0], w[1]);
+        }
+        let base = a.mem as usize;
+        for &(start, end) in &ranges {
+            assert!(start >= base && end <= base + a.capacity());
+        }
+        assert!(a.used() <= a.capacity());
+        (ranges.len(), a.used())
+    }
+
+    fn u64s(a: &SyncArena, n: u64) -> Vec<(usize, usize)> {
+        (0..n)
+            .filter_map(|i| a.new_box(i).ok())
+            .map(|b| {
+                let p = &*b as *const u64 as usize;
+                (p, p + 8)
+            })
+            .collect()
+    }
+
+    fn one_u64(a: &SyncArena) -> Vec<(usize, usize)> {
+        u64s(a, 1)
+    }
+
+    fn two_u64s(a: &SyncArena) -> Vec<(usize, usize)> {
+        u64s(a, 2)
+    }
+
+    fn bytes_and_u64(a: &SyncArena) -> Vec<(usize, usize)> {
+        let mut ranges = Vec::new();
+        if let Ok(b) = a.alloc_slice_copy(&[1u8; 3]) {
+            ranges.push((b.as_ptr() as usize, b.as_ptr() as usize + 3));
+        }
+        if let Ok(b) = a.new_box(2u64) {
+            let p = &*b as *const u64 as usize;
+            assert!(p.is_multiple_of(8));
+            ranges.push((p, p + 8));
+        }
+        ranges
+    }
+
+    #[test]
+    fn loom_sync_two_threads() {
+        loom::model(|| {
+            // Room for exactly the four values, which all fit.
+            assert_eq!(race(32, &[two_u64s, two_u64s]), (4, 32));
+        });
+        loom::model(|| {
+            // Only three of the four fit, whoever gets there first.
+            assert_eq!(race(24, &[two_u64s, two_u64s]), (3, 24));
+        });
+    }
+
+    #[test]
+    fn loom_sync_three_threads() {
+        loom::model(|| {
+            assert_eq!(race(16, &[one_u64, one_u64, one_u64]), (2, 16));
+        });
+        loom::model(|| {
+            // Where the bytes and their padding go is up to the
+            // interleaving, but there is never room for the fourth value.
+            let (count, used) = race(24, &[one_u64, bytes_and_u64, one_u64]);
+            assert_eq!(count, 3);
+            assert!(used >= 19);
+        });
+    }
+}
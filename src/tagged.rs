@@ -0,0 +1,254 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Pointers into an `Arena` that carry a small tag in their low bits.
+//!
+//! A value of type `T` is always placed at an address that is a multiple of
+//! `align_of::<T>()`, so the low `log2(align_of::<T>())` bits of a pointer
+//! to it are always zero. `TaggedArenaPtr` stores a `BITS`-bit tag there,
+//! which is handy for keeping a discriminant next to a node pointer without
+//! making the pointer any larger.
+//!
+//! # Examples
+//!
+//! ```
+//! # use memory_arena::*;
+//! # #[cfg(feature = "alloc")] {
+//! let a = Arena::new(1024, 1024).unwrap();
+//! let mut p: TaggedArenaPtr<u64, 2> = TaggedArenaPtr::from_box(a.new_box(7).unwrap());
+//! p.set_tag(3);
+//! assert_eq!(p.tag(), 3);
+//! assert_eq!(*p.get(), 7);
+//! let b = p.into_box();
+//! assert_eq!(*b, 7);
+//! # }
+//! ```
+//!
+//! The tag has to fit into the alignment of `T`, which is checked at
+//! compile time:
+//!
+//! ```compile_fail
+//! # use memory_arena::*;
+//! let a = Arena::new(1024, 1024).unwrap();
+//! let p: TaggedArenaPtr<u16, 2> = TaggedArenaPtr::from_box(a.new_box(7).unwrap());
+//! ```
+
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem;
+use core::ptr::{self, NonNull};
+
+use arena_box::LiveGuard;
+use ArenaBox;
+
+/// A pointer to a `T` in an `Arena` with a `BITS`-bit tag packed into its
+/// low bits.
+///
+/// An owning `TaggedArenaPtr` is created from an `ArenaBox` and takes over
+/// responsibility for dropping the value, either when it is dropped itself
+/// or after ownership is handed back with `into_box`. A non-owning one,
+/// [`TaggedArenaRef`], is created from a `&'a T`.
+///
+/// `TaggedArenaPtr` is a single pointer wide, and `Option<TaggedArenaPtr>`
+/// is too. With the `live-count` feature, an owning one also carries the
+/// count of live allocations it took over from the box.
+///
+/// [`TaggedArenaRef`]: type.TaggedArenaRef.html
+pub struct TaggedArenaPtr<'a, T: 'a, const BITS: u32, const OWNED: bool = true> {
+    ptr: NonNull<T>,
+    live: LiveGuard<'a>,
+    phantom: PhantomData<ArenaBox<'a, T>>,
+}
+
+/// A non-owning `TaggedArenaPtr`, created from a `&'a T`.
+pub type TaggedArenaRef<'a, T, const BITS: u32> = TaggedArenaPtr<'a, T, BITS, false>;
+
+impl<'a, T: 'a, const BITS: u32, const OWNED: bool> TaggedArenaPtr<'a, T, BITS, OWNED> {
+    const MASK: usize = {
+        assert!(
+            BITS < usize::BITS && mem::align_of::<T>() >= 1 << BITS,
+            "the alignment of T is too small to hold BITS tag bits"
+        );
+        (1 << BITS) - 1
+    };
+
+    fn new(ptr: NonNull<T>, live: LiveGuard<'a>) -> Self {
+        let _ = Self::MASK;
+        TaggedArenaPtr {
+            ptr,
+            live,
+            phantom: PhantomData,
+        }
+    }
+
+    fn as_ptr(&self) -> *mut T {
+        self.ptr.as_ptr().map_addr(|addr| addr & !Self::MASK)
+    }
+
+    /// Returns the tag stored in the low bits of the pointer.
+    pub fn tag(&self) -> usize {
+        self.ptr.as_ptr().addr() & Self::MASK
+    }
+
+    /// Replaces the tag stored in the low bits of the pointer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tag` does not fit in `BITS` bits.
+    pub fn set_tag(&mut self, tag: usize) {
+        assert!(tag <= Self::MASK, "tag {} does not fit in {} bits", tag, BITS);
+        let p = self.as_ptr().map_addr(|addr| addr | tag);
+        self.ptr = unsafe { NonNull::new_unchecked(p) };
+    }
+}
+
+impl<'a, T: 'a, const BITS: u32> TaggedArenaPtr<'a, T, BITS> {
+    /// Takes ownership of the value in `b`, with a tag of zero.
+    pub fn from_box(b: ArenaBox<'a, T>) -> Self {
+        let (p, live) = ArenaBox::into_raw_parts(b);
+        Self::new(unsafe { NonNull::new_unchecked(p) }, live)
+    }
+
+    /// Returns a reference to the pointed-to value.
+    pub fn get(&self) -> &T {
+        unsafe { &*self.as_ptr() }
+    }
+
+    /// Returns a mutable reference to the pointed-to value.
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.as_ptr() }
+    }
+
+    /// Hands ownership of the value back to an `ArenaBox`, discarding the
+    /// tag.
+    pub fn into_box(self) -> ArenaBox<'a, T> {
+        let p = self.as_ptr();
+        let this = mem::ManuallyDrop::new(self);
+        unsafe { ArenaBox::from_raw_parts(p, ptr::read(&this.live)) }
+    }
+}
+
+impl<'a, T: 'a, const BITS: u32> TaggedArenaPtr<'a, T, BITS, false> {
+    /// Creates a non-owning tagged pointer to `r`, with a tag of zero.
+    pub fn from_ref(r: &'a T) -> Self {
+        Self::new(NonNull::from(r), LiveGuard::untracked())
+    }
+
+    /// Returns a reference to the pointed-to value.
+    pub fn get(&self) -> &'a T {
+        unsafe { &*self.as_ptr() }
+    }
+}
+
+impl<'a, T: 'a, const BITS: u32> Clone for TaggedArenaPtr<'a, T, BITS, false> {
+    fn clone(&self) -> Self {
+        Self::new(self.ptr, LiveGuard::untracked())
+    }
+}
+
+impl<'a, T: 'a, const BITS: u32, const OWNED: bool> Drop for TaggedArenaPtr<'a, T, BITS, OWNED> {
+    fn drop(&mut self) {
+        if OWNED {
+            unsafe { ptr::drop_in_place(self.as_ptr()) }
+        }
+    }
+}
+
+impl<'a, T: 'a, const BITS: u32, const OWNED: bool> fmt::Debug
+    for TaggedArenaPtr<'a, T, BITS, OWNED>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TaggedArenaPtr")
+            .field("ptr", &self.as_ptr())
+            .field("tag", &self.tag())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use core::cell::Cell;
+    use test_util::{checked_arena, DropCounter};
+
+    #[test]
+    fn tag_round_trip() {
+        let a = checked_arena(1024, 1024);
+        let mut p: TaggedArenaPtr<u64, 3> = TaggedArenaPtr::from_box(a.new_box(42).unwrap());
+        let addr = p.as_ptr();
+        for tag in 0..(1 << 3) {
+            p.set_tag(tag);
+            assert_eq!(p.tag(), tag);
+            assert_eq!(p.as_ptr(), addr);
+            assert_eq!(*p.get(), 42);
+        }
+        *p.get_mut() += 1;
+        assert_eq!(p.tag(), 7);
+        assert_eq!(*p.into_box(), 43);
+    }
+
+    #[test]
+    fn tag_ref() {
+        let a = checked_arena(1024, 1024);
+        let b = a.new_box(5u32).unwrap();
+        let mut p: TaggedArenaRef<u32, 2> = TaggedArenaPtr::from_ref(&b);
+        p.set_tag(2);
+        let q = p.clone();
+        drop(p);
+        assert_eq!(q.tag(), 2);
+        assert_eq!(*q.get(), 5);
+        assert_eq!(q.get() as *const u32, &*b as *const u32);
+    }
+
+    #[test]
+    #[should_panic(expected = "tag 4 does not fit in 2 bits")]
+    fn tag_too_large() {
+        let a = checked_arena(1024, 1024);
+        let mut p: TaggedArenaPtr<u32, 2> = TaggedArenaPtr::from_box(a.new_box(5).unwrap());
+        p.set_tag(4);
+    }
+
+    #[cfg(not(feature = "live-count"))]
+    #[test]
+    fn tag_niche() {
+        assert_eq!(
+            mem::size_of::<Option<TaggedArenaPtr<u64, 3>>>(),
+            mem::size_of::<*const u64>()
+        );
+        assert_eq!(
+            mem::size_of::<Option<TaggedArenaRef<u64, 3>>>(),
+            mem::size_of::<*const u64>()
+        );
+    }
+
+    #[test]
+    fn tag_drop_once() {
+        let drops = Cell::new(0);
+        let a = checked_arena(1024, 1024);
+        let b = a.new_box((0u64, DropCounter(&drops))).unwrap();
+        let mut p: TaggedArenaPtr<_, 3> = TaggedArenaPtr::from_box(b);
+        p.set_tag(5);
+        let b = p.into_box();
+        assert_eq!(drops.get(), 0);
+        drop(b);
+        assert_eq!(drops.get(), 1);
+
+        let b = a.new_box((0u64, DropCounter(&drops))).unwrap();
+        let p: TaggedArenaPtr<_, 3> = TaggedArenaPtr::from_box(b);
+        drop(p);
+        assert_eq!(drops.get(), 2);
+
+        let b = a.new_box((0u64, DropCounter(&drops))).unwrap();
+        let r: TaggedArenaRef<_, 3> = TaggedArenaPtr::from_ref(&b);
+        drop(r);
+        assert_eq!(drops.get(), 2);
+        drop(b);
+        assert_eq!(drops.get(), 3);
+    }
+}
@@ -0,0 +1,199 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Helpers shared by the unit tests.
+
+#[cfg(not(feature = "alloc"))]
+use core::alloc::Layout;
+use core::alloc::GlobalAlloc;
+use core::cell::Cell;
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+
+#[cfg(not(feature = "alloc"))]
+use arena::Backing;
+#[cfg(not(feature = "alloc"))]
+use regions::Regions;
+use Arena;
+
+/// The global allocator of the test binary, which counts the heap
+/// allocations of each thread, so tests can check that something does not
+/// touch the heap at all.
+struct CountingAllocator;
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+thread_local! {
+    static HEAP_ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: ::core::alloc::Layout) -> *mut u8 {
+        // Threads that are shutting down may no longer have the counter.
+        let _ = HEAP_ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        ::std::alloc::System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, p: *mut u8, layout: ::core::alloc::Layout) {
+        ::std::alloc::System.dealloc(p, layout)
+    }
+}
+
+/// Returns the number of global heap allocations made by the current
+/// thread so far, including those of the Arenas themselves.
+#[cfg(all(feature = "serde", feature = "alloc"))]
+pub fn heap_allocations() -> usize {
+    HEAP_ALLOCATIONS.with(|n| n.get())
+}
+
+/// An `Arena` that verifies its internal invariants when it goes out of
+/// scope, so every test that uses one checks them after it is done.
+///
+/// Without the `libc` feature, the Arena is made over a buffer from the
+/// standard library's allocator instead, so the tests that only need some
+/// Arena still run.
+pub struct CheckedArena(ManuallyDrop<Arena>, #[cfg(not(feature = "alloc"))] Layout);
+
+/// Creates a `CheckedArena`, panicking if the allocation fails.
+#[cfg(feature = "alloc")]
+pub fn checked_arena(size: usize, alignment: usize) -> CheckedArena {
+    CheckedArena(ManuallyDrop::new(Arena::new(size, alignment).unwrap()))
+}
+
+/// Creates a `CheckedArena`, panicking if the allocation fails.
+#[cfg(not(feature = "alloc"))]
+pub fn checked_arena(size: usize, alignment: usize) -> CheckedArena {
+    let layout = Layout::from_size_align(size.max(1), alignment).unwrap();
+    let mem = unsafe { ::std::alloc::alloc(layout) };
+    assert!(!mem.is_null());
+    let arena = Arena::from_parts(mem, size, Backing::Borrowed, Regions::new());
+    CheckedArena(ManuallyDrop::new(arena), layout)
+}
+
+impl Deref for CheckedArena {
+    type Target = Arena;
+
+    fn deref(&self) -> &Arena {
+        &self.0
+    }
+}
+
+impl DerefMut for CheckedArena {
+    fn deref_mut(&mut self) -> &mut Arena {
+        &mut self.0
+    }
+}
+
+impl Drop for CheckedArena {
+    fn drop(&mut self) {
+        if !::std::thread::panicking() {
+            self.0.check_invariants();
+        }
+        #[cfg(not(feature = "alloc"))]
+        let mem = self.0.mem;
+        unsafe { ManuallyDrop::drop(&mut self.0) };
+        #[cfg(not(feature = "alloc"))]
+        unsafe {
+            ::std::alloc::dealloc(mem, self.1)
+        };
+    }
+}
+
+#[cfg(feature = "alloc")]
+/// The arenas other than `Arena` that can verify their invariants.
+pub trait CheckInvariants {
+    fn check_invariants(&self);
+}
+
+#[cfg(feature = "alloc")]
+impl CheckInvariants for ::DoubleEndedArena {
+    fn check_invariants(&self) {
+        ::DoubleEndedArena::check_invariants(self)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl CheckInvariants for ::EpochArena {
+    fn check_invariants(&self) {
+        ::EpochArena::check_invariants(self)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl CheckInvariants for ::GrowableArena {
+    fn check_invariants(&self) {
+        ::GrowableArena::check_invariants(self)
+    }
+}
+
+#[cfg(feature = "std")]
+impl CheckInvariants for ::ShardedArena {
+    fn check_invariants(&self) {
+        ::ShardedArena::check_invariants(self)
+    }
+}
+
+#[cfg(feature = "alloc")]
+/// Like `CheckedArena`, an arena of another kind that verifies its
+/// invariants when it goes out of scope.
+pub struct Checked<A: CheckInvariants>(A);
+
+#[cfg(feature = "alloc")]
+/// Wraps `a` in a `Checked`.
+pub fn checked<A: CheckInvariants>(a: A) -> Checked<A> {
+    Checked(a)
+}
+
+#[cfg(feature = "alloc")]
+impl<A: CheckInvariants> Deref for Checked<A> {
+    type Target = A;
+
+    fn deref(&self) -> &A {
+        &self.0
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<A: CheckInvariants> DerefMut for Checked<A> {
+    fn deref_mut(&mut self) -> &mut A {
+        &mut self.0
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<A: CheckInvariants> Drop for Checked<A> {
+    fn drop(&mut self) {
+        if !::std::thread::panicking() {
+            self.0.check_invariants();
+        }
+    }
+}
+
+/// Increments a shared counter when dropped.
+#[derive(Debug)]
+pub struct DropCounter<'c>(pub &'c Cell<usize>);
+
+impl<'c> Drop for DropCounter<'c> {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() + 1);
+    }
+}
+
+/// Returns the number of system allocations made by the current thread that
+/// have not been freed yet.
+pub fn live_allocations() -> isize {
+    ::alloc::LIVE_ALLOCATIONS.with(|live| live.get())
+}
+
+/// Returns the number of system allocations made by the current thread so
+/// far, including those that have been freed again.
+#[cfg(feature = "alloc")]
+pub fn total_allocations() -> usize {
+    ::alloc::TOTAL_ALLOCATIONS.with(|total| total.get())
+}
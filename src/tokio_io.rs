@@ -0,0 +1,153 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! `tokio::io` support for `ArenaBox`, enabled by the `tokio` feature.
+//!
+//! As with tokio's impls for `Box<T>`, an `ArenaBox<T>` forwards
+//! `AsyncRead`, `AsyncWrite` and `AsyncSeek` when `T` is `Unpin`. I/O
+//! objects that are not `Unpin` can be pinned with the unsafe
+//! [`ArenaBox::into_pin`]; tokio already implements the traits for `Pin<P>`.
+//!
+//! [`ArenaBox::into_pin`]: ../arena_box/struct.ArenaBox.html#method.into_pin
+
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use std::io;
+
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWrite, ReadBuf};
+
+use ArenaBox;
+
+impl<'a, T: AsyncRead + Unpin + ?Sized> AsyncRead for ArenaBox<'a, T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut **self).poll_read(cx, buf)
+    }
+}
+
+impl<'a, T: AsyncWrite + Unpin + ?Sized> AsyncWrite for ArenaBox<'a, T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut **self).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        bufs: &[io::IoSlice],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut **self).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        (**self).is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut **self).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut **self).poll_shutdown(cx)
+    }
+}
+
+impl<'a, T: AsyncSeek + Unpin + ?Sized> AsyncSeek for ArenaBox<'a, T> {
+    fn start_seek(mut self: Pin<&mut Self>, position: io::SeekFrom) -> io::Result<()> {
+        Pin::new(&mut **self).start_seek(position)
+    }
+
+    fn poll_complete(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<u64>> {
+        Pin::new(&mut **self).poll_complete(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use test_util::checked_arena;
+    use std::io::{IoSlice, SeekFrom};
+    use std::vec::Vec;
+    use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+    use tokio::runtime::{Builder, Runtime};
+
+    // `#[tokio::test]` needs `async fn`, which the 2015 edition does not
+    // have, so the tests drive each operation on a current-thread runtime.
+    fn runtime() -> Runtime {
+        Builder::new_current_thread().build().unwrap()
+    }
+
+    #[test]
+    fn duplex_read_write() {
+        let rt = runtime();
+        let a = checked_arena(1024, 1024);
+        let (client, server) = tokio::io::duplex(64);
+        let mut client = a.new_box(client).unwrap();
+        let mut server = a.new_box(server).unwrap();
+
+        rt.block_on(client.write_all(b"ping")).unwrap();
+        rt.block_on(client.flush()).unwrap();
+        let mut buf = [0u8; 4];
+        rt.block_on(server.read_exact(&mut buf)).unwrap();
+        assert_eq!(&buf, b"ping");
+
+        rt.block_on(server.write_all(b"pong")).unwrap();
+        rt.block_on(server.shutdown()).unwrap();
+        let mut v = Vec::new();
+        rt.block_on(client.read_to_end(&mut v)).unwrap();
+        assert_eq!(v, b"pong");
+    }
+
+    #[test]
+    fn duplex_write_vectored() {
+        let rt = runtime();
+        let a = checked_arena(1024, 1024);
+        let (client, server) = tokio::io::duplex(64);
+        let mut client = a.new_box(client).unwrap();
+        let mut server = a.new_box(server).unwrap();
+        assert_eq!(client.is_write_vectored(), (*client).is_write_vectored());
+
+        let bufs = [IoSlice::new(b"arena "), IoSlice::new(b"box")];
+        let n = rt.block_on(client.write_vectored(&bufs)).unwrap();
+        let mut v = vec![0u8; n];
+        rt.block_on(server.read_exact(&mut v)).unwrap();
+        assert_eq!(&v[..], &b"arena box"[..n]);
+    }
+
+    #[test]
+    fn pinned_duplex() {
+        let rt = runtime();
+        let a = checked_arena(1024, 1024);
+        let (client, server) = tokio::io::duplex(64);
+        let mut client = unsafe { ArenaBox::into_pin(a.new_box(client).unwrap()) };
+        let mut server = unsafe { ArenaBox::into_pin(a.new_box(server).unwrap()) };
+        rt.block_on(client.write_all(b"pinned")).unwrap();
+        let mut buf = [0u8; 6];
+        rt.block_on(server.read_exact(&mut buf)).unwrap();
+        assert_eq!(&buf, b"pinned");
+    }
+
+    #[test]
+    fn seek() {
+        let rt = runtime();
+        let a = checked_arena(1024, 1024);
+        let mut cursor = a.new_box(io::Cursor::new(vec![1u8, 2, 3, 4])).unwrap();
+        assert_eq!(rt.block_on(cursor.seek(SeekFrom::Start(2))).unwrap(), 2);
+        let mut buf = [0u8; 2];
+        rt.block_on(cursor.read_exact(&mut buf)).unwrap();
+        assert_eq!(buf, [3, 4]);
+        assert_eq!(rt.block_on(cursor.seek(SeekFrom::End(-3))).unwrap(), 1);
+    }
+}
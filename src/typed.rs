@@ -0,0 +1,417 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! An arena of values of a single type, which can list all of them.
+
+use core::cell::Cell;
+use core::fmt;
+use core::iter::FusedIterator;
+use core::marker::PhantomData;
+use core::mem;
+use core::ptr::{self, NonNull};
+
+use alloc::AllocError;
+use Arena;
+
+struct Chunk {
+    arena: Arena,
+    /// The chunk allocated after this one.
+    next: *mut Chunk,
+}
+
+/// An arena for values of type `T`, which remembers every value placed in
+/// it, and drops them all when it is dropped.
+///
+/// The values are kept in a list of chunks of `chunk_len` values each,
+/// like the chunks of a [`GrowableArena`], and a new chunk is added
+/// whenever the last one is full. Since every slot has the same size, the
+/// values in a chunk sit next to each other, and [`TypedArena::iter`] can
+/// walk all of them, in the order they were allocated, without a separate
+/// list of references. Values never move, so the references `alloc`
+/// returns stay valid for as long as the arena does.
+///
+/// `alloc` only hands out shared references, since `iter` can reach every
+/// value through a shared borrow of the arena. [`TypedArena::iter_mut`]
+/// borrows the arena mutably instead, once the shared references are gone.
+///
+/// [`GrowableArena`]: struct.GrowableArena.html
+/// [`TypedArena::iter`]: #method.iter
+/// [`TypedArena::iter_mut`]: #method.iter_mut
+///
+/// # Examples
+///
+/// ```
+/// # use memory_arena::*;
+/// enum Expr<'a> {
+///     Num(i64),
+///     Add(&'a Expr<'a>, &'a Expr<'a>),
+/// }
+///
+/// let nodes = TypedArena::new(16).unwrap();
+/// let one = nodes.alloc(Expr::Num(1)).ok().unwrap();
+/// let two = nodes.alloc(Expr::Num(2)).ok().unwrap();
+/// let _sum = nodes.alloc(Expr::Add(one, two)).ok().unwrap();
+/// let adds = nodes.iter().filter(|e| matches!(e, Expr::Add(..))).count();
+/// assert_eq!((nodes.len(), adds), (3, 1));
+/// ```
+pub struct TypedArena<T> {
+    chunk_len: usize,
+    /// The oldest chunk, where iteration starts.
+    first: *mut Chunk,
+    /// The chunk values are allocated from, which is the end of the list.
+    last: Cell<*mut Chunk>,
+    chunks: Cell<usize>,
+    len: Cell<usize>,
+    phantom: PhantomData<T>,
+}
+
+impl<T> TypedArena<T> {
+    /// Creates a `TypedArena` whose chunks hold `chunk_len` values each.
+    /// The first chunk is allocated right away, unless `T` is zero-sized,
+    /// in which case no memory is ever needed.
+    ///
+    /// If the size of a chunk overflows `usize`, `AllocError::OutOfMemory`
+    /// is returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_len` is zero.
+    pub fn new(chunk_len: usize) -> Result<Self, AllocError> {
+        assert!(chunk_len > 0, "chunk length must not be zero");
+        let mut a = TypedArena {
+            chunk_len,
+            first: ptr::null_mut(),
+            last: Cell::new(ptr::null_mut()),
+            chunks: Cell::new(0),
+            len: Cell::new(0),
+            phantom: PhantomData,
+        };
+        if mem::size_of::<T>() != 0 {
+            a.first = a.new_chunk()?;
+            a.last.set(a.first);
+        }
+        Ok(a)
+    }
+
+    /// Allocates an empty chunk, and adds it to the end of the list.
+    fn new_chunk(&self) -> Result<*mut Chunk, AllocError> {
+        let size = self
+            .chunk_len
+            .checked_mul(mem::size_of::<T>())
+            .ok_or(AllocError::OutOfMemory)?;
+        let arena = Arena::new(size, mem::align_of::<T>())?;
+        let chunk = unsafe { ::alloc::aligned_alloc(mem::size_of::<Chunk>(), mem::align_of::<Chunk>())? }
+            as *mut Chunk;
+        unsafe {
+            ptr::write(
+                chunk,
+                Chunk {
+                    arena,
+                    next: ptr::null_mut(),
+                },
+            );
+            if let Some(last) = self.last.get().as_mut() {
+                last.next = chunk;
+            }
+        }
+        self.last.set(chunk);
+        self.chunks.set(self.chunks.get() + 1);
+        Ok(chunk)
+    }
+
+    /// Places `x` in the arena, and returns a reference to it.
+    ///
+    /// If a new chunk is needed and cannot be allocated, then the original
+    /// value is returned, wrapped in `Result::Err`.
+    pub fn alloc(&self, x: T) -> Result<&T, T> {
+        if mem::size_of::<T>() == 0 {
+            mem::forget(x);
+            self.len.set(self.len.get() + 1);
+            return Ok(unsafe { &*NonNull::dangling().as_ptr() });
+        }
+        let size = mem::size_of::<T>();
+        let align = mem::align_of::<T>();
+        let p = match unsafe { (*self.last.get()).arena.try_aligned_alloc(size, align) } {
+            Ok(p) => p,
+            Err(_) => match self.new_chunk() {
+                Ok(chunk) => unsafe { (*chunk).arena.try_aligned_alloc(size, align) }
+                    .expect("a new chunk has room for a value"),
+                Err(_) => return Err(x),
+            },
+        } as *mut T;
+        unsafe { ptr::write(p, x) };
+        self.len.set(self.len.get() + 1);
+        Ok(unsafe { &*p })
+    }
+
+    /// Returns the number of values in the arena.
+    pub fn len(&self) -> usize {
+        self.len.get()
+    }
+
+    /// Returns `true` if no values have been allocated.
+    pub fn is_empty(&self) -> bool {
+        self.len.get() == 0
+    }
+
+    /// Returns the number of values a chunk holds.
+    pub fn chunk_len(&self) -> usize {
+        self.chunk_len
+    }
+
+    /// Returns the number of chunks allocated so far.
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.get()
+    }
+
+    /// Returns an iterator over the values, in the order they were
+    /// allocated.
+    ///
+    /// The iterator yields the values that were in the arena when it was
+    /// created; values allocated while iterating are left out.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            raw: RawIter::new(self),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns an iterator over the values that allows modifying them, in
+    /// the order they were allocated.
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            raw: RawIter::new(self),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<T> Drop for TypedArena<T> {
+    fn drop(&mut self) {
+        if mem::size_of::<T>() == 0 {
+            let values = ptr::slice_from_raw_parts_mut(NonNull::<T>::dangling().as_ptr(), self.len.get());
+            unsafe { ptr::drop_in_place(values) };
+            return;
+        }
+        let mut chunk = self.first;
+        while !chunk.is_null() {
+            unsafe {
+                let next = (*chunk).next;
+                let arena = &(*chunk).arena;
+                let len = arena.used() / mem::size_of::<T>();
+                ptr::drop_in_place(ptr::slice_from_raw_parts_mut(arena.mem as *mut T, len));
+                ptr::drop_in_place(chunk);
+                ::alloc::free(chunk as *mut u8, mem::size_of::<Chunk>(), mem::align_of::<Chunk>());
+                chunk = next;
+            }
+        }
+    }
+}
+
+impl<T> fmt::Debug for TypedArena<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TypedArena")
+            .field("chunk_len", &self.chunk_len)
+            .field("chunks", &self.chunks.get())
+            .field("len", &self.len.get())
+            .finish()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a TypedArena<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut TypedArena<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+/// Walks the values of the chunks, starting with the first chunk.
+struct RawIter<T> {
+    /// The chunk after the one `ptr` points into.
+    next: *const Chunk,
+    ptr: *mut T,
+    end: *mut T,
+    remaining: usize,
+}
+
+impl<T> RawIter<T> {
+    fn new(a: &TypedArena<T>) -> Self {
+        RawIter {
+            next: a.first,
+            ptr: NonNull::dangling().as_ptr(),
+            end: NonNull::dangling().as_ptr(),
+            remaining: a.len.get(),
+        }
+    }
+
+    fn next(&mut self) -> Option<*mut T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        if mem::size_of::<T>() == 0 {
+            return Some(NonNull::dangling().as_ptr());
+        }
+        // Only the last chunk can have room left, so when one runs out the
+        // next one has values, unless `remaining` was already zero.
+        while self.ptr == self.end {
+            unsafe {
+                let chunk = &*self.next;
+                self.ptr = chunk.arena.mem as *mut T;
+                self.end = self.ptr.add(chunk.arena.used() / mem::size_of::<T>());
+                self.next = chunk.next;
+            }
+        }
+        let p = self.ptr;
+        self.ptr = unsafe { p.add(1) };
+        Some(p)
+    }
+}
+
+/// An iterator over the values of a [`TypedArena`], in allocation order.
+///
+/// [`TypedArena`]: struct.TypedArena.html
+pub struct Iter<'a, T> {
+    raw: RawIter<T>,
+    phantom: PhantomData<&'a T>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.raw.next().map(|p| unsafe { &*p })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.raw.remaining, Some(self.raw.remaining))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+impl<'a, T> FusedIterator for Iter<'a, T> {}
+
+/// An iterator over the values of a [`TypedArena`], in allocation order,
+/// that allows modifying them.
+///
+/// [`TypedArena`]: struct.TypedArena.html
+pub struct IterMut<'a, T> {
+    raw: RawIter<T>,
+    phantom: PhantomData<&'a mut T>,
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<&'a mut T> {
+        self.raw.next().map(|p| unsafe { &mut *p })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.raw.remaining, Some(self.raw.remaining))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for IterMut<'a, T> {}
+
+impl<'a, T> FusedIterator for IterMut<'a, T> {}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use std::string::{String, ToString};
+    use std::vec::Vec;
+    use test_util::{live_allocations, DropCounter};
+
+    #[test]
+    fn typed_across_chunks() {
+        let a = TypedArena::new(4).unwrap();
+        let refs: Vec<_> = (0..50u64).map(|i| a.alloc(i * 3).unwrap()).collect();
+        assert_eq!((a.len(), a.chunk_count()), (50, 13));
+        assert!(a.iter().copied().eq((0..50).map(|i| i * 3)));
+        assert_eq!(a.iter().len(), 50);
+        // The references handed out are the values the iterator yields.
+        assert!(a.iter().zip(&refs).all(|(x, r)| ptr::eq(x, *r)));
+
+        let mut a = a;
+        for x in &mut a {
+            *x += 1;
+        }
+        assert!(a.iter().copied().eq((0..50).map(|i| i * 3 + 1)));
+    }
+
+    #[test]
+    fn typed_iter_snapshot() {
+        let a = TypedArena::new(3).unwrap();
+        for i in 0..5 {
+            a.alloc(i.to_string()).unwrap();
+        }
+        let mut seen = Vec::new();
+        for s in a.iter() {
+            // Values allocated while iterating are left out.
+            a.alloc(String::from("late")).unwrap();
+            seen.push(s.clone());
+        }
+        assert_eq!(seen, ["0", "1", "2", "3", "4"]);
+        assert_eq!((a.len(), a.chunk_count()), (10, 4));
+        assert_eq!(a.iter().filter(|s| *s == "late").count(), 5);
+        assert!(TypedArena::<u8>::new(1).unwrap().iter().next().is_none());
+    }
+
+    #[test]
+    fn typed_drops_everything() {
+        let drops = Cell::new(0);
+        let before = live_allocations();
+        {
+            let a = TypedArena::new(8).unwrap();
+            for i in 0..21 {
+                a.alloc((i, DropCounter(&drops))).ok().unwrap();
+            }
+            assert_eq!(a.chunk_count(), 3);
+            assert_eq!(live_allocations() - before, 2 * 3);
+            assert_eq!(a.iter().map(|x| x.0).last(), Some(20));
+            assert_eq!(drops.get(), 0);
+        }
+        assert_eq!(drops.get(), 21);
+        assert_eq!(live_allocations(), before);
+    }
+
+    #[test]
+    fn typed_zero_sized() {
+        thread_local!(static DROPS: Cell<usize> = const { Cell::new(0) });
+        struct Unit;
+        impl Drop for Unit {
+            fn drop(&mut self) {
+                DROPS.with(|d| d.set(d.get() + 1));
+            }
+        }
+        {
+            let a = TypedArena::new(2).unwrap();
+            for _ in 0..1000 {
+                a.alloc(Unit).ok().unwrap();
+            }
+            assert_eq!((a.len(), a.chunk_count(), a.iter().count()), (1000, 0, 1000));
+            assert_eq!(DROPS.with(Cell::get), 0);
+        }
+        assert_eq!(DROPS.with(Cell::get), 1000);
+        assert!(TypedArena::<[u64; 2]>::new(usize::MAX).is_err());
+    }
+}
@@ -97,7 +97,7 @@ impl<T: ?Sized> Unique<T> {
         }
     }
 
-    /// Creates a new `Unique` if `ptr` is non-null.
+    // /// Creates a new `Unique` if `ptr` is non-null.
     // pub fn new(ptr: *mut T) -> Option<Self> {
     //     NonZero::new(ptr as *const T).map(|nz| Unique {
     //         pointer: nz,
@@ -143,6 +143,13 @@ where
 {
 }
 
+#[cfg(feature = "coerce_unsized")]
+impl<T: ?Sized, U: ?Sized> ::core::ops::CoerceUnsized<Unique<U>> for Unique<T>
+where
+    T: ::core::marker::Unsize<U>,
+{
+}
+
 impl<T: ?Sized> fmt::Pointer for Unique<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         fmt::Pointer::fmt(&self.as_ptr(), f)
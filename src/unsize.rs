@@ -46,6 +46,7 @@
 /// [rc]: ../../std/rc/struct.Rc.html
 /// [RFC982]: https://github.com/rust-lang/rfcs/blob/master/text/0982-dst-coercion.md
 /// [nomicon-coerce]: ../../nomicon/coercions.html
+#[allow(dead_code)]
 pub trait Unsize<T: ?Sized> {
     // Empty.
 }
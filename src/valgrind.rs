@@ -0,0 +1,124 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Valgrind memory pool client requests, enabled by the `valgrind`
+//! feature.
+//!
+//! To Memcheck, an Arena's buffer is either one heap block or memory it
+//! knows nothing about, so it can neither tell which arena allocations
+//! are live nor flag reads of memory a reset gave back. With the feature,
+//! the buffer is registered as a memory pool anchored at its start, with
+//! each allocation from it a chunk of the pool, and the chunks past the
+//! cursor are trimmed away whenever it moves back. The requests are the
+//! `VALGRIND_*_MEMPOOL` macros of `valgrind.h`, written out for x86-64 and
+//! AArch64. Outside of Valgrind they cost a few instructions, and without
+//! the feature, or on other architectures, these functions do nothing.
+
+#[cfg(all(feature = "valgrind", any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod imp {
+    use core::arch::asm;
+
+    const CREATE_MEMPOOL: usize = 0x1303;
+    const DESTROY_MEMPOOL: usize = 0x1304;
+    const MEMPOOL_ALLOC: usize = 0x1305;
+    const MEMPOOL_TRIM: usize = 0x1307;
+    const MAKE_MEM_NOACCESS: usize = 0x4d43_0000;
+    const MAKE_MEM_DEFINED: usize = 0x4d43_0002;
+
+    /// Issues a client request. The magic sequence does nothing on a real
+    /// CPU, and Valgrind recognizes it and reads the request from `args`.
+    #[inline(always)]
+    fn request(args: [usize; 6]) {
+        let _result: usize;
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            asm!(
+                "rol rdi, 3",
+                "rol rdi, 13",
+                "rol rdi, 61",
+                "rol rdi, 51",
+                "xchg rbx, rbx",
+                in("rax") args.as_ptr(),
+                inout("rdx") 0usize => _result,
+                options(nostack),
+            );
+        }
+        #[cfg(target_arch = "aarch64")]
+        unsafe {
+            asm!(
+                "ror x12, x12, #3",
+                "ror x12, x12, #13",
+                "ror x12, x12, #51",
+                "ror x12, x12, #61",
+                "orr x10, x10, x10",
+                in("x4") args.as_ptr(),
+                inout("x3") 0usize => _result,
+                options(nostack),
+            );
+        }
+    }
+
+    pub(crate) fn create_pool(mem: *const u8, size: usize) {
+        request([CREATE_MEMPOOL, mem as usize, 0, 0, 0, 0]);
+        request([MAKE_MEM_NOACCESS, mem as usize, size, 0, 0, 0]);
+    }
+
+    pub(crate) fn destroy_pool(mem: *const u8, size: usize) {
+        request([DESTROY_MEMPOOL, mem as usize, 0, 0, 0, 0]);
+        request([MAKE_MEM_DEFINED, mem as usize, size, 0, 0, 0]);
+    }
+
+    pub(crate) fn alloc(mem: *const u8, p: *const u8, len: usize) {
+        request([MEMPOOL_ALLOC, mem as usize, p as usize, len, 0, 0]);
+    }
+
+    pub(crate) fn trim(mem: *const u8, len: usize) {
+        request([MEMPOOL_TRIM, mem as usize, mem as usize, len, 0, 0]);
+    }
+
+    pub(crate) fn make_defined(p: *const u8, len: usize) {
+        request([MAKE_MEM_DEFINED, p as usize, len, 0, 0, 0]);
+    }
+
+    pub(crate) fn make_noaccess(p: *const u8, len: usize) {
+        request([MAKE_MEM_NOACCESS, p as usize, len, 0, 0, 0]);
+    }
+}
+
+#[cfg(not(all(feature = "valgrind", any(target_arch = "x86_64", target_arch = "aarch64"))))]
+mod imp {
+    #[inline(always)]
+    pub(crate) fn create_pool(_: *const u8, _: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn destroy_pool(_: *const u8, _: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn alloc(_: *const u8, _: *const u8, _: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn trim(_: *const u8, _: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn make_defined(_: *const u8, _: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn make_noaccess(_: *const u8, _: usize) {}
+}
+
+/// Registers the `size` bytes at `mem` as a pool with no chunks, or
+/// unregisters them, leaving them accessible.
+pub(crate) use self::imp::{create_pool, destroy_pool};
+
+/// Adds the `len` bytes at `p` as a chunk of the pool at `mem`, or keeps
+/// only the chunks in its first `len` bytes.
+pub(crate) use self::imp::{alloc, trim};
+
+/// Marks memory inside the pool as accessible, or as off limits again,
+/// for code that touches it outside of any chunk.
+pub(crate) use self::imp::{make_defined, make_noaccess};
@@ -0,0 +1,408 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A growable vector whose buffer lives in an arena.
+
+use core::fmt;
+use core::mem::{self, ManuallyDrop};
+use core::ops::{Deref, DerefMut};
+use core::ptr::{self, NonNull};
+use core::slice;
+
+use alloc::AllocError;
+use arena_box::LiveGuard;
+use {Arena, ArenaBox};
+
+/// The smallest capacity of a buffer that grows on its own.
+const MIN_CAPACITY: usize = 4;
+
+/// A `Vec`-like vector whose elements are stored in an `Arena`.
+///
+/// When the vector is full, its buffer is extended in place if it is the
+/// most recent allocation from the Arena. Otherwise the elements are moved
+/// into a new buffer with twice the capacity, and the memory of the old one
+/// stays in the Arena until it is reset.
+///
+/// Since the Arena can run out of memory, [`ArenaVec::try_push`] and
+/// [`ArenaVec::try_reserve`] report it instead of panicking like
+/// [`ArenaVec::push`] does.
+///
+/// [`ArenaVec::try_push`]: #method.try_push
+/// [`ArenaVec::try_reserve`]: #method.try_reserve
+/// [`ArenaVec::push`]: #method.push
+///
+/// # Examples
+///
+/// ```
+/// # use memory_arena::*;
+/// # #[cfg(feature = "alloc")] {
+/// let a = Arena::new(1024, 1024).unwrap();
+/// let mut v = a.new_vec();
+/// v.push(1u32);
+/// v.extend_from_slice(&[2, 3]).unwrap();
+/// assert_eq!(v[2], 3);
+/// assert_eq!(v.pop(), Some(3));
+/// let b = v.into_boxed_slice();
+/// assert_eq!(&*b, &[1, 2]);
+/// # }
+/// ```
+pub struct ArenaVec<'a, T> {
+    arena: &'a Arena,
+    ptr: NonNull<T>,
+    len: usize,
+    cap: usize,
+    live: LiveGuard<'a>,
+}
+
+impl<'a, T> ArenaVec<'a, T> {
+    /// Creates an empty vector that allocates from `arena`.
+    ///
+    /// No memory is allocated until the first element is pushed.
+    pub fn new_in(arena: &'a Arena) -> Self {
+        ArenaVec {
+            arena,
+            ptr: NonNull::dangling(),
+            len: 0,
+            cap: if mem::size_of::<T>() == 0 { usize::MAX } else { 0 },
+            live: LiveGuard::new(arena),
+        }
+    }
+
+    /// Creates an empty vector with room for `capacity` elements in
+    /// `arena`.
+    ///
+    /// If there is not enough available memory in the Arena, the error is
+    /// returned.
+    pub fn with_capacity_in(capacity: usize, arena: &'a Arena) -> Result<Self, AllocError> {
+        let mut v = ArenaVec::new_in(arena);
+        v.try_reserve(capacity)?;
+        Ok(v)
+    }
+
+    /// Returns the number of elements.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if there are no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of elements the vector can hold without
+    /// allocating.
+    pub fn capacity(&self) -> usize {
+        self.cap
+    }
+
+    /// Returns the elements as a slice.
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+
+    /// Returns the elements as a mutable slice.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+
+    /// Makes room for at least `additional` more elements.
+    ///
+    /// If there is not enough available memory in the Arena, the error is
+    /// returned and the vector is left as it was.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), AllocError> {
+        if self.cap - self.len >= additional {
+            return Ok(());
+        }
+        let needed = self.len.checked_add(additional).ok_or(AllocError::Exhausted)?;
+        let wanted = self.cap.saturating_mul(2).max(MIN_CAPACITY).max(needed);
+        self.grow_to(wanted).or_else(|_| self.grow_to(needed))
+    }
+
+    /// Resizes the buffer to hold `cap` elements, in place if possible.
+    fn grow_to(&mut self, cap: usize) -> Result<(), AllocError> {
+        let size = mem::size_of::<T>();
+        let bytes = cap.checked_mul(size).ok_or(AllocError::Exhausted)?;
+        let p = self.ptr.as_ptr() as *mut u8;
+        if !self.arena.resize_in_place(p, self.cap * size, bytes) {
+            let new = self.arena.try_aligned_alloc(bytes, mem::align_of::<T>())? as *mut T;
+            unsafe {
+                ptr::copy_nonoverlapping(self.ptr.as_ptr(), new, self.len);
+                self.ptr = NonNull::new_unchecked(new);
+            }
+        }
+        self.cap = cap;
+        Ok(())
+    }
+
+    /// Appends `x` to the end of the vector.
+    ///
+    /// If the vector is full and there is not enough available memory in
+    /// the Arena to grow it, then `x` is returned, wrapped in
+    /// `Result::Err`.
+    pub fn try_push(&mut self, x: T) -> Result<(), T> {
+        if self.len == self.cap && self.try_reserve(1).is_err() {
+            return Err(x);
+        }
+        unsafe { ptr::write(self.ptr.as_ptr().add(self.len), x) };
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Appends `x` to the end of the vector.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the vector is full and there is not enough available
+    /// memory in the Arena to grow it.
+    pub fn push(&mut self, x: T) {
+        if self.try_push(x).is_err() {
+            panic!("arena does not have enough memory left to grow the vector");
+        }
+    }
+
+    /// Removes the last element and returns it, or `None` if the vector is
+    /// empty.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        Some(unsafe { ptr::read(self.ptr.as_ptr().add(self.len)) })
+    }
+
+    /// Drops every element, keeping the buffer.
+    pub fn clear(&mut self) {
+        let elems: *mut [T] = self.as_mut_slice();
+        self.len = 0;
+        unsafe { ptr::drop_in_place(elems) }
+    }
+
+    /// Returns an iterator over the elements.
+    pub fn iter(&self) -> slice::Iter<'_, T> {
+        self.as_slice().iter()
+    }
+
+    /// Returns an iterator that allows modifying each element.
+    pub fn iter_mut(&mut self) -> slice::IterMut<'_, T> {
+        self.as_mut_slice().iter_mut()
+    }
+
+    /// Converts the vector into an arena box of its elements.
+    ///
+    /// The buffer is reused as is; if it is the most recent allocation from
+    /// the Arena, its unused capacity is given back.
+    pub fn into_boxed_slice(self) -> ArenaBox<'a, [T]> {
+        let this = ManuallyDrop::new(self);
+        let size = mem::size_of::<T>();
+        let p = this.ptr.as_ptr();
+        this.arena.resize_in_place(p as *mut u8, this.cap * size, this.len * size);
+        let live = unsafe { ptr::read(&this.live) };
+        unsafe { ArenaBox::from_raw_parts(ptr::slice_from_raw_parts_mut(p, this.len), live) }
+    }
+}
+
+impl<'a, T: Clone> ArenaVec<'a, T> {
+    /// Clones and appends every element of `xs`.
+    ///
+    /// Room for all of them is made first, so if there is not enough
+    /// available memory in the Arena, the error is returned and nothing is
+    /// appended.
+    pub fn extend_from_slice(&mut self, xs: &[T]) -> Result<(), AllocError> {
+        self.try_reserve(xs.len())?;
+        for x in xs {
+            unsafe { ptr::write(self.ptr.as_ptr().add(self.len), x.clone()) };
+            self.len += 1;
+        }
+        Ok(())
+    }
+}
+
+impl Arena {
+    /// Creates an empty [`ArenaVec`] that allocates from the Arena.
+    ///
+    /// [`ArenaVec`]: struct.ArenaVec.html
+    pub fn new_vec<T>(&self) -> ArenaVec<'_, T> {
+        ArenaVec::new_in(self)
+    }
+}
+
+impl<'a, T> Drop for ArenaVec<'a, T> {
+    fn drop(&mut self) {
+        unsafe { ptr::drop_in_place(self.as_mut_slice()) }
+    }
+}
+
+impl<'a, T> Deref for ArenaVec<'a, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<'a, T> DerefMut for ArenaVec<'a, T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+impl<'s, 'a, T> IntoIterator for &'s ArenaVec<'a, T> {
+    type Item = &'s T;
+    type IntoIter = slice::Iter<'s, T>;
+
+    fn into_iter(self) -> slice::Iter<'s, T> {
+        self.iter()
+    }
+}
+
+impl<'s, 'a, T> IntoIterator for &'s mut ArenaVec<'a, T> {
+    type Item = &'s mut T;
+    type IntoIter = slice::IterMut<'s, T>;
+
+    fn into_iter(self) -> slice::IterMut<'s, T> {
+        self.iter_mut()
+    }
+}
+
+impl<'a, T: fmt::Debug> fmt::Debug for ArenaVec<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_slice(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use core::cell::Cell;
+    use std::string::String;
+    use std::vec::Vec;
+    use test_util::{checked_arena, DropCounter};
+
+    #[test]
+    fn vec_grow_in_place() {
+        let a = checked_arena(1024, 1024);
+        let mut v = a.new_vec::<u32>();
+        for i in 0..100 {
+            v.push(i);
+        }
+        assert_eq!(v.capacity(), 128);
+        assert_eq!(a.used(), 128 * 4);
+        assert_eq!(v.as_ptr() as usize, a.mem as usize);
+        assert!(v.iter().copied().eq(0..100));
+    }
+
+    #[test]
+    fn vec_reallocate() {
+        let a = checked_arena(64 * 1024, 4096);
+        let drops = Cell::new(0);
+        let mut model = Vec::new();
+        {
+            let mut v = ArenaVec::with_capacity_in(2, &a).unwrap();
+            for i in 0..200u32 {
+                v.push((i, DropCounter(&drops)));
+                model.push(i);
+                // Something else allocated after the buffer keeps it from
+                // growing in place.
+                let _ = a.new_box(i).unwrap();
+                if i % 9 == 8 {
+                    drop(v.pop());
+                    model.pop();
+                }
+                assert!(v.iter().map(|x| x.0).eq(model.iter().copied()));
+            }
+            assert_eq!(drops.get(), 200 / 9);
+            for x in &mut v {
+                x.0 *= 2;
+            }
+            assert_eq!(v[5].0, model[5] * 2);
+        }
+        assert_eq!(drops.get(), 200);
+    }
+
+    #[test]
+    fn vec_exhausted() {
+        let a = checked_arena(32, 32);
+        let mut v = a.new_vec::<u64>();
+        v.extend_from_slice(&[1, 2, 3]).unwrap();
+        assert_eq!(v.extend_from_slice(&[4, 5]), Err(AllocError::Exhausted));
+        assert_eq!(v.as_slice(), &[1, 2, 3]);
+        v.try_push(4).unwrap();
+        assert_eq!(v.try_push(5), Err(5));
+        assert_eq!(v.len(), 4);
+        assert!(ArenaVec::<u64>::with_capacity_in(5, &a).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "does not have enough memory left to grow the vector")]
+    fn vec_push_panic() {
+        let a = checked_arena(8, 8);
+        let mut v = a.new_vec();
+        v.push(1u64);
+        v.push(2u64);
+    }
+
+    #[test]
+    fn vec_zero_sized() {
+        thread_local!(static DROPS: Cell<usize> = const { Cell::new(0) });
+
+        struct Unit;
+
+        impl Drop for Unit {
+            fn drop(&mut self) {
+                DROPS.with(|d| d.set(d.get() + 1));
+            }
+        }
+
+        let a = checked_arena(8, 8);
+        let mut v = a.new_vec::<Unit>();
+        assert_eq!(v.capacity(), usize::MAX);
+        for _ in 0..1000 {
+            v.push(Unit);
+        }
+        drop(v.pop());
+        assert_eq!(DROPS.with(|d| d.get()), 1);
+        assert_eq!(v.len(), 999);
+        assert_eq!(a.used(), 0);
+        let b = v.into_boxed_slice();
+        assert_eq!(b.len(), 999);
+        drop(b);
+        assert_eq!(DROPS.with(|d| d.get()), 1000);
+
+        let mut units = a.new_vec::<()>();
+        units.extend_from_slice(&[(); 24]).unwrap();
+        assert_eq!(units.len(), 24);
+        assert_eq!(units.try_reserve(usize::MAX), Err(AllocError::Exhausted));
+    }
+
+    #[test]
+    fn vec_into_boxed_slice() {
+        let a = checked_arena(1024, 1024);
+        let drops = Cell::new(0);
+        let mut v = a.new_vec();
+        for _ in 0..5 {
+            v.push(DropCounter(&drops));
+        }
+        assert_eq!(v.capacity(), 8);
+        let b = v.into_boxed_slice();
+        assert_eq!(b.len(), 5);
+        // The unused room at the end was given back.
+        assert_eq!(a.used(), 5 * mem::size_of::<DropCounter>());
+        assert_eq!(drops.get(), 0);
+        drop(b);
+        assert_eq!(drops.get(), 5);
+
+        let mut v = a.new_vec::<u16>();
+        v.extend_from_slice(&[1, 2, 3]).unwrap();
+        let _later = a.new_box(0u8).unwrap();
+        let b = v.into_boxed_slice();
+        assert_eq!(&*b, &[1, 2, 3]);
+        let empty = a.new_vec::<String>().into_boxed_slice();
+        assert!(empty.is_empty());
+    }
+}
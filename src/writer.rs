@@ -0,0 +1,181 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! `std::io::Write` into an `Arena`, enabled by the `std` feature.
+
+use std::io::{self, Write};
+
+use vec::ArenaVec;
+use {Arena, ArenaBox};
+
+/// A `std::io::Write` implementation that appends the written bytes to an
+/// `Arena`.
+///
+/// This lets serializers and formatters that write to `impl Write` put their
+/// output straight into the Arena, without a heap `Vec<u8>` in between. The
+/// buffer grows like an [`ArenaVec`]: in place while it is the most recent
+/// allocation from the Arena, and by moving to a bigger one otherwise.
+///
+/// A write that does not fit in the Arena fails with
+/// `io::ErrorKind::OutOfMemory` and writes nothing. [`ArenaWriter::finish`]
+/// consumes the writer, so nothing can be written after it.
+///
+/// [`ArenaVec`]: struct.ArenaVec.html
+/// [`ArenaWriter::finish`]: #method.finish
+///
+/// # Examples
+///
+/// ```
+/// # use memory_arena::*;
+/// use std::io::Write;
+///
+/// let a = Arena::new(1024, 1024).unwrap();
+/// let mut w = ArenaWriter::new(&a);
+/// write!(w, "{}-{}", "node", 7).unwrap();
+/// w.write_all(b"!").unwrap();
+/// assert_eq!(&*w.finish(), b"node-7!");
+/// ```
+pub struct ArenaWriter<'a> {
+    buf: ArenaVec<'a, u8>,
+}
+
+impl<'a> ArenaWriter<'a> {
+    /// Creates a writer that writes into `arena`.
+    ///
+    /// No memory is allocated until something is written.
+    pub fn new(arena: &'a Arena) -> Self {
+        ArenaWriter {
+            buf: ArenaVec::new_in(arena),
+        }
+    }
+
+    /// Returns the number of bytes written so far.
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Returns `true` if nothing has been written.
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// Returns the bytes written so far.
+    pub fn as_slice(&self) -> &[u8] {
+        self.buf.as_slice()
+    }
+
+    /// Returns the bytes written, as an arena box.
+    ///
+    /// Any unused capacity at the end of the buffer is given back to the
+    /// Arena.
+    pub fn finish(self) -> ArenaBox<'a, [u8]> {
+        self.buf.into_boxed_slice()
+    }
+
+    /// Returns the bytes written, borrowed from the Arena for as long as it
+    /// lives.
+    ///
+    /// Like with [`ArenaBox::leak`], the bytes stay allocated until the
+    /// Arena is reset or dropped.
+    ///
+    /// [`ArenaBox::leak`]: struct.ArenaBox.html#method.leak
+    pub fn finish_slice(self) -> &'a [u8] {
+        ArenaBox::leak(self.finish())
+    }
+}
+
+impl<'a> Write for ArenaWriter<'a> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        match self.buf.extend_from_slice(bytes) {
+            Ok(()) => Ok(bytes.len()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.write(bytes).map(|_| ())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use std::vec::Vec;
+    use test_util::checked_arena;
+
+    /// Writes a few kilobytes of mixed formatted and raw output to `w`.
+    fn write_records<W: Write>(w: &mut W) -> io::Result<()> {
+        for i in 0..200u32 {
+            write!(w, "{{\"id\":{},\"name\":\"item-{:04x}\",\"tags\":[", i, i * 7)?;
+            for t in 0..i % 5 {
+                write!(w, "{}\"t{}\"", if t == 0 { "" } else { "," }, t)?;
+            }
+            w.write_all(b"]}\n")?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn writer_matches_vec() {
+        let a = checked_arena(64 * 1024, 4096);
+        let mut model = Vec::new();
+        write_records(&mut model).unwrap();
+        assert!(model.len() > 4096);
+
+        let mut w = ArenaWriter::new(&a);
+        write_records(&mut w).unwrap();
+        w.flush().unwrap();
+        assert_eq!(w.as_slice(), &model[..]);
+        let b = w.finish();
+        assert_eq!(&*b, &model[..]);
+        // The buffer was the only allocation, so it grew in place and was
+        // trimmed to its length when finished.
+        assert_eq!(a.used(), model.len());
+    }
+
+    #[test]
+    fn writer_interleaved() {
+        let a = checked_arena(64 * 1024, 4096);
+        let mut model = Vec::new();
+        let mut w = ArenaWriter::new(&a);
+        for i in 0..100u64 {
+            write!(w, "{};", i).unwrap();
+            write!(model, "{};", i).unwrap();
+            // Something else allocated after the buffer keeps it from
+            // growing in place.
+            let _ = a.new_box(i).unwrap();
+        }
+        assert_eq!(w.len(), model.len());
+        let s = w.finish_slice();
+        assert_eq!(s, &model[..]);
+    }
+
+    #[test]
+    fn writer_exhausted() {
+        let a = checked_arena(16, 16);
+        let mut w = ArenaWriter::new(&a);
+        assert!(w.is_empty());
+        w.write_all(b"0123456789").unwrap();
+        let e = w.write_all(b"abcdefghij").unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::OutOfMemory);
+        assert_eq!(
+            e.get_ref().and_then(|e| e.downcast_ref::<::AllocError>()),
+            Some(&::AllocError::Exhausted),
+        );
+        // A failed write leaves what was written before intact.
+        assert_eq!(w.as_slice(), b"0123456789");
+        assert_eq!(write!(w, "{}", 123456789).unwrap_err().kind(), io::ErrorKind::OutOfMemory);
+        w.write_all(b"abcdef").unwrap();
+        assert_eq!(&*w.finish(), b"0123456789abcdef");
+    }
+}
@@ -0,0 +1,172 @@
+// See the COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Tests of how zero-sized values behave across every allocation entry
+//! point: they never take up memory, never fail, and are still dropped.
+
+use core::cell::Cell;
+use core::mem;
+
+use std::vec::Vec;
+use test_util::{checked_arena, CheckedArena};
+#[cfg(feature = "alloc")]
+use {Arena, EpochArena};
+use {alloc_soa, ArenaBox, SmallArenaVec};
+
+thread_local! {
+    static DROPS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// A zero-sized type that counts its drops in `DROPS`.
+#[derive(Clone, Debug, PartialEq)]
+struct Zst;
+
+impl Drop for Zst {
+    fn drop(&mut self) {
+        DROPS.with(|d| d.set(d.get() + 1));
+    }
+}
+
+fn drops() -> usize {
+    DROPS.with(|d| d.get())
+}
+
+/// An Arena that has no memory left, not even for a single byte.
+fn full_arena() -> CheckedArena {
+    let a = checked_arena(64, 64);
+    mem::forget(a.take_remaining());
+    assert_eq!(a.new_box(0u8), Err(0));
+    a
+}
+
+#[test]
+fn zst_boxes() {
+    let a = full_arena();
+    let used = a.used.get();
+    let start = drops();
+    let boxes: Vec<ArenaBox<Zst>> = (0..1000).map(|_| a.new_box(Zst).unwrap()).collect();
+    assert_eq!(a.used.get(), used);
+    assert_eq!(drops(), start);
+    drop(boxes);
+    assert_eq!(drops(), start + 1000);
+    assert_eq!(*a.new_box(()).unwrap(), ());
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn zst_zero_capacity() {
+    let a = Arena::from_regions(&mut []).unwrap();
+    let start = drops();
+    let b = a.new_box(Zst).unwrap();
+    assert_eq!(*a.new_box(()).unwrap(), ());
+    let f = a.alloc_fn_once(|| 7).ok().unwrap();
+    assert_eq!(f.call_once(()), 7);
+    drop(b);
+    assert_eq!(drops(), start + 1);
+    assert_eq!(a.new_box(0u8), Err(0));
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn zst_no_quota_or_padding() {
+    let mut a = Arena::new(1024, 1024).unwrap();
+    a.set_quota(0);
+    a.set_min_alignment(64);
+    let b = a.new_box(Zst).unwrap();
+    let c = a.new_box([(); 16]).unwrap();
+    assert_eq!(a.used.get(), 0);
+    assert_eq!(a.min_alignment_padding(), 0);
+    assert!(!a.reclaim(b));
+    assert_eq!(a.used.get(), 0);
+    assert_eq!(ArenaBox::into_slice(c).len(), 16);
+}
+
+#[test]
+fn zst_huge_slices() {
+    let a = full_arena();
+    let s = ArenaBox::into_slice(a.new_box([(); usize::MAX]).unwrap());
+    assert_eq!(s.len(), usize::MAX);
+}
+
+#[test]
+fn zst_soa() {
+    struct Pair {
+        unit: (),
+        zst: Zst,
+    }
+    let a = full_arena();
+    let start = drops();
+    let pairs = (0..10).map(|_| Pair { unit: (), zst: Zst });
+    let (units, zsts) = alloc_soa!(&a, pairs => unit, zst).unwrap();
+    assert_eq!((units.len(), zsts.len()), (10, 10));
+    assert_eq!(drops(), start);
+    drop(zsts);
+    assert_eq!(drops(), start + 10);
+}
+
+#[test]
+fn zst_slice_drops() {
+    let a = full_arena();
+    let start = drops();
+    let s = ArenaBox::into_slice(a.new_box([Zst, Zst, Zst]).unwrap());
+    assert_eq!(s.len(), 3);
+    drop(s);
+    assert_eq!(drops(), start + 3);
+
+    let mut v: SmallArenaVec<Zst, 0> = SmallArenaVec::new(&a);
+    for _ in 0..1000 {
+        v.push(Zst).unwrap();
+    }
+    assert!(!v.spilled());
+    assert_eq!(v.capacity(), usize::MAX);
+    let s = v.into_boxed_slice(&a).ok().unwrap();
+    assert_eq!(s.len(), 1000);
+    assert_eq!(drops(), start + 3);
+    drop(s);
+    assert_eq!(drops(), start + 1003);
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn zst_epoch() {
+    let mut a = EpochArena::new(16, 0).unwrap();
+    let start = drops();
+    for _ in 0..100 {
+        a.new_box(Zst).ok().unwrap();
+    }
+    let x = a.new_box(1u64).ok().unwrap();
+    assert_eq!(a.get(&x), Some(&1));
+    a.advance().unwrap();
+    a.retain_last(1);
+    assert_eq!(drops(), start + 100);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn zst_sharded() {
+    let a = ::ShardedArena::new(64, 64, 2).unwrap();
+    let huge = unsafe {
+        ::core::slice::from_raw_parts(::core::ptr::NonNull::<()>::dangling().as_ptr(), usize::MAX)
+    };
+    let s = a.alloc_slice_copy(huge).unwrap();
+    assert_eq!(s.len(), usize::MAX);
+    assert_eq!(*a.new_box(()).unwrap(), ());
+    assert_eq!(a.used(), 0);
+}
+
+#[cfg(feature = "dyn-clone")]
+#[test]
+fn zst_clone_dyn() {
+    let a = full_arena();
+    let start = drops();
+    let z = Zst;
+    let b = a.clone_dyn(&z).unwrap();
+    assert_eq!(*b, z);
+    drop(b);
+    assert_eq!(drops(), start + 1);
+}